@@ -0,0 +1,285 @@
+use crate::semantic::{Attrs, Graph};
+
+#[cfg(test)]
+use crate::{ast, tokenize::tokenize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArrowShape {
+    Box,
+    Crow,
+    Curve,
+    ICurve,
+    Diamond,
+    Dot,
+    Inv,
+    None,
+    Normal,
+    Tee,
+    Vee,
+}
+
+const SHAPES: [(&str, ArrowShape); 11] = [
+    ("box", ArrowShape::Box),
+    ("crow", ArrowShape::Crow),
+    ("curve", ArrowShape::Curve),
+    ("icurve", ArrowShape::ICurve),
+    ("diamond", ArrowShape::Diamond),
+    ("dot", ArrowShape::Dot),
+    ("inv", ArrowShape::Inv),
+    ("none", ArrowShape::None),
+    ("normal", ArrowShape::Normal),
+    ("tee", ArrowShape::Tee),
+    ("vee", ArrowShape::Vee),
+];
+
+// Which halves of the shape are drawn, set by the l and r modifiers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Both,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arrow {
+    pub shape: ArrowShape,
+    pub open: bool,
+    pub side: Side,
+}
+
+const MAX_ARROWS: usize = 4;
+
+// Old names Graphviz still accepts, with what they stand for.
+const SYNONYMS: [(&str, &str); 5] = [
+    ("ediamond", "odiamond"),
+    ("empty", "onormal"),
+    ("halfopen", "lvee"),
+    ("invempty", "oinv"),
+    ("open", "vee"),
+];
+
+// Parses an arrowhead or arrowtail value such as "lteeoldiamond": up to four shapes, each
+// optionally preceded by o for an open shape and l or r for half of it, drawn from the node
+// outwards.
+pub fn parse_arrow(value: &str) -> Result<Vec<Arrow>, String> {
+    let mut arrows = Vec::new();
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let mut name = rest;
+        let synonym = SYNONYMS
+            .iter()
+            .find(|(synonym, _)| rest.starts_with(synonym));
+        if let Some((synonym, replacement)) = synonym {
+            rest = &rest[synonym.len()..];
+            name = replacement;
+        }
+        let open = name.starts_with('o');
+        if open {
+            name = &name[1..];
+        }
+        let side = match name.chars().next() {
+            Some('l') => Side::Left,
+            Some('r') => Side::Right,
+            _ => Side::Both,
+        };
+        if side != Side::Both {
+            name = &name[1..];
+        }
+        let (shape_name, shape) = match SHAPES.iter().find(|(shape, _)| name.starts_with(shape)) {
+            Some(shape) => *shape,
+            None => {
+                return Err(format!(
+                    "{}:{} Unknown arrow shape {}",
+                    file!(),
+                    line!(),
+                    value
+                ))
+            }
+        };
+        if synonym.is_none() {
+            rest = &name[shape_name.len()..];
+        }
+        arrows.push(Arrow { shape, open, side });
+    }
+    if arrows.is_empty() || arrows.len() > MAX_ARROWS {
+        return Err(format!(
+            "{}:{} An arrow needs 1 to {} shapes: {}",
+            file!(),
+            line!(),
+            MAX_ARROWS,
+            value
+        ));
+    }
+    Ok(arrows)
+}
+
+#[test]
+fn test_parse_arrow() {
+    let arrow = |shape, open, side| Arrow { shape, open, side };
+    assert_eq!(
+        parse_arrow("normal"),
+        Ok(vec![arrow(ArrowShape::Normal, false, Side::Both)])
+    );
+    assert_eq!(
+        parse_arrow("lteeoldiamond"),
+        Ok(vec![
+            arrow(ArrowShape::Tee, false, Side::Left),
+            arrow(ArrowShape::Diamond, true, Side::Left),
+        ])
+    );
+    assert_eq!(
+        parse_arrow("invodot"),
+        Ok(vec![
+            arrow(ArrowShape::Inv, false, Side::Both),
+            arrow(ArrowShape::Dot, true, Side::Both),
+        ])
+    );
+    assert_eq!(
+        parse_arrow("icurveorbox"),
+        Ok(vec![
+            arrow(ArrowShape::ICurve, false, Side::Both),
+            arrow(ArrowShape::Box, true, Side::Right),
+        ])
+    );
+    assert_eq!(
+        parse_arrow("emptyhalfopen"),
+        Ok(vec![
+            arrow(ArrowShape::Normal, true, Side::Both),
+            arrow(ArrowShape::Vee, false, Side::Left),
+        ])
+    );
+    assert_eq!(
+        parse_arrow("nonenormal"),
+        Ok(vec![
+            arrow(ArrowShape::None, false, Side::Both),
+            arrow(ArrowShape::Normal, false, Side::Both),
+        ])
+    );
+
+    assert!(parse_arrow("").is_err());
+    assert!(parse_arrow("arrow").is_err());
+    assert!(parse_arrow("normalx").is_err());
+    assert!(parse_arrow("dotdotdotdotdot").is_err());
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Dir {
+    Forward,
+    Back,
+    Both,
+    None,
+}
+
+// The arrows drawn at the ends of an edge, after dir has been applied.
+#[derive(Debug, PartialEq)]
+pub struct EdgeArrows {
+    pub head: Vec<Arrow>,
+    pub tail: Vec<Arrow>,
+    pub size: f64,
+}
+
+fn parse_dir(value: &str) -> Result<Dir, String> {
+    match value {
+        "forward" => Ok(Dir::Forward),
+        "back" => Ok(Dir::Back),
+        "both" => Ok(Dir::Both),
+        "none" => Ok(Dir::None),
+        _ => Err(format!("{}:{} Unknown dir {}", file!(), line!(), value)),
+    }
+}
+
+// dir defaults to forward in a directed graph and to none otherwise.
+pub fn edge_arrows(attrs: &Attrs, directed: bool) -> Result<EdgeArrows, String> {
+    let dir = match attrs.get("dir") {
+        Some(dir) => parse_dir(dir)?,
+        None if directed => Dir::Forward,
+        None => Dir::None,
+    };
+    let end = |attr, drawn| match (drawn, attrs.get(attr)) {
+        (false, _) => Ok(Vec::new()),
+        (true, Some(value)) => parse_arrow(value),
+        (true, None) => parse_arrow("normal"),
+    };
+    let size = match attrs.get("arrowsize") {
+        Some(size) => match size.parse::<f64>() {
+            Ok(size) if size >= 0.0 => size,
+            _ => return Err(format!("{}:{} Bad arrowsize {}", file!(), line!(), size)),
+        },
+        None => 1.0,
+    };
+    Ok(EdgeArrows {
+        head: end("arrowhead", matches!(dir, Dir::Forward | Dir::Both))?,
+        tail: end("arrowtail", matches!(dir, Dir::Back | Dir::Both))?,
+        size,
+    })
+}
+
+// Checks dir, arrowhead, arrowtail and arrowsize of every edge.
+pub fn check(graph: &Graph) -> Vec<String> {
+    let op = if graph.directed { "->" } else { "--" };
+    let mut warnings = Vec::new();
+    for edge in graph.edges.iter() {
+        if let Err(e) = edge_arrows(&edge.attrs, graph.directed) {
+            warnings.push(format!(
+                "Edge {} {} {} has bad arrows: {}",
+                graph.nodes[edge.tail].name, op, graph.nodes[edge.head].name, e
+            ));
+        }
+    }
+    warnings
+}
+
+#[test]
+fn test_edge_arrows() {
+    let tokens = tokenize(
+        r#"digraph {
+    a -> b
+    a -> c [dir = both, arrowtail = odot, arrowsize = 0.5]
+    b -> c [dir = back, arrowhead = nonsense]
+    c -> a [dir = sideways]
+    c -> b [arrowhead = lvee, arrowsize = -1]
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let graph = Graph::from_ast(&graph);
+    let normal = Arrow {
+        shape: ArrowShape::Normal,
+        open: false,
+        side: Side::Both,
+    };
+    assert_eq!(
+        edge_arrows(&graph.edges[0].attrs, true),
+        Ok(EdgeArrows {
+            head: vec![normal],
+            tail: vec![],
+            size: 1.0
+        })
+    );
+    assert_eq!(
+        edge_arrows(&graph.edges[0].attrs, false).unwrap().head,
+        vec![]
+    );
+    assert_eq!(
+        edge_arrows(&graph.edges[1].attrs, true),
+        Ok(EdgeArrows {
+            head: vec![normal],
+            tail: vec![Arrow {
+                shape: ArrowShape::Dot,
+                open: true,
+                side: Side::Both
+            }],
+            size: 0.5
+        })
+    );
+    // arrowhead is not drawn with dir=back, so it is not checked either.
+    assert_eq!(
+        edge_arrows(&graph.edges[2].attrs, true).unwrap().head,
+        vec![]
+    );
+
+    let warnings = check(&graph);
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0].starts_with("Edge c -> a has bad arrows"));
+    assert!(warnings[1].starts_with("Edge c -> b has bad arrows"));
+}
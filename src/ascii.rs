@@ -0,0 +1,461 @@
+use crate::draw::{self, Object, Op, Pen};
+use crate::layout::{self, rect_around, Point, Rect, Spline, POINTS_PER_INCH};
+use crate::semantic::Graph;
+use crate::text::Justification;
+
+#[cfg(test)]
+use crate::layout::Engine;
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// The size of a character cell in points. Terminal characters are about twice as high as they
+// are wide, and a cell is a little narrower than an average letter of a 14 point label, so that
+// labels fit in their nodes.
+const CELL_WIDTH: f64 = 6.0;
+const CELL_HEIGHT: f64 = 12.0;
+
+// The lines that leave a cell, which pick the box-drawing character for it.
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+// The top left and bottom right cells of a box.
+type CellBox = ((i64, i64), (i64, i64));
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cell {
+    Empty,
+    Lines(u8),
+    Char(char),
+}
+
+// The characters of one of the two styles.
+struct Charset {
+    // Indexed by the lines leaving the cell. The ends of lines, which leave on one side only,
+    // are drawn as if they went through.
+    lines: [char; 16],
+    // The corners of ellipses and other round shapes: top left, top right, bottom left and
+    // bottom right.
+    round: [char; 4],
+    // Lines going up and down to the right.
+    diagonals: [char; 2],
+    // Arrowheads pointing right, left, up and down.
+    arrows: [char; 4],
+    point: char,
+}
+
+const ASCII: Charset = Charset {
+    lines: [
+        ' ', '|', '|', '|', '-', '+', '+', '+', '-', '+', '+', '+', '-', '+', '+', '+',
+    ],
+    round: ['/', '\\', '\\', '/'],
+    diagonals: ['/', '\\'],
+    arrows: ['>', '<', '^', 'v'],
+    point: 'o',
+};
+
+const UNICODE: Charset = Charset {
+    lines: [
+        ' ', '│', '│', '│', '─', '┘', '┐', '┤', '─', '└', '┌', '├', '─', '┴', '┬', '┼',
+    ],
+    round: ['╭', '╮', '╰', '╯'],
+    diagonals: ['╱', '╲'],
+    arrows: ['▶', '◀', '▲', '▼'],
+    point: '●',
+};
+
+struct Grid {
+    cells: Vec<Vec<Cell>>,
+    bb: Rect,
+}
+
+impl Grid {
+    // A grid a cell larger than bb on every side, as boxes are rounded to whole cells and may
+    // stick out of it. Blank rows and columns are trimmed when the grid is written out.
+    fn new((x0, y0, x1, y1): Rect) -> Grid {
+        let bb = (
+            x0 - CELL_WIDTH,
+            y0 - CELL_HEIGHT,
+            x1 + CELL_WIDTH,
+            y1 + CELL_HEIGHT,
+        );
+        let mut grid = Grid {
+            cells: Vec::new(),
+            bb,
+        };
+        let (columns, rows) = grid.cell((bb.2, bb.1));
+        grid.cells = vec![vec![Cell::Empty; columns as usize + 1]; rows as usize + 1];
+        grid
+    }
+
+    // The column and row of the cell a point is in, counted from the top left.
+    fn cell(&self, (x, y): Point) -> (i64, i64) {
+        (
+            ((x - self.bb.0) / CELL_WIDTH).floor() as i64,
+            ((self.bb.3 - y) / CELL_HEIGHT).floor() as i64,
+        )
+    }
+
+    fn get(&mut self, (column, row): (i64, i64)) -> Option<&mut Cell> {
+        let row = self.cells.get_mut(usize::try_from(row).ok()?)?;
+        row.get_mut(usize::try_from(column).ok()?)
+    }
+
+    fn set(&mut self, at: (i64, i64), cell: Cell) {
+        if let Some(old) = self.get(at) {
+            *old = cell;
+        }
+    }
+
+    // Adds lines leaving a cell, joining them with the lines already there.
+    fn add_lines(&mut self, at: (i64, i64), lines: u8) {
+        if let Some(cell) = self.get(at) {
+            *cell = match *cell {
+                Cell::Lines(old) => Cell::Lines(old | lines),
+                _ => Cell::Lines(lines),
+            };
+        }
+    }
+
+    // Draws a straight line from one cell to another that is in the same row or column.
+    fn line(&mut self, from: (i64, i64), to: (i64, i64)) {
+        let (dc, dr) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+        let (forward, backward) = match (dc, dr) {
+            (1, 0) => (RIGHT, LEFT),
+            (-1, 0) => (LEFT, RIGHT),
+            (0, 1) => (DOWN, UP),
+            (0, -1) => (UP, DOWN),
+            _ => return,
+        };
+        let mut at = from;
+        while at != to {
+            self.add_lines(at, forward);
+            at = (at.0 + dc, at.1 + dr);
+            self.add_lines(at, backward);
+        }
+    }
+
+    // The cells of a box, cleared inside and outlined, with round corners when asked for.
+    fn outline(&mut self, (c0, r0): (i64, i64), (c1, r1): (i64, i64), round: Option<[char; 4]>) {
+        for row in r0..=r1 {
+            for column in c0..=c1 {
+                self.set((column, row), Cell::Empty);
+            }
+        }
+        self.line((c0, r0), (c1, r0));
+        self.line((c1, r0), (c1, r1));
+        self.line((c1, r1), (c0, r1));
+        self.line((c0, r1), (c0, r0));
+        if let Some(round) = round {
+            for (corner, c) in [(c0, r0), (c1, r0), (c0, r1), (c1, r1)]
+                .into_iter()
+                .zip(round)
+            {
+                self.set(corner, Cell::Char(c));
+            }
+        }
+    }
+
+    // The top left and bottom right cells of a box, centered on its middle cell. Halves round
+    // down, so that a single line label has a row to itself, and boxes are at least three
+    // cells across to have room for their outline.
+    fn corners(&self, (x0, y0, x1, y1): Rect) -> CellBox {
+        let (column, row) = self.cell(((x0 + x1) / 2.0, (y0 + y1) / 2.0));
+        let half = |length: f64, cell: f64| ((length / 2.0 / cell - 0.01).round() as i64).max(1);
+        let (dc, dr) = (half(x1 - x0, CELL_WIDTH), half(y1 - y0, CELL_HEIGHT));
+        ((column - dc, row - dr), (column + dc, row + dr))
+    }
+
+    fn text(&mut self, pen: &Pen, pos: Point, justification: Justification, text: &str) {
+        let chars: Vec<char> = text.chars().collect();
+        let middle = (pos.0, pos.1 + draw::BASELINE_DROP * pen.font_size);
+        let (column, row) = self.cell(middle);
+        let start = match justification {
+            Justification::Left => column,
+            Justification::Center => column - chars.len() as i64 / 2,
+            Justification::Right => column - chars.len() as i64,
+        };
+        for (i, &c) in chars.iter().enumerate() {
+            self.set((start + i as i64, row), Cell::Char(c));
+        }
+    }
+
+    fn texts(&mut self, ops: &[Op]) {
+        let mut pen = Pen::default();
+        for op in ops {
+            if pen.set(op) {
+                continue;
+            }
+            if let Op::Text {
+                pos,
+                justification,
+                text,
+                ..
+            } = op
+            {
+                self.text(&pen, *pos, *justification, text);
+            }
+        }
+    }
+
+    // The drawn lines, without the blank lines and columns around them.
+    fn to_string(&self, charset: &Charset) -> String {
+        let lines: Vec<String> = self
+            .cells
+            .iter()
+            .map(|row| {
+                let line: String = row
+                    .iter()
+                    .map(|cell| match *cell {
+                        Cell::Empty => ' ',
+                        Cell::Lines(lines) => charset.lines[lines as usize],
+                        Cell::Char(c) => c,
+                    })
+                    .collect();
+                line.trim_end().to_string()
+            })
+            .collect();
+        let (Some(first), Some(last)) = (
+            lines.iter().position(|line| !line.is_empty()),
+            lines.iter().rposition(|line| !line.is_empty()),
+        ) else {
+            return String::new();
+        };
+        let lines = &lines[first..=last];
+        let indent = lines
+            .iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.chars().take_while(|&c| c == ' ').count())
+            .min()
+            .unwrap_or(0);
+        let mut out = String::new();
+        for line in lines {
+            out.extend(line.chars().skip(indent));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// The cells an edge passes through from its tail to its head, each next to the one before.
+fn edge_cells(grid: &Grid, spline: &Spline) -> Vec<(i64, i64)> {
+    let points = spline
+        .start
+        .into_iter()
+        .chain(spline.sample())
+        .chain(spline.end);
+    let mut cells: Vec<(i64, i64)> = Vec::new();
+    for point in points {
+        let to = grid.cell(point);
+        let Some(&from) = cells.last() else {
+            cells.push(to);
+            continue;
+        };
+        // Steps one cell at a time along the straight line between the points.
+        let (mut column, mut row) = from;
+        let (dc, dr) = (to.0 - from.0, to.1 - from.1);
+        let steps = dc.abs().max(dr.abs());
+        for i in 1..=steps {
+            let next = (
+                from.0 + (dc * i + dc.signum() * steps / 2) / steps.max(1),
+                from.1 + (dr * i + dr.signum() * steps / 2) / steps.max(1),
+            );
+            if next != (column, row) {
+                cells.push(next);
+                (column, row) = next;
+            }
+        }
+    }
+    cells
+}
+
+// The arrowhead that points from one cell to the next.
+fn arrow(charset: &Charset, from: (i64, i64), to: (i64, i64)) -> char {
+    match (to.0 - from.0, to.1 - from.1) {
+        (_, dr) if dr < 0 => charset.arrows[2],
+        (_, dr) if dr > 0 => charset.arrows[3],
+        (dc, _) if dc < 0 => charset.arrows[1],
+        _ => charset.arrows[0],
+    }
+}
+
+// Draws an edge in the cells outside nodes, with arrowheads at the ends that have them.
+fn draw_edge(grid: &mut Grid, charset: &Charset, spline: &Spline, nodes: &[CellBox]) {
+    let cells = edge_cells(grid, spline);
+    let inside = |(column, row): (i64, i64)| {
+        nodes
+            .iter()
+            .any(|&(from, to)| (from.0..=to.0).contains(&column) && (from.1..=to.1).contains(&row))
+    };
+    let free: Vec<usize> = (0..cells.len()).filter(|&i| !inside(cells[i])).collect();
+    for pair in cells.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if inside(a) || inside(b) {
+            continue;
+        }
+        if a.0 == b.0 || a.1 == b.1 {
+            grid.line(a, b);
+        } else {
+            let diagonal = if (b.0 - a.0) * (b.1 - a.1) < 0 {
+                charset.diagonals[0]
+            } else {
+                charset.diagonals[1]
+            };
+            for at in [a, b] {
+                if matches!(grid.get(at), Some(Cell::Empty)) {
+                    grid.set(at, Cell::Char(diagonal));
+                }
+            }
+        }
+    }
+    // An arrowhead takes the last cell before the node it points at, and points the way the
+    // edge goes there.
+    if let (Some(_), Some(&last)) = (spline.end, free.last()) {
+        if last > 0 {
+            grid.set(
+                cells[last],
+                Cell::Char(arrow(charset, cells[last - 1], cells[last])),
+            );
+        }
+    }
+    if let (Some(_), Some(&first)) = (spline.start, free.first()) {
+        if let Some(&next) = cells.get(first + 1) {
+            grid.set(cells[first], Cell::Char(arrow(charset, next, cells[first])));
+        }
+    }
+}
+
+// Renders a laid-out graph as text, with nodes as boxes and edges as lines of ASCII characters
+// or of Unicode box-drawing characters. Each character stands for 6 by 12 points, so small
+// graphs fit in a terminal.
+pub fn render(graph: &Graph, unicode: bool) -> Result<String, String> {
+    let charset = if unicode { &UNICODE } else { &ASCII };
+    let objects = draw::objects(graph)?;
+    let bb = draw::Canvas::new(graph)?.bb;
+    let mut grid = Grid::new(bb);
+
+    // Clusters go under everything else.
+    for (object, _) in &objects {
+        if let Object::Cluster(i) = *object {
+            if let Some(bb) = draw::bb_of(&graph.subgraphs[i].attrs)? {
+                let (from, to) = grid.corners(bb);
+                let round = draw::has_style(&graph.subgraphs[i].attrs, "rounded");
+                grid.outline(from, to, round.then_some(charset.round));
+            }
+        }
+    }
+
+    // The cells each node covers, which edges stay out of.
+    let mut nodes = Vec::new();
+    let mut node_cells = Vec::new();
+    for (object, ops) in &objects {
+        if let Object::Node(i) = *object {
+            let attrs = &graph.nodes[i].attrs;
+            let Some(pos) = draw::position(attrs, "pos")? else {
+                continue;
+            };
+            let size = (
+                layout::number(attrs, "width", 0.0)? * POINTS_PER_INCH,
+                layout::number(attrs, "height", 0.0)? * POINTS_PER_INCH,
+            );
+            let (from, to) = grid.corners(rect_around(pos, size));
+            node_cells.push((from, to));
+            nodes.push((i, pos, from, to, ops));
+        }
+    }
+    for (object, _) in &objects {
+        if let Object::Edge(i) = *object {
+            if let Some(pos) = graph.edges[i].attrs.get("pos") {
+                draw_edge(&mut grid, charset, &Spline::parse(pos)?, &node_cells);
+            }
+        }
+    }
+    for (i, pos, from, to, ops) in nodes {
+        if graph.nodes[i].attrs.get("shape") == Some("point") {
+            let center = grid.cell(pos);
+            grid.set(center, Cell::Char(charset.point));
+            continue;
+        }
+        let draw: Vec<&Op> = ops
+            .iter()
+            .filter(|(attr, _)| *attr == "_draw_")
+            .flat_map(|(_, ops)| ops)
+            .collect();
+        // Plaintext and other shapes without an outline only show their label.
+        if draw.iter().any(|op| draw::path(op).is_some()) {
+            let round = draw
+                .iter()
+                .any(|op| matches!(op, Op::Ellipse { .. } | Op::Bezier { .. }));
+            grid.outline(from, to, round.then_some(charset.round));
+        }
+        // The lines between the fields of records, which end on the outline.
+        let clamp =
+            |(column, row): (i64, i64)| (column.clamp(from.0, to.0), row.clamp(from.1, to.1));
+        for op in draw {
+            if let Op::Polyline(points) = op {
+                for pair in points.windows(2) {
+                    grid.line(clamp(grid.cell(pair[0])), clamp(grid.cell(pair[1])));
+                }
+            }
+        }
+    }
+
+    // Labels go on top.
+    for (_, ops) in &objects {
+        for (attr, ops) in ops {
+            if *attr != "_draw_" && *attr != "_hdraw_" && *attr != "_tdraw_" {
+                grid.texts(ops);
+            }
+        }
+    }
+    Ok(grid.to_string(charset))
+}
+
+#[test]
+fn test_render() {
+    let graph = from_dot(
+        r#"digraph {
+    a [pos = "0,0!", shape = box]
+    b [pos = "2,0!"]
+    c [pos = "2,-1.5!", shape = record, label = "x|y"]
+    a -> b
+    b -> c [dir = none]
+}"#,
+    );
+    let laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    assert_eq!(
+        render(&laid_out, false).unwrap(),
+        "\
++-------+               /-------\\
+|   a   |-------------->|   b   |
++-------+               \\-------/
+                            |
+                            |
+                            |
+                            |
+                            |
+                            |
+                        +---+---+
+                        | x | y |
+                        +---+---+
+"
+    );
+    assert_eq!(
+        render(&laid_out, true).unwrap(),
+        "\
+┌───────┐               ╭───────╮
+│   a   │──────────────▶│   b   │
+└───────┘               ╰───────╯
+                            │
+                            │
+                            │
+                            │
+                            │
+                            │
+                        ┌───┬───┐
+                        │ x │ y │
+                        └───┴───┘
+"
+    );
+}
@@ -1,24 +1,110 @@
+use std::fmt;
+
+#[cfg(test)]
 use crate::tokenize::tokenize;
 
-#[derive(Debug, PartialEq)]
-struct ID {
+// html records whether the ID was an HTML string. Its value keeps the angle brackets, so this
+// is what tells <<b>x</b>> from the quoted string "<<b>x</b>>".
+#[derive(Clone, Debug, PartialEq)]
+pub struct ID {
     name: String,
+    html: bool,
+}
+
+impl ID {
+    // Makes an ID for an arbitrary string value, quoting it when it is not a valid bare ID.
+    pub fn from_value(value: &str) -> ID {
+        let name = if valid_as_bare_id(value) {
+            value.to_string()
+        } else {
+            format!("\"{}\"", value.replace('"', "\\\""))
+        };
+        ID { name, html: false }
+    }
+
+    // Makes an HTML string ID from its value, angle brackets included.
+    pub fn from_html(value: &str) -> ID {
+        ID {
+            name: value.to_string(),
+            html: true,
+        }
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.html
+    }
+
+    // Returns the name without the surrounding double quotes of a quoted ID. HTML strings keep
+    // their angle brackets.
+    pub fn value(&self) -> String {
+        if is_quoted(&self.name) {
+            self.name[1..self.name.len() - 1].replace("\\\"", "\"")
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+impl fmt::Display for ID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
 }
 
 const RESERVED_WORDS: [&str; 6] = ["node", "edge", "graph", "digraph", "subgraph", "strict"];
 
-fn valid_as_id(token: &String) -> bool {
+fn is_quoted(token: &str) -> bool {
+    token.len() >= 2 && token.starts_with('"') && token.ends_with('"')
+}
+
+fn is_html(token: &str) -> bool {
+    token.len() >= 2 && token.starts_with('<') && token.ends_with('>')
+}
+
+// numeral : [-]?(.[0-9]+ | [0-9]+(.[0-9]*)?)
+fn is_numeral(token: &str) -> bool {
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (digits, None),
+    };
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    all_digits(integer)
+        && fraction.is_none_or(all_digits)
+        && !(integer.is_empty() && fraction.unwrap_or("").is_empty())
+}
+
+#[test]
+fn test_is_numeral() {
+    for numeral in ["2", "-2", "0.5", ".5", "-.5", "20.", "1.25"] {
+        assert!(is_numeral(numeral), "{}", numeral);
+    }
+    for not_numeral in ["", "-", ".", "-.", "1.2.3", "1e3", "2a", "--"] {
+        assert!(!is_numeral(not_numeral), "{}", not_numeral);
+    }
+}
+
+fn valid_as_id(token: &str) -> bool {
+    is_quoted(token) || is_html(token) || valid_as_bare_id(token)
+}
+
+// Whether the value can be written without quotes.
+fn valid_as_bare_id(token: &str) -> bool {
     for reserved_word in RESERVED_WORDS.iter() {
-        if token.to_lowercase() == reserved_word.to_string() {
+        if token.to_lowercase() == *reserved_word {
             return false;
         }
     }
-    token.chars().all(|c| c.is_alphanumeric())
-        && token.chars().next().unwrap_or(' ').is_alphabetic()
+    if is_numeral(token) {
+        return true;
+    }
+    let first = token.chars().next().unwrap_or(' ');
+    token.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && (first.is_alphabetic() || first == '_')
 }
 
-fn parse_id(tokens: &Vec<String>) -> Result<(ID, Vec<String>), String> {
-    if tokens.len() == 0 {
+fn parse_id(tokens: &[String]) -> Result<(ID, &[String]), String> {
+    if tokens.is_empty() {
         return Err(format!("{}:{} No tokens", file!(), line!()));
     }
     if !valid_as_id(&tokens[0]) {
@@ -27,8 +113,9 @@ fn parse_id(tokens: &Vec<String>) -> Result<(ID, Vec<String>), String> {
     Ok((
         ID {
             name: tokens[0].clone(),
+            html: is_html(&tokens[0]),
         },
-        tokens[1..].to_vec(),
+        &tokens[1..],
     ))
 }
 
@@ -40,25 +127,70 @@ fn test_parse_id() {
     assert_eq!(rest, vec!["b".to_string()]);
 
     let tokens = vec!["1".to_string(), "b".to_string()];
+    let (id, rest) = parse_id(&tokens).unwrap();
+    assert_eq!(id.name, "1");
+    assert_eq!(rest, vec!["b".to_string()]);
+
+    let tokens = vec!["1b".to_string()];
     let result = parse_id(&tokens);
     assert!(result.is_err());
+
+    let tokens = tokenize(r#"cluster_a "a b" node"#.to_string());
+    let (id, rest) = parse_id(&tokens).unwrap();
+    assert_eq!(id.name, "cluster_a");
+    let (id, rest) = parse_id(rest).unwrap();
+    assert_eq!(id.name, "\"a b\"");
+    assert_eq!(id.value(), "a b");
+    assert!(parse_id(rest).is_err());
+
+    assert_eq!(ID::from_value("LR").name, "LR");
+    assert_eq!(ID::from_value("0.5").name, "0.5");
+    assert_eq!(ID::from_value("1.2.3").name, "\"1.2.3\"");
+    assert_eq!(ID::from_value("a \"b\"").name, r#""a \"b\"""#);
+    assert_eq!(ID::from_value("a \"b\"").value(), "a \"b\"");
+
+    let tokens = tokenize("<<b>a b</b>>".to_string());
+    let (id, _) = parse_id(&tokens).unwrap();
+    assert_eq!(id.value(), "<<b>a b</b>>");
+    assert!(id.is_html());
+    assert_eq!(ID::from_html("<<i>c</i>>").name, "<<i>c</i>>");
+
+    // A quoted string that looks like HTML stays a quoted string.
+    let tokens = tokenize(r#""<init>""#.to_string());
+    let (id, _) = parse_id(&tokens).unwrap();
+    assert_eq!(id.value(), "<init>");
+    assert!(!id.is_html());
+    assert_eq!(ID::from_value("<init>").to_string(), r#""<init>""#);
 }
 
 #[derive(Debug, PartialEq)]
-struct IDEqStmt {
+pub(crate) struct IDEqStmt {
     id_left: ID,
     id_right: ID,
 }
 
-fn parse_id_eq_stmt(tokens: &Vec<String>) -> Result<(IDEqStmt, Vec<String>), String> {
+impl IDEqStmt {
+    // Returns the unquoted name with the value, which knows whether it is an HTML string.
+    pub(crate) fn attr(&self) -> (String, ID) {
+        (self.id_left.value(), self.id_right.clone())
+    }
+}
+
+impl fmt::Display for IDEqStmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.id_left, self.id_right)
+    }
+}
+
+fn parse_id_eq_stmt(tokens: &[String]) -> Result<(IDEqStmt, &[String]), String> {
     let (id_left, rest) = parse_id(tokens)?;
-    if rest.len() == 0 {
+    if rest.is_empty() {
         return Err(format!("{}:{} No tokens", file!(), line!()));
     }
     if rest[0] != "=" {
         return Err(format!("{}:{} Expected '='", file!(), line!()));
     }
-    let (id_right, rest) = parse_id(&rest[1..].to_vec())?;
+    let (id_right, rest) = parse_id(&rest[1..])?;
     Ok((IDEqStmt { id_left, id_right }, rest))
 }
 
@@ -86,10 +218,83 @@ fn test_parse_id_eq_stmt() {
     assert!(result.is_err());
 }
 
+// port : ':' ID [ ':' compass_pt ] | ':' compass_pt
 #[derive(Debug, PartialEq)]
-enum EdgeStmtEdge {
-    // TODO: We can take subgraph as the left side of the edge.
-    NodeID(ID),
+pub(crate) struct Port {
+    id: ID,
+    compass_pt: Option<ID>,
+}
+
+impl Port {
+    // Returns the port the way Graphviz stores it in tailport and headport, e.g. "f0:n".
+    pub(crate) fn value(&self) -> String {
+        match &self.compass_pt {
+            Some(compass_pt) => format!("{}:{}", self.id.value(), compass_pt.value()),
+            None => self.id.value(),
+        }
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ":{}", self.id)?;
+        if let Some(compass_pt) = &self.compass_pt {
+            write!(f, ":{}", compass_pt)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_port(tokens: &[String]) -> Result<(Port, &[String]), String> {
+    if tokens.is_empty() {
+        return Err(format!("{}:{} No tokens", file!(), line!()));
+    }
+    if tokens[0] != ":" {
+        return Err(format!("{}:{} Expected ':'", file!(), line!()));
+    }
+    let (id, rest) = parse_id(&tokens[1..])?;
+    if !rest.is_empty() && rest[0] == ":" {
+        let (compass_pt, rest) = parse_id(&rest[1..])?;
+        return Ok((
+            Port {
+                id,
+                compass_pt: Some(compass_pt),
+            },
+            rest,
+        ));
+    }
+    Ok((
+        Port {
+            id,
+            compass_pt: None,
+        },
+        rest,
+    ))
+}
+
+#[test]
+fn test_parse_port() {
+    let tokens = tokenize("a:f0:n -> b".to_string());
+    let (port, rest) = parse_port(&tokens[1..]).unwrap();
+    assert_eq!(port.value(), "f0:n");
+    assert_eq!(port.to_string(), ":f0:n");
+    assert_eq!(rest, vec!["->".to_string(), "b".to_string()]);
+
+    let tokens = tokenize(r#":"f 1" ]"#.to_string());
+    let (port, rest) = parse_port(&tokens).unwrap();
+    assert_eq!(port.value(), "f 1");
+    assert_eq!(port.compass_pt, None);
+    assert_eq!(rest, vec!["]".to_string()]);
+
+    let tokens = tokenize("a : f0".to_string());
+    assert!(parse_port(&tokens).is_err());
+}
+
+// node_id : ID [ port ], or a subgraph, which stands for all of its nodes.
+#[derive(Debug, PartialEq)]
+pub(crate) enum EdgeStmtEdge {
+    NodeID(ID, Option<Port>),
+    Subgraph(Subgraph),
 }
 
 #[derive(Debug, PartialEq)]
@@ -106,15 +311,270 @@ struct EdgeStmtRHS {
 }
 
 #[derive(Debug, PartialEq)]
-struct EdgeStmt {
+pub(crate) struct EdgeStmt {
     edge_edge: EdgeStmtEdge,
     edge_rhs: Option<Box<EdgeStmtRHS>>,
-    // TODO attributes
+    attr_list: Option<AttrList>,
+}
+
+impl EdgeStmt {
+    // Returns the ends of the edge chain in order, e.g. a, b and c for `a -> b -> c`.
+    pub(crate) fn edge_ends(&self) -> Vec<&EdgeStmtEdge> {
+        let mut edge_ends = vec![&self.edge_edge];
+        let mut edge_rhs = &self.edge_rhs;
+        while let Some(rhs) = edge_rhs {
+            edge_ends.push(&rhs.edge_egdge);
+            edge_rhs = &rhs.edge_rhs;
+        }
+        edge_ends
+    }
+
+    pub(crate) fn attrs(&self) -> Vec<(String, ID)> {
+        match &self.attr_list {
+            Some(attr_list) => attr_list.attrs(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for EdgeStmtEdge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdgeStmtEdge::NodeID(id, Some(port)) => write!(f, "{}{}", id, port),
+            EdgeStmtEdge::NodeID(id, None) => write!(f, "{}", id),
+            EdgeStmtEdge::Subgraph(subgraph) => write!(f, "{}", subgraph),
+        }
+    }
+}
+
+impl fmt::Display for EdgeStmtOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdgeStmtOp::Directed => write!(f, "->"),
+            EdgeStmtOp::Undirected => write!(f, "--"),
+        }
+    }
+}
+
+impl fmt::Display for EdgeStmtRHS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, " {} {}", self.edge_op, self.edge_egdge)?;
+        if let Some(edge_rhs) = &self.edge_rhs {
+            write!(f, "{}", edge_rhs)?;
+        }
+        Ok(())
+    }
 }
 
-fn parse_edge_stmt_edge(tokens: &Vec<String>) -> Result<(EdgeStmtEdge, Vec<String>), String> {
+impl fmt::Display for EdgeStmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.edge_edge)?;
+        if let Some(edge_rhs) = &self.edge_rhs {
+            write!(f, "{}", edge_rhs)?;
+        }
+        if let Some(attr_list) = &self.attr_list {
+            write!(f, " {}", attr_list)?;
+        }
+        Ok(())
+    }
+}
+
+// a_list : ID '=' ID [ (';' | ',') ] [ a_list ]
+#[derive(Debug, PartialEq)]
+struct AList {
+    id_eq_stmt: IDEqStmt,
+    a_list: Option<Box<AList>>,
+}
+
+impl fmt::Display for AList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id_eq_stmt)?;
+        if let Some(a_list) = &self.a_list {
+            write!(f, ", {}", a_list)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_a_list(tokens: &[String]) -> Result<(AList, &[String]), String> {
+    let (id_eq_stmt, mut rest) = parse_id_eq_stmt(tokens)?;
+    if !rest.is_empty() && (rest[0] == ";" || rest[0] == ",") {
+        rest = &rest[1..];
+    }
+    match parse_a_list(rest) {
+        Ok((a_list, rest)) => Ok((
+            AList {
+                id_eq_stmt,
+                a_list: Some(Box::new(a_list)),
+            },
+            rest,
+        )),
+        Err(_) => Ok((
+            AList {
+                id_eq_stmt,
+                a_list: None,
+            },
+            rest,
+        )),
+    }
+}
+
+#[test]
+fn test_parse_a_list() {
+    let tokens = tokenize("a = b, c = d; e = f ]".to_string());
+    let (a_list, rest) = parse_a_list(&tokens).unwrap();
+    assert_eq!(a_list.id_eq_stmt.id_left.name, "a");
+    assert_eq!(a_list.id_eq_stmt.id_right.name, "b");
+    match a_list.a_list {
+        Some(a_list) => {
+            assert_eq!(a_list.id_eq_stmt.id_left.name, "c");
+            match a_list.a_list {
+                Some(a_list) => {
+                    assert_eq!(a_list.id_eq_stmt.id_left.name, "e");
+                    assert_eq!(a_list.a_list, None);
+                }
+                None => panic!("expected a_list"),
+            }
+        }
+        None => panic!("expected a_list"),
+    }
+    assert_eq!(rest, vec!["]".to_string()]);
+}
+
+// attr_list : '[' [ a_list ] ']' [ attr_list ]
+#[derive(Debug, PartialEq)]
+struct AttrList {
+    a_list: Option<AList>,
+    attr_list: Option<Box<AttrList>>,
+}
+
+impl AttrList {
+    fn new(attrs: &[(String, ID)]) -> AttrList {
+        let mut a_list = None;
+        for (name, value) in attrs.iter().rev() {
+            a_list = Some(AList {
+                id_eq_stmt: IDEqStmt {
+                    id_left: ID::from_value(name),
+                    id_right: value.clone(),
+                },
+                a_list: a_list.map(Box::new),
+            });
+        }
+        AttrList {
+            a_list,
+            attr_list: None,
+        }
+    }
+
+    // Flattens all brackets into (name, value) pairs in source order.
+    fn attrs(&self) -> Vec<(String, ID)> {
+        let mut attrs = Vec::new();
+        let mut attr_list = Some(self);
+        while let Some(list) = attr_list {
+            let mut a_list = list.a_list.as_ref();
+            while let Some(a) = a_list {
+                attrs.push(a.id_eq_stmt.attr());
+                a_list = a.a_list.as_deref();
+            }
+            attr_list = list.attr_list.as_deref();
+        }
+        attrs
+    }
+}
+
+impl fmt::Display for AttrList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        if let Some(a_list) = &self.a_list {
+            write!(f, "{}", a_list)?;
+        }
+        write!(f, "]")?;
+        if let Some(attr_list) = &self.attr_list {
+            write!(f, " {}", attr_list)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_attr_list(tokens: &[String]) -> Result<(AttrList, &[String]), String> {
+    if tokens.is_empty() {
+        return Err(format!("{}:{} No tokens", file!(), line!()));
+    }
+    if tokens[0] != "[" {
+        return Err(format!("{}:{} Expected '['", file!(), line!()));
+    }
+    let (a_list, rest) = match parse_a_list(&tokens[1..]) {
+        Ok((a_list, rest)) => (Some(a_list), rest),
+        Err(_) => (None, &tokens[1..]),
+    };
+    if rest.is_empty() {
+        return Err(format!("{}:{} No tokens", file!(), line!()));
+    }
+    if rest[0] != "]" {
+        return Err(format!("{}:{} Expected ']'", file!(), line!()));
+    }
+    match parse_attr_list(&rest[1..]) {
+        Ok((attr_list, rest)) => Ok((
+            AttrList {
+                a_list,
+                attr_list: Some(Box::new(attr_list)),
+            },
+            rest,
+        )),
+        Err(_) => Ok((
+            AttrList {
+                a_list,
+                attr_list: None,
+            },
+            &rest[1..],
+        )),
+    }
+}
+
+#[test]
+fn test_parse_attr_list() {
+    let tokens = tokenize("[lhead = cluster_a] [] a".to_string());
+    let (attr_list, rest) = parse_attr_list(&tokens).unwrap();
+    match attr_list.a_list {
+        Some(a_list) => {
+            assert_eq!(a_list.id_eq_stmt.id_left.name, "lhead");
+            assert_eq!(a_list.id_eq_stmt.id_right.name, "cluster_a");
+        }
+        None => panic!("expected a_list"),
+    }
+    match attr_list.attr_list {
+        Some(attr_list) => {
+            assert_eq!(attr_list.a_list, None);
+            assert_eq!(attr_list.attr_list, None);
+        }
+        None => panic!("expected attr_list"),
+    }
+    assert_eq!(rest, vec!["a".to_string()]);
+
+    let tokens = tokenize("[a = b".to_string());
+    assert!(parse_attr_list(&tokens).is_err());
+
+    let tokens = tokenize(r#"[a = b, c = "d e"] [f = <<b>g</b>>]"#.to_string());
+    let (attr_list, _) = parse_attr_list(&tokens).unwrap();
+    assert_eq!(
+        attr_list.attrs(),
+        vec![
+            ("a".to_string(), ID::from_value("b")),
+            ("c".to_string(), ID::from_value("d e")),
+            ("f".to_string(), ID::from_html("<<b>g</b>>")),
+        ]
+    );
+}
+
+fn parse_edge_stmt_edge(tokens: &[String]) -> Result<(EdgeStmtEdge, &[String]), String> {
+    if let Ok((subgraph, rest)) = parse_subgraph(tokens) {
+        return Ok((EdgeStmtEdge::Subgraph(subgraph), rest));
+    }
     let (id, rest) = parse_id(tokens)?;
-    Ok((EdgeStmtEdge::NodeID(id), rest))
+    match parse_port(rest) {
+        Ok((port, rest)) => Ok((EdgeStmtEdge::NodeID(id, Some(port)), rest)),
+        Err(_) => Ok((EdgeStmtEdge::NodeID(id, None), rest)),
+    }
 }
 
 #[test]
@@ -122,18 +582,51 @@ fn test_parse_edge_stmt_edge() {
     let tokens = vec!["a".to_string()];
     let (edge_edge, rest) = parse_edge_stmt_edge(&tokens).unwrap();
     match edge_edge {
-        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+        _ => panic!("expected node_id"),
     }
     assert_eq!(rest, vec![] as Vec<String>);
+
+    let tokens = tokenize("a:f0 -> b".to_string());
+    let (edge_edge, rest) = parse_edge_stmt_edge(&tokens).unwrap();
+    match edge_edge {
+        EdgeStmtEdge::NodeID(id, port) => {
+            assert_eq!(id.name, "a");
+            assert_eq!(port.unwrap().value(), "f0");
+        }
+        _ => panic!("expected node_id"),
+    }
+    assert_eq!(rest, vec!["->".to_string(), "b".to_string()]);
+
+    let tokens = tokenize("subgraph s { a b } -> c".to_string());
+    let (edge_edge, rest) = parse_edge_stmt_edge(&tokens).unwrap();
+    match edge_edge {
+        EdgeStmtEdge::Subgraph(subgraph) => {
+            assert_eq!(subgraph.id().unwrap().name, "s");
+            assert_eq!(subgraph.stmts().len(), 2);
+        }
+        _ => panic!("expected subgraph"),
+    }
+    assert_eq!(rest, vec!["->".to_string(), "c".to_string()]);
+
+    // A subgraph only starts an edge statement when an edge operator follows it.
+    let tokens = tokenize("{ a } -> { b }".to_string());
+    let (stmt, rest) = parse_stmt(&tokens).unwrap();
+    assert!(matches!(stmt, Stmt::EdgeStmt(_)));
+    assert_eq!(stmt.to_string(), "{\n    a;\n} -> {\n    b;\n};");
+    assert_eq!(rest, vec![] as Vec<String>);
+    let tokens = tokenize("{ a } b".to_string());
+    let (stmt, _) = parse_stmt(&tokens).unwrap();
+    assert!(matches!(stmt, Stmt::Subgraph(_)));
 }
 
-fn parse_edge_stmt_op(tokens: &Vec<String>) -> Result<(EdgeStmtOp, Vec<String>), String> {
-    if tokens.len() == 0 {
+fn parse_edge_stmt_op(tokens: &[String]) -> Result<(EdgeStmtOp, &[String]), String> {
+    if tokens.is_empty() {
         return Err(format!("{}:{} No tokens", file!(), line!()));
     }
     match tokens[0].as_str() {
-        "--" => Ok((EdgeStmtOp::Undirected, tokens[1..].to_vec())),
-        "->" => Ok((EdgeStmtOp::Directed, tokens[1..].to_vec())),
+        "--" => Ok((EdgeStmtOp::Undirected, &tokens[1..])),
+        "->" => Ok((EdgeStmtOp::Directed, &tokens[1..])),
         _ => Err(format!("{}:{} Expected edge operator", file!(), line!())),
     }
 }
@@ -161,28 +654,28 @@ fn test_parse_edge_stmt_op() {
     assert!(result.is_err());
 }
 
-fn parse_edge_stmt_rhs(tokens: &Vec<String>) -> Result<(EdgeStmtRHS, Vec<String>), String> {
+fn parse_edge_stmt_rhs(tokens: &[String]) -> Result<(EdgeStmtRHS, &[String]), String> {
     let (edge_op, rest) = parse_edge_stmt_op(tokens)?;
-    let (edge_edge, rest) = parse_edge_stmt_edge(&rest)?;
-    let try_rhs = parse_edge_stmt_rhs(&rest);
+    let (edge_edge, rest) = parse_edge_stmt_edge(rest)?;
+    let try_rhs = parse_edge_stmt_rhs(rest);
     if let Ok((edge_rhs, rest)) = try_rhs {
-        return Ok((
+        Ok((
             EdgeStmtRHS {
                 edge_op,
                 edge_egdge: edge_edge,
                 edge_rhs: Some(Box::new(edge_rhs)),
             },
             rest,
-        ));
+        ))
     } else {
-        return Ok((
+        Ok((
             EdgeStmtRHS {
                 edge_op,
                 edge_egdge: edge_edge,
                 edge_rhs: None,
             },
             rest,
-        ));
+        ))
     }
 }
 
@@ -191,7 +684,8 @@ fn test_parse_edge_stmt_rhs() {
     let tokens = tokenize("-- a".to_string());
     let (edge_rhs, rest) = parse_edge_stmt_rhs(&tokens).unwrap();
     match edge_rhs.edge_egdge {
-        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+        _ => panic!("expected node_id"),
     }
     match edge_rhs.edge_op {
         EdgeStmtOp::Undirected => {}
@@ -202,7 +696,8 @@ fn test_parse_edge_stmt_rhs() {
     let tokens = tokenize("-- a -- b".to_string());
     let (edge_rhs, rest) = parse_edge_stmt_rhs(&tokens).unwrap();
     match edge_rhs.edge_egdge {
-        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+        _ => panic!("expected node_id"),
     }
     match edge_rhs.edge_op {
         EdgeStmtOp::Undirected => {}
@@ -211,7 +706,8 @@ fn test_parse_edge_stmt_rhs() {
     match edge_rhs.edge_rhs {
         Some(rhs) => {
             match rhs.edge_egdge {
-                EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "b"),
+                EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "b"),
+                _ => panic!("expected node_id"),
             }
             match rhs.edge_op {
                 EdgeStmtOp::Undirected => {}
@@ -224,26 +720,24 @@ fn test_parse_edge_stmt_rhs() {
     assert_eq!(rest, vec![] as Vec<String>);
 }
 
-fn parse_edge_stmt(tokens: &Vec<String>) -> Result<(EdgeStmt, Vec<String>), String> {
+fn parse_edge_stmt(tokens: &[String]) -> Result<(EdgeStmt, &[String]), String> {
     let (edge_edge, rest) = parse_edge_stmt_edge(tokens)?;
-    let try_rhs = parse_edge_stmt_rhs(&rest);
-    if let Ok((edge_rhs, rest)) = try_rhs {
-        return Ok((
-            EdgeStmt {
-                edge_edge,
-                edge_rhs: Some(Box::new(edge_rhs)),
-            },
-            rest,
-        ));
-    } else {
-        return Ok((
-            EdgeStmt {
-                edge_edge,
-                edge_rhs: None,
-            },
-            rest,
-        ));
-    }
+    let (edge_rhs, rest) = match parse_edge_stmt_rhs(rest) {
+        Ok((edge_rhs, rest)) => (Some(Box::new(edge_rhs)), rest),
+        Err(_) => (None, rest),
+    };
+    let (attr_list, rest) = match parse_attr_list(rest) {
+        Ok((attr_list, rest)) => (Some(attr_list), rest),
+        Err(_) => (None, rest),
+    };
+    Ok((
+        EdgeStmt {
+            edge_edge,
+            edge_rhs,
+            attr_list,
+        },
+        rest,
+    ))
 }
 
 #[test]
@@ -251,12 +745,14 @@ fn test_parse_edge_stmt() {
     let tokens = tokenize("a -- b".to_string());
     let (edge_stmt, rest) = parse_edge_stmt(&tokens).unwrap();
     match edge_stmt.edge_edge {
-        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+        _ => panic!("expected node_id"),
     }
     match edge_stmt.edge_rhs {
         Some(rhs) => {
             match rhs.edge_egdge {
-                EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "b"),
+                EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "b"),
+                _ => panic!("expected node_id"),
             }
             match rhs.edge_op {
                 EdgeStmtOp::Undirected => {}
@@ -271,12 +767,14 @@ fn test_parse_edge_stmt() {
     let tokens = tokenize("a -- b -- c".to_string());
     let (edge_stmt, rest) = parse_edge_stmt(&tokens).unwrap();
     match edge_stmt.edge_edge {
-        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+        _ => panic!("expected node_id"),
     }
     match edge_stmt.edge_rhs {
         Some(rhs) => {
             match rhs.edge_egdge {
-                EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "b"),
+                EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "b"),
+                _ => panic!("expected node_id"),
             }
             match rhs.edge_op {
                 EdgeStmtOp::Undirected => {}
@@ -285,7 +783,8 @@ fn test_parse_edge_stmt() {
             match rhs.edge_rhs {
                 Some(rhs) => {
                     match rhs.edge_egdge {
-                        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "c"),
+                        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "c"),
+                        _ => panic!("expected node_id"),
                     }
                     match rhs.edge_op {
                         EdgeStmtOp::Undirected => {}
@@ -303,12 +802,14 @@ fn test_parse_edge_stmt() {
     let tokens = tokenize("a -> b }".to_string());
     let (edge_stmt, rest) = parse_edge_stmt(&tokens).unwrap();
     match edge_stmt.edge_edge {
-        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+        _ => panic!("expected node_id"),
     }
     match edge_stmt.edge_rhs {
         Some(rhs) => {
             match rhs.edge_egdge {
-                EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "b"),
+                EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "b"),
+                _ => panic!("expected node_id"),
             }
             match rhs.edge_op {
                 EdgeStmtOp::Directed => {}
@@ -319,19 +820,185 @@ fn test_parse_edge_stmt() {
         None => panic!("expected edge_rhs"),
     }
     assert_eq!(rest, vec!["}".to_string()]);
+
+    let tokens = tokenize("a -> b [ltail = cluster_a, lhead = cluster_b]".to_string());
+    let (edge_stmt, rest) = parse_edge_stmt(&tokens).unwrap();
+    match edge_stmt.attr_list {
+        Some(attr_list) => match attr_list.a_list {
+            Some(a_list) => {
+                assert_eq!(a_list.id_eq_stmt.id_left.name, "ltail");
+                assert_eq!(a_list.id_eq_stmt.id_right.name, "cluster_a");
+            }
+            None => panic!("expected a_list"),
+        },
+        None => panic!("expected attr_list"),
+    }
+    assert_eq!(rest, vec![] as Vec<String>);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttrStmtKind {
+    Graph,
+    Node,
+    Edge,
+}
+
+// attr_stmt : (graph | node | edge) attr_list
+#[derive(Debug, PartialEq)]
+pub(crate) struct AttrStmt {
+    kind: AttrStmtKind,
+    attr_list: AttrList,
+}
+
+impl AttrStmt {
+    pub(crate) fn kind(&self) -> AttrStmtKind {
+        self.kind
+    }
+
+    pub(crate) fn attrs(&self) -> Vec<(String, ID)> {
+        self.attr_list.attrs()
+    }
+
+    pub(crate) fn new(kind: AttrStmtKind, attrs: &[(String, ID)]) -> AttrStmt {
+        AttrStmt {
+            kind,
+            attr_list: AttrList::new(attrs),
+        }
+    }
+}
+
+impl fmt::Display for AttrStmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            AttrStmtKind::Graph => "graph",
+            AttrStmtKind::Node => "node",
+            AttrStmtKind::Edge => "edge",
+        };
+        write!(f, "{} {}", kind, self.attr_list)
+    }
+}
+
+fn parse_attr_stmt(tokens: &[String]) -> Result<(AttrStmt, &[String]), String> {
+    if tokens.is_empty() {
+        return Err(format!("{}:{} No tokens", file!(), line!()));
+    }
+    let kind = match tokens[0].to_lowercase().as_str() {
+        "graph" => AttrStmtKind::Graph,
+        "node" => AttrStmtKind::Node,
+        "edge" => AttrStmtKind::Edge,
+        _ => {
+            return Err(format!(
+                "{}:{} Expected graph, node or edge",
+                file!(),
+                line!()
+            ))
+        }
+    };
+    let (attr_list, rest) = parse_attr_list(&tokens[1..])?;
+    Ok((AttrStmt { kind, attr_list }, rest))
+}
+
+#[test]
+fn test_parse_attr_stmt() {
+    let tokens = tokenize("node [shape = box] a".to_string());
+    let (attr_stmt, rest) = parse_attr_stmt(&tokens).unwrap();
+    assert_eq!(attr_stmt.kind, AttrStmtKind::Node);
+    match attr_stmt.attr_list.a_list {
+        Some(a_list) => {
+            assert_eq!(a_list.id_eq_stmt.id_left.name, "shape");
+            assert_eq!(a_list.id_eq_stmt.id_right.name, "box");
+        }
+        None => panic!("expected a_list"),
+    }
+    assert_eq!(rest, vec!["a".to_string()]);
+
+    let tokens = tokenize("edge a".to_string());
+    assert!(parse_attr_stmt(&tokens).is_err());
+
+    let attrs = vec![
+        ("rankdir".to_string(), ID::from_value("LR")),
+        ("label".to_string(), ID::from_value("a b")),
+    ];
+    let attr_stmt = AttrStmt::new(AttrStmtKind::Graph, &attrs);
+    assert_eq!(attr_stmt.to_string(), r#"graph [rankdir=LR, label="a b"]"#);
 }
 
 #[derive(Debug, PartialEq)]
-enum Stmt {
+pub(crate) enum Stmt {
     IDEqStmt(IDEqStmt),
     EdgeStmt(EdgeStmt),
+    AttrStmt(AttrStmt),
+    Subgraph(Subgraph),
 }
 
-fn parse_stmt(tokens: &Vec<String>) -> Result<(Stmt, Vec<String>), String> {
+// Constructors for readers of other input formats, which build the same tree as the DOT parser.
+impl Stmt {
+    pub(crate) fn id_eq(name: &str, value: ID) -> Stmt {
+        Stmt::IDEqStmt(IDEqStmt {
+            id_left: ID::from_value(name),
+            id_right: value,
+        })
+    }
+
+    pub(crate) fn node(name: &str, attrs: &[(String, ID)]) -> Stmt {
+        Stmt::EdgeStmt(EdgeStmt {
+            edge_edge: EdgeStmtEdge::NodeID(ID::from_value(name), None),
+            edge_rhs: None,
+            attr_list: (!attrs.is_empty()).then(|| AttrList::new(attrs)),
+        })
+    }
+
+    pub(crate) fn edge(tail: &str, head: &str, is_digraph: bool, attrs: &[(String, ID)]) -> Stmt {
+        Stmt::EdgeStmt(EdgeStmt {
+            edge_edge: EdgeStmtEdge::NodeID(ID::from_value(tail), None),
+            edge_rhs: Some(Box::new(EdgeStmtRHS {
+                edge_op: if is_digraph {
+                    EdgeStmtOp::Directed
+                } else {
+                    EdgeStmtOp::Undirected
+                },
+                edge_egdge: EdgeStmtEdge::NodeID(ID::from_value(head), None),
+                edge_rhs: None,
+            })),
+            attr_list: (!attrs.is_empty()).then(|| AttrList::new(attrs)),
+        })
+    }
+
+    pub(crate) fn subgraph(name: Option<&str>, stmts: Vec<Stmt>) -> Stmt {
+        Stmt::Subgraph(Subgraph {
+            id: name.map(ID::from_value),
+            stmt_list: StmtList::from_stmts(stmts).map(Box::new),
+        })
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stmt::IDEqStmt(id_eq_stmt) => write!(f, "{};", id_eq_stmt),
+            Stmt::EdgeStmt(edge_stmt) => write!(f, "{};", edge_stmt),
+            Stmt::AttrStmt(attr_stmt) => write!(f, "{};", attr_stmt),
+            Stmt::Subgraph(subgraph) => write!(f, "{}", subgraph),
+        }
+    }
+}
+
+fn parse_stmt(tokens: &[String]) -> Result<(Stmt, &[String]), String> {
     let try_id_eq_stmt = parse_id_eq_stmt(tokens);
     if let Ok((id_eq_stmt, rest)) = try_id_eq_stmt {
         return Ok((Stmt::IDEqStmt(id_eq_stmt), rest));
     }
+    let try_attr_stmt = parse_attr_stmt(tokens);
+    if let Ok((attr_stmt, rest)) = try_attr_stmt {
+        return Ok((Stmt::AttrStmt(attr_stmt), rest));
+    }
+    // A subgraph followed by an edge operator is the tail of an edge statement.
+    let try_subgraph = parse_subgraph(tokens);
+    if let Ok((subgraph, rest)) = try_subgraph {
+        if parse_edge_stmt_op(rest).is_err() {
+            return Ok((Stmt::Subgraph(subgraph), rest));
+        }
+    }
     let try_edge_stmt = parse_edge_stmt(tokens);
     if let Ok((edge_stmt, rest)) = try_edge_stmt {
         return Ok((Stmt::EdgeStmt(edge_stmt), rest));
@@ -362,12 +1029,14 @@ fn test_parse_stmt() {
     match stmt {
         Stmt::EdgeStmt(edge_stmt) => {
             match edge_stmt.edge_edge {
-                EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+                EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+                _ => panic!("expected node_id"),
             }
             match edge_stmt.edge_rhs {
                 Some(rhs) => {
                     match rhs.edge_egdge {
-                        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "b"),
+                        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "b"),
+                        _ => panic!("expected node_id"),
                     }
                     match rhs.edge_op {
                         EdgeStmtOp::Undirected => {}
@@ -389,28 +1058,69 @@ struct StmtList {
     stmt_list: Option<Box<StmtList>>,
 }
 
-fn parse_stmt_list(tokens: &Vec<String>) -> Result<(StmtList, Vec<String>), String> {
-    let (stmt, rest) = parse_stmt(tokens)?;
-    let try_stmt_list = parse_stmt_list(&rest);
-    match try_stmt_list {
-        Ok((stmt_list, rest)) => {
-            return Ok((
-                StmtList {
-                    stmt,
-                    stmt_list: Some(Box::new(stmt_list)),
-                },
-                rest,
-            ));
-        }
-        Err(_) => {
-            return Ok((
-                StmtList {
-                    stmt,
-                    stmt_list: None,
-                },
-                rest,
-            ));
+impl StmtList {
+    fn from_stmts(stmts: Vec<Stmt>) -> Option<StmtList> {
+        let mut stmt_list = None;
+        for stmt in stmts.into_iter().rev() {
+            stmt_list = Some(StmtList {
+                stmt,
+                stmt_list: stmt_list.map(Box::new),
+            });
+        }
+        stmt_list
+    }
+
+    fn stmts(&self) -> Vec<&Stmt> {
+        let mut stmts = vec![&self.stmt];
+        let mut stmt_list = &self.stmt_list;
+        while let Some(next) = stmt_list {
+            stmts.push(&next.stmt);
+            stmt_list = &next.stmt_list;
         }
+        stmts
+    }
+}
+
+// Unlinks the list one statement at a time, as dropping it recursively can run out of stack on
+// large graphs.
+impl Drop for StmtList {
+    fn drop(&mut self) {
+        let mut stmt_list = self.stmt_list.take();
+        while let Some(mut next) = stmt_list {
+            stmt_list = next.stmt_list.take();
+        }
+    }
+}
+
+impl fmt::Display for StmtList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for stmt in self.stmts() {
+            writeln!(f, "{}", stmt)?;
+        }
+        Ok(())
+    }
+}
+
+// Loops instead of recursing per statement so that graphs with many statements do not run out
+// of stack.
+fn parse_stmt_list(tokens: &[String]) -> Result<(StmtList, &[String]), String> {
+    let (stmt, mut rest) = parse_stmt(tokens)?;
+    let mut stmts = vec![stmt];
+    loop {
+        if !rest.is_empty() && rest[0] == ";" {
+            rest = &rest[1..];
+        }
+        match parse_stmt(rest) {
+            Ok((stmt, stmt_rest)) => {
+                stmts.push(stmt);
+                rest = stmt_rest;
+            }
+            Err(_) => break,
+        }
+    }
+    match StmtList::from_stmts(stmts) {
+        Some(stmt_list) => Ok((stmt_list, rest)),
+        None => Err(format!("{}:{} No statements", file!(), line!())),
     }
 }
 
@@ -418,24 +1128,26 @@ fn parse_stmt_list(tokens: &Vec<String>) -> Result<(StmtList, Vec<String>), Stri
 fn test_parse_stmt_list() {
     let tokens = tokenize("a = b\na -- b".to_string());
     let (stmt_list, rest) = parse_stmt_list(&tokens).unwrap();
-    match stmt_list.stmt {
+    match &stmt_list.stmt {
         Stmt::IDEqStmt(id_eq_stmt) => {
             assert_eq!(id_eq_stmt.id_left.name, "a");
             assert_eq!(id_eq_stmt.id_right.name, "b");
         }
         _ => panic!("expected IDEqStmt"),
     }
-    match stmt_list.stmt_list {
+    match &stmt_list.stmt_list {
         Some(stmt_list) => {
-            match stmt_list.stmt {
+            match &stmt_list.stmt {
                 Stmt::EdgeStmt(edge_stmt) => {
-                    match edge_stmt.edge_edge {
-                        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+                    match &edge_stmt.edge_edge {
+                        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+                        _ => panic!("expected node_id"),
                     }
-                    match edge_stmt.edge_rhs {
+                    match &edge_stmt.edge_rhs {
                         Some(rhs) => {
-                            match rhs.edge_egdge {
-                                EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "b"),
+                            match &rhs.edge_egdge {
+                                EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "b"),
+                                _ => panic!("expected node_id"),
                             }
                             match rhs.edge_op {
                                 EdgeStmtOp::Undirected => {}
@@ -455,20 +1167,212 @@ fn test_parse_stmt_list() {
     assert_eq!(rest, vec![] as Vec<String>);
 }
 
-struct Graph {
+// subgraph : [ subgraph [ ID ] ] '{' stmt_list '}'
+#[derive(Debug, PartialEq)]
+pub struct Subgraph {
+    id: Option<ID>,
+    stmt_list: Option<Box<StmtList>>,
+}
+
+// Subgraphs whose name starts with "cluster" are drawn as boxes around their nodes.
+pub fn is_cluster(name: &str) -> bool {
+    name.starts_with("cluster")
+}
+
+impl Subgraph {
+    pub fn id(&self) -> Option<&ID> {
+        self.id.as_ref()
+    }
+
+    pub(crate) fn stmts(&self) -> Vec<&Stmt> {
+        match &self.stmt_list {
+            Some(stmt_list) => stmt_list.stmts(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Subgraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.id {
+            Some(id) => writeln!(f, "subgraph {} {{", id)?,
+            None => writeln!(f, "{{")?,
+        }
+        if let Some(stmt_list) = &self.stmt_list {
+            for line in stmt_list.to_string().lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+fn parse_subgraph(tokens: &[String]) -> Result<(Subgraph, &[String]), String> {
+    let mut rest = tokens;
+    let mut id = None;
+    if !rest.is_empty() && rest[0].to_lowercase() == "subgraph" {
+        rest = &rest[1..];
+        if let Ok((subgraph_id, subgraph_rest)) = parse_id(rest) {
+            id = Some(subgraph_id);
+            rest = subgraph_rest;
+        }
+    }
+    if rest.is_empty() {
+        return Err(format!("{}:{} No tokens", file!(), line!()));
+    }
+    if rest[0] != "{" {
+        return Err(format!("{}:{} Expected '{{'", file!(), line!()));
+    }
+    let (stmt_list, rest) = match parse_stmt_list(&rest[1..]) {
+        Ok((stmt_list, rest)) => (Some(Box::new(stmt_list)), rest),
+        Err(_) => (None, &rest[1..]),
+    };
+    if rest.is_empty() {
+        return Err(format!("{}:{} No tokens", file!(), line!()));
+    }
+    if rest[0] != "}" {
+        return Err(format!("{}:{} Expected '}}'", file!(), line!()));
+    }
+    Ok((Subgraph { id, stmt_list }, &rest[1..]))
+}
+
+#[test]
+fn test_parse_subgraph() {
+    let tokens = tokenize(
+        r#"subgraph cluster_a {
+    label = "Frontend"
+    a -- b
+} c"#
+            .to_string(),
+    );
+    let (subgraph, rest) = parse_subgraph(&tokens).unwrap();
+    assert_eq!(subgraph.id().unwrap().name, "cluster_a");
+    match subgraph.stmt_list {
+        Some(stmt_list) => {
+            match &stmt_list.stmt {
+                Stmt::IDEqStmt(id_eq_stmt) => {
+                    assert_eq!(id_eq_stmt.id_left.name, "label");
+                    assert_eq!(id_eq_stmt.id_right.value(), "Frontend");
+                }
+                _ => panic!("expected IDEqStmt"),
+            }
+            assert_eq!(stmt_list.stmts().len(), 2);
+        }
+        None => panic!("expected stmt_list"),
+    }
+    assert_eq!(rest, vec!["c".to_string()]);
+
+    let tokens = tokenize("subgraph \"cluster b\" {}".to_string());
+    let (subgraph, rest) = parse_subgraph(&tokens).unwrap();
+    assert_eq!(subgraph.id().unwrap().value(), "cluster b");
+    assert_eq!(subgraph.stmt_list, None);
+    assert_eq!(rest, vec![] as Vec<String>);
+
+    let tokens = tokenize("subgraph x { a -- b }".to_string());
+    let (subgraph, _) = parse_subgraph(&tokens).unwrap();
+    assert_eq!(subgraph.id().unwrap().name, "x");
+
+    let tokens = tokenize("{ a -- b }".to_string());
+    let (subgraph, _) = parse_subgraph(&tokens).unwrap();
+    assert_eq!(subgraph.id, None);
+
+    let tokens = tokenize("subgraph cluster_a { a -- b".to_string());
+    assert!(parse_subgraph(&tokens).is_err());
+}
+
+pub struct Graph {
     strict: bool,
     is_digraph: bool,
-    stmt_list: StmtList,
+    id: Option<ID>,
+    stmt_list: Option<Box<StmtList>>,
+}
+
+impl Graph {
+    pub(crate) fn new(
+        strict: bool,
+        is_digraph: bool,
+        name: Option<&str>,
+        stmts: Vec<Stmt>,
+    ) -> Graph {
+        Graph {
+            strict,
+            is_digraph,
+            id: name.map(ID::from_value),
+            stmt_list: StmtList::from_stmts(stmts).map(Box::new),
+        }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn is_digraph(&self) -> bool {
+        self.is_digraph
+    }
+
+    pub fn id(&self) -> Option<&ID> {
+        self.id.as_ref()
+    }
+
+    pub(crate) fn stmts(&self) -> Vec<&Stmt> {
+        match &self.stmt_list {
+            Some(stmt_list) => stmt_list.stmts(),
+            None => Vec::new(),
+        }
+    }
+
+    // Inserts `graph|node|edge [name=value, ...]` before the first statement. Because
+    // later statements override earlier ones, attributes set in the file take precedence. A value
+    // in angle brackets, as in `-Nlabel=<<b>x</b>>`, is an HTML string like it is in a file.
+    pub fn prepend_attr_stmt(&mut self, kind: AttrStmtKind, attrs: &[(String, String)]) {
+        if attrs.is_empty() {
+            return;
+        }
+        let attrs: Vec<(String, ID)> = attrs
+            .iter()
+            .map(|(name, value)| {
+                let id = if is_html(value) {
+                    ID::from_html(value)
+                } else {
+                    ID::from_value(value)
+                };
+                (name.clone(), id)
+            })
+            .collect();
+        self.stmt_list = Some(Box::new(StmtList {
+            stmt: Stmt::AttrStmt(AttrStmt::new(kind, &attrs)),
+            stmt_list: self.stmt_list.take(),
+        }));
+    }
+}
+
+impl fmt::Display for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.strict {
+            write!(f, "strict ")?;
+        }
+        write!(f, "{}", if self.is_digraph { "digraph" } else { "graph" })?;
+        if let Some(id) = &self.id {
+            write!(f, " {}", id)?;
+        }
+        writeln!(f, " {{")?;
+        if let Some(stmt_list) = &self.stmt_list {
+            for line in stmt_list.to_string().lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        writeln!(f, "}}")
+    }
 }
 
-fn parse_graph(tokens: &Vec<String>) -> Result<(Graph, Vec<String>), String> {
-    let mut rest = tokens.clone();
+pub fn parse_graph(tokens: &[String]) -> Result<(Graph, &[String]), String> {
+    let mut rest = tokens;
     let mut strict = false;
-    if rest.len() > 0 && rest[0].to_lowercase() == "strict" {
+    if !rest.is_empty() && rest[0].to_lowercase() == "strict" {
         strict = true;
-        rest = rest[1..].to_vec();
+        rest = &rest[1..];
     }
-    if rest.len() == 0 {
+    if rest.is_empty() {
         return Err(format!("{}:{} No tokens", file!(), line!()));
     }
     let is_digraph = match rest[0].to_lowercase().as_str() {
@@ -476,31 +1380,38 @@ fn parse_graph(tokens: &Vec<String>) -> Result<(Graph, Vec<String>), String> {
         "digraph" => true,
         _ => return Err(format!("{}:{} Expected graph or digraph", file!(), line!())),
     };
-    rest = rest[1..].to_vec();
-    if rest.len() == 0 {
+    rest = &rest[1..];
+    let mut id = None;
+    if let Ok((graph_id, graph_rest)) = parse_id(rest) {
+        id = Some(graph_id);
+        rest = graph_rest;
+    }
+    if rest.is_empty() {
         return Err(format!("{}:{} No tokens", file!(), line!()));
     }
     match rest[0].as_str() {
         "{" => {}
         _ => return Err(format!("{}:{} Expected {{", file!(), line!())),
     }
-    rest = rest[1..].to_vec();
-    if rest.len() == 0 {
-        return Err(format!("{}:{} Expected {{", file!(), line!()));
-    }
-    let (stmt_list, mut rest) = parse_stmt_list(&rest[0..].to_vec())?;
-    if rest.len() == 0 {
-        return Err(format!("{}:{} Expected {{", file!(), line!()));
+    rest = &rest[1..];
+    // The statement list may be empty, as in `digraph {}`.
+    let (stmt_list, mut rest) = match parse_stmt_list(rest) {
+        Ok((stmt_list, rest)) => (Some(Box::new(stmt_list)), rest),
+        Err(_) => (None, rest),
+    };
+    if rest.is_empty() {
+        return Err(format!("{}:{} Expected '}}'", file!(), line!()));
     }
     match rest[0].as_str() {
         "}" => {}
         _ => return Err(format!("{}:{} Expected '}}'", file!(), line!())),
     }
-    rest = rest[1..].to_vec();
+    rest = &rest[1..];
     Ok((
         Graph {
             strict,
             is_digraph,
+            id,
             stmt_list,
         },
         rest,
@@ -517,26 +1428,29 @@ fn test_parse_graph() {
         .to_string(),
     );
     let (graph, rest) = parse_graph(&tokens).unwrap();
-    assert_eq!(graph.strict, false);
-    assert_eq!(graph.is_digraph, false);
-    match graph.stmt_list.stmt {
+    assert!(!graph.strict);
+    assert!(!graph.is_digraph);
+    let stmt_list = graph.stmt_list.as_ref().unwrap();
+    match &stmt_list.stmt {
         Stmt::IDEqStmt(id_eq_stmt) => {
             assert_eq!(id_eq_stmt.id_left.name, "a");
             assert_eq!(id_eq_stmt.id_right.name, "b");
         }
         _ => panic!("expected IDEqStmt"),
     }
-    match graph.stmt_list.stmt_list {
+    match &stmt_list.stmt_list {
         Some(stmt_list) => {
-            match stmt_list.stmt {
+            match &stmt_list.stmt {
                 Stmt::EdgeStmt(edge_stmt) => {
-                    match edge_stmt.edge_edge {
-                        EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "a"),
+                    match &edge_stmt.edge_edge {
+                        EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "a"),
+                        _ => panic!("expected node_id"),
                     }
-                    match edge_stmt.edge_rhs {
+                    match &edge_stmt.edge_rhs {
                         Some(rhs) => {
-                            match rhs.edge_egdge {
-                                EdgeStmtEdge::NodeID(id) => assert_eq!(id.name, "b"),
+                            match &rhs.edge_egdge {
+                                EdgeStmtEdge::NodeID(id, _) => assert_eq!(id.name, "b"),
+                                _ => panic!("expected node_id"),
                             }
                             match rhs.edge_op {
                                 EdgeStmtOp::Undirected => {}
@@ -554,4 +1468,85 @@ fn test_parse_graph() {
         None => panic!("expected stmt_list"),
     }
     assert_eq!(rest, vec![] as Vec<String>);
+
+    let tokens = tokenize("strict digraph G {}".to_string());
+    let (graph, rest) = parse_graph(&tokens).unwrap();
+    assert!(graph.stmts().is_empty());
+    assert_eq!(graph.to_string(), "strict digraph G {\n}\n");
+    assert_eq!(rest, vec![] as Vec<String>);
+
+    let tokens = tokenize("graph { a".to_string());
+    assert!(parse_graph(&tokens).is_err());
+}
+
+#[test]
+fn test_parse_graph_large() {
+    // Parsing walks the tokens without copying them, so large graphs parse in linear time.
+    let edges: Vec<String> = (1..50000)
+        .map(|i| format!("n{} -- n{};", i / 2, i))
+        .collect();
+    let tokens = tokenize(format!("graph {{ {} }}", edges.join("\n")));
+    let (graph, rest) = parse_graph(&tokens).unwrap();
+    assert_eq!(graph.stmts().len(), 49999);
+    assert_eq!(rest, vec![] as Vec<String>);
+}
+
+#[test]
+fn test_prepend_attr_stmt() {
+    let tokens = tokenize("digraph { node [shape = circle] a -> b }".to_string());
+    let (mut graph, _) = parse_graph(&tokens).unwrap();
+    graph.prepend_attr_stmt(AttrStmtKind::Graph, &[]);
+    graph.prepend_attr_stmt(
+        AttrStmtKind::Node,
+        &[
+            ("shape".to_string(), "box".to_string()),
+            ("label".to_string(), "<<b>x</b>>".to_string()),
+            ("xlabel".to_string(), "<b".to_string()),
+        ],
+    );
+    graph.prepend_attr_stmt(
+        AttrStmtKind::Graph,
+        &[("rankdir".to_string(), "LR".to_string())],
+    );
+    assert_eq!(
+        graph.to_string(),
+        r#"digraph {
+    graph [rankdir=LR];
+    node [shape=box, label=<<b>x</b>>, xlabel="<b"];
+    node [shape=circle];
+    a -> b;
+}
+"#
+    );
+}
+
+#[test]
+fn test_new_graph() {
+    let graph = Graph::new(
+        false,
+        true,
+        Some("G"),
+        vec![
+            Stmt::id_eq("label", ID::from_value("my graph")),
+            Stmt::node("a", &[("shape".to_string(), ID::from_value("box"))]),
+            Stmt::subgraph(Some("cluster_0"), vec![Stmt::node("b", &[])]),
+            Stmt::edge("a", "b", true, &[]),
+        ],
+    );
+    let tokens = tokenize(
+        r#"digraph G {
+    label = "my graph"
+    a [shape = box]
+    subgraph cluster_0 { b }
+    a -> b
+}"#
+        .to_string(),
+    );
+    let (parsed, _) = parse_graph(&tokens).unwrap();
+    assert_eq!(graph.to_string(), parsed.to_string());
+
+    assert_eq!(
+        Graph::new(false, true, None, vec![]).to_string(),
+        "digraph {\n}\n"
+    );
 }
@@ -0,0 +1,215 @@
+use crate::layout::{self, number, Point, POINTS_PER_INCH};
+use crate::semantic::Graph;
+use std::collections::{HashSet, VecDeque};
+use std::f64::consts::PI;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz default, in inches.
+const DEFAULT_MINDIST: f64 = 1.0;
+
+// The biconnected components of the component reached from root, found by Tarjan's algorithm
+// without recursion. An isolated node is a block of its own.
+fn blocks(neighbours: &[Vec<usize>], root: usize) -> Vec<Vec<usize>> {
+    if neighbours[root].is_empty() {
+        return vec![vec![root]];
+    }
+    let n = neighbours.len();
+    let mut discovered = vec![usize::MAX; n];
+    let mut low = vec![usize::MAX; n];
+    let mut time = 0;
+    discovered[root] = 0;
+    low[root] = 0;
+    // Each entry is a node, its parent and the index of the next neighbour to visit.
+    let mut stack = vec![(root, root, 0)];
+    let mut edges = Vec::new();
+    let mut blocks = Vec::new();
+    // The block each node was last added to, so that it is added only once.
+    let mut last_block = vec![usize::MAX; n];
+    while let Some(&mut (node, parent, ref mut next)) = stack.last_mut() {
+        if let Some(&other) = neighbours[node].get(*next) {
+            *next += 1;
+            if discovered[other] == usize::MAX {
+                time += 1;
+                discovered[other] = time;
+                low[other] = time;
+                edges.push((node, other));
+                stack.push((other, node, 0));
+            } else if other != parent && discovered[other] < discovered[node] {
+                edges.push((node, other));
+                low[node] = low[node].min(discovered[other]);
+            }
+            continue;
+        }
+        stack.pop();
+        if node == root {
+            continue;
+        }
+        low[parent] = low[parent].min(low[node]);
+        if low[node] >= discovered[parent] {
+            let mut block = Vec::new();
+            while let Some((a, b)) = edges.pop() {
+                for x in [a, b] {
+                    if last_block[x] != blocks.len() {
+                        last_block[x] = blocks.len();
+                        block.push(x);
+                    }
+                }
+                if (a, b) == (parent, node) {
+                    break;
+                }
+            }
+            blocks.push(block);
+        }
+    }
+    blocks
+}
+
+#[test]
+fn test_blocks() {
+    let graph = from_dot("graph { a -- b -- c -- a; c -- d; d -- e -- f -- d; g }");
+    let neighbours = layout::neighbours(&graph);
+    let mut found: Vec<Vec<usize>> = blocks(&neighbours, 0)
+        .into_iter()
+        .map(|mut block| {
+            block.sort();
+            block
+        })
+        .collect();
+    found.sort();
+    assert_eq!(found, vec![vec![0, 1, 2], vec![2, 3], vec![3, 4, 5]]);
+    assert_eq!(blocks(&neighbours, 6), vec![vec![6]]);
+}
+
+// The nodes of a block in depth-first order from first, which goes around a cycle in order.
+fn cyclic_order(block: &[usize], neighbours: &[Vec<usize>], first: usize) -> Vec<usize> {
+    let members: HashSet<usize> = block.iter().copied().collect();
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+    let mut stack = vec![first];
+    while let Some(node) = stack.pop() {
+        if !visited.insert(node) {
+            continue;
+        }
+        order.push(node);
+        for &next in neighbours[node].iter().rev() {
+            if members.contains(&next) && !visited.contains(&next) {
+                stack.push(next);
+            }
+        }
+    }
+    order
+}
+
+// Circular layout: every biconnected component is put on a circle, just large enough to keep
+// its nodes mindist apart. The block containing the root, or else the largest block, is at the
+// center, and every other block hangs off the cut vertex it shares with its parent, on the side
+// away from the parent's center. Components are all centered at the origin, layout::apply
+// packs them.
+pub fn layout(graph: &Graph) -> Result<Vec<Point>, String> {
+    let n = graph.nodes.len();
+    let mindist = number(&graph.attrs, "mindist", DEFAULT_MINDIST)? * POINTS_PER_INCH;
+    let neighbours = layout::neighbours(graph);
+    let components = layout::components(&neighbours);
+    let diameters: Vec<f64> = graph
+        .nodes
+        .iter()
+        .map(|node| {
+            let (width, height) = layout::node_size(graph, node);
+            width.max(height)
+        })
+        .collect();
+    let root = graph
+        .attrs
+        .get("root")
+        .and_then(|name| graph.nodes.iter().position(|node| node.name == name));
+
+    let mut positions = vec![(0.0, 0.0); n];
+    for component in components.iter() {
+        let blocks = blocks(&neighbours, component[0]);
+        let mut blocks_of = vec![Vec::new(); n];
+        for (i, block) in blocks.iter().enumerate() {
+            for &node in block.iter() {
+                blocks_of[node].push(i);
+            }
+        }
+        let central = match root.filter(|root| component.contains(root)) {
+            Some(root) => blocks_of[root][0],
+            None => (0..blocks.len()).max_by_key(|&i| blocks[i].len()).unwrap(),
+        };
+
+        // Each entry is a block, the cut vertex it hangs off and the direction it hangs in.
+        let mut placed = vec![false; blocks.len()];
+        placed[central] = true;
+        let mut queue = VecDeque::from([(central, None, 0.0)]);
+        while let Some((block, attachment, direction)) = queue.pop_front() {
+            let nodes = &blocks[block];
+            let first = attachment.unwrap_or(nodes[0]);
+            let order = cyclic_order(nodes, &neighbours, first);
+            let largest = nodes.iter().map(|&i| diameters[i]).fold(0.0, f64::max);
+            let radius = match order.len() {
+                1 => 0.0,
+                k => (largest + mindist) / (2.0 * (PI / k as f64).sin()),
+            };
+            let (center, start): (Point, f64) = match attachment {
+                Some(a) => (
+                    (
+                        positions[a].0 + radius * f64::cos(direction),
+                        positions[a].1 + radius * f64::sin(direction),
+                    ),
+                    direction + PI,
+                ),
+                None => ((0.0, 0.0), 0.0),
+            };
+            for (i, &node) in order.iter().enumerate() {
+                let angle = start + 2.0 * PI * i as f64 / order.len() as f64;
+                positions[node] = (
+                    center.0 + radius * angle.cos(),
+                    center.1 + radius * angle.sin(),
+                );
+            }
+            for (i, &node) in order.iter().enumerate() {
+                let children: Vec<usize> = blocks_of[node]
+                    .iter()
+                    .copied()
+                    .filter(|&child| !placed[child])
+                    .collect();
+                // Children of the same cut vertex fan out around the outward direction.
+                let outward = start + 2.0 * PI * i as f64 / order.len() as f64;
+                let spread = PI / (children.len() + 1) as f64;
+                for (j, &child) in children.iter().enumerate() {
+                    placed[child] = true;
+                    let offset = (j as f64 - (children.len() - 1) as f64 / 2.0) * spread;
+                    queue.push_back((child, Some(node), outward + offset));
+                }
+            }
+        }
+    }
+    Ok(positions)
+}
+
+#[test]
+fn test_layout() {
+    let distance = |a: Point, b: Point| (a.0 - b.0).hypot(a.1 - b.1);
+    let graph = from_dot("graph { a -- b -- c -- d -- e -- a; c -- f -- g -- c }");
+    let positions = layout(&graph).unwrap();
+    // The ring keeps its order, with neighbours 54 + 72 points apart.
+    for (i, j) in [(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)] {
+        assert!((distance(positions[i], positions[j]) - 126.0).abs() < 1e-9);
+    }
+    // The triangle is on a circle of its own, sharing c with the ring.
+    assert!((distance(positions[5], positions[6]) - 126.0).abs() < 1e-9);
+    assert!((distance(positions[2], positions[5]) - 126.0).abs() < 1e-9);
+    let center = (
+        (positions[0].0 + positions[1].0 + positions[2].0 + positions[3].0 + positions[4].0) / 5.0,
+        (positions[0].1 + positions[1].1 + positions[2].1 + positions[3].1 + positions[4].1) / 5.0,
+    );
+    assert!(distance(center, positions[5]) > distance(center, positions[2]));
+
+    let graph = from_dot(r#"graph { mindist = 0; a -- b }"#);
+    let positions = layout(&graph).unwrap();
+    assert!((distance(positions[0], positions[1]) - 54.0).abs() < 1e-9);
+
+    assert!(layout(&from_dot(r#"graph { mindist = "x"; a }"#)).is_err());
+}
@@ -0,0 +1,516 @@
+use crate::semantic::{Attrs, Graph};
+use std::fmt;
+
+#[cfg(test)]
+use crate::{ast, tokenize::tokenize};
+
+// "name r g b" lines of the X11 color names as Graphviz spells them, lower case without spaces,
+// and of the SVG color names, which differ from X11 for gray, green, maroon and purple.
+const X11_COLORS: &str = include_str!("x11_colors.txt");
+const SVG_COLORS: &str = include_str!("svg_colors.txt");
+
+// "scheme hex..." lines of the sequential and diverging Brewer color schemes. Unlike the
+// qualitative ones, each size of these has its own palette, so blues3 is not a prefix of blues9.
+const BREWER_SIZED_COLORS: &str = include_str!("brewer_colors.txt");
+
+// Qualitative Brewer color schemes. A scheme such as set35 takes the first 5 colors of set3.
+const BREWER_COLORS: [(&str, &[u32]); 8] = [
+    (
+        "accent",
+        &[
+            0x7fc97f, 0xbeaed4, 0xfdc086, 0xffff99, 0x386cb0, 0xf0027f, 0xbf5b17, 0x666666,
+        ],
+    ),
+    (
+        "dark2",
+        &[
+            0x1b9e77, 0xd95f02, 0x7570b3, 0xe7298a, 0x66a61e, 0xe6ab02, 0xa6761d, 0x666666,
+        ],
+    ),
+    (
+        "paired",
+        &[
+            0xa6cee3, 0x1f78b4, 0xb2df8a, 0x33a02c, 0xfb9a99, 0xe31a1c, 0xfdbf6f, 0xff7f00,
+            0xcab2d6, 0x6a3d9a, 0xffff99, 0xb15928,
+        ],
+    ),
+    (
+        "pastel1",
+        &[
+            0xfbb4ae, 0xb3cde3, 0xccebc5, 0xdecbe4, 0xfed9a6, 0xffffcc, 0xe5d8bd, 0xfddaec,
+            0xf2f2f2,
+        ],
+    ),
+    (
+        "pastel2",
+        &[
+            0xb3e2cd, 0xfdcdac, 0xcbd5e8, 0xf4cae4, 0xe6f5c9, 0xfff2ae, 0xf1e2cc, 0xcccccc,
+        ],
+    ),
+    (
+        "set1",
+        &[
+            0xe41a1c, 0x377eb8, 0x4daf4a, 0x984ea3, 0xff7f00, 0xffff33, 0xa65628, 0xf781bf,
+            0x999999,
+        ],
+    ),
+    (
+        "set2",
+        &[
+            0x66c2a5, 0xfc8d62, 0x8da0cb, 0xe78ac3, 0xa6d854, 0xffd92f, 0xe5c494, 0xb3b3b3,
+        ],
+    ),
+    (
+        "set3",
+        &[
+            0x8dd3c7, 0xffffb3, 0xbebada, 0xfb8072, 0x80b1d3, 0xfdb462, 0xb3de69, 0xfccde5,
+            0xd9d9d9, 0xbc80bd, 0xccebc5, 0xffed6f,
+        ],
+    ),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    fn from_hex(hex: u32) -> Rgba {
+        Rgba {
+            r: (hex >> 16) as u8,
+            g: (hex >> 8) as u8,
+            b: hex as u8,
+            a: 255,
+        }
+    }
+}
+
+// "#rrggbb", with the alpha appended as "aa" when the color is not opaque.
+impl fmt::Display for Rgba {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+const TRANSPARENT: Rgba = Rgba {
+    r: 255,
+    g: 255,
+    b: 254,
+    a: 0,
+};
+
+fn lookup_table(table: &str, name: &str) -> Option<Rgba> {
+    let line = table
+        .lines()
+        .find(|line| line.split(' ').next() == Some(name))?;
+    let rgb: Vec<u8> = line
+        .split(' ')
+        .skip(1)
+        .filter_map(|c| c.parse().ok())
+        .collect();
+    Some(Rgba {
+        r: rgb[0],
+        g: rgb[1],
+        b: rgb[2],
+        a: 255,
+    })
+}
+
+fn lookup_brewer(scheme: &str, name: &str) -> Result<Option<Rgba>, String> {
+    if let Some(line) = BREWER_SIZED_COLORS
+        .lines()
+        .find(|line| line.split(' ').next() == Some(scheme))
+    {
+        // Field 0 is the scheme name, so color i is field i.
+        return Ok(match name.parse::<usize>() {
+            Ok(index) if index >= 1 => line
+                .split(' ')
+                .nth(index)
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .map(Rgba::from_hex),
+            _ => None,
+        });
+    }
+    for (base, colors) in BREWER_COLORS.iter() {
+        let size = match scheme.strip_prefix(base).map(|size| size.parse::<usize>()) {
+            Some(Ok(size)) if (3..=colors.len()).contains(&size) => size,
+            _ => continue,
+        };
+        return Ok(match name.parse::<usize>() {
+            Ok(index) if (1..=size).contains(&index) => Some(Rgba::from_hex(colors[index - 1])),
+            _ => None,
+        });
+    }
+    Err(format!(
+        "{}:{} Unknown color scheme {}",
+        file!(),
+        line!(),
+        scheme
+    ))
+}
+
+fn lookup_name(scheme: &str, name: &str) -> Result<Option<Rgba>, String> {
+    match scheme {
+        "" | "x11" => Ok(lookup_table(X11_COLORS, name)),
+        "svg" => Ok(lookup_table(SVG_COLORS, name)),
+        _ => lookup_brewer(scheme, name),
+    }
+}
+
+fn from_hsv(hsv: &[f64]) -> Rgba {
+    let (h, s, v) = (hsv[0] * 6.0 % 6.0, hsv[1], hsv[2]);
+    let f = h - h.floor();
+    let (p, q, t) = (v * (1.0 - s), v * (1.0 - s * f), v * (1.0 - s * (1.0 - f)));
+    let (r, g, b) = match h as u32 {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    };
+    let to_u8 = |x: f64| (x * 255.0).round() as u8;
+    Rgba {
+        r: to_u8(r),
+        g: to_u8(g),
+        b: to_u8(b),
+        a: hsv.get(3).map_or(255, |a| to_u8(*a)),
+    }
+}
+
+// Parses "#rrggbb[aa]", "H,S,V[,A]" with components in [0, 1], or a color name. A name is
+// looked up in colorscheme unless it is written as "/scheme/name" or "//name", falling back
+// to X11 like Graphviz does.
+pub fn parse_color(value: &str, colorscheme: Option<&str>) -> Result<Rgba, String> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        return match (hex.len(), u32::from_str_radix(hex, 16)) {
+            (6, Ok(rgb)) => Ok(Rgba::from_hex(rgb)),
+            (8, Ok(rgba)) => Ok(Rgba {
+                a: rgba as u8,
+                ..Rgba::from_hex(rgba >> 8)
+            }),
+            _ => Err(format!("{}:{} Bad color {}", file!(), line!(), value)),
+        };
+    }
+    // A single number is a color name in a Brewer scheme such as "3".
+    let hsv: Vec<&str> = value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .collect();
+    if value.starts_with(|c: char| c.is_ascii_digit() || c == '.') && hsv.len() > 1 {
+        let hsv: Vec<f64> = hsv.iter().map(|x| x.parse().unwrap_or(-1.0)).collect();
+        if !(3..=4).contains(&hsv.len()) || hsv.iter().any(|x| !(0.0..=1.0).contains(x)) {
+            return Err(format!("{}:{} Bad HSV color {}", file!(), line!(), value));
+        }
+        return Ok(from_hsv(&hsv));
+    }
+
+    // Like Graphviz, names are case insensitive and spaces in them are ignored.
+    let lower = value.to_lowercase().replace(' ', "");
+    if ["transparent", "invis", "none"].contains(&lower.as_str()) {
+        return Ok(TRANSPARENT);
+    }
+    let (scheme, name) = match lower.strip_prefix('/') {
+        Some(rest) => match rest.split_once('/') {
+            Some((scheme, name)) => (scheme.to_string(), name),
+            None => (String::new(), rest),
+        },
+        None => (
+            colorscheme.unwrap_or_default().to_lowercase(),
+            lower.as_str(),
+        ),
+    };
+    if let Some(color) = lookup_name(&scheme, name)? {
+        return Ok(color);
+    }
+    match lookup_table(X11_COLORS, name) {
+        Some(color) => Ok(color),
+        None => Err(format!("{}:{} Unknown color {}", file!(), line!(), value)),
+    }
+}
+
+#[test]
+fn test_parse_color() {
+    let rgba = |r, g, b, a| Rgba { r, g, b, a };
+    assert_eq!(parse_color("Red", None), Ok(rgba(255, 0, 0, 255)));
+    assert_eq!(rgba(255, 0, 16, 255).to_string(), "#ff0010");
+    assert_eq!(rgba(255, 0, 16, 128).to_string(), "#ff001080");
+    assert_eq!(
+        parse_color("Light Goldenrod", None),
+        Ok(rgba(238, 221, 130, 255))
+    );
+    assert_eq!(parse_color("gray50", None), Ok(rgba(127, 127, 127, 255)));
+    assert_eq!(parse_color("green", Some("svg")), Ok(rgba(0, 128, 0, 255)));
+    assert_eq!(
+        parse_color("/svg/green", Some("x11")),
+        Ok(rgba(0, 128, 0, 255))
+    );
+    assert_eq!(
+        parse_color("//green", Some("svg")),
+        Ok(rgba(0, 255, 0, 255))
+    );
+    assert_eq!(
+        parse_color("/set13/2", None),
+        Ok(rgba(0x37, 0x7e, 0xb8, 255))
+    );
+    assert_eq!(
+        parse_color("3", Some("paired12")),
+        Ok(rgba(0xb2, 0xdf, 0x8a, 255))
+    );
+    assert_eq!(
+        parse_color("/blues9/1", None),
+        Ok(rgba(0xf7, 0xfb, 0xff, 255))
+    );
+    assert_eq!(
+        parse_color("11", Some("rdylgn11")),
+        Ok(rgba(0x00, 0x68, 0x37, 255))
+    );
+    assert_eq!(parse_color("red", Some("set13")), Ok(rgba(255, 0, 0, 255)));
+    assert_eq!(parse_color("#ff800080", None), Ok(rgba(255, 128, 0, 128)));
+    assert_eq!(parse_color("0.0,1.0,1.0", None), Ok(rgba(255, 0, 0, 255)));
+    assert_eq!(parse_color("0.5 1 0.5", None), Ok(rgba(0, 128, 128, 255)));
+    assert_eq!(parse_color("transparent", None), Ok(TRANSPARENT));
+
+    assert!(parse_color("#ff80", None).is_err());
+    assert!(parse_color("#gg0000", None).is_err());
+    assert!(parse_color("0.5,1.5,0.5", None).is_err());
+    assert!(parse_color("/set14/5", None).is_err());
+    assert!(parse_color("/blues9/10", None).is_err());
+    assert!(parse_color("/blues10/1", None).is_err());
+    assert!(parse_color("reddish", None).is_err());
+}
+
+// An entry of a color list such as "red;0.3:blue".
+#[derive(Debug, PartialEq)]
+pub struct WeightedColor {
+    pub color: Rgba,
+    pub fraction: Option<f64>,
+}
+
+pub fn parse_color_list(
+    value: &str,
+    colorscheme: Option<&str>,
+) -> Result<Vec<WeightedColor>, String> {
+    let mut colors = Vec::new();
+    for item in value.split(':') {
+        let (color, fraction) = match item.split_once(';') {
+            Some((color, fraction)) => match fraction.trim().parse::<f64>() {
+                Ok(fraction) if (0.0..=1.0).contains(&fraction) => (color, Some(fraction)),
+                _ => return Err(format!("{}:{} Bad fraction {}", file!(), line!(), fraction)),
+            },
+            None => (item, None),
+        };
+        colors.push(WeightedColor {
+            color: parse_color(color, colorscheme)?,
+            fraction,
+        });
+    }
+    Ok(colors)
+}
+
+// Gives colors without a fraction an equal share of what the others leave.
+fn resolve_fractions(colors: &[WeightedColor]) -> Result<Vec<(Rgba, f64)>, String> {
+    let total: f64 = colors.iter().filter_map(|color| color.fraction).sum();
+    if total > 1.0 + 1e-9 {
+        return Err(format!(
+            "{}:{} Color fractions add up to more than 1",
+            file!(),
+            line!()
+        ));
+    }
+    let rest = colors
+        .iter()
+        .filter(|color| color.fraction.is_none())
+        .count();
+    let share = if rest == 0 {
+        0.0
+    } else {
+        (1.0 - total) / rest as f64
+    };
+    Ok(colors
+        .iter()
+        .map(|color| (color.color, color.fraction.unwrap_or(share)))
+        .collect())
+}
+
+// How the inside of a node or cluster is painted.
+#[derive(Debug, PartialEq)]
+pub enum Fill {
+    Solid(Rgba),
+    Linear { from: Rgba, to: Rgba, angle: f64 },
+    Radial { from: Rgba, to: Rgba, angle: f64 },
+    Wedged(Vec<(Rgba, f64)>),
+    Striped(Vec<(Rgba, f64)>),
+}
+
+const DEFAULT_FILLCOLOR: Rgba = Rgba {
+    r: 211,
+    g: 211,
+    b: 211,
+    a: 255,
+};
+
+// Works out the fill from style, fillcolor, color and gradientangle. Returns None when the
+// style does not ask for a fill.
+pub fn fill(attrs: &Attrs) -> Result<Option<Fill>, String> {
+    let style = attrs.get("style").unwrap_or_default();
+    let styles: Vec<&str> = style.split(',').map(|style| style.trim()).collect();
+    let kind = match ["radial", "wedged", "striped", "filled"]
+        .into_iter()
+        .find(|kind| styles.contains(kind))
+    {
+        Some(kind) => kind,
+        None => return Ok(None),
+    };
+    let colorscheme = attrs.get("colorscheme");
+    let colors = match attrs.get("fillcolor").or(attrs.get("color")) {
+        Some(value) => parse_color_list(value, colorscheme)?,
+        None => vec![WeightedColor {
+            color: DEFAULT_FILLCOLOR,
+            fraction: None,
+        }],
+    };
+    let angle = match attrs.get("gradientangle") {
+        Some(angle) => angle
+            .parse()
+            .map_err(|_| format!("{}:{} Bad gradientangle {}", file!(), line!(), angle))?,
+        None => 0.0,
+    };
+    let from = colors[0].color;
+    let to = colors.get(1).map_or(from, |color| color.color);
+    Ok(Some(match kind {
+        "wedged" => Fill::Wedged(resolve_fractions(&colors)?),
+        "striped" => Fill::Striped(resolve_fractions(&colors)?),
+        "radial" => Fill::Radial { from, to, angle },
+        _ if colors.len() > 1 => Fill::Linear { from, to, angle },
+        _ => Fill::Solid(from),
+    }))
+}
+
+#[test]
+fn test_fill() {
+    let attrs = |pairs: &[(&str, &str)]| {
+        let mut attrs = Attrs::default();
+        for (name, value) in pairs {
+            attrs.set(name, value);
+        }
+        attrs
+    };
+    let red = Rgba::from_hex(0xff0000);
+    let blue = Rgba::from_hex(0x0000ff);
+    assert_eq!(fill(&attrs(&[("color", "red")])), Ok(None));
+    assert_eq!(
+        fill(&attrs(&[("style", "filled")])),
+        Ok(Some(Fill::Solid(DEFAULT_FILLCOLOR)))
+    );
+    assert_eq!(
+        fill(&attrs(&[("style", "bold,filled"), ("color", "red")])),
+        Ok(Some(Fill::Solid(red)))
+    );
+    assert_eq!(
+        fill(&attrs(&[
+            ("style", "filled"),
+            ("fillcolor", "red:blue"),
+            ("gradientangle", "90")
+        ])),
+        Ok(Some(Fill::Linear {
+            from: red,
+            to: blue,
+            angle: 90.0
+        }))
+    );
+    assert_eq!(
+        fill(&attrs(&[
+            ("style", "wedged"),
+            ("fillcolor", "red;0.5:blue:red")
+        ])),
+        Ok(Some(Fill::Wedged(vec![
+            (red, 0.5),
+            (blue, 0.25),
+            (red, 0.25)
+        ])))
+    );
+    assert!(fill(&attrs(&[
+        ("style", "striped"),
+        ("fillcolor", "red;0.7:blue;0.7")
+    ]))
+    .is_err());
+    assert!(fill(&attrs(&[("style", "filled"), ("fillcolor", "red;x")])).is_err());
+}
+
+// Attributes holding a single color, and those that may hold a color list.
+const COLOR_ATTRS: [&str; 4] = ["fontcolor", "pencolor", "labelfontcolor", "bgcolor"];
+const COLOR_LIST_ATTRS: [&str; 2] = ["color", "fillcolor"];
+
+fn check_attrs(what: &str, attrs: &Attrs, warnings: &mut Vec<String>) {
+    let colorscheme = attrs.get("colorscheme");
+    for attr in COLOR_ATTRS {
+        if let Some(Err(e)) = attrs.get(attr).map(|value| parse_color(value, colorscheme)) {
+            warnings.push(format!("{} has a bad {}: {}", what, attr, e));
+        }
+    }
+    for attr in COLOR_LIST_ATTRS {
+        if let Some(Err(e)) = attrs
+            .get(attr)
+            .map(|value| parse_color_list(value, colorscheme))
+        {
+            warnings.push(format!("{} has a bad {}: {}", what, attr, e));
+        }
+    }
+    if let Err(e) = fill(attrs) {
+        warnings.push(format!("{} has a bad fill: {}", what, e));
+    }
+}
+
+// Checks the color attributes of the graph, its subgraphs, nodes and edges.
+pub fn check(graph: &Graph) -> Vec<String> {
+    let mut warnings = Vec::new();
+    check_attrs(
+        format!("Graph {}", graph.name).trim_end(),
+        &graph.attrs,
+        &mut warnings,
+    );
+    for subgraph in graph.subgraphs.iter() {
+        let what = format!("Subgraph {}", subgraph.name);
+        check_attrs(&what, &subgraph.attrs, &mut warnings);
+    }
+    for node in graph.nodes.iter() {
+        check_attrs(&format!("Node {}", node.name), &node.attrs, &mut warnings);
+    }
+    let op = if graph.directed { "->" } else { "--" };
+    for edge in graph.edges.iter() {
+        let what = format!(
+            "Edge {} {} {}",
+            graph.nodes[edge.tail].name, op, graph.nodes[edge.head].name
+        );
+        check_attrs(&what, &edge.attrs, &mut warnings);
+    }
+    warnings
+}
+
+#[test]
+fn test_check() {
+    let tokens = tokenize(
+        r#"digraph {
+    bgcolor = "/blues9/3"
+    node [colorscheme = set39]
+    a [color = 9, fontcolor = 10]
+    b [style = wedged, fillcolor = "red;0.6:blue;0.6"]
+    a -> b [color = "red:#00ff00:blue"]
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let warnings = check(&Graph::from_ast(&graph));
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0].starts_with("Node a has a bad fontcolor"));
+    assert!(warnings[1].starts_with("Node b has a bad fill"));
+}
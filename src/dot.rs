@@ -0,0 +1,1357 @@
+use crate::layout::{self, ClusterBox, Point, POINTS_PER_INCH};
+use crate::semantic::{Attrs, Graph};
+use crate::text;
+use log::warn;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+#[cfg(test)]
+use crate::layout::Rect;
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz defaults, in inches.
+const DEFAULT_NODESEP: f64 = 0.25;
+const MIN_NODESEP: f64 = 0.02;
+const DEFAULT_RANKSEP: f64 = 0.5;
+const MIN_RANKSEP: f64 = 0.02;
+// Space around the contents of a cluster, in points.
+const MARGIN: f64 = 8.0;
+// Rounds of crossing reduction, each a sweep down and then up the ranks.
+const ORDER_ROUNDS: usize = 8;
+// How much more keeping an edge straight is worth when one or both of its ends are bends of a
+// longer edge, so that long edges run straight as in Graphviz.
+const STRAIGHTEN: [i64; 3] = [1, 2, 8];
+
+// rank[head] - rank[tail] must be at least minlen, and weight * (rank[head] - rank[tail]) is
+// kept small.
+#[derive(Clone, Copy, Debug)]
+struct Constraint {
+    tail: usize,
+    head: usize,
+    minlen: i64,
+    weight: i64,
+}
+
+// The state of network simplex: the ranks, the spanning tree of tight constraints, and for every
+// node the tree edge to its parent and the postorder numbers low to lim of its subtree. The
+// nodes of a subtree are those numbered from its low to its lim.
+struct Simplex<'a> {
+    constraints: &'a [Constraint],
+    incident: Vec<Vec<usize>>,
+    root: usize,
+    rank: Vec<i64>,
+    tree: Vec<bool>,
+    // The tree edges at every node.
+    tree_edges: Vec<Vec<usize>>,
+    parent: Vec<Option<usize>>,
+    low: Vec<usize>,
+    lim: Vec<usize>,
+    // The node numbered lim.
+    numbered: Vec<usize>,
+    cut: Vec<i64>,
+}
+
+impl Simplex<'_> {
+    fn slack(&self, e: usize) -> i64 {
+        let c = &self.constraints[e];
+        self.rank[c.head] - self.rank[c.tail] - c.minlen
+    }
+
+    fn other(&self, e: usize, v: usize) -> usize {
+        let c = &self.constraints[e];
+        if c.tail == v {
+            c.head
+        } else {
+            c.tail
+        }
+    }
+
+    // Whether w is in the subtree below v.
+    fn below(&self, v: usize, w: usize) -> bool {
+        self.low[v] <= self.lim[w] && self.lim[w] <= self.lim[v]
+    }
+
+    // The nodes in the subtree below v.
+    fn subtree(&self, v: usize) -> &[usize] {
+        &self.numbered[self.low[v]..=self.lim[v]]
+    }
+
+    // Longest paths from the nodes that no constraint comes into, in topological order.
+    fn init_rank(&mut self) {
+        let mut pending = vec![0; self.rank.len()];
+        for c in self.constraints {
+            pending[c.head] += 1;
+        }
+        let mut ready: Vec<usize> = (0..pending.len()).filter(|&v| pending[v] == 0).collect();
+        while let Some(v) = ready.pop() {
+            for &e in self.incident[v].iter() {
+                let c = self.constraints[e];
+                if c.tail == v {
+                    self.rank[c.head] = self.rank[c.head].max(self.rank[v] + c.minlen);
+                    pending[c.head] -= 1;
+                    if pending[c.head] == 0 {
+                        ready.push(c.head);
+                    }
+                }
+            }
+        }
+    }
+
+    // Makes a spanning tree of tight constraints, as feasible_tree in Graphviz does: trees of
+    // tight constraints are grown from every node, and then the smallest is moved until the
+    // constraint with the least slack out of it is tight and joins it to another.
+    fn feasible_tree(&mut self) {
+        let n = self.rank.len();
+        let mut part = vec![usize::MAX; n];
+        let mut members: Vec<Vec<usize>> = Vec::new();
+        for v in 0..n {
+            if part[v] != usize::MAX {
+                continue;
+            }
+            let p = members.len();
+            part[v] = p;
+            let mut nodes = vec![v];
+            let mut i = 0;
+            while i < nodes.len() {
+                let u = nodes[i];
+                i += 1;
+                for &e in self.incident[u].iter() {
+                    let w = self.other(e, u);
+                    if part[w] == usize::MAX && self.slack(e) == 0 {
+                        part[w] = p;
+                        self.tree[e] = true;
+                        nodes.push(w);
+                    }
+                }
+            }
+            members.push(nodes);
+        }
+        let mut smallest: BinaryHeap<Reverse<(usize, usize)>> = members
+            .iter()
+            .enumerate()
+            .map(|(p, nodes)| Reverse((nodes.len(), p)))
+            .collect();
+        while let Some(Reverse((size, p))) = smallest.pop() {
+            // Trees that have grown or joined another since are left out.
+            if members[p].len() != size {
+                continue;
+            }
+            let mut best: Option<(usize, i64)> = None;
+            for &u in members[p].iter() {
+                for &e in self.incident[u].iter() {
+                    if part[self.other(e, u)] != p {
+                        let slack = self.slack(e);
+                        if best.is_none_or(|(_, least)| slack < least) {
+                            best = Some((e, slack));
+                        }
+                    }
+                }
+            }
+            let Some((e, slack)) = best else {
+                break;
+            };
+            let c = self.constraints[e];
+            let (delta, q) = if part[c.tail] == p {
+                (slack, part[c.head])
+            } else {
+                (-slack, part[c.tail])
+            };
+            let nodes = std::mem::take(&mut members[p]);
+            for &u in nodes.iter() {
+                self.rank[u] += delta;
+                part[u] = q;
+            }
+            self.tree[e] = true;
+            members[q].extend(nodes);
+            smallest.push(Reverse((members[q].len(), q)));
+        }
+        for (e, c) in self.constraints.iter().enumerate() {
+            if self.tree[e] {
+                self.tree_edges[c.tail].push(e);
+                self.tree_edges[c.head].push(e);
+            }
+        }
+    }
+
+    // Numbers the subtree below v in postorder from low, as dfs_range in Graphviz does.
+    // Subtrees that keep their parent and low keep their numbers, so the nodes above any change
+    // must have been marked by setting their low to usize::MAX.
+    fn number(&mut self, v: usize, parent: Option<usize>, low: usize) {
+        let mut next = low;
+        self.parent[v] = parent;
+        self.low[v] = next;
+        let mut stack = vec![(v, 0)];
+        while let Some(top) = stack.last_mut() {
+            let (u, i) = *top;
+            if i < self.tree_edges[u].len() {
+                top.1 += 1;
+                let e = self.tree_edges[u][i];
+                if Some(e) != self.parent[u] {
+                    let w = self.other(e, u);
+                    if self.parent[w] == Some(e) && self.low[w] == next {
+                        next = self.lim[w] + 1;
+                        continue;
+                    }
+                    self.parent[w] = Some(e);
+                    self.low[w] = next;
+                    stack.push((w, 0));
+                }
+            } else {
+                self.lim[u] = next;
+                self.numbered[next] = u;
+                next += 1;
+                stack.pop();
+            }
+        }
+    }
+
+    // Works out the cut value of every tree edge from those of the edges below it, as x_cutval
+    // in Graphviz does.
+    fn cut_values(&mut self) {
+        self.number(self.root, None, 0);
+        for i in 0..self.numbered.len() {
+            let v = self.numbered[i];
+            let Some(f) = self.parent[v] else {
+                continue;
+            };
+            let from_tail = self.constraints[f].tail == v;
+            let mut sum = 0;
+            for &e in self.incident[v].iter() {
+                let c = self.constraints[e];
+                let outside = !self.below(v, self.other(e, v));
+                let mut value = match (outside, self.tree[e]) {
+                    (true, _) => c.weight,
+                    (false, true) => self.cut[e] - c.weight,
+                    (false, false) => -c.weight,
+                };
+                let forward = if from_tail { c.head == v } else { c.tail == v };
+                if forward == outside {
+                    value = -value;
+                }
+                sum += value;
+            }
+            self.cut[f] = sum;
+        }
+    }
+
+    // Adds value to the cut values of the tree edges on the way up from v until w is below, in
+    // the direction dir, and returns where it stopped, as treeupdate in Graphviz does.
+    fn update_path(&mut self, mut v: usize, w: usize, value: i64, dir: bool) -> usize {
+        while !self.below(v, w) {
+            let e = self.parent[v].expect("the root is above every node");
+            let c = self.constraints[e];
+            if (v == c.tail) == dir {
+                self.cut[e] += value;
+            } else {
+                self.cut[e] -= value;
+            }
+            v = self.up(v);
+        }
+        v
+    }
+
+    // The node above v in the tree.
+    fn up(&self, v: usize) -> usize {
+        let e = self.parent[v].expect("the root is above every node");
+        let c = &self.constraints[e];
+        if self.lim[c.tail] > self.lim[c.head] {
+            c.tail
+        } else {
+            c.head
+        }
+    }
+
+    // Puts e in the tree in place of f, which must cross the cut of f the other way, moving the
+    // nodes below f so that e is tight. Only the cut values on the tree paths from the ends of e
+    // to where they meet change, and only the nodes below there are numbered again.
+    fn exchange(&mut self, f: usize, e: usize) {
+        let c = self.constraints[f];
+        let v = if self.lim[c.tail] < self.lim[c.head] {
+            c.tail
+        } else {
+            c.head
+        };
+        let delta = if self.below(v, self.constraints[e].tail) {
+            self.slack(e)
+        } else {
+            -self.slack(e)
+        };
+        for i in self.low[v]..=self.lim[v] {
+            self.rank[self.numbered[i]] += delta;
+        }
+        let value = self.cut[f];
+        let (tail, head) = (self.constraints[e].tail, self.constraints[e].head);
+        let top = self.update_path(tail, head, value, true);
+        self.update_path(head, tail, value, false);
+        let low = self.low[top];
+        for mut u in [tail, head] {
+            while u != top {
+                self.low[u] = usize::MAX;
+                u = self.up(u);
+            }
+        }
+        self.cut[e] = -value;
+        self.cut[f] = 0;
+        self.tree[f] = false;
+        self.tree[e] = true;
+        for u in [c.tail, c.head] {
+            self.tree_edges[u].retain(|&g| g != f);
+        }
+        self.tree_edges[tail].push(e);
+        self.tree_edges[head].push(e);
+        self.number(top, self.parent[top], low);
+    }
+}
+
+// How many tree edges with negative cut values are looked at for the one that leaves, as in
+// Graphviz.
+const SEARCH_SIZE: usize = 30;
+
+// Ranks of n nodes that satisfy every constraint with the least total weighted length, by the
+// network simplex method of "A Technique for Drawing Directed Graphs" by Gansner et al. The
+// constraints must not form a cycle. Nodes that are not linked by constraints are ranked as if
+// they were, and the smallest rank is 0. At most max_iterations exchanges are made.
+fn network_simplex(n: usize, constraints: &[Constraint], max_iterations: usize) -> Vec<i64> {
+    // A root before a node of every connected part links them all without changing what is
+    // best.
+    let mut parts: Vec<usize> = (0..n).collect();
+    for c in constraints {
+        let (a, b) = (find(&mut parts, c.tail), find(&mut parts, c.head));
+        parts[a] = b;
+    }
+    let mut all = constraints.to_vec();
+    for v in 0..n {
+        if find(&mut parts, v) == v {
+            all.push(Constraint {
+                tail: n,
+                head: v,
+                minlen: 0,
+                weight: 0,
+            });
+        }
+    }
+    let mut incident = vec![Vec::new(); n + 1];
+    for (e, c) in all.iter().enumerate() {
+        incident[c.tail].push(e);
+        incident[c.head].push(e);
+    }
+    let mut simplex = Simplex {
+        constraints: &all,
+        incident,
+        root: n,
+        rank: vec![0; n + 1],
+        tree: vec![false; all.len()],
+        tree_edges: vec![Vec::new(); n + 1],
+        parent: vec![None; n + 1],
+        low: vec![0; n + 1],
+        lim: vec![0; n + 1],
+        numbered: vec![0; n + 1],
+        cut: vec![0; all.len()],
+    };
+    simplex.init_rank();
+    simplex.feasible_tree();
+    simplex.cut_values();
+    let mut tree: Vec<usize> = (0..all.len()).filter(|&e| simplex.tree[e]).collect();
+    let mut start = 0;
+    for _ in 0..max_iterations {
+        // The most negative of the next few tree edges with negative cut values leaves the tree,
+        // searching on from the last one.
+        let mut leaving: Option<usize> = None;
+        let mut found = 0;
+        for i in (start..tree.len()).chain(0..start) {
+            let e = tree[i];
+            if simplex.cut[e] < 0 {
+                if leaving.is_none_or(|j| simplex.cut[e] < simplex.cut[tree[j]]) {
+                    leaving = Some(i);
+                }
+                found += 1;
+                if found == SEARCH_SIZE {
+                    break;
+                }
+            }
+        }
+        let Some(i) = leaving else {
+            break;
+        };
+        start = i;
+        let f = tree[i];
+        // The edge with the least slack that crosses the cut the other way comes in, and a tight
+        // one ends the search.
+        let c = all[f];
+        let v = if simplex.lim[c.tail] < simplex.lim[c.head] {
+            c.tail
+        } else {
+            c.head
+        };
+        let mut entering: Option<(usize, i64)> = None;
+        'search: for &w in simplex.subtree(v) {
+            for &e in simplex.incident[w].iter() {
+                let crosses = if v == c.tail {
+                    all[e].head == w && !simplex.below(v, all[e].tail)
+                } else {
+                    all[e].tail == w && !simplex.below(v, all[e].head)
+                };
+                if crosses && !simplex.tree[e] {
+                    let slack = simplex.slack(e);
+                    if entering.is_none_or(|(_, least)| slack < least) {
+                        entering = Some((e, slack));
+                        if slack == 0 {
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+        let Some((e, _)) = entering else {
+            break;
+        };
+        simplex.exchange(f, e);
+        tree[i] = e;
+    }
+    let min = simplex.rank[..n].iter().copied().min().unwrap_or(0);
+    simplex.rank[..n].iter().map(|rank| rank - min).collect()
+}
+
+#[test]
+fn test_network_simplex() {
+    let constraint = |tail: usize, head: usize| Constraint {
+        tail,
+        head,
+        minlen: 1,
+        weight: 1,
+    };
+    // Longest paths would put e at the top, but it is best next to d.
+    let constraints = [
+        constraint(0, 1),
+        constraint(1, 2),
+        constraint(2, 3),
+        constraint(4, 3),
+    ];
+    assert_eq!(
+        network_simplex(5, &constraints, usize::MAX),
+        vec![0, 1, 2, 3, 2]
+    );
+    // The heavier edge is kept short.
+    let constraints = [
+        constraint(0, 1),
+        constraint(1, 2),
+        Constraint {
+            weight: 3,
+            ..constraint(3, 1)
+        },
+        constraint(3, 2),
+        constraint(0, 3),
+    ];
+    assert_eq!(
+        network_simplex(4, &constraints, usize::MAX),
+        vec![0, 2, 3, 1]
+    );
+    assert_eq!(network_simplex(2, &[], usize::MAX), vec![0, 0]);
+}
+
+// The direction the ranks go in, from rankdir.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Direction {
+    Down,
+    Up,
+    Right,
+    Left,
+}
+
+impl Direction {
+    fn of(graph: &Graph) -> Direction {
+        match graph.attrs.get("rankdir") {
+            None | Some("TB") => Direction::Down,
+            Some("BT") => Direction::Up,
+            Some("LR") => Direction::Right,
+            Some("RL") => Direction::Left,
+            Some(value) => {
+                warn!("Rankdir {} is not supported, using TB", value);
+                Direction::Down
+            }
+        }
+    }
+
+    fn sideways(self) -> bool {
+        matches!(self, Direction::Right | Direction::Left)
+    }
+
+    // From the layout, where ranks go down and the order of a rank goes right, to the drawing.
+    // Sideways, the first of a rank is at the top.
+    fn turn(self, (x, y): Point) -> Point {
+        match self {
+            Direction::Down => (x, y),
+            Direction::Up => (x, -y),
+            Direction::Right => (-y, -x),
+            Direction::Left => (y, -x),
+        }
+    }
+}
+
+// The clusters as a tree: the parent of every cluster, the clusters from the outside in, and
+// the innermost cluster of every node. A node in clusters that are not nested goes in the first.
+struct Tree {
+    parent: Vec<Option<usize>>,
+    clusters: Vec<usize>,
+    of_node: Vec<Option<usize>>,
+}
+
+impl Tree {
+    fn new(graph: &Graph) -> Tree {
+        let mut tree = Tree {
+            parent: vec![None; graph.subgraphs.len()],
+            clusters: Vec::new(),
+            of_node: vec![None; graph.nodes.len()],
+        };
+        let mut parents = vec![None];
+        let mut i = 0;
+        while i < parents.len() {
+            let parent = parents[i];
+            i += 1;
+            for cluster in layout::clusters_in(graph, parent) {
+                tree.parent[cluster] = parent;
+                tree.clusters.push(cluster);
+                parents.push(Some(cluster));
+                for &node in graph.subgraphs[cluster].nodes.iter() {
+                    if tree.of_node[node] == parent {
+                        tree.of_node[node] = Some(cluster);
+                    }
+                }
+            }
+        }
+        tree
+    }
+
+    // A cluster and the clusters around it, from the inside out.
+    fn chain(&self, cluster: Option<usize>) -> Vec<usize> {
+        let mut chain = Vec::new();
+        let mut cluster = cluster;
+        while let Some(c) = cluster {
+            chain.push(c);
+            cluster = self.parent[c];
+        }
+        chain
+    }
+
+    // The innermost cluster around both, if any.
+    fn common(&self, a: Option<usize>, b: Option<usize>) -> Option<usize> {
+        let chain = self.chain(b);
+        self.chain(a).into_iter().find(|c| chain.contains(c))
+    }
+}
+
+// The length of an edge in ranks, and how much keeping it short is worth.
+fn minlen_and_weight(attrs: &Attrs) -> Result<(i64, i64), String> {
+    let minlen = layout::number(attrs, "minlen", 1.0)?;
+    let weight = layout::number(attrs, "weight", 1.0)?;
+    Ok((
+        minlen.round().max(0.0) as i64,
+        weight.round().max(0.0) as i64,
+    ))
+}
+
+// Finds the set of a union-find forest, halving paths on the way.
+fn find(sets: &mut [usize], mut v: usize) -> usize {
+    while sets[v] != v {
+        sets[v] = sets[sets[v]];
+        v = sets[v];
+    }
+    v
+}
+
+// The rank of every node. Edges point down the ranks unless constraint is false, except those
+// that close a cycle, found depth first, which point up. Subgraphs with rank=same keep their
+// nodes on one rank, min and source put them on the first rank and max and sink on the last.
+// Each connected part of the graph starts at rank 0.
+fn rank(graph: &Graph) -> Result<Vec<usize>, String> {
+    let n = graph.nodes.len();
+    let mut same: Vec<usize> = (0..n).collect();
+    let mut ends = Vec::new();
+    for subgraph in graph.subgraphs.iter() {
+        let Some(kind) = subgraph.attrs.get("rank") else {
+            continue;
+        };
+        if let Some((&first, rest)) = subgraph.nodes.split_first() {
+            for &node in rest {
+                let (a, b) = (find(&mut same, first), find(&mut same, node));
+                same[b] = a;
+            }
+            match kind {
+                "min" | "source" => ends.push((first, false)),
+                "max" | "sink" => ends.push((first, true)),
+                _ => {}
+            }
+        }
+    }
+
+    let mut edges = Vec::new();
+    for edge in graph.edges.iter() {
+        if edge.attrs.get("constraint") == Some("false") {
+            continue;
+        }
+        let (tail, head) = (find(&mut same, edge.tail), find(&mut same, edge.head));
+        if tail != head {
+            let (minlen, weight) = minlen_and_weight(&edge.attrs)?;
+            edges.push(Constraint {
+                tail,
+                head,
+                minlen,
+                weight,
+            });
+        }
+    }
+    // Depth first from every node in turn, reversing the edges to nodes still being searched.
+    let mut out = vec![Vec::new(); n];
+    for (e, edge) in edges.iter().enumerate() {
+        out[edge.tail].push(e);
+    }
+    let mut state = vec![0u8; n];
+    for first in 0..n {
+        if state[first] != 0 {
+            continue;
+        }
+        state[first] = 1;
+        let mut stack = vec![(first, 0)];
+        while let Some(top) = stack.last_mut() {
+            let (v, i) = *top;
+            if i == out[v].len() {
+                state[v] = 2;
+                stack.pop();
+                continue;
+            }
+            top.1 += 1;
+            let e = out[v][i];
+            let head = edges[e].head;
+            match state[head] {
+                0 => {
+                    state[head] = 1;
+                    stack.push((head, 0));
+                }
+                1 => {
+                    edges[e].head = v;
+                    edges[e].tail = head;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let limit = layout::number(&graph.attrs, "nslimit1", f64::INFINITY)? * n as f64;
+    let ranks = network_simplex(n, &edges, limit as usize);
+    let mut parts: Vec<usize> = (0..n).collect();
+    for edge in edges.iter() {
+        let (a, b) = (find(&mut parts, edge.tail), find(&mut parts, edge.head));
+        parts[b] = a;
+    }
+    let mut first = vec![i64::MAX; n];
+    for (v, &rank) in ranks.iter().enumerate() {
+        let part = find(&mut parts, v);
+        first[part] = first[part].min(rank);
+    }
+    let mut ranks: Vec<usize> = (0..n)
+        .map(|v| (ranks[v] - first[find(&mut parts, v)]) as usize)
+        .collect();
+    let last = ranks.iter().copied().max().unwrap_or(0);
+    for (node, at_end) in ends {
+        let node = find(&mut same, node);
+        ranks[node] = if at_end { last } else { 0 };
+    }
+    Ok((0..n).map(|v| ranks[find(&mut same, v)]).collect())
+}
+
+#[test]
+fn test_rank() {
+    let graph = from_dot(
+        r#"digraph {
+    a -> b -> c -> a
+    d -> e [minlen = 2]
+    c -> f [constraint = false]
+    { rank = same; b; d }
+    { rank = sink; g }
+}"#,
+    );
+    assert_eq!(rank(&graph).unwrap(), vec![0, 1, 2, 1, 3, 0, 3]);
+}
+
+// A node of the layout: a node of the graph, a bend of an edge that spans several ranks, or a
+// placeholder that keeps a cluster together on a rank where it has nothing else. Sizes are in
+// points, with the width along the rank.
+struct Vnode {
+    node: Option<usize>,
+    cluster: Option<usize>,
+    rank: usize,
+    width: f64,
+    height: f64,
+}
+
+// A part of an edge between consecutive ranks, from the upper to the lower node.
+struct Segment {
+    upper: usize,
+    lower: usize,
+    weight: i64,
+}
+
+// Sorts the nodes of a rank by key, keeping the contents of every cluster together. A cluster
+// goes where the mean key of its contents puts it among the nodes around it, but the clusters in
+// the same cluster keep the order of cluster_keys, so that they are in the same order on every
+// rank and their boxes can be side by side.
+fn arrange(
+    items: &[usize],
+    level: Option<usize>,
+    keys: &[f64],
+    vnodes: &[Vnode],
+    tree: &Tree,
+    cluster_keys: &[f64],
+) -> Vec<usize> {
+    // A node, or a cluster with its contents, and the key it is sorted by.
+    let mut entries: Vec<(Option<usize>, Vec<usize>, f64)> = Vec::new();
+    for &v in items {
+        let chain = tree.chain(vnodes[v].cluster);
+        match chain.into_iter().find(|&c| tree.parent[c] == level) {
+            Some(c) => match entries
+                .iter_mut()
+                .find(|(cluster, _, _)| *cluster == Some(c))
+            {
+                Some((_, contents, key)) => {
+                    contents.push(v);
+                    *key += keys[v];
+                }
+                None => entries.push((Some(c), vec![v], keys[v])),
+            },
+            None => entries.push((None, vec![v], keys[v])),
+        }
+    }
+    for (_, contents, key) in entries.iter_mut() {
+        *key /= contents.len() as f64;
+    }
+    entries.sort_by(|a, b| a.2.total_cmp(&b.2));
+    let slots: Vec<usize> = (0..entries.len())
+        .filter(|&i| entries[i].0.is_some())
+        .collect();
+    let mut clusters: Vec<_> = slots.iter().map(|&i| entries[i].clone()).collect();
+    clusters.sort_by(|a, b| cluster_keys[a.0.unwrap()].total_cmp(&cluster_keys[b.0.unwrap()]));
+    for (slot, cluster) in slots.into_iter().zip(clusters) {
+        entries[slot] = cluster;
+    }
+    entries
+        .into_iter()
+        .flat_map(|(cluster, contents, _)| match cluster {
+            Some(c) => arrange(&contents, Some(c), keys, vnodes, tree, cluster_keys),
+            None => contents,
+        })
+        .collect()
+}
+
+// The number of edges that cross between every pair of consecutive ranks.
+fn crossings(layers: &[Vec<usize>], position: &[usize], below: &[Vec<usize>]) -> usize {
+    let mut total = 0;
+    for pair in layers.windows(2) {
+        let mut ends: Vec<(usize, usize)> = pair[0]
+            .iter()
+            .flat_map(|&u| below[u].iter().map(move |&w| (position[u], position[w])))
+            .collect();
+        ends.sort();
+        // Counts the edges seen so far that end further right, with a Fenwick tree.
+        let mut counts = vec![0; pair[1].len() + 1];
+        for (i, &(_, lower)) in ends.iter().enumerate() {
+            let mut not_right = 0;
+            let mut j = lower + 1;
+            while j > 0 {
+                not_right += counts[j];
+                j &= j - 1;
+            }
+            total += i - not_right;
+            let mut j = lower + 1;
+            while j < counts.len() {
+                counts[j] += 1;
+                j += j & j.wrapping_neg();
+            }
+        }
+    }
+    total
+}
+
+// The number of crossings between the edges of u and those of v when u is left of v.
+fn pair_crossings(
+    u: usize,
+    v: usize,
+    position: &[usize],
+    above: &[Vec<usize>],
+    below: &[Vec<usize>],
+) -> usize {
+    [above, below]
+        .iter()
+        .map(|neighbours| {
+            neighbours[u]
+                .iter()
+                .flat_map(|&a| neighbours[v].iter().map(move |&b| (a, b)))
+                .filter(|&(a, b)| position[a] > position[b])
+                .count()
+        })
+        .sum()
+}
+
+// Orders the nodes of every rank to avoid crossings, by sweeping the ranks down and up with the
+// mean position of the neighbours in the rank before, and swapping neighbours in the same
+// cluster while that helps. The order with the fewest crossings is kept.
+fn order(
+    layers: &mut Vec<Vec<usize>>,
+    vnodes: &[Vnode],
+    tree: &Tree,
+    above: &[Vec<usize>],
+    below: &[Vec<usize>],
+) {
+    let count = tree.parent.len();
+    let mut cluster_keys: Vec<f64> = vec![0.0; count];
+    for (i, &c) in tree.clusters.iter().enumerate() {
+        cluster_keys[c] = i as f64;
+    }
+    let mut position = vec![0; vnodes.len()];
+    let place = |layers: &[Vec<usize>], position: &mut [usize]| {
+        for layer in layers {
+            for (i, &v) in layer.iter().enumerate() {
+                position[v] = i;
+            }
+        }
+    };
+    let sort = |layer: &mut Vec<usize>, keys: &[f64], cluster_keys: &[f64]| {
+        *layer = arrange(layer, None, keys, vnodes, tree, cluster_keys);
+    };
+    place(layers, &mut position);
+    let mut keys: Vec<f64> = position.iter().map(|&p| p as f64).collect();
+    for layer in layers.iter_mut() {
+        sort(layer, &keys, &cluster_keys);
+    }
+    place(layers, &mut position);
+    let mut best = (crossings(layers, &position, below), layers.clone());
+    for _ in 0..ORDER_ROUNDS {
+        if best.0 == 0 {
+            break;
+        }
+        let down = (1..layers.len()).map(|r| (r, above));
+        let up = (0..layers.len().saturating_sub(1))
+            .rev()
+            .map(|r| (r, below));
+        for (r, neighbours) in down.chain(up) {
+            for &v in layers[r].iter() {
+                keys[v] = if neighbours[v].is_empty() {
+                    position[v] as f64
+                } else {
+                    neighbours[v]
+                        .iter()
+                        .map(|&w| position[w] as f64)
+                        .sum::<f64>()
+                        / neighbours[v].len() as f64
+                };
+            }
+            sort(&mut layers[r], &keys, &cluster_keys);
+            place(&layers[r..=r], &mut position);
+        }
+
+        // Clusters in the same cluster are ordered by where their contents went, on every rank.
+        let mut sums = vec![(0.0, 0); count];
+        for layer in layers.iter() {
+            for &v in layer {
+                for c in tree.chain(vnodes[v].cluster) {
+                    sums[c].0 += position[v] as f64 / layer.len() as f64;
+                    sums[c].1 += 1;
+                }
+            }
+        }
+        for (key, (sum, n)) in cluster_keys.iter_mut().zip(sums) {
+            if n > 0 {
+                *key = sum / n as f64;
+            }
+        }
+        for (v, key) in keys.iter_mut().enumerate() {
+            *key = position[v] as f64;
+        }
+        for layer in layers.iter_mut() {
+            sort(layer, &keys, &cluster_keys);
+        }
+        place(layers, &mut position);
+
+        let mut swapped = true;
+        while swapped {
+            swapped = false;
+            for layer in layers.iter_mut() {
+                for i in 1..layer.len() {
+                    let (u, v) = (layer[i - 1], layer[i]);
+                    if vnodes[u].cluster == vnodes[v].cluster
+                        && pair_crossings(v, u, &position, above, below)
+                            < pair_crossings(u, v, &position, above, below)
+                    {
+                        layer.swap(i - 1, i);
+                        position[u] = i;
+                        position[v] = i - 1;
+                        swapped = true;
+                    }
+                }
+            }
+        }
+        let crossed = crossings(layers, &position, below);
+        if crossed < best.0 {
+            best = (crossed, layers.clone());
+        }
+    }
+    *layers = best.1;
+}
+
+#[test]
+fn test_order() {
+    let graph = from_dot(
+        r#"digraph {
+    a; b; c; d
+    subgraph cluster_x { b; d }
+    a -> b; a -> c; a -> d
+    b -> e; c -> f; d -> g
+}"#,
+    );
+    let tree = Tree::new(&graph);
+    let vnode = |node: usize, rank: usize| Vnode {
+        node: Some(node),
+        cluster: tree.of_node[node],
+        rank,
+        width: 54.0,
+        height: 36.0,
+    };
+    let vnodes: Vec<Vnode> = [0, 1, 1, 1, 2, 2, 2]
+        .iter()
+        .enumerate()
+        .map(|(i, &rank)| vnode(i, rank))
+        .collect();
+    let (mut above, mut below) = (vec![Vec::new(); 7], vec![Vec::new(); 7]);
+    for edge in graph.edges.iter() {
+        below[edge.tail].push(edge.head);
+        above[edge.head].push(edge.tail);
+    }
+    let mut layers = vec![vec![0], vec![1, 2, 3], vec![4, 5, 6]];
+    order(&mut layers, &vnodes, &tree, &above, &below);
+    // b and d are kept together, and their children follow them.
+    let middle = &layers[1];
+    let (b, d) = (
+        middle.iter().position(|&v| v == 1).unwrap(),
+        middle.iter().position(|&v| v == 3).unwrap(),
+    );
+    assert_eq!(b.abs_diff(d), 1);
+    let positions: Vec<usize> = layers[2]
+        .iter()
+        .map(|&v| middle.iter().position(|&u| below[u].contains(&v)).unwrap())
+        .collect();
+    assert_eq!(positions, vec![0, 1, 2]);
+}
+
+// A separation graph attribute such as nodesep, in points. ranksep may be followed by
+// "equally", which is ignored.
+fn separation(graph: &Graph, name: &str, default: f64, min: f64) -> Result<f64, String> {
+    let inches = match graph.attrs.get(name) {
+        Some(value) => value
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .parse::<f64>()
+            .map_err(|_| format!("{}:{} Bad {} {}", file!(), line!(), name, value))?,
+        None => default,
+    };
+    Ok(inches.max(min) * POINTS_PER_INCH)
+}
+
+// The positions of the nodes, the boxes of the clusters and the points every edge bends at.
+type Layout = (Vec<Point>, Vec<ClusterBox>, Vec<Vec<Point>>);
+
+// Hierarchical layout as in Graphviz dot, given the sizes of the nodes in points. Nodes are put
+// on ranks so that edges point down, see rank, with bends on the ranks between for edges that
+// span several. The ranks are ordered to avoid crossings, see order, and the nodes are placed
+// along them by network simplex, keeping them nodesep apart and keeping edges straight. Every
+// cluster is a box that holds its contents on every rank it spans, with a margin and room for its
+// label, and nothing else; rankdir turns the whole drawing. Returns the positions of the nodes,
+// the boxes of the clusters and the points every edge bends at, from tail to head.
+pub fn layout(graph: &Graph, sizes: &[(f64, f64)]) -> Result<Layout, String> {
+    let n = graph.nodes.len();
+    let direction = Direction::of(graph);
+    let tree = Tree::new(graph);
+    let ranks = rank(graph)?;
+    let mut vnodes: Vec<Vnode> = (0..n)
+        .map(|i| {
+            let (width, height) = match direction.sideways() {
+                true => (sizes[i].1, sizes[i].0),
+                false => sizes[i],
+            };
+            Vnode {
+                node: Some(i),
+                cluster: tree.of_node[i],
+                rank: ranks[i],
+                width,
+                height,
+            }
+        })
+        .collect();
+
+    // Edges that span several ranks bend on the ranks between, in the innermost cluster around
+    // both ends.
+    let mut segments = Vec::new();
+    let mut chains = vec![Vec::new(); graph.edges.len()];
+    let mut flat = Vec::new();
+    for (i, edge) in graph.edges.iter().enumerate() {
+        if edge.tail == edge.head {
+            continue;
+        }
+        let (_, weight) = minlen_and_weight(&edge.attrs)?;
+        let (upper, lower) = if ranks[edge.tail] <= ranks[edge.head] {
+            (edge.tail, edge.head)
+        } else {
+            (edge.head, edge.tail)
+        };
+        if ranks[upper] == ranks[lower] {
+            flat.push((i, weight));
+            continue;
+        }
+        let cluster = tree.common(tree.of_node[upper], tree.of_node[lower]);
+        let mut chain = vec![upper];
+        for rank in ranks[upper] + 1..ranks[lower] {
+            chain.push(vnodes.len());
+            vnodes.push(Vnode {
+                node: None,
+                cluster,
+                rank,
+                width: 0.0,
+                height: 0.0,
+            });
+        }
+        chain.push(lower);
+        for pair in chain.windows(2) {
+            segments.push(Segment {
+                upper: pair[0],
+                lower: pair[1],
+                weight,
+            });
+        }
+        chains[i] = chain;
+    }
+
+    // The ranks each cluster spans, with a placeholder on those where it has nothing. Inner
+    // clusters come first, so that their placeholders also serve the clusters around them.
+    let rank_count = vnodes.iter().map(|v| v.rank + 1).max().unwrap_or(0);
+    let mut present = vec![vec![false; rank_count]; graph.subgraphs.len()];
+    for v in vnodes.iter() {
+        for c in tree.chain(v.cluster) {
+            present[c][v.rank] = true;
+        }
+    }
+    let span = |c: usize, present: &[Vec<bool>]| {
+        let first = present[c].iter().position(|&p| p)?;
+        let last = present[c].iter().rposition(|&p| p)?;
+        Some((first, last))
+    };
+    for &c in tree.clusters.iter().rev() {
+        let Some((first, last)) = span(c, &present) else {
+            continue;
+        };
+        let missing: Vec<usize> = (first..=last).filter(|&rank| !present[c][rank]).collect();
+        for rank in missing {
+            vnodes.push(Vnode {
+                node: None,
+                cluster: Some(c),
+                rank,
+                width: 0.0,
+                height: 0.0,
+            });
+            for outer in tree.chain(Some(c)) {
+                present[outer][rank] = true;
+            }
+        }
+    }
+    let spans: Vec<Option<(usize, usize)>> = (0..graph.subgraphs.len())
+        .map(|c| span(c, &present))
+        .collect();
+
+    // The first order is breadth first from the nodes with nothing above them.
+    let (mut above, mut below) = (
+        vec![Vec::new(); vnodes.len()],
+        vec![Vec::new(); vnodes.len()],
+    );
+    for segment in segments.iter() {
+        below[segment.upper].push(segment.lower);
+        above[segment.lower].push(segment.upper);
+    }
+    let mut layers = vec![Vec::new(); rank_count];
+    let mut seen = vec![false; vnodes.len()];
+    let mut queue: Vec<usize> = (0..vnodes.len()).filter(|&v| above[v].is_empty()).collect();
+    let mut i = 0;
+    while i < queue.len() {
+        let v = queue[i];
+        i += 1;
+        if !seen[v] {
+            seen[v] = true;
+            layers[vnodes[v].rank].push(v);
+            queue.extend(below[v].iter().copied());
+        }
+    }
+    order(&mut layers, &vnodes, &tree, &above, &below);
+
+    // Room for the labels of clusters, on the side of the layout that is their top, or their
+    // bottom when labelloc is b: before or after their ranks, or left or right of them sideways.
+    let count = graph.subgraphs.len();
+    let (mut first_room, mut last_room) = (vec![0.0; count], vec![0.0; count]);
+    let (mut left_room, mut right_room) = (vec![0.0; count], vec![0.0; count]);
+    let mut label_widths = vec![0.0; count];
+    for &c in tree.clusters.iter() {
+        let subgraph = &graph.subgraphs[c];
+        let Some((width, height)) = text::cluster_label_size(graph, subgraph) else {
+            continue;
+        };
+        label_widths[c] = width;
+        let bottom = subgraph.attrs.get("labelloc") == Some("b");
+        let room = match (direction, bottom) {
+            (Direction::Down, false) | (Direction::Up, true) => &mut first_room,
+            (Direction::Down, true) | (Direction::Up, false) => &mut last_room,
+            (_, false) => &mut left_room,
+            (_, true) => &mut right_room,
+        };
+        room[c] = height;
+    }
+
+    // Places the nodes along the ranks. Every cluster has a left and a right side, which its
+    // contents stay inside of, and which the nodes and clusters beside it stay outside of.
+    let nodesep = separation(graph, "nodesep", DEFAULT_NODESEP, MIN_NODESEP)?;
+    let length = |x: f64| x.ceil() as i64;
+    let mut sides = vec![(0, 0); count];
+    let mut next = vnodes.len();
+    for &c in tree.clusters.iter() {
+        sides[c] = (next, next + 1);
+        next += 2;
+    }
+    let mut constraints = Vec::new();
+    let mut separate = |tail: usize, head: usize, minlen: f64| {
+        constraints.push(Constraint {
+            tail,
+            head,
+            minlen: length(minlen),
+            weight: 0,
+        })
+    };
+    for layer in layers.iter() {
+        for pair in layer.windows(2) {
+            let (u, v) = (&vnodes[pair[0]], &vnodes[pair[1]]);
+            let (left, right) = (tree.chain(u.cluster), tree.chain(v.cluster));
+            let outer_left = left.iter().rev().find(|c| !right.contains(c));
+            let outer_right = right.iter().rev().find(|c| !left.contains(c));
+            let (tail, tail_half) = match outer_left {
+                Some(&c) => (sides[c].1, 0.0),
+                None => (pair[0], u.width / 2.0),
+            };
+            let (head, head_half) = match outer_right {
+                Some(&c) => (sides[c].0, 0.0),
+                None => (pair[1], v.width / 2.0),
+            };
+            separate(tail, head, tail_half + nodesep + head_half);
+        }
+    }
+    for (i, v) in vnodes.iter().enumerate() {
+        if let Some(c) = v.cluster {
+            separate(sides[c].0, i, v.width / 2.0 + MARGIN + left_room[c]);
+            separate(i, sides[c].1, v.width / 2.0 + MARGIN + right_room[c]);
+        }
+    }
+    for &c in tree.clusters.iter() {
+        if let Some(parent) = tree.parent[c] {
+            separate(sides[parent].0, sides[c].0, MARGIN + left_room[parent]);
+            separate(sides[c].1, sides[parent].1, MARGIN + right_room[parent]);
+        }
+    }
+    // Clusters are kept narrow, and wide enough for their labels unless those are sideways.
+    for &c in tree.clusters.iter() {
+        let mut minlen = 2.0 * MARGIN + left_room[c] + right_room[c];
+        if !direction.sideways() {
+            minlen = minlen.max(label_widths[c] + 2.0 * MARGIN);
+        }
+        constraints.push(Constraint {
+            tail: sides[c].0,
+            head: sides[c].1,
+            minlen: length(minlen),
+            weight: 1,
+        });
+    }
+    // Edges are kept straight by a node left of both ends, which is best as close as it can be.
+    let mut straighten = |a: usize, b: usize, weight: i64| {
+        for end in [a, b] {
+            constraints.push(Constraint {
+                tail: next,
+                head: end,
+                minlen: 0,
+                weight,
+            });
+        }
+        next += 1;
+    };
+    for segment in segments.iter() {
+        let bends = [segment.upper, segment.lower]
+            .iter()
+            .filter(|&&v| vnodes[v].node.is_none())
+            .count();
+        straighten(
+            segment.upper,
+            segment.lower,
+            segment.weight * STRAIGHTEN[bends],
+        );
+    }
+    for &(i, weight) in flat.iter() {
+        straighten(graph.edges[i].tail, graph.edges[i].head, weight);
+    }
+    let limit = layout::number(&graph.attrs, "nslimit", f64::INFINITY)? * next as f64;
+    let xs: Vec<f64> = network_simplex(next, &constraints, limit as usize)
+        .into_iter()
+        .map(|x| x as f64)
+        .collect();
+
+    // The ranks go down ranksep apart, or further when the sides of clusters are between them.
+    let ranksep = separation(graph, "ranksep", DEFAULT_RANKSEP, MIN_RANKSEP)?;
+    let mut half = vec![0.0f64; rank_count];
+    for v in vnodes.iter() {
+        half[v.rank] = half[v.rank].max(v.height / 2.0);
+    }
+    let (mut first_pad, mut last_pad) = (vec![0.0f64; count], vec![0.0f64; count]);
+    for &c in tree.clusters.iter().rev() {
+        let Some((first, last)) = spans[c] else {
+            continue;
+        };
+        first_pad[c] += MARGIN + first_room[c];
+        last_pad[c] += MARGIN + last_room[c];
+        if let Some(parent) = tree.parent[c] {
+            if spans[parent].map(|(f, _)| f) == Some(first) {
+                first_pad[parent] = first_pad[parent].max(first_pad[c]);
+            }
+            if spans[parent].map(|(_, l)| l) == Some(last) {
+                last_pad[parent] = last_pad[parent].max(last_pad[c]);
+            }
+        }
+    }
+    let mut ys = vec![0.0; rank_count];
+    for rank in 1..rank_count {
+        let (mut after, mut before) = (0.0f64, 0.0f64);
+        for &c in tree.clusters.iter() {
+            match spans[c] {
+                Some((_, last)) if last == rank - 1 => after = after.max(last_pad[c]),
+                _ => {}
+            }
+            match spans[c] {
+                Some((first, _)) if first == rank => before = before.max(first_pad[c]),
+                _ => {}
+            }
+        }
+        ys[rank] =
+            ys[rank - 1] - half[rank - 1] - ranksep.max(after + before + MARGIN) - half[rank];
+    }
+
+    let positions = (0..n)
+        .map(|i| direction.turn((xs[i], ys[ranks[i]])))
+        .collect();
+    let mut boxes = Vec::new();
+    for c in 0..count {
+        let Some((first, last)) = spans[c] else {
+            continue;
+        };
+        let (x0, y0) = direction.turn((xs[sides[c].0], ys[first] + half[first] + first_pad[c]));
+        let (x1, y1) = direction.turn((xs[sides[c].1], ys[last] - half[last] - last_pad[c]));
+        let (mut x0, y0, mut x1, y1) = (x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1));
+        // Sideways, labels wider than their cluster stick out on both sides.
+        let grow = (label_widths[c] + 2.0 * MARGIN - (x1 - x0)).max(0.0) / 2.0;
+        x0 -= grow;
+        x1 += grow;
+        boxes.push((c, (x0, y0, x1, y1)));
+    }
+
+    // Edges bend at the nodes they have on the ranks between their ends. Edges within a rank go
+    // over the nodes between their ends.
+    let mut routes = vec![Vec::new(); graph.edges.len()];
+    for (i, edge) in graph.edges.iter().enumerate() {
+        let chain = &chains[i];
+        if chain.len() > 2 {
+            let mut route: Vec<Point> = chain[1..chain.len() - 1]
+                .iter()
+                .map(|&v| direction.turn((xs[v], ys[vnodes[v].rank])))
+                .collect();
+            if chain[0] != edge.tail {
+                route.reverse();
+            }
+            routes[i] = route;
+        }
+    }
+    let mut position = vec![0; vnodes.len()];
+    for layer in layers.iter() {
+        for (i, &v) in layer.iter().enumerate() {
+            position[v] = i;
+        }
+    }
+    for &(i, _) in flat.iter() {
+        let (tail, head) = (graph.edges[i].tail, graph.edges[i].head);
+        if position[tail].abs_diff(position[head]) > 1 {
+            let rank = ranks[tail];
+            let x = (xs[tail] + xs[head]) / 2.0;
+            routes[i] = vec![direction.turn((x, ys[rank] + half[rank] + ranksep / 2.0))];
+        }
+    }
+    Ok((positions, boxes, routes))
+}
+
+#[test]
+fn test_layout() {
+    let graph = from_dot(
+        r#"digraph {
+    subgraph cluster_a {
+        label = "a cluster with a long label"
+        a -> b
+        subgraph cluster_b { c; d }
+        b -> c; b -> d
+    }
+    e -> a
+    e -> f -> g -> h
+    e -> d
+}"#,
+    );
+    let sizes = vec![(54.0, 36.0); graph.nodes.len()];
+    let (positions, boxes, routes) = layout(&graph, &sizes).unwrap();
+    let inside = |(x, y): Point, (x0, y0, x1, y1): Rect| {
+        x - 27.0 >= x0 && x + 27.0 <= x1 && y - 18.0 >= y0 && y + 18.0 <= y1
+    };
+    let outside = |(x, y): Point, (x0, y0, x1, y1): Rect| {
+        x + 27.0 <= x0 || x - 27.0 >= x1 || y + 18.0 <= y0 || y - 18.0 >= y1
+    };
+    // Edges point down, one rank at a time.
+    let y = |i: usize| positions[i].1;
+    assert_eq!(y(4) - y(0), 36.0 + 36.0);
+    assert_eq!(y(0) - y(1), 72.0);
+    assert_eq!(y(1) - y(2), 72.0);
+    assert_eq!(y(2), y(3));
+    assert!(y(5) > y(6) && y(6) > y(7));
+    let (a, b) = (boxes[0], boxes[1]);
+    assert_eq!(graph.subgraphs[b.0].name, "cluster_b");
+    for (i, &position) in positions.iter().enumerate() {
+        if i < 4 {
+            assert!(inside(position, a.1), "{}", i);
+        } else {
+            assert!(outside(position, a.1), "{}", i);
+        }
+    }
+    assert!(inside(positions[2], b.1) && inside(positions[3], b.1));
+    assert!(outside(positions[0], b.1) && outside(positions[1], b.1));
+    let (x0, y0, x1, y1) = b.1;
+    assert!(x0 >= a.1 .0 + MARGIN && x1 <= a.1 .2 - MARGIN && y0 >= a.1 .1 + MARGIN);
+    assert!(y1 <= a.1 .3 - MARGIN);
+    // The label fits in its cluster, above the nodes.
+    let (width, height) = text::cluster_label_size(&graph, &graph.subgraphs[0]).unwrap();
+    assert!(a.1 .2 - a.1 .0 >= width + 2.0 * MARGIN);
+    assert!(a.1 .3 - (y(0) + 18.0) >= height + MARGIN - 1e-9);
+    // Nodes on the same rank are nodesep apart.
+    assert!((positions[0].0 - positions[5].0).abs() >= 54.0 + 18.0);
+    // e -> d bends on the ranks of a and b.
+    assert_eq!(routes[7].len(), 2);
+    assert!(routes[0].is_empty());
+
+    let graph = from_dot("digraph { rankdir = LR; a -> b; a -> c }");
+    let (positions, _, _) = layout(&graph, &[(54.0, 36.0); 3]).unwrap();
+    assert_eq!(positions[1].0 - positions[0].0, 54.0 + 36.0);
+    assert_eq!(positions[1].0, positions[2].0);
+    assert!(positions[1].1 > positions[2].1);
+}
@@ -0,0 +1,1348 @@
+use crate::arrow::{self, Arrow, ArrowShape, Side};
+use crate::color::{self, Fill, Rgba};
+use crate::html;
+use crate::layout::POINTS_PER_INCH;
+use crate::layout::{self, format_number, parse_pos, rect_around, Point, Rect, Spline};
+use crate::record;
+use crate::semantic::{Attrs, Graph};
+use crate::shape::{self, Shape, PERIPHERY_GAP};
+use crate::text::{self, Font, Justification, LINE_SPACING};
+use log::warn;
+use std::fmt;
+
+#[cfg(test)]
+use crate::layout::Engine;
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// How far the baseline of a line of text is below the middle of the line, in font sizes. This
+// is where Graphviz puts it for a single line of Times.
+pub const BASELINE_DROP: f64 = 0.264;
+// Half the width of an arrow across the edge, in arrow lengths.
+const ARROW_WIDTH: f64 = 0.35;
+// Radius of the corners of rounded boxes, in points.
+const CORNER_RADIUS: f64 = 12.0;
+// Margin around the drawing, in points.
+const DEFAULT_PAD: f64 = 4.0;
+
+pub const BLACK: Rgba = Rgba {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 255,
+};
+const WHITE: Rgba = Rgba {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+const TRANSPARENT: Rgba = Rgba {
+    r: 255,
+    g: 255,
+    b: 254,
+    a: 0,
+};
+
+// Flags of the t operation, which changes the look of the text drawn after it.
+pub const BOLD: u32 = 1;
+pub const ITALIC: u32 = 2;
+pub const UNDERLINE: u32 = 4;
+pub const SUPERSCRIPT: u32 = 8;
+pub const SUBSCRIPT: u32 = 16;
+pub const STRIKE: u32 = 32;
+pub const OVERLINE: u32 = 64;
+
+// A drawing operation of xdot, see https://graphviz.org/docs/outputs/canon/#xdot. Shapes are
+// drawn with the last pen color, and filled ones are filled with the last fill color.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    Ellipse {
+        filled: bool,
+        center: Point,
+        radii: (f64, f64),
+    },
+    Polygon {
+        filled: bool,
+        points: Vec<Point>,
+    },
+    Polyline(Vec<Point>),
+    // The control points of a cubic Bezier spline.
+    Bezier {
+        filled: bool,
+        points: Vec<Point>,
+    },
+    // A line of text with its baseline at pos, which is at its left, middle or right.
+    Text {
+        pos: Point,
+        justification: Justification,
+        width: f64,
+        text: String,
+    },
+    PenColor(Rgba),
+    FillColor(Rgba),
+    Font {
+        size: f64,
+        name: String,
+    },
+    // Line styles such as dashed or setlinewidth(2).
+    Style(String),
+    // BOLD, ITALIC and the other flags of the text that follows.
+    FontChars(u32),
+}
+
+fn write_points(f: &mut fmt::Formatter, points: &[Point]) -> fmt::Result {
+    write!(f, "{}", points.len())?;
+    for &(x, y) in points {
+        write!(f, " {} {}", format_number(x, 2), format_number(y, 2))?;
+    }
+    Ok(())
+}
+
+// Strings are given with their length in bytes, as in "5 -hello".
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "{} -{}", s.len(), s)
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = |x: f64| format_number(x, 2);
+        match self {
+            Op::Ellipse {
+                filled,
+                center,
+                radii,
+            } => write!(
+                f,
+                "{} {} {} {} {}",
+                if *filled { "E" } else { "e" },
+                n(center.0),
+                n(center.1),
+                n(radii.0),
+                n(radii.1)
+            ),
+            Op::Polygon { filled, points } => {
+                write!(f, "{} ", if *filled { "P" } else { "p" })?;
+                write_points(f, points)
+            }
+            Op::Polyline(points) => {
+                write!(f, "L ")?;
+                write_points(f, points)
+            }
+            Op::Bezier { filled, points } => {
+                write!(f, "{} ", if *filled { "b" } else { "B" })?;
+                write_points(f, points)
+            }
+            Op::Text {
+                pos,
+                justification,
+                width,
+                text,
+            } => {
+                let justification = match justification {
+                    Justification::Left => -1,
+                    Justification::Center => 0,
+                    Justification::Right => 1,
+                };
+                write!(
+                    f,
+                    "T {} {} {} {} ",
+                    n(pos.0),
+                    n(pos.1),
+                    justification,
+                    n(*width)
+                )?;
+                write_string(f, text)
+            }
+            Op::PenColor(color) => {
+                write!(f, "c ")?;
+                write_string(f, &color.to_string())
+            }
+            Op::FillColor(color) => {
+                write!(f, "C ")?;
+                write_string(f, &color.to_string())
+            }
+            Op::Font { size, name } => {
+                write!(f, "F {} ", n(*size))?;
+                write_string(f, name)
+            }
+            Op::Style(style) => {
+                write!(f, "S ")?;
+                write_string(f, style)
+            }
+            Op::FontChars(flags) => write!(f, "t {}", flags),
+        }
+    }
+}
+
+// Operations as the value of an xdot attribute such as _draw_, each followed by a space as in
+// Graphviz.
+pub fn xdot(ops: &[Op]) -> String {
+    ops.iter().map(|op| format!("{} ", op)).collect()
+}
+
+#[test]
+fn test_xdot() {
+    let ops = [
+        Op::PenColor(BLACK),
+        Op::FillColor(TRANSPARENT),
+        Op::Ellipse {
+            filled: true,
+            center: (27.0, 18.0),
+            radii: (27.0, 18.0),
+        },
+        Op::Polygon {
+            filled: false,
+            points: vec![(0.0, 0.0), (1.5, 0.0), (0.0, 1.0)],
+        },
+        Op::Bezier {
+            filled: false,
+            points: vec![(0.0, 0.0), (1.0, 1.0), (2.0, 1.0), (3.0, 0.0)],
+        },
+        Op::Font {
+            size: 14.0,
+            name: "Times-Roman".to_string(),
+        },
+        Op::FontChars(BOLD),
+        Op::Text {
+            pos: (27.0, 14.3),
+            justification: Justification::Left,
+            width: 6.22,
+            text: "né".to_string(),
+        },
+        Op::Style("dashed".to_string()),
+    ];
+    assert_eq!(
+        xdot(&ops),
+        "c 7 -#000000 C 9 -#fffffe00 E 27 18 27 18 p 3 0 0 1.5 0 0 1 \
+         B 4 0 0 1 1 2 1 3 0 F 14 11 -Times-Roman t 1 T 27 14.3 -1 6.22 3 -né S 6 -dashed "
+    );
+}
+
+// The drawing operations of an object, by the xdot attribute they go in.
+pub type Ops = Vec<(&'static str, Vec<Op>)>;
+
+fn styles(attrs: &Attrs) -> impl Iterator<Item = &str> {
+    attrs
+        .get("style")
+        .unwrap_or_default()
+        .split(',')
+        .map(|style| style.trim())
+}
+
+pub fn has_style(attrs: &Attrs, name: &str) -> bool {
+    styles(attrs).any(|style| style == name)
+}
+
+// Line styles from style and penwidth. As in Graphviz, bold is a line width of 2 and
+// setlinewidth(n) in style overrides penwidth.
+fn line_styles(attrs: &Attrs) -> Vec<Op> {
+    let mut ops = Vec::new();
+    let mut width = attrs
+        .get("penwidth")
+        .and_then(|width| width.parse().ok())
+        .unwrap_or(1.0);
+    for style in styles(attrs) {
+        match style {
+            "dashed" | "dotted" => ops.push(Op::Style(style.to_string())),
+            "bold" => width = 2.0,
+            _ => {
+                if let Some(w) = style
+                    .strip_prefix("setlinewidth(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .and_then(|w| w.parse().ok())
+                {
+                    width = w;
+                }
+            }
+        }
+    }
+    if width != 1.0 {
+        ops.push(Op::Style(format!(
+            "setlinewidth({})",
+            format_number(width, 2)
+        )));
+    }
+    ops
+}
+
+// The first color of the first of the attributes that is set, or default when none is or the
+// color is bad, which has already been warned about.
+fn color_of(attrs: &Attrs, names: &[&str], default: Rgba) -> Rgba {
+    names
+        .iter()
+        .find_map(|name| attrs.get(name))
+        .and_then(|value| color::parse_color_list(value, attrs.get("colorscheme")).ok())
+        .map_or(default, |colors| colors[0].color)
+}
+
+// The color to fill with when style asks for a fill. Gradients, wedges and stripes are filled
+// with their first color.
+fn fill_color(attrs: &Attrs) -> Option<Rgba> {
+    match color::fill(attrs).ok()?? {
+        Fill::Solid(color)
+        | Fill::Linear { from: color, .. }
+        | Fill::Radial { from: color, .. } => Some(color),
+        Fill::Wedged(colors) | Fill::Striped(colors) => colors.first().map(|&(color, _)| color),
+    }
+}
+
+// The closed Bezier spline around a box with rounded corners.
+fn rounded_box((x0, y0, x1, y1): Rect) -> Vec<Point> {
+    let r = CORNER_RADIUS.min((x1 - x0) / 4.0).min((y1 - y0) / 4.0);
+    let line = |a: Point, b: Point| [a, a, b, b];
+    let mut points = vec![(x0 + r, y0)];
+    for segment in [
+        line((x0 + r, y0), (x1 - r, y0)),
+        [(x1 - r, y0), (x1, y0), (x1, y0), (x1, y0 + r)],
+        line((x1, y0 + r), (x1, y1 - r)),
+        [(x1, y1 - r), (x1, y1), (x1, y1), (x1 - r, y1)],
+        line((x1 - r, y1), (x0 + r, y1)),
+        [(x0 + r, y1), (x0, y1), (x0, y1), (x0, y1 - r)],
+        line((x0, y1 - r), (x0, y0 + r)),
+        [(x0, y0 + r), (x0, y0), (x0, y0), (x0 + r, y0)],
+    ] {
+        points.extend_from_slice(&segment[1..]);
+    }
+    points
+}
+
+fn box_points((x0, y0, x1, y1): Rect) -> Vec<Point> {
+    vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+}
+
+// The outline of a box, filled when a fill color is given.
+fn box_ops(rect: Rect, fill: Option<Rgba>, rounded: bool) -> Vec<Op> {
+    let mut ops: Vec<Op> = fill.map(Op::FillColor).into_iter().collect();
+    let filled = fill.is_some();
+    ops.push(if rounded {
+        Op::Bezier {
+            filled,
+            points: rounded_box(rect),
+        }
+    } else {
+        Op::Polygon {
+            filled,
+            points: box_points(rect),
+        }
+    });
+    ops
+}
+
+// Lines of text centered at pos in the font of the object, or in labelfontname, labelfontsize
+// and labelfontcolor with the prefix "label". Left and right justified lines are aligned with
+// the sides of the widest line.
+fn text_ops(graph: &Graph, attrs: &Attrs, prefix: &str, label: &str, pos: Point) -> Vec<Op> {
+    let (fontname, fontsize) = text::font_of(attrs, prefix);
+    let font = Font::load(fontname, graph.attrs.get("fontpath"));
+    let fontcolor = format!("{}fontcolor", prefix);
+    let lines = text::split_lines(label);
+    let widths: Vec<f64> = lines
+        .iter()
+        .map(|(line, _)| font.width(line, fontsize))
+        .collect();
+    let width = widths.iter().copied().fold(0.0, f64::max);
+    let line_height = fontsize * LINE_SPACING;
+    let top = pos.1 + lines.len() as f64 * line_height / 2.0;
+    let mut ops = vec![
+        Op::Font {
+            size: fontsize,
+            name: fontname.to_string(),
+        },
+        Op::PenColor(color_of(attrs, &[&fontcolor, "fontcolor"], BLACK)),
+    ];
+    for (i, ((line, justification), line_width)) in lines.into_iter().zip(widths).enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let x = match justification {
+            Justification::Left => pos.0 - width / 2.0,
+            Justification::Center => pos.0,
+            Justification::Right => pos.0 + width / 2.0,
+        };
+        let y = top - (i as f64 + 0.5) * line_height - BASELINE_DROP * fontsize;
+        ops.push(Op::Text {
+            pos: (x, y),
+            justification,
+            width: line_width,
+            text: line,
+        });
+    }
+    ops
+}
+
+// The label attribute attr of an object centered at pos. Text labels have their escapes
+// expanded by expand, HTML labels are drawn as they are. Bad HTML labels, which have already
+// been warned about, are not drawn.
+fn label_ops(
+    graph: &Graph,
+    attrs: &Attrs,
+    attr: &str,
+    prefix: &str,
+    expand: &dyn Fn(&str) -> String,
+    pos: Point,
+) -> Vec<Op> {
+    let label = match attrs.get(attr) {
+        Some(label) if !label.is_empty() => label,
+        _ => return Vec::new(),
+    };
+    if !attrs.is_html(attr) {
+        return text_ops(graph, attrs, prefix, &expand(label), pos);
+    }
+    let Ok(label) = html::parse_html_label(label) else {
+        return Vec::new();
+    };
+    let font = html::base_font(graph, attrs, prefix);
+    let fontcolor = format!("{}fontcolor", prefix);
+    let color = color_of(attrs, &[&fontcolor, "fontcolor"], BLACK);
+    label.draw(font, color, rect_around(pos, label.size(font)))
+}
+
+// The position stored in attr by the layout, if any.
+pub fn position(attrs: &Attrs, attr: &str) -> Result<Option<Point>, String> {
+    attrs.get(attr).map(|pos| Ok(parse_pos(pos)?.0)).transpose()
+}
+
+// Adds operations to draw the label attr at the position stored in pos_attr.
+fn push_label(
+    ops: &mut Vec<Op>,
+    graph: &Graph,
+    attrs: &Attrs,
+    (attr, pos_attr): (&str, &str),
+    prefix: &str,
+    expand: &dyn Fn(&str) -> String,
+) -> Result<(), String> {
+    if let Some(pos) = position(attrs, pos_attr)? {
+        ops.extend(label_ops(graph, attrs, attr, prefix, expand, pos));
+    }
+    Ok(())
+}
+
+pub fn bb_of(attrs: &Attrs) -> Result<Option<Rect>, String> {
+    let Some(bb) = attrs.get("bb") else {
+        return Ok(None);
+    };
+    let bb: Vec<f64> = bb
+        .split(',')
+        .map(|x| x.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("{}:{} Bad bb {}", file!(), line!(), bb))?;
+    match bb[..] {
+        [x0, y0, x1, y1] => Ok(Some((x0, y0, x1, y1))),
+        _ => Err(format!("{}:{} Bad bb {:?}", file!(), line!(), bb)),
+    }
+}
+
+// Leaves out the attributes that only set colors and styles without drawing anything.
+fn ops_of(list: [(&'static str, Vec<Op>); 6]) -> Ops {
+    let draws = |op: &Op| {
+        !matches!(
+            op,
+            Op::PenColor(_) | Op::FillColor(_) | Op::Font { .. } | Op::Style(_) | Op::FontChars(_)
+        )
+    };
+    list.into_iter()
+        .filter(|(_, ops)| ops.iter().any(draws))
+        .collect()
+}
+
+// The color of the canvas, bgcolor or white.
+pub fn background(graph: &Graph) -> Rgba {
+    color_of(&graph.attrs, &["bgcolor"], WHITE)
+}
+
+// The root graph: its background in bgcolor, white by default, and its label.
+pub fn graph_ops(graph: &Graph) -> Result<Ops, String> {
+    let attrs = &graph.attrs;
+    let mut draw = Vec::new();
+    if let Some(bb) = bb_of(attrs)? {
+        draw.push(Op::PenColor(TRANSPARENT));
+        draw.extend(box_ops(bb, Some(background(graph)), false));
+    }
+    let mut ldraw = Vec::new();
+    let expand = |label: &str| label.replace("\\G", &graph.name);
+    push_label(&mut ldraw, graph, attrs, ("label", "lp"), "", &expand)?;
+    Ok(ops_of([
+        ("_draw_", draw),
+        ("_ldraw_", ldraw),
+        ("_hdraw_", Vec::new()),
+        ("_tdraw_", Vec::new()),
+        ("_hldraw_", Vec::new()),
+        ("_tldraw_", Vec::new()),
+    ]))
+}
+
+// A cluster that has been laid out: its box outlined in pencolor or color, filled as style asks
+// or with bgcolor, and its label.
+pub fn cluster_ops(graph: &Graph, cluster: usize) -> Result<Ops, String> {
+    let cluster = &graph.subgraphs[cluster];
+    let attrs = &cluster.attrs;
+    let bb = match bb_of(attrs)? {
+        Some(bb) if cluster.is_cluster() && !has_style(attrs, "invis") => bb,
+        _ => return Ok(Vec::new()),
+    };
+    let mut draw = line_styles(attrs);
+    draw.push(Op::PenColor(color_of(attrs, &["pencolor", "color"], BLACK)));
+    let fill = fill_color(attrs).or(attrs
+        .get("bgcolor")
+        .map(|_| color_of(attrs, &["bgcolor"], WHITE)));
+    draw.extend(box_ops(bb, fill, has_style(attrs, "rounded")));
+    let mut ldraw = Vec::new();
+    let expand = |label: &str| label.replace("\\G", &cluster.name);
+    push_label(&mut ldraw, graph, attrs, ("label", "lp"), "", &expand)?;
+    Ok(ops_of([
+        ("_draw_", draw),
+        ("_ldraw_", ldraw),
+        ("_hdraw_", Vec::new()),
+        ("_tdraw_", Vec::new()),
+        ("_hldraw_", Vec::new()),
+        ("_tldraw_", Vec::new()),
+    ]))
+}
+
+// Joins lines (at, from, to) that are at the same place and touch, such as the left sides of
+// the fields of a group, which all lie on the line between the group and the field before it.
+fn merge_lines(mut lines: Vec<(f64, f64, f64)>) -> Vec<(f64, f64, f64)> {
+    lines.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    let mut merged: Vec<(f64, f64, f64)> = Vec::new();
+    for line in lines {
+        match merged.last_mut() {
+            Some(last) if (last.0 - line.0).abs() < 1e-6 && line.1 <= last.2 + 1e-6 => {
+                last.2 = last.2.max(line.2);
+            }
+            _ => merged.push(line),
+        }
+    }
+    merged
+}
+
+// The outline of a node shape of the given size, as an ellipse or a polygon. Boxes may have
+// rounded corners.
+fn shape_op(shape: &Shape, (x, y): Point, size: (f64, f64), filled: bool, rounded: bool) -> Op {
+    if shape.is_ellipse() {
+        return Op::Ellipse {
+            filled,
+            center: (x, y),
+            radii: (size.0 / 2.0, size.1 / 2.0),
+        };
+    }
+    if rounded && shape.sides == 4 {
+        return Op::Bezier {
+            filled,
+            points: rounded_box(rect_around((x, y), size)),
+        };
+    }
+    Op::Polygon {
+        filled,
+        points: shape
+            .vertices(size)
+            .into_iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .collect(),
+    }
+}
+
+// A node: its shape with every periphery, the innermost filled as style asks, and its label and
+// xlabel. Records are boxes with lines between their fields and a label in each field. Points
+// are filled with their fillcolor or color. Shapes such as note or cylinder are drawn as boxes.
+pub fn node_ops(graph: &Graph, node: usize) -> Result<Ops, String> {
+    let node = &graph.nodes[node];
+    let attrs = &node.attrs;
+    if has_style(attrs, "invis") {
+        return Ok(Vec::new());
+    }
+    let Some(center) = position(attrs, "pos")? else {
+        return Ok(Vec::new());
+    };
+    let size = (
+        layout::number(attrs, "width", 0.0)? * POINTS_PER_INCH,
+        layout::number(attrs, "height", 0.0)? * POINTS_PER_INCH,
+    );
+    let shape = shape::shape_of(node);
+    let pen = color_of(attrs, &["color"], BLACK);
+    let fill = fill_color(attrs);
+    let record = matches!(attrs.get("shape"), Some("record") | Some("Mrecord"));
+    let rounded = has_style(attrs, "rounded") || attrs.get("shape") == Some("Mrecord");
+    let expand = |label: &str| text::node_text(graph, node, label);
+    let mut draw = line_styles(attrs);
+    let mut ldraw = Vec::new();
+    draw.push(Op::PenColor(pen));
+    if shape.point {
+        draw.push(Op::FillColor(fill.unwrap_or(pen)));
+        draw.push(shape_op(&shape, center, size, true, false));
+    } else if record {
+        draw.extend(box_ops(rect_around(center, size), fill, rounded));
+        let (x, y) = center;
+        let (left, bottom) = (-size.0 / 2.0 + 1e-6, -size.1 / 2.0 + 1e-6);
+        let (mut vertical, mut horizontal) = (Vec::new(), Vec::new());
+        for field in record::field_boxes(graph, node, size) {
+            let (x0, y0, x1, y1) = field.rect;
+            if x0 > left {
+                vertical.push((x + x0, y + y0, y + y1));
+            }
+            if y0 > bottom {
+                horizontal.push((y + y0, x + x0, x + x1));
+            }
+            if !field.text.is_empty() {
+                let pos = (x + (x0 + x1) / 2.0, y + (y0 + y1) / 2.0);
+                ldraw.extend(text_ops(graph, attrs, "", &expand(&field.text), pos));
+            }
+        }
+        for (x, y0, y1) in merge_lines(vertical) {
+            draw.push(Op::Polyline(vec![(x, y0), (x, y1)]));
+        }
+        for (y, x0, x1) in merge_lines(horizontal) {
+            draw.push(Op::Polyline(vec![(x0, y), (x1, y)]));
+        }
+    } else {
+        // Without an outline, a fill is drawn in its own color.
+        if shape.peripheries == 0 {
+            if let Some(fill) = fill {
+                draw.push(Op::PenColor(fill));
+                draw.push(Op::FillColor(fill));
+                draw.push(shape_op(&shape, center, size, true, rounded));
+            }
+        }
+        draw.extend(fill.map(Op::FillColor));
+        for i in 0..shape.peripheries {
+            let inset = 2.0 * PERIPHERY_GAP * (shape.peripheries - 1 - i) as f64;
+            let size = (size.0 - inset, size.1 - inset);
+            let filled = i == 0 && fill.is_some();
+            draw.push(shape_op(&shape, center, size, filled, rounded));
+        }
+    }
+    if !record && !shape.point {
+        ldraw.extend(match attrs.get("label") {
+            Some(_) => label_ops(graph, attrs, "label", "", &expand, center),
+            None => text_ops(graph, attrs, "", &expand("\\N"), center),
+        });
+    }
+    push_label(&mut ldraw, graph, attrs, ("xlabel", "xlp"), "", &expand)?;
+    Ok(ops_of([
+        ("_draw_", draw),
+        ("_ldraw_", ldraw),
+        ("_hdraw_", Vec::new()),
+        ("_tdraw_", Vec::new()),
+        ("_hldraw_", Vec::new()),
+        ("_tldraw_", Vec::new()),
+    ]))
+}
+
+// The polygons, lines and Bezier splines of an arrow shape with its base at (0, 0) and its tip
+// at (1, 0), in arrow lengths along and across the arrow. Dots are circles between base and tip.
+type Outline = (Vec<Vec<Point>>, Vec<Vec<Point>>, Vec<Vec<Point>>);
+
+fn arrow_outline(shape: ArrowShape) -> Outline {
+    let w = ARROW_WIDTH;
+    let axis = vec![(0.0, 0.0), (1.0, 0.0)];
+    match shape {
+        ArrowShape::Normal => (vec![vec![(0.0, w), (1.0, 0.0), (0.0, -w)]], vec![], vec![]),
+        ArrowShape::Inv => (vec![vec![(0.0, 0.0), (1.0, w), (1.0, -w)]], vec![], vec![]),
+        ArrowShape::Vee => (
+            vec![vec![(1.0, 0.0), (0.0, w), (0.25, 0.0), (0.0, -w)]],
+            vec![],
+            vec![],
+        ),
+        ArrowShape::Crow => (
+            vec![vec![(0.0, 0.0), (1.0, w), (0.75, 0.0), (1.0, -w)]],
+            vec![axis],
+            vec![],
+        ),
+        ArrowShape::Diamond => (
+            vec![vec![(0.0, 0.0), (0.5, w), (1.0, 0.0), (0.5, -w)]],
+            vec![],
+            vec![],
+        ),
+        ArrowShape::Box => (
+            vec![vec![(0.0, w), (0.8, w), (0.8, -w), (0.0, -w)]],
+            vec![axis],
+            vec![],
+        ),
+        ArrowShape::Tee => {
+            let w = 1.5 * w;
+            (
+                vec![vec![(0.4, w), (0.6, w), (0.6, -w), (0.4, -w)]],
+                vec![axis],
+                vec![],
+            )
+        }
+        // Arcs bulging away from the node, or towards it.
+        ArrowShape::Curve => (
+            vec![],
+            vec![axis],
+            vec![vec![(0.8, w), (0.4, w), (0.4, -w), (0.8, -w)]],
+        ),
+        ArrowShape::ICurve => (
+            vec![],
+            vec![axis],
+            vec![vec![(0.2, w), (0.6, w), (0.6, -w), (0.2, -w)]],
+        ),
+        ArrowShape::Dot => (vec![], vec![], vec![]),
+        ArrowShape::None => (vec![], vec![axis], vec![]),
+    }
+}
+
+// An arrow from base to tip. Open arrows are outlined, others filled with the pen color, and
+// arrows with l or r keep only the half on the left or right of the edge.
+fn arrow_ops(arrow: &Arrow, base: Point, tip: Point) -> Vec<Op> {
+    let (ux, uy) = (tip.0 - base.0, tip.1 - base.1);
+    let filled = !arrow.open;
+    let place = |(along, across): Point| {
+        let across = match arrow.side {
+            Side::Both => across,
+            Side::Left => across.max(0.0),
+            Side::Right => across.min(0.0),
+        };
+        (
+            base.0 + ux * along - uy * across,
+            base.1 + uy * along + ux * across,
+        )
+    };
+    let place_all = |points: Vec<Point>| points.into_iter().map(place).collect();
+    let mut ops = Vec::new();
+    if arrow.shape == ArrowShape::Dot {
+        let radius = ux.hypot(uy) / 2.0;
+        ops.push(Op::Ellipse {
+            filled,
+            center: place((0.5, 0.0)),
+            radii: (radius, radius),
+        });
+    }
+    let (polygons, lines, curves) = arrow_outline(arrow.shape);
+    for polygon in polygons {
+        ops.push(Op::Polygon {
+            filled,
+            points: place_all(polygon),
+        });
+    }
+    ops.extend(lines.into_iter().map(|line| Op::Polyline(place_all(line))));
+    for curve in curves {
+        ops.push(Op::Bezier {
+            filled: false,
+            points: place_all(curve),
+        });
+    }
+    ops
+}
+
+// The arrows at one end of an edge, going from where the spline ends to the tip at the node,
+// the first one at the node. Arrows are drawn with solid lines.
+fn arrows_ops(arrows: &[Arrow], from: Point, tip: Point, pen: &[Op]) -> Vec<Op> {
+    if arrows.is_empty() {
+        return Vec::new();
+    }
+    let mut ops: Vec<Op> = pen
+        .iter()
+        .filter(|op| !matches!(op, Op::Style(style) if style == "dashed" || style == "dotted"))
+        .cloned()
+        .collect();
+    if let Some(Op::PenColor(color)) = pen.last() {
+        ops.push(Op::FillColor(*color));
+    }
+    let n = arrows.len() as f64;
+    let at = |k: f64| {
+        (
+            tip.0 + (from.0 - tip.0) * k / n,
+            tip.1 + (from.1 - tip.1) * k / n,
+        )
+    };
+    for (k, arrow) in arrows.iter().enumerate() {
+        ops.extend(arrow_ops(arrow, at(k as f64 + 1.0), at(k as f64)));
+    }
+    ops
+}
+
+// An edge: its spline, the arrows at its ends, and its label, xlabel, headlabel and taillabel.
+// The arrows of edges with bad arrows, which have already been warned about, are left out.
+pub fn edge_ops(graph: &Graph, edge: usize) -> Result<Ops, String> {
+    let edge = &graph.edges[edge];
+    let attrs = &edge.attrs;
+    if has_style(attrs, "invis") {
+        return Ok(Vec::new());
+    }
+    let Some(pos) = attrs.get("pos") else {
+        return Ok(Vec::new());
+    };
+    let spline = Spline::parse(pos)?;
+    let mut pen = line_styles(attrs);
+    pen.push(Op::PenColor(color_of(attrs, &["color"], BLACK)));
+    let mut draw = pen.clone();
+    draw.push(Op::Bezier {
+        filled: false,
+        points: spline.points.clone(),
+    });
+    let (mut hdraw, mut tdraw) = (Vec::new(), Vec::new());
+    if let Ok(arrows) = arrow::edge_arrows(attrs, graph.directed) {
+        if let (Some(tip), Some(&from)) = (spline.end, spline.points.last()) {
+            hdraw = arrows_ops(&arrows.head, from, tip, &pen);
+        }
+        if let (Some(tip), Some(&from)) = (spline.start, spline.points.first()) {
+            tdraw = arrows_ops(&arrows.tail, from, tip, &pen);
+        }
+    }
+    let expand = |label: &str| text::edge_text(graph, edge, label);
+    let (mut ldraw, mut hldraw, mut tldraw) = (Vec::new(), Vec::new(), Vec::new());
+    push_label(&mut ldraw, graph, attrs, ("label", "lp"), "", &expand)?;
+    push_label(&mut ldraw, graph, attrs, ("xlabel", "xlp"), "", &expand)?;
+    let head = ("headlabel", "head_lp");
+    push_label(&mut hldraw, graph, attrs, head, "label", &expand)?;
+    let tail = ("taillabel", "tail_lp");
+    push_label(&mut tldraw, graph, attrs, tail, "label", &expand)?;
+    Ok(ops_of([
+        ("_draw_", draw),
+        ("_ldraw_", ldraw),
+        ("_hdraw_", hdraw),
+        ("_tdraw_", tdraw),
+        ("_hldraw_", hldraw),
+        ("_tldraw_", tldraw),
+    ]))
+}
+
+// What a group of drawing operations belongs to, with its index in Graph::subgraphs,
+// Graph::nodes or Graph::edges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Object {
+    Graph,
+    Cluster(usize),
+    Node(usize),
+    Edge(usize),
+}
+
+// The operations of everything in a laid-out graph in the order they are drawn: the graph,
+// its clusters, nodes and edges. Objects that draw nothing are left out.
+pub fn objects(graph: &Graph) -> Result<Vec<(Object, Ops)>, String> {
+    let mut objects = vec![(Object::Graph, graph_ops(graph)?)];
+    for i in 0..graph.subgraphs.len() {
+        objects.push((Object::Cluster(i), cluster_ops(graph, i)?));
+    }
+    for i in 0..graph.nodes.len() {
+        objects.push((Object::Node(i), node_ops(graph, i)?));
+    }
+    for i in 0..graph.edges.len() {
+        objects.push((Object::Edge(i), edge_ops(graph, i)?));
+    }
+    objects.retain(|(_, ops)| !ops.is_empty());
+    Ok(objects)
+}
+
+// Adds the drawing operations of a laid-out graph as xdot attributes, with the xdotversion they
+// follow.
+pub fn set_attrs(graph: &mut Graph) -> Result<(), String> {
+    for (object, ops) in objects(graph)? {
+        let attrs = match object {
+            Object::Graph => &mut graph.attrs,
+            Object::Cluster(i) => &mut graph.subgraphs[i].attrs,
+            Object::Node(i) => &mut graph.nodes[i].attrs,
+            Object::Edge(i) => &mut graph.edges[i].attrs,
+        };
+        for (attr, ops) in ops {
+            attrs.set(attr, &xdot(&ops));
+        }
+    }
+    graph.attrs.set("xdotversion", "1.7");
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dash {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+// The colors, font and line style set by the operations drawn so far. Each xdot attribute
+// starts with the default pen: black, solid, 1 point wide and in the default font.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pen {
+    pub color: Rgba,
+    pub fill: Rgba,
+    pub font: String,
+    pub font_size: f64,
+    pub flags: u32,
+    pub dash: Dash,
+    pub width: f64,
+}
+
+impl Default for Pen {
+    fn default() -> Pen {
+        Pen {
+            color: BLACK,
+            fill: BLACK,
+            font: text::DEFAULT_FONTNAME.to_string(),
+            font_size: text::DEFAULT_FONTSIZE,
+            flags: 0,
+            dash: Dash::Solid,
+            width: 1.0,
+        }
+    }
+}
+
+impl Pen {
+    // Takes the settings of an operation that changes the pen, and returns whether it was one.
+    pub fn set(&mut self, op: &Op) -> bool {
+        match op {
+            Op::PenColor(color) => self.color = *color,
+            Op::FillColor(color) => self.fill = *color,
+            Op::Font { size, name } => {
+                self.font = name.clone();
+                self.font_size = *size;
+            }
+            Op::FontChars(flags) => self.flags = *flags,
+            Op::Style(style) => match style.as_str() {
+                "solid" => self.dash = Dash::Solid,
+                "dashed" => self.dash = Dash::Dashed,
+                "dotted" => self.dash = Dash::Dotted,
+                "bold" => self.width = 2.0,
+                _ => {
+                    if let Some(width) = style
+                        .strip_prefix("setlinewidth(")
+                        .and_then(|rest| rest.strip_suffix(')'))
+                        .and_then(|width| width.parse().ok())
+                    {
+                        self.width = width;
+                    }
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[test]
+fn test_pen() {
+    let mut pen = Pen::default();
+    assert!(pen.set(&Op::Style("setlinewidth(2.5)".to_string())));
+    assert!(pen.set(&Op::Style("dotted".to_string())));
+    assert!(pen.set(&Op::FillColor(WHITE)));
+    assert!(!pen.set(&Op::Polyline(Vec::new())));
+    assert_eq!(
+        pen,
+        Pen {
+            fill: WHITE,
+            dash: Dash::Dotted,
+            width: 2.5,
+            ..Pen::default()
+        }
+    );
+}
+
+// Where a laid-out graph goes on the page: its bounding box, scaled down to fit size or up to
+// fill it when size ends in '!', with pad points of margin around it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Canvas {
+    pub bb: Rect,
+    pub scale: f64,
+    pub pad: f64,
+}
+
+impl Canvas {
+    pub fn new(graph: &Graph) -> Result<Canvas, String> {
+        let bb = bb_of(&graph.attrs)?.unwrap_or_default();
+        let pad =
+            layout::number(&graph.attrs, "pad", DEFAULT_PAD / POINTS_PER_INCH)? * POINTS_PER_INCH;
+        let (width, height) = (bb.2 - bb.0, bb.3 - bb.1);
+        let mut scale = 1.0;
+        if let Some((max_width, max_height, fill)) = layout::sides(&graph.attrs, "size")? {
+            if width > 0.0 && height > 0.0 && max_width > 0.0 && max_height > 0.0 {
+                let fit = f64::min(max_width / width, max_height / height);
+                if fit < 1.0 || fill {
+                    scale = fit;
+                }
+            }
+        }
+        Ok(Canvas { bb, scale, pad })
+    }
+
+    // Width and height of the page in points.
+    pub fn size(&self) -> (f64, f64) {
+        (
+            (self.bb.2 - self.bb.0) * self.scale + 2.0 * self.pad,
+            (self.bb.3 - self.bb.1) * self.scale + 2.0 * self.pad,
+        )
+    }
+}
+
+#[test]
+fn test_canvas() {
+    let graph = from_dot(r#"graph { bb = "0,0,144,72"; size = "1,4" }"#);
+    let canvas = Canvas::new(&graph).unwrap();
+    assert_eq!(canvas.scale, 0.5);
+    assert_eq!(canvas.size(), (72.0 + 8.0, 36.0 + 8.0));
+    // Smaller drawings are only scaled up to fill size when it ends in !.
+    let graph = from_dot(r#"graph { bb = "0,0,144,72"; size = "4!"; pad = 0 }"#);
+    let canvas = Canvas::new(&graph).unwrap();
+    assert_eq!((canvas.scale, canvas.size()), (2.0, (288.0, 144.0)));
+    let graph = from_dot(r#"graph { bb = "0,0,144,72"; size = "4" }"#);
+    assert_eq!(Canvas::new(&graph).unwrap().scale, 1.0);
+    let graph = from_dot(r#"graph { bb = "0,0,144,72"; size = "1,2,3" }"#);
+    assert!(Canvas::new(&graph).is_err());
+}
+
+// The parts of the canvas printed on each page, in the order of pagedir. Without page the whole
+// canvas is one page. The first letter of pagedir is the side where the rows (B or T) or columns
+// (L or R) of pages start, and the second is where the pages of a row or column start.
+pub fn pages(graph: &Graph, canvas: &Canvas) -> Result<Vec<Rect>, String> {
+    let (width, height) = canvas.size();
+    let Some((page_width, page_height, _)) = layout::sides(&graph.attrs, "page")? else {
+        return Ok(vec![(0.0, 0.0, width, height)]);
+    };
+    if page_width <= 0.0 || page_height <= 0.0 {
+        return Err(format!("{}:{} Bad page size", file!(), line!()));
+    }
+    let columns = (width / page_width).ceil().max(1.0) as usize;
+    let rows = (height / page_height).ceil().max(1.0) as usize;
+    let vertical = |c: char| c == 'B' || c == 'T';
+    let pagedir: Vec<char> = graph.attrs.get("pagedir").unwrap_or("BL").chars().collect();
+    let (major, minor) = match pagedir[..] {
+        [major, minor]
+            if "BTLR".contains(major)
+                && "BTLR".contains(minor)
+                && vertical(major) != vertical(minor) =>
+        {
+            (major, minor)
+        }
+        _ => {
+            warn!(
+                "Pagedir {:?} is not supported, using BL",
+                graph.attrs.get("pagedir")
+            );
+            ('B', 'L')
+        }
+    };
+    // Pages are counted from the bottom left, and backwards from the top or right.
+    let order = |c: char| -> Vec<usize> {
+        let n = if vertical(c) { rows } else { columns };
+        match c {
+            'B' | 'L' => (0..n).collect(),
+            _ => (0..n).rev().collect(),
+        }
+    };
+    let mut pages = Vec::new();
+    for a in order(major) {
+        for b in order(minor) {
+            let (column, row) = if vertical(major) { (b, a) } else { (a, b) };
+            let (x, y) = (column as f64 * page_width, row as f64 * page_height);
+            pages.push((x, y, x + page_width, y + page_height));
+        }
+    }
+    Ok(pages)
+}
+
+#[test]
+fn test_pages() {
+    let origins = |dot: &str| -> Vec<Point> {
+        let graph = from_dot(dot);
+        let canvas = Canvas::new(&graph).unwrap();
+        let pages = pages(&graph, &canvas).unwrap();
+        pages.iter().map(|page| (page.0, page.1)).collect()
+    };
+    assert_eq!(
+        origins(r#"graph { bb = "0,0,136,136"; page = 1 }"#),
+        [(0.0, 0.0), (72.0, 0.0), (0.0, 72.0), (72.0, 72.0)]
+    );
+    assert_eq!(
+        origins(r#"graph { bb = "0,0,136,136"; page = 1; pagedir = RT }"#),
+        [(72.0, 72.0), (72.0, 0.0), (0.0, 72.0), (0.0, 0.0)]
+    );
+    assert_eq!(origins(r#"graph { bb = "0,0,136,136" }"#), [(0.0, 0.0)]);
+}
+
+// A piece of the outline of a shape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Segment {
+    Move(Point),
+    Line(Point),
+    Curve(Point, Point, Point),
+    Close,
+}
+
+// How much further than the ends of a quarter of a circle the control points of the Bezier
+// spline that draws it are, in radii.
+const KAPPA: f64 = 0.5522847498;
+
+// The outline of a shape drawing operation, and whether it is filled.
+pub fn path(op: &Op) -> Option<(Vec<Segment>, bool)> {
+    let lines = |points: &[Point], close: bool| {
+        let mut path: Vec<Segment> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| {
+                if i == 0 {
+                    Segment::Move(p)
+                } else {
+                    Segment::Line(p)
+                }
+            })
+            .collect();
+        if close {
+            path.push(Segment::Close);
+        }
+        path
+    };
+    match op {
+        Op::Ellipse {
+            filled,
+            center: (x, y),
+            radii: (rx, ry),
+        } => {
+            let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+            Some((
+                vec![
+                    Segment::Move((x + rx, *y)),
+                    Segment::Curve((x + rx, y + ky), (x + kx, y + ry), (*x, y + ry)),
+                    Segment::Curve((x - kx, y + ry), (x - rx, y + ky), (x - rx, *y)),
+                    Segment::Curve((x - rx, y - ky), (x - kx, y - ry), (*x, y - ry)),
+                    Segment::Curve((x + kx, y - ry), (x + rx, y - ky), (x + rx, *y)),
+                    Segment::Close,
+                ],
+                *filled,
+            ))
+        }
+        Op::Polygon { filled, points } => Some((lines(points, true), *filled)),
+        Op::Polyline(points) => Some((lines(points, false), false)),
+        Op::Bezier { filled, points } => {
+            let (first, rest) = points.split_first()?;
+            let mut path = vec![Segment::Move(*first)];
+            path.extend(
+                rest.chunks_exact(3)
+                    .map(|c| Segment::Curve(c[0], c[1], c[2])),
+            );
+            if *filled {
+                path.push(Segment::Close);
+            }
+            Some((path, *filled))
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test_path() {
+    let (ellipse, filled) = path(&Op::Ellipse {
+        filled: true,
+        center: (0.0, 0.0),
+        radii: (2.0, 1.0),
+    })
+    .unwrap();
+    assert!(filled);
+    assert_eq!(ellipse.len(), 6);
+    assert_eq!(
+        ellipse[2],
+        Segment::Curve((-1.1045694996, 1.0), (-2.0, 0.5522847498), (-2.0, 0.0))
+    );
+    let bezier = Op::Bezier {
+        filled: false,
+        points: vec![(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)],
+    };
+    assert_eq!(
+        path(&bezier),
+        Some((
+            vec![
+                Segment::Move((0.0, 0.0)),
+                Segment::Curve((1.0, 0.0), (2.0, 0.0), (3.0, 0.0))
+            ],
+            false
+        ))
+    );
+    assert_eq!(path(&Op::FontChars(0)), None);
+}
+
+// Where a line of text starts, given the position and width of a text operation.
+pub fn text_start((x, y): Point, justification: Justification, width: f64) -> Point {
+    match justification {
+        Justification::Left => (x, y),
+        Justification::Center => (x - width / 2.0, y),
+        Justification::Right => (x - width, y),
+    }
+}
+
+// The standard PostScript fonts every PostScript and PDF reader has, by family and then by
+// regular, bold, italic and bold italic.
+const STANDARD_FONTS: [[&str; 4]; 3] = [
+    [
+        "Times-Roman",
+        "Times-Bold",
+        "Times-Italic",
+        "Times-BoldItalic",
+    ],
+    [
+        "Helvetica",
+        "Helvetica-Bold",
+        "Helvetica-Oblique",
+        "Helvetica-BoldOblique",
+    ],
+    [
+        "Courier",
+        "Courier-Bold",
+        "Courier-Oblique",
+        "Courier-BoldOblique",
+    ],
+];
+
+// The standard font closest to a fontname, in bold or italic when the name or the flags of the
+// text ask for it.
+pub fn standard_font(name: &str, flags: u32) -> &'static str {
+    let name = name.to_lowercase();
+    let family = if name.contains("courier") || name.contains("mono") {
+        2
+    } else if name.contains("helvetica") || name.contains("arial") || name.contains("sans") {
+        1
+    } else {
+        0
+    };
+    let bold = flags & BOLD != 0 || name.contains("bold");
+    let italic = flags & ITALIC != 0 || name.contains("italic") || name.contains("oblique");
+    STANDARD_FONTS[family][bold as usize + 2 * italic as usize]
+}
+
+#[test]
+fn test_standard_font() {
+    assert_eq!(standard_font("Times-Roman", 0), "Times-Roman");
+    assert_eq!(
+        standard_font("Times-Roman", BOLD | ITALIC),
+        "Times-BoldItalic"
+    );
+    assert_eq!(standard_font("Arial Bold", 0), "Helvetica-Bold");
+    assert_eq!(standard_font("DejaVu Sans Mono", ITALIC), "Courier-Oblique");
+    assert_eq!(standard_font("Palatino", 0), "Times-Roman");
+}
+
+#[test]
+fn test_set_attrs() {
+    let graph = from_dot(
+        r#"digraph {
+    a [pos = "0,0!", style = filled]
+    b [pos = "2,0!", shape = box, label = "b\lbb\r"]
+    c [pos = "4,0!", shape = record, label = "x|y", style = invis]
+    a -> b [arrowhead = odot, arrowtail = inv, dir = both, label = ab]
+}"#,
+    );
+    let mut laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    set_attrs(&mut laid_out).unwrap();
+    assert_eq!(laid_out.attrs.get("xdotversion"), Some("1.7"));
+    assert_eq!(
+        laid_out.attrs.get("_draw_"),
+        Some("c 9 -#fffffe00 C 7 -#ffffff P 4 0 0 342 0 342 41.6 0 41.6 ")
+    );
+    let a = &laid_out.nodes[0].attrs;
+    assert_eq!(
+        a.get("_draw_"),
+        Some("c 7 -#000000 C 7 -#d3d3d3 E 27 20.8 27 18 ")
+    );
+    assert_eq!(
+        a.get("_ldraw_"),
+        Some("F 14 11 -Times-Roman c 7 -#000000 T 27 17.1 0 6.22 1 -a ")
+    );
+    // Left and right justified lines start at the sides of the widest line.
+    let b = laid_out.nodes[1].attrs.get("_ldraw_").unwrap();
+    assert!(b.contains(" T 164 25.5 -1 7 1 -b T 178 8.7 1 14 2 -bb "));
+    assert_eq!(laid_out.nodes[2].attrs.get("_draw_"), None);
+
+    let edge = &laid_out.edges[0].attrs;
+    assert_eq!(
+        edge.get("_draw_"),
+        Some("c 7 -#000000 B 4 64 20.8 87.33 20.8 110.67 20.8 134 20.8 ")
+    );
+    assert_eq!(
+        edge.get("_hdraw_"),
+        Some("c 7 -#000000 C 7 -#000000 e 139 20.8 5 5 ")
+    );
+    assert_eq!(
+        edge.get("_tdraw_"),
+        Some("c 7 -#000000 C 7 -#000000 P 3 64 20.8 54 17.3 54 24.3 ")
+    );
+    assert!(edge.get("_ldraw_").unwrap().ends_with(" 0 13.22 2 -ab "));
+    assert_eq!(edge.get("_hldraw_"), None);
+}
+
+#[test]
+fn test_cluster_ops() {
+    let graph = from_dot(
+        r#"graph {
+    subgraph cluster_x { label = X; style = filled; fillcolor = yellow; a }
+    subgraph cluster_y { bgcolor = blue; pencolor = red; b }
+}"#,
+    );
+    let laid_out = layout::apply(&graph, Engine::Osage).unwrap();
+    let x = cluster_ops(&laid_out, 0).unwrap();
+    assert_eq!(x[0].0, "_draw_");
+    assert!(xdot(&x[0].1).starts_with("c 7 -#000000 C 7 -#ffff00 P 4 "));
+    assert!(xdot(&x[1].1).ends_with(" 0 10.11 1 -X "));
+    let y = cluster_ops(&laid_out, 1).unwrap();
+    assert!(xdot(&y[0].1).starts_with("c 7 -#ff0000 C 7 -#0000ff P 4 "));
+    assert_eq!(y.len(), 1);
+}
+
+#[test]
+fn test_record_ops() {
+    let graph = from_dot(
+        r#"graph {
+    a [shape = record, label = "x|{y|z}", pos = "100,100", width = 1, height = 0.5]
+}"#,
+    );
+    let ops = node_ops(&graph, 0).unwrap();
+    let lines: Vec<&Op> = ops[0]
+        .1
+        .iter()
+        .filter(|op| matches!(op, Op::Polyline(_)))
+        .collect();
+    // One line between x and the group, one between y and z.
+    assert_eq!(
+        lines,
+        vec![
+            &Op::Polyline(vec![(100.0, 82.0), (100.0, 118.0)]),
+            &Op::Polyline(vec![(100.0, 100.0), (136.0, 100.0)]),
+        ]
+    );
+    assert_eq!(ops[1].0, "_ldraw_");
+    let texts = ops[1]
+        .1
+        .iter()
+        .filter(|op| matches!(op, Op::Text { .. }))
+        .count();
+    assert_eq!(texts, 3);
+}
+
+#[test]
+fn test_arrow_ops() {
+    let normal = Arrow {
+        shape: ArrowShape::Normal,
+        open: false,
+        side: Side::Both,
+    };
+    assert_eq!(
+        arrow_ops(&normal, (0.0, 0.0), (10.0, 0.0)),
+        vec![Op::Polygon {
+            filled: true,
+            points: vec![(0.0, 3.5), (10.0, 0.0), (0.0, -3.5)]
+        }]
+    );
+    let left = Arrow {
+        side: Side::Left,
+        open: true,
+        ..normal
+    };
+    assert_eq!(
+        arrow_ops(&left, (0.0, 0.0), (0.0, 10.0)),
+        vec![Op::Polygon {
+            filled: false,
+            points: vec![(-3.5, 0.0), (0.0, 10.0), (0.0, 0.0)]
+        }]
+    );
+    // Two arrows share the room between the end of the spline and the tip.
+    let ops = arrows_ops(
+        &[normal, normal],
+        (0.0, 0.0),
+        (20.0, 0.0),
+        &[Op::PenColor(BLACK)],
+    );
+    assert_eq!(ops[1], Op::FillColor(BLACK));
+    assert_eq!(
+        ops[2],
+        Op::Polygon {
+            filled: true,
+            points: vec![(10.0, 3.5), (20.0, 0.0), (10.0, -3.5)]
+        }
+    );
+}
@@ -0,0 +1,122 @@
+use crate::layout::{self, number, Point, POINTS_PER_INCH};
+use crate::semantic::Graph;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz defaults. Lengths are in inches while the layout runs.
+const DEFAULT_K: f64 = 0.3;
+const DEFAULT_MAXITER: usize = 600;
+
+// K, the ideal edge length, shared with sfdp.
+pub fn spring_constant(graph: &Graph) -> Result<f64, String> {
+    let k = number(&graph.attrs, "K", DEFAULT_K)?;
+    if k <= 0.0 {
+        return Err(format!("{}:{} K must be positive: {}", file!(), line!(), k));
+    }
+    Ok(k)
+}
+
+// The edges as (tail, head, weight) without loops, which exert no force.
+pub fn springs(graph: &Graph) -> Result<Vec<(usize, usize, f64)>, String> {
+    let mut springs = Vec::new();
+    for edge in graph.edges.iter().filter(|edge| edge.tail != edge.head) {
+        let weight = number(&edge.attrs, "weight", 1.0)?;
+        if weight < 0.0 {
+            return Err(format!(
+                "{}:{} Negative weight {}",
+                file!(),
+                line!(),
+                weight
+            ));
+        }
+        springs.push((edge.tail, edge.head, weight));
+    }
+    Ok(springs)
+}
+
+// Fruchterman-Reingold: every pair of nodes repels with K^2/d and every edge pulls its ends
+// together with weight * d^2/K. How far a node moves is limited by a temperature that cools down
+// linearly over maxiter iterations.
+pub fn layout(graph: &Graph) -> Result<Vec<Point>, String> {
+    let n = graph.nodes.len();
+    let k = spring_constant(graph)?;
+    let maxiter = match graph.attrs.get("maxiter") {
+        Some(maxiter) => maxiter
+            .parse()
+            .map_err(|_| format!("{}:{} Bad maxiter {}", file!(), line!(), maxiter))?,
+        None => DEFAULT_MAXITER,
+    };
+    let springs = springs(graph)?;
+    let side = (n as f64).sqrt() * k;
+    let (mut positions, pinned) = layout::initial_positions(graph, side)?;
+    let initial_temperature = side / 5.0;
+    for iteration in 0..maxiter {
+        let temperature = initial_temperature * (maxiter - iteration) as f64 / maxiter as f64;
+        let mut moves = vec![(0.0, 0.0); n];
+        for i in 0..n {
+            for j in i + 1..n {
+                let (dx, dy) = (
+                    positions[i].0 - positions[j].0,
+                    positions[i].1 - positions[j].1,
+                );
+                let d = dx.hypot(dy);
+                if d == 0.0 {
+                    continue;
+                }
+                let force = k * k / d;
+                moves[i].0 += dx / d * force;
+                moves[i].1 += dy / d * force;
+                moves[j].0 -= dx / d * force;
+                moves[j].1 -= dy / d * force;
+            }
+        }
+        for &(tail, head, weight) in springs.iter() {
+            let (dx, dy) = (
+                positions[head].0 - positions[tail].0,
+                positions[head].1 - positions[tail].1,
+            );
+            let d = dx.hypot(dy);
+            let force = weight * d / k;
+            moves[tail].0 += dx * force;
+            moves[tail].1 += dy * force;
+            moves[head].0 -= dx * force;
+            moves[head].1 -= dy * force;
+        }
+        for i in (0..n).filter(|&i| !pinned[i]) {
+            let length = moves[i].0.hypot(moves[i].1);
+            if length > 0.0 {
+                let step = length.min(temperature) / length;
+                positions[i].0 += moves[i].0 * step;
+                positions[i].1 += moves[i].1 * step;
+            }
+        }
+    }
+    Ok(positions
+        .into_iter()
+        .map(|(x, y)| (x * POINTS_PER_INCH, y * POINTS_PER_INCH))
+        .collect())
+}
+
+#[test]
+fn test_layout() {
+    let distance = |positions: &[Point], i: usize, j: usize| {
+        let (a, b): (Point, Point) = (positions[i], positions[j]);
+        (a.0 - b.0).hypot(a.1 - b.1) / POINTS_PER_INCH
+    };
+    let graph = from_dot("graph { a -- b -- c -- d -- a; e }");
+    let positions = layout(&graph).unwrap();
+    // Neighbours end up closer than the corners of the square, and e is pushed away.
+    assert!(distance(&positions, 0, 1) < distance(&positions, 0, 2));
+    assert!(distance(&positions, 0, 1) < distance(&positions, 0, 4));
+    assert_eq!(positions, layout(&graph).unwrap());
+
+    let wide = layout(&from_dot(r#"graph { K = 1; a -- b -- c -- d -- a; e }"#)).unwrap();
+    assert!(distance(&wide, 0, 1) > 2.0 * distance(&positions, 0, 1));
+
+    let graph = from_dot(r#"graph { a [pos = "1,2!"]; b; a -- b }"#);
+    assert_eq!(layout(&graph).unwrap()[0], (72.0, 144.0));
+
+    assert!(layout(&from_dot(r#"graph { K = 0; a }"#)).is_err());
+    assert!(layout(&from_dot(r#"graph { a -- b [weight = -1] }"#)).is_err());
+}
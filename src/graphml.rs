@@ -0,0 +1,252 @@
+use crate::ast::{self, AttrStmt, AttrStmtKind, Stmt, ID};
+use log::warn;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+
+#[cfg(test)]
+use crate::semantic;
+
+// A <key> declaration. Data elements refer to keys by id and get their attribute name from
+// attr.name, falling back to the id when it is missing.
+struct Key {
+    domain: String,
+    name: String,
+    default: Option<String>,
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    tag_name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == tag_name)
+}
+
+fn attrs_of(node: Node, keys: &HashMap<String, Key>) -> Vec<(String, ID)> {
+    let mut attrs = Vec::new();
+    for data in children(node, "data") {
+        let key = data.attribute("key").unwrap_or_default();
+        let name = match keys.get(key) {
+            Some(key) => key.name.clone(),
+            None => key.to_string(),
+        };
+        attrs.push((name, ID::from_value(data.text().unwrap_or_default())));
+    }
+    attrs
+}
+
+// Converts the children of a <graph> element. A node containing a nested <graph> becomes a
+// subgraph named after the node, holding the node's data as subgraph attributes.
+fn graph_stmts(
+    graph: Node,
+    keys: &HashMap<String, Key>,
+    is_digraph: bool,
+) -> Result<Vec<Stmt>, String> {
+    let mut stmts = Vec::new();
+    for (name, value) in attrs_of(graph, keys) {
+        stmts.push(Stmt::id_eq(&name, value));
+    }
+    for child in graph.children().filter(|child| child.is_element()) {
+        match child.tag_name().name() {
+            "node" => {
+                let id = match child.attribute("id") {
+                    Some(id) => id,
+                    None => return Err(format!("{}:{} Node has no id", file!(), line!())),
+                };
+                match children(child, "graph").next() {
+                    Some(nested) => {
+                        let mut sub_stmts = Vec::new();
+                        for (name, value) in attrs_of(child, keys) {
+                            sub_stmts.push(Stmt::id_eq(&name, value));
+                        }
+                        sub_stmts.extend(graph_stmts(nested, keys, is_digraph)?);
+                        stmts.push(Stmt::subgraph(Some(id), sub_stmts));
+                    }
+                    None => stmts.push(Stmt::node(id, &attrs_of(child, keys))),
+                }
+            }
+            "edge" => {
+                let (source, target) = match (child.attribute("source"), child.attribute("target"))
+                {
+                    (Some(source), Some(target)) => (source, target),
+                    _ => {
+                        return Err(format!(
+                            "{}:{} Edge needs a source and a target",
+                            file!(),
+                            line!()
+                        ))
+                    }
+                };
+                // An edge can differ from edgedefault. DOT has one kind of edge per graph, so
+                // the difference is kept as the edge's dir, which data for dir overrides.
+                let mut attrs = Vec::new();
+                match (child.attribute("directed"), is_digraph) {
+                    (Some("false"), true) => {
+                        attrs.push(("dir".to_string(), ID::from_value("none")))
+                    }
+                    (Some("true"), false) => {
+                        attrs.push(("dir".to_string(), ID::from_value("forward")))
+                    }
+                    (Some("true" | "false") | None, _) => {}
+                    (Some(directed), _) => {
+                        warn!(
+                            "Ignoring directed={} on edge {} -> {}",
+                            directed, source, target
+                        )
+                    }
+                }
+                attrs.extend(attrs_of(child, keys));
+                stmts.push(Stmt::edge(source, target, is_digraph, &attrs));
+            }
+            "data" => {}
+            tag_name => warn!("Ignoring unsupported GraphML element <{}>", tag_name),
+        }
+    }
+    Ok(stmts)
+}
+
+// Reads the first <graph> of a GraphML document into a syntax tree. Key defaults become
+// `graph`, `node` and `edge` attribute statements at the top of the graph.
+pub fn parse(graphml_str: &str) -> Result<ast::Graph, String> {
+    let document =
+        Document::parse(graphml_str).map_err(|e| format!("{}:{} {}", file!(), line!(), e))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "graphml" {
+        return Err(format!("{}:{} Expected <graphml>", file!(), line!()));
+    }
+
+    let mut keys = HashMap::new();
+    for key in children(root, "key") {
+        let id = match key.attribute("id") {
+            Some(id) => id,
+            None => return Err(format!("{}:{} Key has no id", file!(), line!())),
+        };
+        keys.insert(
+            id.to_string(),
+            Key {
+                domain: key.attribute("for").unwrap_or("all").to_string(),
+                name: key.attribute("attr.name").unwrap_or(id).to_string(),
+                default: children(key, "default")
+                    .next()
+                    .map(|default| default.text().unwrap_or_default().to_string()),
+            },
+        );
+    }
+
+    let graph = match children(root, "graph").next() {
+        Some(graph) => graph,
+        None => return Err(format!("{}:{} Expected <graph>", file!(), line!())),
+    };
+    // edgedefault is required by the GraphML schema; treat a missing one as directed.
+    let is_digraph = graph.attribute("edgedefault") != Some("undirected");
+
+    let mut stmts = Vec::new();
+    for (kind, domain) in [
+        (AttrStmtKind::Graph, "graph"),
+        (AttrStmtKind::Node, "node"),
+        (AttrStmtKind::Edge, "edge"),
+    ] {
+        let mut defaults: Vec<(String, String)> = keys
+            .values()
+            .filter(|key| key.domain == domain || key.domain == "all")
+            .filter_map(|key| Some((key.name.clone(), key.default.clone()?)))
+            .collect();
+        defaults.sort();
+        let defaults: Vec<(String, ID)> = defaults
+            .into_iter()
+            .map(|(name, value)| (name, ID::from_value(&value)))
+            .collect();
+        if !defaults.is_empty() {
+            stmts.push(Stmt::AttrStmt(AttrStmt::new(kind, &defaults)));
+        }
+    }
+    stmts.extend(graph_stmts(graph, &keys, is_digraph)?);
+
+    Ok(ast::Graph::new(
+        false,
+        is_digraph,
+        graph.attribute("id"),
+        stmts,
+    ))
+}
+
+#[test]
+fn test_parse() {
+    let graphml_str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="d0" for="node" attr.name="color" attr.type="string">
+    <default>yellow</default>
+  </key>
+  <key id="d1" for="edge" attr.name="weight" attr.type="double"/>
+  <key id="d2" for="graph" attr.name="label" attr.type="string"/>
+  <graph id="G" edgedefault="undirected">
+    <data key="d2">My graph</data>
+    <node id="n0">
+      <data key="d0">green</data>
+    </node>
+    <node id="n1"/>
+    <node id="cluster_g">
+      <data key="d2">Group</data>
+      <graph id="cluster_g:" edgedefault="undirected">
+        <node id="n2"/>
+        <edge source="n1" target="n2"/>
+      </graph>
+    </node>
+    <edge id="e0" source="n0" target="n1">
+      <data key="d1">1.5</data>
+    </edge>
+  </graph>
+</graphml>"#;
+    let graph = parse(graphml_str).unwrap();
+    assert_eq!(
+        graph.to_string(),
+        r#"graph G {
+    node [color=yellow];
+    label="My graph";
+    n0 [color=green];
+    n1;
+    subgraph cluster_g {
+        label=Group;
+        n2;
+        n1 -- n2;
+    }
+    n0 -- n1 [weight=1.5];
+}
+"#
+    );
+
+    let semantic_graph = semantic::Graph::from_ast(&graph);
+    assert_eq!(semantic_graph.nodes.len(), 3);
+    assert_eq!(semantic_graph.edges.len(), 2);
+    assert_eq!(semantic_graph.subgraphs[0].nodes, vec![2, 1]);
+
+    assert!(parse("<graph/>").is_err());
+    assert!(parse("<graphml><graph><edge source=\"a\"/></graph></graphml>").is_err());
+    assert!(parse("<graphml").is_err());
+
+    let graph = parse(
+        r#"<graphml>
+  <graph edgedefault="directed">
+    <edge source="a" target="b" directed="false"/>
+    <edge source="a" target="c" directed="true"/>
+  </graph>
+  <graph edgedefault="undirected"/>
+</graphml>"#,
+    )
+    .unwrap();
+    assert_eq!(
+        graph.to_string(),
+        "digraph {\n    a -> b [dir=none];\n    a -> c;\n}\n"
+    );
+    let graph = parse(
+        r#"<graphml><graph edgedefault="undirected"><edge source="a" target="b" directed="true"/></graph></graphml>"#,
+    )
+    .unwrap();
+    assert_eq!(graph.to_string(), "graph {\n    a -- b [dir=forward];\n}\n");
+    assert_eq!(
+        parse(r#"<graphml><graph edgedefault="directed"/></graphml>"#)
+            .unwrap()
+            .to_string(),
+        "digraph {\n}\n"
+    );
+}
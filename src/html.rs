@@ -0,0 +1,1151 @@
+use crate::color::{self, Rgba};
+use crate::draw::{self, Op};
+use crate::layout::Rect;
+use crate::record;
+use crate::semantic::{Attrs, Graph};
+use crate::text::{self, Font, Justification, LINE_SPACING};
+use roxmltree::{Document, Node};
+
+#[cfg(test)]
+use crate::layout::Point;
+#[cfg(test)]
+use crate::semantic::from_dot;
+#[cfg(test)]
+use crate::{ast, tokenize::tokenize};
+
+// Font settings inherited from enclosing <FONT>, <B>, <I>, ... elements.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextStyle {
+    pub face: Option<String>,
+    pub color: Option<String>,
+    pub point_size: Option<String>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub overline: bool,
+    pub subscript: bool,
+    pub superscript: bool,
+    pub strike: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TextItem {
+    Str { text: String, style: TextStyle },
+    Br { align: Option<String> },
+}
+
+// An HTML-like label is either formatted text or a single table.
+#[derive(Debug, PartialEq)]
+pub enum HtmlLabel {
+    Text(Vec<TextItem>),
+    Table(Table),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Table {
+    pub attrs: Vec<(String, String)>,
+    pub style: TextStyle,
+    pub rows: Vec<Row>,
+}
+
+// rule_above is set by an <HR/> between this row and the previous one.
+#[derive(Debug, PartialEq)]
+pub struct Row {
+    pub rule_above: bool,
+    pub cells: Vec<Cell>,
+}
+
+// rule_before is set by a <VR/> between this cell and the previous one.
+#[derive(Debug, PartialEq)]
+pub struct Cell {
+    pub rule_before: bool,
+    pub attrs: Vec<(String, String)>,
+    pub content: CellContent,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CellContent {
+    Label(HtmlLabel),
+    Image { src: String, scale: Option<String> },
+}
+
+const TABLE_ATTRS: [&str; 22] = [
+    "align",
+    "bgcolor",
+    "border",
+    "cellborder",
+    "cellpadding",
+    "cellspacing",
+    "color",
+    "columns",
+    "fixedsize",
+    "gradientangle",
+    "height",
+    "href",
+    "id",
+    "port",
+    "rows",
+    "sides",
+    "style",
+    "target",
+    "title",
+    "tooltip",
+    "valign",
+    "width",
+];
+const TD_ATTRS: [&str; 3] = ["colspan", "rowspan", "balign"];
+const FONT_ATTRS: [&str; 3] = ["color", "face", "point-size"];
+const BR_ATTRS: [&str; 1] = ["align"];
+const IMG_ATTRS: [&str; 2] = ["scale", "src"];
+const NO_ATTRS: [&str; 0] = [];
+
+// Attributes that must be non-negative integers, with their upper bounds.
+const INTEGER_ATTRS: [(&str, u32); 6] = [
+    ("border", 127),
+    ("cellborder", 127),
+    ("cellpadding", 255),
+    ("cellspacing", 127),
+    ("colspan", 65535),
+    ("rowspan", 65535),
+];
+
+// Graphviz defaults for tables, in points.
+const DEFAULT_BORDER: f64 = 1.0;
+const DEFAULT_CELLPADDING: f64 = 2.0;
+const DEFAULT_CELLSPACING: f64 = 2.0;
+
+// Named entities from HTML that XML does not know. Graphviz accepts the whole HTML 4 list; these
+// are the ones that show up in practice.
+const ENTITIES: [(&str, u32); 24] = [
+    ("nbsp", 160),
+    ("copy", 169),
+    ("reg", 174),
+    ("deg", 176),
+    ("plusmn", 177),
+    ("micro", 181),
+    ("para", 182),
+    ("middot", 183),
+    ("times", 215),
+    ("divide", 247),
+    ("alpha", 945),
+    ("beta", 946),
+    ("gamma", 947),
+    ("delta", 948),
+    ("lambda", 955),
+    ("pi", 960),
+    ("ndash", 8211),
+    ("mdash", 8212),
+    ("bull", 8226),
+    ("hellip", 8230),
+    ("larr", 8592),
+    ("rarr", 8594),
+    ("harr", 8596),
+    ("infin", 8734),
+];
+
+// Rewrites HTML entities into numeric character references and a lone '&' into "&amp;", as
+// Graphviz does before handing the label to an XML parser.
+fn escape_entities(text: &str) -> String {
+    let mut escaped = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        escaped.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        let name = rest.split(';').next().unwrap_or_default();
+        let is_entity = rest.contains(';')
+            && !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '#');
+        match ENTITIES.iter().find(|(entity, _)| *entity == name) {
+            Some((_, code)) if is_entity => {
+                escaped.push_str(&format!("&#{};", code));
+                rest = &rest[name.len() + 1..];
+            }
+            _ if is_entity => escaped.push('&'),
+            _ => escaped.push_str("&amp;"),
+        }
+    }
+    escaped.push_str(rest);
+    escaped
+}
+
+fn tag_name(node: Node) -> String {
+    node.tag_name().name().to_lowercase()
+}
+
+fn is_blank(node: Node) -> bool {
+    node.is_text() && node.text().unwrap_or_default().trim().is_empty()
+}
+
+fn attrs_of(node: Node, allowed: &[&str]) -> Result<Vec<(String, String)>, String> {
+    let mut attrs = Vec::new();
+    for attr in node.attributes() {
+        let name = attr.name().to_lowercase();
+        if !allowed.contains(&name.as_str()) {
+            return Err(format!(
+                "{}:{} Illegal attribute {} in <{}>",
+                file!(),
+                line!(),
+                attr.name(),
+                node.tag_name().name()
+            ));
+        }
+        if let Some((_, max)) = INTEGER_ATTRS.iter().find(|(attr, _)| *attr == name) {
+            let min = if name == "colspan" || name == "rowspan" {
+                1
+            } else {
+                0
+            };
+            match attr.value().parse::<u32>() {
+                Ok(n) if min <= n && n <= *max => {}
+                _ => {
+                    return Err(format!(
+                        "{}:{} Bad value {} for {}",
+                        file!(),
+                        line!(),
+                        attr.value(),
+                        attr.name()
+                    ))
+                }
+            }
+        }
+        attrs.push((name, attr.value().to_string()));
+    }
+    Ok(attrs)
+}
+
+// Applies <FONT>, <B>, <I>, ... to the inherited style. Returns None for other elements.
+fn styled(node: Node, style: &TextStyle) -> Result<Option<TextStyle>, String> {
+    let mut style = style.clone();
+    match tag_name(node).as_str() {
+        "font" => {
+            for (name, value) in attrs_of(node, &FONT_ATTRS)? {
+                match name.as_str() {
+                    "color" => style.color = Some(value),
+                    "face" => style.face = Some(value),
+                    _ => style.point_size = Some(value),
+                }
+            }
+            return Ok(Some(style));
+        }
+        "b" => style.bold = true,
+        "i" => style.italic = true,
+        "u" => style.underline = true,
+        "o" => style.overline = true,
+        "sub" => style.subscript = true,
+        "sup" => style.superscript = true,
+        "s" => style.strike = true,
+        _ => return Ok(None),
+    }
+    attrs_of(node, &NO_ATTRS)?;
+    Ok(Some(style))
+}
+
+// Returns the table when the only content of the node is a table, possibly wrapped in font
+// elements.
+fn find_table(node: Node, style: &TextStyle) -> Result<Option<Table>, String> {
+    let mut children = node.children().filter(|child| !is_blank(*child));
+    let child = match (children.next(), children.next()) {
+        (Some(child), None) if child.is_element() => child,
+        _ => return Ok(None),
+    };
+    if tag_name(child) == "table" {
+        return Ok(Some(parse_table(child, style)?));
+    }
+    match styled(child, style)? {
+        Some(style) => find_table(child, &style),
+        None => Ok(None),
+    }
+}
+
+fn parse_text(node: Node, style: &TextStyle, items: &mut Vec<TextItem>) -> Result<(), String> {
+    for child in node.children() {
+        if child.is_text() {
+            items.push(TextItem::Str {
+                text: child.text().unwrap_or_default().to_string(),
+                style: style.clone(),
+            });
+            continue;
+        }
+        if !child.is_element() {
+            continue;
+        }
+        if tag_name(child) == "br" {
+            let attrs = attrs_of(child, &BR_ATTRS)?;
+            items.push(TextItem::Br {
+                align: attrs.into_iter().next().map(|(_, value)| value),
+            });
+            continue;
+        }
+        match styled(child, style)? {
+            Some(style) => parse_text(child, &style, items)?,
+            None => {
+                return Err(format!(
+                    "{}:{} Unexpected <{}> in text",
+                    file!(),
+                    line!(),
+                    child.tag_name().name()
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_label(node: Node, style: &TextStyle) -> Result<HtmlLabel, String> {
+    if let Some(table) = find_table(node, style)? {
+        return Ok(HtmlLabel::Table(table));
+    }
+    let mut items = Vec::new();
+    parse_text(node, style, &mut items)?;
+    Ok(HtmlLabel::Text(items))
+}
+
+// Calls f on each child element in order with whether a rule element such as <HR/> came right
+// before it. Rules are only allowed between two elements.
+fn for_each_ruled<'a, 'input>(
+    node: Node<'a, 'input>,
+    element: &str,
+    rule: &str,
+    mut f: impl FnMut(Node<'a, 'input>, bool) -> Result<(), String>,
+) -> Result<usize, String> {
+    let mut count = 0;
+    let mut ruled = false;
+    for child in node.children().filter(|child| !is_blank(*child)) {
+        let name = if child.is_element() {
+            tag_name(child)
+        } else if child.is_text() {
+            "text".to_string()
+        } else {
+            continue;
+        };
+        if name == rule && count > 0 && !ruled {
+            attrs_of(child, &NO_ATTRS)?;
+            ruled = true;
+        } else if name == element {
+            f(child, ruled)?;
+            count += 1;
+            ruled = false;
+        } else {
+            return Err(format!(
+                "{}:{} Unexpected {} in <{}>",
+                file!(),
+                line!(),
+                name,
+                node.tag_name().name()
+            ));
+        }
+    }
+    if count == 0 || ruled {
+        return Err(format!(
+            "{}:{} <{}> needs at least one <{}> and no trailing <{}/>",
+            file!(),
+            line!(),
+            node.tag_name().name(),
+            element,
+            rule
+        ));
+    }
+    Ok(count)
+}
+
+fn parse_table(node: Node, style: &TextStyle) -> Result<Table, String> {
+    let attrs = attrs_of(node, &TABLE_ATTRS)?;
+    let mut rows = Vec::new();
+    for_each_ruled(node, "tr", "hr", |tr, rule_above| {
+        attrs_of(tr, &NO_ATTRS)?;
+        let mut cells = Vec::new();
+        for_each_ruled(tr, "td", "vr", |td, rule_before| {
+            cells.push(parse_cell(td, style, rule_before)?);
+            Ok(())
+        })?;
+        rows.push(Row { rule_above, cells });
+        Ok(())
+    })?;
+    Ok(Table {
+        attrs,
+        style: style.clone(),
+        rows,
+    })
+}
+
+fn parse_cell(node: Node, style: &TextStyle, rule_before: bool) -> Result<Cell, String> {
+    let allowed: Vec<&str> = TABLE_ATTRS
+        .iter()
+        .filter(|attr| !["cellborder", "columns", "rows"].contains(attr))
+        .chain(TD_ATTRS.iter())
+        .copied()
+        .collect();
+    let attrs = attrs_of(node, &allowed)?;
+    let mut children = node.children().filter(|child| !is_blank(*child));
+    let content = match (children.next(), children.next()) {
+        (Some(img), None) if img.is_element() && tag_name(img) == "img" => {
+            let mut src = None;
+            let mut scale = None;
+            for (name, value) in attrs_of(img, &IMG_ATTRS)? {
+                match name.as_str() {
+                    "src" => src = Some(value),
+                    _ => scale = Some(value),
+                }
+            }
+            match src {
+                Some(src) => CellContent::Image { src, scale },
+                None => return Err(format!("{}:{} <IMG> needs SRC", file!(), line!())),
+            }
+        }
+        _ => CellContent::Label(parse_label(node, style)?),
+    };
+    Ok(Cell {
+        rule_before,
+        attrs,
+        content,
+    })
+}
+
+// Parses the value of an HTML string ID, angle brackets included. Element and attribute names
+// are case insensitive and stored in lower case.
+pub fn parse_html_label(label: &str) -> Result<HtmlLabel, String> {
+    let markup = match label.strip_prefix('<').and_then(|l| l.strip_suffix('>')) {
+        Some(markup) => markup,
+        None => return Err(format!("{}:{} Not an HTML string", file!(), line!())),
+    };
+    let xml = format!("<html>{}</html>", escape_entities(markup));
+    let document = Document::parse(&xml).map_err(|e| format!("{}:{} {}", file!(), line!(), e))?;
+    parse_label(document.root_element(), &TextStyle::default())
+}
+
+#[test]
+fn test_parse_html_label() {
+    let bold = TextStyle {
+        bold: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_html_label("<a<B>b &amp; &nbsp;</B><br align=\"left\"/>>").unwrap(),
+        HtmlLabel::Text(vec![
+            TextItem::Str {
+                text: "a".to_string(),
+                style: TextStyle::default(),
+            },
+            TextItem::Str {
+                text: "b & \u{a0}".to_string(),
+                style: bold.clone(),
+            },
+            TextItem::Br {
+                align: Some("left".to_string()),
+            },
+        ])
+    );
+
+    let label = parse_html_label(
+        r#"<<b><TABLE BORDER="0" CELLPADDING="4">
+  <TR><TD COLSPAN="2" PORT="top" BGCOLOR="yellow">a</TD></TR>
+  <HR/>
+  <TR><TD><IMG SRC="x.png"/></TD><VR/><TD><FONT COLOR="red">c</FONT></TD></TR>
+</TABLE></b>>"#,
+    )
+    .unwrap();
+    let table = match label {
+        HtmlLabel::Table(table) => table,
+        _ => panic!("expected a table"),
+    };
+    assert_eq!(table.style, bold);
+    assert_eq!(
+        table.attrs,
+        vec![
+            ("border".to_string(), "0".to_string()),
+            ("cellpadding".to_string(), "4".to_string()),
+        ]
+    );
+    assert_eq!(table.rows.len(), 2);
+    assert!(!table.rows[0].rule_above);
+    assert!(table.rows[1].rule_above);
+    assert_eq!(table.rows[0].cells[0].attrs[1].1, "top");
+    assert_eq!(
+        table.rows[1].cells[0].content,
+        CellContent::Image {
+            src: "x.png".to_string(),
+            scale: None,
+        }
+    );
+    assert!(table.rows[1].cells[1].rule_before);
+    assert_eq!(
+        table.rows[1].cells[1].content,
+        CellContent::Label(HtmlLabel::Text(vec![TextItem::Str {
+            text: "c".to_string(),
+            style: TextStyle {
+                color: Some("red".to_string()),
+                ..bold
+            },
+        }]))
+    );
+
+    assert!(parse_html_label("<<b>a>").is_err());
+    assert!(parse_html_label("<<table></table>>").is_err());
+    assert!(parse_html_label("<<table><hr/><tr><td>a</td></tr></table>>").is_err());
+    assert!(parse_html_label("<<table><tr><td colspan=\"0\">a</td></tr></table>>").is_err());
+    assert!(parse_html_label("<<table><tr><td foo=\"1\">a</td></tr></table>>").is_err());
+    assert!(parse_html_label("<a<table><tr><td>b</td></tr></table>>").is_err());
+    assert!(parse_html_label("<<img src=\"x.png\"/>>").is_err());
+}
+
+// The font of text outside of any <FONT> element, from fontname and fontsize of the object the
+// label belongs to, and fontpath of the graph.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BaseFont<'a> {
+    pub name: &'a str,
+    pub size: f64,
+    pub path: Option<&'a str>,
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(attr, _)| attr == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn number(attrs: &[(String, String)], name: &str) -> Option<f64> {
+    attr(attrs, name).and_then(|value| value.parse().ok())
+}
+
+// The font size of a string of formatted text and its width. Bold text is measured with the
+// bold metrics of its face.
+fn measure(text: &str, style: &TextStyle, font: BaseFont) -> (f64, f64) {
+    let size = style
+        .point_size
+        .as_deref()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(font.size);
+    let mut name = style.face.as_deref().unwrap_or(font.name).to_string();
+    if style.bold && !name.to_lowercase().contains("bold") {
+        name.push_str(" Bold");
+    }
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    (size, Font::load(&name, font.path).width(&text, size))
+}
+
+// Width and height of formatted text. Each <BR/> ends a line, which is as high as its largest
+// font. Bold text is measured with the bold metrics of its face.
+fn text_size(items: &[TextItem], font: BaseFont) -> (f64, f64) {
+    let (mut width, mut height) = (0.0, 0.0);
+    let (mut line_width, mut line_height) = (0.0, 0.0f64);
+    let mut open = false;
+    for item in items {
+        match item {
+            TextItem::Str { text, style } => {
+                let (size, width) = measure(text, style, font);
+                line_width += width;
+                line_height = line_height.max(size * LINE_SPACING);
+                open = true;
+            }
+            TextItem::Br { .. } => {
+                width = f64::max(width, line_width);
+                height += if open {
+                    line_height
+                } else {
+                    font.size * LINE_SPACING
+                };
+                (line_width, line_height, open) = (0.0, 0.0, false);
+            }
+        }
+    }
+    if open {
+        width = f64::max(width, line_width);
+        height += line_height;
+    }
+    (width, height)
+}
+
+// Where the cells of a table go: the widths of its columns and heights of its rows, and for
+// each cell in the order of the rows its row, column, rowspan and colspan.
+struct Grid {
+    widths: Vec<f64>,
+    heights: Vec<f64>,
+    cells: Vec<[usize; 4]>,
+    border: f64,
+    spacing: f64,
+}
+
+impl Grid {
+    // Assigns cells to rows and columns as Graphviz does, skipping places taken by cells with a
+    // ROWSPAN from rows above. Columns and rows are as large as their largest cell, and a cell
+    // spanning several of them makes each at least an equal share of it.
+    fn new(table: &Table, font: BaseFont) -> Grid {
+        let border = number(&table.attrs, "border").unwrap_or(DEFAULT_BORDER);
+        let spacing = number(&table.attrs, "cellspacing").unwrap_or(DEFAULT_CELLSPACING);
+        let mut taken = std::collections::HashSet::new();
+        let mut cells = Vec::new();
+        let mut sizes = Vec::new();
+        for (row, cells_of_row) in table.rows.iter().enumerate() {
+            let mut column = 0;
+            for cell in cells_of_row.cells.iter() {
+                while taken.contains(&(row, column)) {
+                    column += 1;
+                }
+                let span = |name| number(&cell.attrs, name).map_or(1, |n| n as usize).max(1);
+                let (rowspan, colspan) = (span("rowspan"), span("colspan"));
+                for r in row..row + rowspan {
+                    for c in column..column + colspan {
+                        taken.insert((r, c));
+                    }
+                }
+                cells.push([row, column, rowspan, colspan]);
+                sizes.push(cell_size(table, cell, font));
+                column += colspan;
+            }
+        }
+        let rows = cells.iter().map(|c| c[0] + c[2]).max().unwrap_or(0);
+        let columns = cells.iter().map(|c| c[1] + c[3]).max().unwrap_or(0);
+        let mut widths = vec![0.0f64; columns];
+        let mut heights = vec![0.0f64; rows];
+        let share = |size: f64, span: usize| (size - (span - 1) as f64 * spacing) / span as f64;
+        for (&[row, column, rowspan, colspan], &(width, height)) in cells.iter().zip(&sizes) {
+            for width_of in widths[column..column + colspan].iter_mut() {
+                *width_of = width_of.max(share(width, colspan));
+            }
+            for height_of in heights[row..row + rowspan].iter_mut() {
+                *height_of = height_of.max(share(height, rowspan));
+            }
+        }
+        let mut grid = Grid {
+            widths,
+            heights,
+            cells,
+            border,
+            spacing,
+        };
+        // WIDTH and HEIGHT of the table are minimums, and the room they add is shared equally.
+        let (width, height) = grid.size();
+        let grow = |lengths: &mut Vec<f64>, extra: f64| {
+            if extra > 0.0 && !lengths.is_empty() {
+                let n = lengths.len() as f64;
+                lengths.iter_mut().for_each(|length| *length += extra / n);
+            }
+        };
+        grow(
+            &mut grid.widths,
+            number(&table.attrs, "width").unwrap_or(0.0) - width,
+        );
+        grow(
+            &mut grid.heights,
+            number(&table.attrs, "height").unwrap_or(0.0) - height,
+        );
+        grid
+    }
+
+    fn size(&self) -> (f64, f64) {
+        let length = |lengths: &[f64]| {
+            lengths.iter().sum::<f64>()
+                + (lengths.len() + 1) as f64 * self.spacing
+                + 2.0 * self.border
+        };
+        (length(&self.widths), length(&self.heights))
+    }
+
+    // Boxes of the cells in a table drawn in the given box, rows from the top.
+    fn cell_rects(&self, (x0, _, _, y1): Rect) -> Vec<Rect> {
+        let start = self.border + self.spacing;
+        let offsets = |lengths: &[f64]| {
+            let mut offsets = vec![start];
+            for length in lengths {
+                offsets.push(offsets[offsets.len() - 1] + length + self.spacing);
+            }
+            offsets
+        };
+        let (xs, ys) = (offsets(&self.widths), offsets(&self.heights));
+        self.cells
+            .iter()
+            .map(|&[row, column, rowspan, colspan]| {
+                (
+                    x0 + xs[column],
+                    y1 - ys[row + rowspan] + self.spacing,
+                    x0 + xs[column + colspan] - self.spacing,
+                    y1 - ys[row],
+                )
+            })
+            .collect()
+    }
+}
+
+// Size of a cell: its content with CELLPADDING and its border on each side, at least WIDTH by
+// HEIGHT. Cells take CELLPADDING from the table and their border from CELLBORDER, or else BORDER,
+// of the table.
+fn cell_size(table: &Table, cell: &Cell, font: BaseFont) -> (f64, f64) {
+    let padding = number(&cell.attrs, "cellpadding")
+        .or(number(&table.attrs, "cellpadding"))
+        .unwrap_or(DEFAULT_CELLPADDING);
+    let border = number(&cell.attrs, "border")
+        .or(number(&table.attrs, "cellborder"))
+        .or(number(&table.attrs, "border"))
+        .unwrap_or(DEFAULT_BORDER);
+    let (width, height) = match &cell.content {
+        CellContent::Label(label) => label.size(font),
+        // Images are not read, so they take no room.
+        CellContent::Image { .. } => (0.0, 0.0),
+    };
+    let margin = 2.0 * (padding + border);
+    (
+        f64::max(width + margin, number(&cell.attrs, "width").unwrap_or(0.0)),
+        f64::max(
+            height + margin,
+            number(&cell.attrs, "height").unwrap_or(0.0),
+        ),
+    )
+}
+
+impl HtmlLabel {
+    // Width and height of the label in points.
+    pub fn size(&self, font: BaseFont) -> (f64, f64) {
+        match self {
+            HtmlLabel::Text(items) => text_size(items, font),
+            HtmlLabel::Table(table) => Grid::new(table, font).size(),
+        }
+    }
+
+    // The boxes of the table and cells with a PORT, for the label drawn centered in rect. Nested
+    // labels are centered in their cells.
+    pub fn ports(&self, font: BaseFont, rect: Rect) -> Vec<(String, Rect)> {
+        let table = match self {
+            HtmlLabel::Table(table) => table,
+            HtmlLabel::Text(_) => return Vec::new(),
+        };
+        let grid = Grid::new(table, font);
+        let (width, height) = grid.size();
+        let (cx, cy) = ((rect.0 + rect.2) / 2.0, (rect.1 + rect.3) / 2.0);
+        let rect = (
+            cx - width / 2.0,
+            cy - height / 2.0,
+            cx + width / 2.0,
+            cy + height / 2.0,
+        );
+        let mut ports: Vec<(String, Rect)> = attr(&table.attrs, "port")
+            .map(|port| (port.to_string(), rect))
+            .into_iter()
+            .collect();
+        let cells = table.rows.iter().flat_map(|row| row.cells.iter());
+        for (cell, rect) in cells.zip(grid.cell_rects(rect)) {
+            if let Some(port) = attr(&cell.attrs, "port") {
+                ports.push((port.to_string(), rect));
+            }
+            if let CellContent::Label(label) = &cell.content {
+                ports.extend(label.ports(font, rect));
+            }
+        }
+        ports
+    }
+}
+
+// Formatted text split into lines: the strings of each line with their styles, and the ALIGN
+// of the <BR/> ending it. A <BR/> at the end does not start another line.
+type Line<'a> = (Vec<(&'a str, &'a TextStyle)>, Option<&'a str>);
+
+fn text_lines(items: &[TextItem]) -> Vec<Line<'_>> {
+    let mut lines: Vec<Line> = vec![(Vec::new(), None)];
+    for item in items {
+        let last = lines.len() - 1;
+        match item {
+            TextItem::Str { text, style } => lines[last].0.push((text, style)),
+            TextItem::Br { align } => {
+                lines[last].1 = align.as_deref();
+                lines.push((Vec::new(), None));
+            }
+        }
+    }
+    if lines.len() > 1 && lines[lines.len() - 1].0.is_empty() {
+        lines.pop();
+    }
+    lines
+}
+
+fn text_flags(style: &TextStyle) -> u32 {
+    [
+        (style.bold, draw::BOLD),
+        (style.italic, draw::ITALIC),
+        (style.underline, draw::UNDERLINE),
+        (style.superscript, draw::SUPERSCRIPT),
+        (style.subscript, draw::SUBSCRIPT),
+        (style.strike, draw::STRIKE),
+        (style.overline, draw::OVERLINE),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, flag)| flag)
+    .sum()
+}
+
+fn parse_color(value: Option<&str>) -> Option<Rgba> {
+    color::parse_color(value?, None).ok()
+}
+
+// Formatted text centered in rect, each line aligned as its <BR/> asks within rect.
+fn draw_text(items: &[TextItem], font: BaseFont, color: Rgba, rect: Rect) -> Vec<Op> {
+    let lines = text_lines(items);
+    let heights: Vec<f64> = lines
+        .iter()
+        .map(|(strings, _)| {
+            strings
+                .iter()
+                .map(|(text, style)| measure(text, style, font).0 * LINE_SPACING)
+                .reduce(f64::max)
+                .unwrap_or(font.size * LINE_SPACING)
+        })
+        .collect();
+    let mut top = (rect.1 + rect.3) / 2.0 + heights.iter().sum::<f64>() / 2.0;
+    let mut ops = Vec::new();
+    let mut flags = 0;
+    for ((strings, align), height) in lines.iter().zip(heights) {
+        let measured: Vec<(f64, f64)> = strings
+            .iter()
+            .map(|(text, style)| measure(text, style, font))
+            .collect();
+        let width: f64 = measured.iter().map(|(_, width)| width).sum();
+        let mut x = match *align {
+            Some("left") => rect.0,
+            Some("right") => rect.2 - width,
+            _ => (rect.0 + rect.2) / 2.0 - width / 2.0,
+        };
+        let y = top - height / 2.0 - draw::BASELINE_DROP * height / LINE_SPACING;
+        for (&(text, style), (size, width)) in strings.iter().zip(measured) {
+            ops.push(Op::Font {
+                size,
+                name: style.face.as_deref().unwrap_or(font.name).to_string(),
+            });
+            if text_flags(style) != flags {
+                flags = text_flags(style);
+                ops.push(Op::FontChars(flags));
+            }
+            ops.push(Op::PenColor(
+                parse_color(style.color.as_deref()).unwrap_or(color),
+            ));
+            ops.push(Op::Text {
+                pos: (x, y),
+                justification: Justification::Left,
+                width,
+                text: text.to_string(),
+            });
+            x += width;
+        }
+        top -= height;
+    }
+    if flags != 0 {
+        ops.push(Op::FontChars(0));
+    }
+    ops
+}
+
+// The background of a table or cell in bgcolor and its border, drawn inside rect.
+fn draw_box(
+    ops: &mut Vec<Op>,
+    (x0, y0, x1, y1): Rect,
+    bgcolor: Option<Rgba>,
+    border: f64,
+    pen: Rgba,
+) {
+    let points = |d: f64| {
+        vec![
+            (x0 + d, y0 + d),
+            (x1 - d, y0 + d),
+            (x1 - d, y1 - d),
+            (x0 + d, y1 - d),
+        ]
+    };
+    if let Some(bgcolor) = bgcolor {
+        ops.push(Op::PenColor(bgcolor));
+        ops.push(Op::FillColor(bgcolor));
+        ops.push(Op::Polygon {
+            filled: true,
+            points: points(0.0),
+        });
+    }
+    if border > 0.0 {
+        let width = |width: f64| Op::Style(format!("setlinewidth({})", width));
+        if border != 1.0 {
+            ops.push(width(border));
+        }
+        ops.push(Op::PenColor(pen));
+        ops.push(Op::Polygon {
+            filled: false,
+            points: points(border / 2.0),
+        });
+        if border != 1.0 {
+            ops.push(width(1.0));
+        }
+    }
+}
+
+// A table centered in rect: its background and border, then each cell with its own and its
+// content. Borders are in COLOR of the table or cell, black by default.
+fn draw_table(table: &Table, font: BaseFont, color: Rgba, rect: Rect) -> Vec<Op> {
+    let grid = Grid::new(table, font);
+    let (width, height) = grid.size();
+    let (cx, cy) = ((rect.0 + rect.2) / 2.0, (rect.1 + rect.3) / 2.0);
+    let rect = (
+        cx - width / 2.0,
+        cy - height / 2.0,
+        cx + width / 2.0,
+        cy + height / 2.0,
+    );
+    let pen = parse_color(attr(&table.attrs, "color")).unwrap_or(draw::BLACK);
+    let bgcolor = parse_color(attr(&table.attrs, "bgcolor"));
+    let mut ops = Vec::new();
+    draw_box(&mut ops, rect, bgcolor, grid.border, pen);
+    let cells = table.rows.iter().flat_map(|row| row.cells.iter());
+    for (cell, rect) in cells.zip(grid.cell_rects(rect)) {
+        let border = number(&cell.attrs, "border")
+            .or(number(&table.attrs, "cellborder"))
+            .unwrap_or(grid.border);
+        let bgcolor = parse_color(attr(&cell.attrs, "bgcolor"));
+        let pen = parse_color(attr(&cell.attrs, "color")).unwrap_or(pen);
+        draw_box(&mut ops, rect, bgcolor, border, pen);
+        if let CellContent::Label(label) = &cell.content {
+            ops.extend(label.draw(font, color, rect));
+        }
+    }
+    ops
+}
+
+impl HtmlLabel {
+    // The operations drawing the label centered in rect, with text outside of any <FONT> in the
+    // given font and color. Images are not drawn.
+    pub fn draw(&self, font: BaseFont, color: Rgba, rect: Rect) -> Vec<Op> {
+        match self {
+            HtmlLabel::Text(items) => draw_text(items, font, color, rect),
+            HtmlLabel::Table(table) => draw_table(table, font, color, rect),
+        }
+    }
+}
+
+#[test]
+fn test_draw() {
+    let font = BaseFont {
+        name: "Courier",
+        size: 10.0,
+        path: None,
+    };
+    let label = parse_html_label("<a<br align=\"left\"/><b>bb</b>c>").unwrap();
+    let ops = label.draw(font, draw::BLACK, (0.0, 0.0, 100.0, 24.0));
+    let texts: Vec<(Point, &str)> = ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::Text { pos, text, .. } => Some((*pos, text.as_str())),
+            _ => None,
+        })
+        .collect();
+    let baseline = |top: f64| top - 6.0 - draw::BASELINE_DROP * 10.0;
+    assert_eq!(
+        texts,
+        vec![
+            ((0.0, baseline(24.0)), "a"),
+            ((41.0, baseline(12.0)), "bb"),
+            ((53.0, baseline(12.0)), "c"),
+        ]
+    );
+    // Bold is set for bb and cleared for c.
+    let flags: Vec<&Op> = ops
+        .iter()
+        .filter(|op| matches!(op, Op::FontChars(_)))
+        .collect();
+    assert_eq!(flags, vec![&Op::FontChars(draw::BOLD), &Op::FontChars(0)]);
+
+    // A border of 2 around the table is drawn 1 point inside it, and cells have the border of
+    // the table unless CELLBORDER is given.
+    let label = parse_html_label(
+        "<<table border=\"2\" cellborder=\"0\" bgcolor=\"red\"><tr><td>a</td></tr></table>>",
+    )
+    .unwrap();
+    let (width, height) = label.size(font);
+    let ops = label.draw(font, draw::BLACK, (0.0, 0.0, width, height));
+    let polygons: Vec<&Op> = ops
+        .iter()
+        .filter(|op| matches!(op, Op::Polygon { .. }))
+        .collect();
+    assert_eq!(polygons.len(), 2);
+    assert_eq!(
+        polygons[1],
+        &Op::Polygon {
+            filled: false,
+            points: vec![
+                (1.0, 1.0),
+                (width - 1.0, 1.0),
+                (width - 1.0, height - 1.0),
+                (1.0, height - 1.0)
+            ]
+        }
+    );
+}
+
+// The HTML label of a node, when it has a good one, and the font it is in.
+fn node_label<'a>(graph: &'a Graph, attrs: &'a Attrs) -> Option<(HtmlLabel, BaseFont<'a>)> {
+    let label = attrs.get("label").filter(|_| attrs.is_html("label"))?;
+    let label = parse_html_label(label).ok()?;
+    Some((label, base_font(graph, attrs, "")))
+}
+
+// The font of a label from fontname and fontsize, or with a prefix such as labelfontname for
+// head and tail labels.
+pub fn base_font<'a>(graph: &'a Graph, attrs: &'a Attrs, prefix: &str) -> BaseFont<'a> {
+    let (name, size) = text::font_of(attrs, prefix);
+    BaseFont {
+        name,
+        size,
+        path: graph.attrs.get("fontpath"),
+    }
+}
+
+// The box of the table or cell named by port in the HTML label of a node, relative to the center
+// of the node.
+pub fn port_rect(graph: &Graph, attrs: &Attrs, port: &str) -> Option<Rect> {
+    let (label, font) = node_label(graph, attrs)?;
+    label
+        .ports(font, (0.0, 0.0, 0.0, 0.0))
+        .into_iter()
+        .find(|(name, _)| name == port)
+        .map(|(_, rect)| rect)
+}
+
+#[test]
+fn test_size() {
+    let font = BaseFont {
+        name: "Courier",
+        size: 10.0,
+        path: None,
+    };
+    let size = |label: &str| parse_html_label(label).unwrap().size(font);
+    assert_eq!(
+        size("<ab<br/><font point-size=\"20\">c</font>>"),
+        (12.0, 36.0)
+    );
+    assert_eq!(size("<<br/>>"), (0.0, 12.0));
+    // One cell: text, 2 points of padding and 1 of border on each side, 2 points of spacing and
+    // 1 of border around it.
+    assert_eq!(
+        size("<<table><tr><td>ab</td></tr></table>>"),
+        (12.0 + 6.0 + 6.0, 12.0 + 6.0 + 6.0)
+    );
+    // The cell spanning two columns makes each at least half of it, less the spacing.
+    let table = r#"<<table border="0" cellborder="0" cellpadding="0" cellspacing="4">
+<tr><td colspan="2">abcdefgh</td><td rowspan="2">a</td></tr>
+<tr><td>a</td><td port="p">a</td></tr>
+</table>>"#;
+    assert_eq!(size(table), (48.0 + 6.0 + 3.0 * 4.0, 24.0 + 3.0 * 4.0));
+    let ports = parse_html_label(table)
+        .unwrap()
+        .ports(font, (0.0, 0.0, 0.0, 0.0));
+    assert_eq!(ports, vec![("p".to_string(), (-3.0, -14.0, 19.0, -2.0))]);
+    assert_eq!(
+        size("<<table width=\"100\"><tr><td></td></tr></table>>").0,
+        100.0
+    );
+
+    let graph = from_dot(
+        r#"graph {
+    a [label = <<table><tr><td port="p">a</td><td>b</td></tr></table>>]
+}"#,
+    );
+    let rect = port_rect(&graph, &graph.nodes[0].attrs, "p").unwrap();
+    assert!(rect.2 < 0.0 && rect.0 < 0.0);
+    assert_eq!(port_rect(&graph, &graph.nodes[0].attrs, "q"), None);
+}
+
+fn collect_ports<'a>(label: &'a HtmlLabel, ports: &mut Vec<&'a str>) {
+    let table = match label {
+        HtmlLabel::Table(table) => table,
+        HtmlLabel::Text(_) => return,
+    };
+    let port_of = |attrs: &'a [(String, String)]| {
+        attrs
+            .iter()
+            .find(|(name, _)| name == "port")
+            .map(|(_, value)| value.as_str())
+    };
+    ports.extend(port_of(&table.attrs));
+    for cell in table.rows.iter().flat_map(|row| row.cells.iter()) {
+        ports.extend(port_of(&cell.attrs));
+        if let CellContent::Label(label) = &cell.content {
+            collect_ports(label, ports);
+        }
+    }
+}
+
+const LABEL_ATTRS: [&str; 4] = ["label", "xlabel", "headlabel", "taillabel"];
+
+// Warns about bad HTML labels, and returns the label attribute when it is a good HTML label.
+fn check_labels(
+    kind: &str,
+    name: &str,
+    attrs: &Attrs,
+    warnings: &mut Vec<String>,
+) -> Option<HtmlLabel> {
+    let mut label = None;
+    for attr in LABEL_ATTRS {
+        match attrs
+            .get(attr)
+            .filter(|_| attrs.is_html(attr))
+            .map(parse_html_label)
+        {
+            Some(Err(e)) => {
+                warnings.push(format!("{} {} has a bad HTML {}: {}", kind, name, attr, e))
+            }
+            Some(Ok(parsed)) if attr == "label" => label = Some(parsed),
+            _ => {}
+        }
+    }
+    label
+}
+
+// Checks HTML labels everywhere and that ports used by edges name a PORT of the node's table.
+pub fn check(graph: &Graph) -> Vec<String> {
+    let mut warnings = Vec::new();
+    check_labels("Graph", &graph.name, &graph.attrs, &mut warnings);
+    for subgraph in graph.subgraphs.iter() {
+        check_labels("Subgraph", &subgraph.name, &subgraph.attrs, &mut warnings);
+    }
+    let labels: Vec<Option<HtmlLabel>> = graph
+        .nodes
+        .iter()
+        .map(|node| check_labels("Node", &node.name, &node.attrs, &mut warnings))
+        .collect();
+    for edge in graph.edges.iter() {
+        let op = if graph.directed { "->" } else { "--" };
+        let name = format!(
+            "{} {} {}",
+            graph.nodes[edge.tail].name, op, graph.nodes[edge.head].name
+        );
+        check_labels("Edge", &name, &edge.attrs, &mut warnings);
+    }
+    let ports: Vec<Option<Vec<&str>>> = labels
+        .iter()
+        .map(|label| {
+            label.as_ref().map(|label| {
+                let mut ports = Vec::new();
+                collect_ports(label, &mut ports);
+                ports
+            })
+        })
+        .collect();
+    warnings.extend(record::check_ports(graph, &ports));
+    warnings
+}
+
+#[test]
+fn test_check() {
+    let tokens = tokenize(
+        r#"digraph {
+    label = <<u>title</u>>
+    a [label = <<table><tr><td port="p">a</td></tr></table>>]
+    b [label = <<table><tr>b</tr></table>>]
+    a:p -> b
+    a:q -> b [taillabel = <<b>x</i>>]
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let warnings = check(&Graph::from_ast(&graph));
+    assert_eq!(warnings.len(), 3);
+    assert!(warnings[0].starts_with("Node b has a bad HTML label"));
+    assert!(warnings[1].starts_with("Edge a -> b has a bad HTML taillabel"));
+    assert_eq!(warnings[2], "Node a has no port q");
+}
@@ -0,0 +1,46 @@
+use crate::ast::Graph;
+use crate::{ast, graphml, json, tokenize};
+use clap::ValueEnum;
+use log::info;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    Dot,
+    Json,
+    Graphml,
+}
+
+// Guesses the input format from the extension of the input file, e.g. "graph.graphml".
+pub fn infer_input_format(input_file: &str) -> Option<InputFormat> {
+    let extension = Path::new(input_file).extension()?.to_str()?;
+    match extension.to_lowercase().as_str() {
+        "dot" | "gv" => Some(InputFormat::Dot),
+        "json" => Some(InputFormat::Json),
+        "graphml" | "xml" => Some(InputFormat::Graphml),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_infer_input_format() {
+    assert_eq!(infer_input_format("a.gv"), Some(InputFormat::Dot));
+    assert_eq!(infer_input_format("dir/a.JSON"), Some(InputFormat::Json));
+    assert_eq!(infer_input_format("a.graphml"), Some(InputFormat::Graphml));
+    assert_eq!(infer_input_format("a"), None);
+}
+
+fn parse_dot(dot_str: String) -> Result<Graph, String> {
+    info!("parsing dot string");
+    let tokens = tokenize::tokenize(dot_str);
+    let (graph, _) = ast::parse_graph(&tokens)?;
+    Ok(graph)
+}
+
+pub fn parse(input_str: String, format: InputFormat) -> Result<Graph, String> {
+    match format {
+        InputFormat::Dot => parse_dot(input_str),
+        InputFormat::Json => json::parse(&input_str),
+        InputFormat::Graphml => graphml::parse(&input_str),
+    }
+}
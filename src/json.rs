@@ -0,0 +1,543 @@
+use crate::ast::{self, Stmt, ID};
+use crate::draw::{self, Op};
+use crate::layout::Point;
+use crate::semantic::{Attrs, Graph};
+use crate::text::Justification;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+#[cfg(test)]
+use crate::layout::{self, Engine};
+#[cfg(test)]
+use crate::tokenize::tokenize;
+
+fn insert_attrs(object: &mut Map<String, Value>, attrs: &Attrs) {
+    for (name, value) in attrs.iter() {
+        object.insert(name.clone(), json!(value));
+    }
+}
+
+// An xdot operation as an object of `dot -Tjson`, such as {"op": "e", "rect": [x, y, rx, ry]}.
+// Coordinates have two decimals, as in the xdot attributes.
+fn op_value(op: &Op) -> Value {
+    let n = |x: f64| (x * 100.0).round() / 100.0;
+    let points =
+        |points: &[Point]| -> Vec<[f64; 2]> { points.iter().map(|&(x, y)| [n(x), n(y)]).collect() };
+    let letter = |filled: bool, yes: &str, no: &str| if filled { yes } else { no }.to_string();
+    match op {
+        Op::Ellipse {
+            filled,
+            center,
+            radii,
+        } => json!({
+            "op": letter(*filled, "E", "e"),
+            "rect": [n(center.0), n(center.1), n(radii.0), n(radii.1)]
+        }),
+        Op::Polygon { filled, points: p } => {
+            json!({"op": letter(*filled, "P", "p"), "points": points(p)})
+        }
+        Op::Polyline(p) => json!({"op": "L", "points": points(p)}),
+        Op::Bezier { filled, points: p } => {
+            json!({"op": letter(*filled, "b", "B"), "points": points(p)})
+        }
+        Op::Text {
+            pos,
+            justification,
+            width,
+            text,
+        } => {
+            let align = match justification {
+                Justification::Left => "l",
+                Justification::Center => "c",
+                Justification::Right => "r",
+            };
+            json!({
+                "op": "T",
+                "pt": [n(pos.0), n(pos.1)],
+                "align": align,
+                "width": n(*width),
+                "text": text
+            })
+        }
+        Op::PenColor(color) => json!({"op": "c", "grad": "none", "color": color.to_string()}),
+        Op::FillColor(color) => json!({"op": "C", "grad": "none", "color": color.to_string()}),
+        Op::Font { size, name } => json!({"op": "F", "size": size, "face": name}),
+        Op::Style(style) => json!({"op": "S", "style": style}),
+        Op::FontChars(flags) => json!({"op": "t", "fontchar": flags}),
+    }
+}
+
+fn insert_ops(object: &mut Map<String, Value>, ops: draw::Ops) {
+    for (attr, ops) in ops {
+        let ops: Vec<Value> = ops.iter().map(op_value).collect();
+        object.insert(attr.to_string(), Value::Array(ops));
+    }
+}
+
+// Writes the graph in the layout of `dot -Tjson`, or of `dot -Tjson0` when with_ops is false.
+// Subgraphs and nodes share the "objects" array, subgraphs first, and every "_gvid" is the
+// index of the object in that array. -Tjson adds the xdot drawing operations of a laid-out
+// graph as arrays of objects.
+pub fn render(graph: &Graph, with_ops: bool) -> Result<String, String> {
+    let node_gvid = |node: &usize| node + graph.subgraphs.len();
+
+    let mut root = Map::new();
+    root.insert("name".to_string(), json!(graph.name));
+    root.insert("directed".to_string(), json!(graph.directed));
+    root.insert("strict".to_string(), json!(graph.strict));
+    insert_attrs(&mut root, &graph.attrs);
+    if with_ops {
+        insert_ops(&mut root, draw::graph_ops(graph)?);
+        root.insert("xdotversion".to_string(), json!("1.7"));
+    }
+    root.insert("_subgraph_cnt".to_string(), json!(graph.subgraphs.len()));
+
+    let mut objects = Vec::new();
+    for (gvid, subgraph) in graph.subgraphs.iter().enumerate() {
+        let mut object = Map::new();
+        object.insert("_gvid".to_string(), json!(gvid));
+        object.insert("name".to_string(), json!(subgraph.name));
+        insert_attrs(&mut object, &subgraph.attrs);
+        if with_ops {
+            insert_ops(&mut object, draw::cluster_ops(graph, gvid)?);
+        }
+        if !subgraph.subgraphs.is_empty() {
+            object.insert("subgraphs".to_string(), json!(subgraph.subgraphs));
+        }
+        if !subgraph.nodes.is_empty() {
+            let nodes: Vec<usize> = subgraph.nodes.iter().map(node_gvid).collect();
+            object.insert("nodes".to_string(), json!(nodes));
+        }
+        if !subgraph.edges.is_empty() {
+            object.insert("edges".to_string(), json!(subgraph.edges));
+        }
+        objects.push(Value::Object(object));
+    }
+    for (index, node) in graph.nodes.iter().enumerate() {
+        let mut object = Map::new();
+        object.insert("_gvid".to_string(), json!(node_gvid(&index)));
+        object.insert("name".to_string(), json!(node.name));
+        insert_attrs(&mut object, &node.attrs);
+        if with_ops {
+            insert_ops(&mut object, draw::node_ops(graph, index)?);
+        }
+        objects.push(Value::Object(object));
+    }
+    if !objects.is_empty() {
+        root.insert("objects".to_string(), Value::Array(objects));
+    }
+
+    let mut edges = Vec::new();
+    for (gvid, edge) in graph.edges.iter().enumerate() {
+        let mut object = Map::new();
+        object.insert("_gvid".to_string(), json!(gvid));
+        object.insert("tail".to_string(), json!(node_gvid(&edge.tail)));
+        object.insert("head".to_string(), json!(node_gvid(&edge.head)));
+        insert_attrs(&mut object, &edge.attrs);
+        if with_ops {
+            insert_ops(&mut object, draw::edge_ops(graph, gvid)?);
+        }
+        edges.push(Value::Object(object));
+    }
+    if !edges.is_empty() {
+        root.insert("edges".to_string(), Value::Array(edges));
+    }
+
+    let mut json_str = serde_json::to_string_pretty(&Value::Object(root)).unwrap();
+    json_str.push('\n');
+    Ok(json_str)
+}
+
+#[test]
+fn test_render() {
+    let tokens = tokenize(
+        r#"digraph G {
+    label = "G"
+    subgraph cluster_a { a -> b [color = red] }
+    b -> c
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let json_str = render(&Graph::from_ast(&graph), false).unwrap();
+    let value: Value = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "name": "G",
+            "directed": true,
+            "strict": false,
+            "label": "G",
+            "_subgraph_cnt": 1,
+            "objects": [
+                {"_gvid": 0, "name": "cluster_a", "nodes": [1, 2], "edges": [0]},
+                {"_gvid": 1, "name": "a"},
+                {"_gvid": 2, "name": "b"},
+                {"_gvid": 3, "name": "c"}
+            ],
+            "edges": [
+                {"_gvid": 0, "tail": 1, "head": 2, "color": "red"},
+                {"_gvid": 1, "tail": 2, "head": 3}
+            ]
+        })
+    );
+}
+
+#[test]
+fn test_render_ops() {
+    let tokens = tokenize(r#"digraph { a [pos = "0,0"]; b [pos = "1,0"]; a -> b }"#.to_string());
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let laid_out = layout::apply(&Graph::from_ast(&graph), Engine::Neato).unwrap();
+    let value: Value = serde_json::from_str(&render(&laid_out, true).unwrap()).unwrap();
+    assert_eq!(value["xdotversion"], json!("1.7"));
+    assert_eq!(
+        value["objects"][0]["_draw_"],
+        json!([
+            {"op": "c", "grad": "none", "color": "#000000"},
+            {"op": "e", "rect": [27.0, 18.0, 27.0, 18.0]}
+        ])
+    );
+    assert_eq!(
+        value["objects"][0]["_ldraw_"][2],
+        json!({"op": "T", "pt": [27.0, 14.3], "align": "c", "width": 6.22, "text": "a"})
+    );
+    assert_eq!(value["edges"][0]["_hdraw_"][2]["op"], json!("P"));
+    let value: Value = serde_json::from_str(&render(&laid_out, false).unwrap()).unwrap();
+    assert_eq!(value.get("xdotversion"), None);
+    assert_eq!(value["objects"][0].get("_draw_"), None);
+}
+
+// Keys that describe the structure of an object rather than its attributes. Keys starting with
+// '_' are bookkeeping such as "_gvid" or xdot drawing operations and are skipped as well.
+const STRUCTURAL_KEYS: [&str; 9] = [
+    "name",
+    "directed",
+    "strict",
+    "objects",
+    "nodes",
+    "edges",
+    "subgraphs",
+    "tail",
+    "head",
+];
+
+fn attrs_of(object: &Map<String, Value>) -> Vec<(String, ID)> {
+    let mut attrs = Vec::new();
+    for (name, value) in object {
+        if name.starts_with('_') || STRUCTURAL_KEYS.contains(&name.as_str()) {
+            continue;
+        }
+        match value {
+            Value::String(value) => attrs.push((name.clone(), ID::from_value(value))),
+            Value::Number(_) | Value::Bool(_) => {
+                attrs.push((name.clone(), ID::from_value(&value.to_string())))
+            }
+            _ => {}
+        }
+    }
+    attrs
+}
+
+fn gvids_of(object: &Map<String, Value>, key: &str) -> Vec<u64> {
+    match object.get(key).and_then(Value::as_array) {
+        Some(gvids) => gvids.iter().filter_map(Value::as_u64).collect(),
+        None => Vec::new(),
+    }
+}
+
+// A subgraph with its attributes, its nodes, the edges it is the innermost subgraph of, taken
+// from edges, and its own subgraphs.
+fn subgraph_stmt(
+    gvid: u64,
+    subgraphs: &HashMap<u64, &Map<String, Value>>,
+    node_names: &HashMap<u64, String>,
+    edges: &mut HashMap<u64, Vec<Stmt>>,
+) -> Result<Stmt, String> {
+    let object = subgraphs[&gvid];
+    let mut stmts = Vec::new();
+    for (name, value) in attrs_of(object) {
+        stmts.push(Stmt::id_eq(&name, value));
+    }
+    for node in gvids_of(object, "nodes") {
+        match node_names.get(&node) {
+            Some(name) => stmts.push(Stmt::node(name, &[])),
+            None => return Err(format!("{}:{} Unknown node {}", file!(), line!(), node)),
+        }
+    }
+    stmts.extend(edges.remove(&gvid).unwrap_or_default());
+    for child in gvids_of(object, "subgraphs") {
+        if !subgraphs.contains_key(&child) {
+            return Err(format!(
+                "{}:{} Unknown subgraph {}",
+                file!(),
+                line!(),
+                child
+            ));
+        }
+        stmts.push(subgraph_stmt(child, subgraphs, node_names, edges)?);
+    }
+    let name = object.get("name").and_then(Value::as_str);
+    Ok(Stmt::subgraph(name, stmts))
+}
+
+// Returns an error when a subgraph contains itself, directly or through its children, as such a
+// hierarchy cannot be written out or walked up.
+fn check_subgraph_cycles(subgraphs: &HashMap<u64, &Map<String, Value>>) -> Result<(), String> {
+    // path holds the subgraphs being visited, outermost first, and done those whose children
+    // have all been visited.
+    fn visit(
+        gvid: u64,
+        subgraphs: &HashMap<u64, &Map<String, Value>>,
+        path: &mut Vec<u64>,
+        done: &mut HashSet<u64>,
+    ) -> Result<(), String> {
+        if done.contains(&gvid) {
+            return Ok(());
+        }
+        if path.contains(&gvid) {
+            return Err(format!(
+                "{}:{} Subgraph {} contains itself",
+                file!(),
+                line!(),
+                gvid
+            ));
+        }
+        path.push(gvid);
+        for child in gvids_of(subgraphs[&gvid], "subgraphs") {
+            if subgraphs.contains_key(&child) {
+                visit(child, subgraphs, path, done)?;
+            }
+        }
+        path.pop();
+        done.insert(gvid);
+        Ok(())
+    }
+    let mut gvids: Vec<u64> = subgraphs.keys().copied().collect();
+    gvids.sort();
+    let mut done = HashSet::new();
+    for gvid in gvids {
+        visit(gvid, subgraphs, &mut Vec::new(), &mut done)?;
+    }
+    Ok(())
+}
+
+// Reads the output of `dot -Tjson` (or -Tjson0) back into a syntax tree. Nodes are declared
+// first with their attributes, followed by each subgraph once, as semantic::Graph::to_ast writes
+// them, with the edges in the innermost subgraph containing them.
+pub fn parse(json_str: &str) -> Result<ast::Graph, String> {
+    let value: Value =
+        serde_json::from_str(json_str).map_err(|e| format!("{}:{} {}", file!(), line!(), e))?;
+    let root = match value.as_object() {
+        Some(root) => root,
+        None => return Err(format!("{}:{} Expected a JSON object", file!(), line!())),
+    };
+    let is_digraph = root
+        .get("directed")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let strict = root.get("strict").and_then(Value::as_bool).unwrap_or(false);
+    let subgraph_cnt = root
+        .get("_subgraph_cnt")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+
+    let mut subgraphs = HashMap::new();
+    let mut node_names = HashMap::new();
+    let mut stmts = Vec::new();
+    for (name, value) in attrs_of(root) {
+        stmts.push(Stmt::id_eq(&name, value));
+    }
+    let objects = root.get("objects").and_then(Value::as_array);
+    for (index, object) in objects.into_iter().flatten().enumerate() {
+        let object = match object.as_object() {
+            Some(object) => object,
+            None => return Err(format!("{}:{} Expected a JSON object", file!(), line!())),
+        };
+        let gvid = object
+            .get("_gvid")
+            .and_then(Value::as_u64)
+            .unwrap_or(index as u64);
+        if gvid < subgraph_cnt {
+            subgraphs.insert(gvid, object);
+            continue;
+        }
+        let name = match object.get("name").and_then(Value::as_str) {
+            Some(name) => name.to_string(),
+            None => return Err(format!("{}:{} Node {} has no name", file!(), line!(), gvid)),
+        };
+        stmts.push(Stmt::node(&name, &attrs_of(object)));
+        node_names.insert(gvid, name);
+    }
+
+    check_subgraph_cycles(&subgraphs)?;
+    let children: HashSet<u64> = subgraphs
+        .values()
+        .flat_map(|object| gvids_of(object, "subgraphs"))
+        .collect();
+    let mut top_level: Vec<u64> = subgraphs
+        .keys()
+        .filter(|gvid| !children.contains(gvid))
+        .copied()
+        .collect();
+    top_level.sort();
+
+    let mut parents = HashMap::new();
+    for (&gvid, object) in subgraphs.iter() {
+        for child in gvids_of(object, "subgraphs") {
+            parents.insert(child, gvid);
+        }
+    }
+    let depth = |mut gvid: u64| {
+        let mut depth = 0;
+        while let Some(&parent) = parents.get(&gvid) {
+            depth += 1;
+            gvid = parent;
+        }
+        depth
+    };
+    let mut innermost_subgraphs: HashMap<u64, u64> = HashMap::new();
+    for (&gvid, object) in subgraphs.iter() {
+        for edge in gvids_of(object, "edges") {
+            match innermost_subgraphs.get(&edge) {
+                Some(&current) if depth(current) >= depth(gvid) => {}
+                _ => {
+                    innermost_subgraphs.insert(edge, gvid);
+                }
+            }
+        }
+    }
+
+    let mut subgraph_edges: HashMap<u64, Vec<Stmt>> = HashMap::new();
+    let mut root_edges = Vec::new();
+    let edges = root.get("edges").and_then(Value::as_array);
+    for (index, edge) in edges.into_iter().flatten().enumerate() {
+        let edge = match edge.as_object() {
+            Some(edge) => edge,
+            None => return Err(format!("{}:{} Expected a JSON object", file!(), line!())),
+        };
+        let gvid = edge
+            .get("_gvid")
+            .and_then(Value::as_u64)
+            .unwrap_or(index as u64);
+        let endpoint = |key: &str| {
+            edge.get(key)
+                .and_then(Value::as_u64)
+                .and_then(|gvid| node_names.get(&gvid))
+                .ok_or(format!("{}:{} Edge has no valid {}", file!(), line!(), key))
+        };
+        let stmt = Stmt::edge(
+            endpoint("tail")?,
+            endpoint("head")?,
+            is_digraph,
+            &attrs_of(edge),
+        );
+        match innermost_subgraphs.get(&gvid) {
+            Some(&subgraph) => subgraph_edges.entry(subgraph).or_default().push(stmt),
+            None => root_edges.push(stmt),
+        }
+    }
+    for gvid in top_level {
+        stmts.push(subgraph_stmt(
+            gvid,
+            &subgraphs,
+            &node_names,
+            &mut subgraph_edges,
+        )?);
+    }
+    stmts.extend(root_edges);
+
+    // render writes "" for an anonymous graph.
+    let name = root
+        .get("name")
+        .and_then(Value::as_str)
+        .filter(|name| !name.is_empty());
+    Ok(ast::Graph::new(strict, is_digraph, name, stmts))
+}
+
+#[test]
+fn test_parse() {
+    let json_str = r#"{
+  "name": "G",
+  "directed": true,
+  "strict": false,
+  "rankdir": "LR",
+  "_subgraph_cnt": 2,
+  "objects": [
+    {"_gvid": 0, "name": "cluster_a", "label": "A", "subgraphs": [1], "nodes": [2, 3], "edges": [1]},
+    {"_gvid": 1, "name": "cluster_b", "nodes": [3], "edges": [1]},
+    {"_gvid": 2, "name": "a", "shape": "box", "_draw_": []},
+    {"_gvid": 3, "name": "b c"}
+  ],
+  "edges": [
+    {"_gvid": 0, "tail": 2, "head": 3, "weight": 2},
+    {"_gvid": 1, "tail": 3, "head": 3}
+  ]
+}"#;
+    let graph = parse(json_str).unwrap();
+    assert_eq!(
+        graph.to_string(),
+        r#"digraph G {
+    rankdir=LR;
+    a [shape=box];
+    "b c";
+    subgraph cluster_a {
+        label=A;
+        a;
+        "b c";
+        subgraph cluster_b {
+            "b c";
+            "b c" -> "b c";
+        }
+    }
+    a -> "b c" [weight=2];
+}
+"#
+    );
+
+    let semantic_graph = Graph::from_ast(&graph);
+    assert_eq!(semantic_graph.nodes.len(), 2);
+    assert_eq!(semantic_graph.subgraphs[0].nodes, vec![0, 1]);
+    // The edge inside the clusters is read first.
+    assert_eq!(semantic_graph.edges[1].attrs.get("weight"), Some("2"));
+    assert_eq!(semantic_graph.subgraphs[0].edges, vec![0]);
+    assert_eq!(semantic_graph.subgraphs[1].edges, vec![0]);
+
+    assert!(parse("[]").is_err());
+    let cycle = r#"{"_subgraph_cnt": 2, "objects": [
+        {"name": "cluster_a", "subgraphs": [1]},
+        {"name": "cluster_b", "subgraphs": [0]}
+    ]}"#;
+    match parse(cycle) {
+        Err(e) => assert!(e.ends_with("Subgraph 0 contains itself")),
+        Ok(_) => panic!("expected an error"),
+    }
+    let self_loop = r#"{"_subgraph_cnt": 2, "objects": [
+        {"name": "cluster_a", "subgraphs": [1]},
+        {"name": "cluster_b", "subgraphs": [1]}
+    ]}"#;
+    assert!(parse(self_loop).is_err());
+    assert_eq!(
+        parse(r#"{"name": "G", "directed": true}"#)
+            .unwrap()
+            .to_string(),
+        "digraph G {\n}\n"
+    );
+    assert!(parse(r#"{"objects": [{"_gvid": 0}]}"#).is_err());
+    assert!(parse(r#"{"objects": [{"name": "a"}], "edges": [{"tail": 0, "head": 1}]}"#).is_err());
+}
+
+#[test]
+fn test_render_parse_round_trip() {
+    let tokens = tokenize(
+        r#"graph {
+    subgraph cluster_a { a -- b [color = red] subgraph cluster_b { b -- c } }
+    c [label = "C"]
+    a -- c
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let json_str = render(&Graph::from_ast(&graph), false).unwrap();
+    let parsed = parse(&json_str).unwrap();
+    assert_eq!(render(&Graph::from_ast(&parsed), false).unwrap(), json_str);
+}
@@ -0,0 +1,403 @@
+use crate::layout::{self, format_number, format_point, rect_around, ClusterBox, Grid, Point};
+use crate::layout::{Rect, Spline, POINTS_PER_INCH};
+use crate::route;
+use crate::semantic::Graph;
+use crate::text;
+
+#[cfg(test)]
+use crate::layout::Engine;
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz defaults for head and tail labels: the angle in degrees between the edge and the line
+// from its end to the label, and the length of that line for labeldistance 1, in points.
+const DEFAULT_LABEL_ANGLE: f64 = -25.0;
+const PORT_LABEL_DISTANCE: f64 = 10.0;
+// Space around the graph label, and between a left or right justified cluster label and the
+// side of the cluster, in points.
+const LABEL_PAD_X: f64 = 8.0;
+const LABEL_PAD_Y: f64 = 4.0;
+
+// A label of the given size centered at pos.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placed {
+    pub pos: Point,
+    pub size: (f64, f64),
+}
+
+impl Placed {
+    pub fn rect(&self) -> Rect {
+        rect_around(self.pos, self.size)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EdgeLabels {
+    pub label: Option<Placed>,
+    pub xlabel: Option<Placed>,
+    pub head: Option<Placed>,
+    pub tail: Option<Placed>,
+}
+
+// Where the labels drawn outside of nodes go. Clusters are indexed like Graph::subgraphs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Labels {
+    pub graph: Option<Placed>,
+    pub clusters: Vec<Option<Placed>>,
+    pub nodes: Vec<Option<Placed>>,
+    pub edges: Vec<EdgeLabels>,
+}
+
+impl Labels {
+    fn all(&self) -> impl Iterator<Item = &Placed> {
+        self.graph
+            .iter()
+            .chain(self.clusters.iter().flatten())
+            .chain(self.nodes.iter().flatten())
+            .chain(self.edges.iter().flat_map(|edge| {
+                [&edge.label, &edge.xlabel, &edge.head, &edge.tail]
+                    .into_iter()
+                    .flatten()
+            }))
+    }
+
+    pub fn rects(&self) -> Vec<Rect> {
+        self.all().map(Placed::rect).collect()
+    }
+
+    pub fn translate(&mut self, (dx, dy): Point) {
+        let edges = self.edges.iter_mut().flat_map(|edge| {
+            [
+                &mut edge.label,
+                &mut edge.xlabel,
+                &mut edge.head,
+                &mut edge.tail,
+            ]
+        });
+        for placed in self
+            .clusters
+            .iter_mut()
+            .chain(self.nodes.iter_mut())
+            .chain(std::iter::once(&mut self.graph))
+            .chain(edges)
+            .flatten()
+        {
+            placed.pos = (placed.pos.0 + dx, placed.pos.1 + dy);
+        }
+    }
+
+    // Puts the label of the root graph below the drawing, or above it when labelloc is t, and
+    // returns the bounding box grown to make room for it.
+    pub fn place_graph_label(&mut self, graph: &Graph, bb: Rect) -> Option<Rect> {
+        let size = text::graph_label_size(graph)?;
+        let (width, height) = (size.0 + 2.0 * LABEL_PAD_X, size.1 + 2.0 * LABEL_PAD_Y);
+        let (mut x0, mut y0, mut x1, mut y1) = bb;
+        let grow = f64::max(width - (x1 - x0), 0.0) / 2.0;
+        x0 -= grow;
+        x1 += grow;
+        let y = if graph.attrs.get("labelloc") == Some("t") {
+            y1 += height;
+            y1 - height / 2.0
+        } else {
+            y0 -= height;
+            y0 + height / 2.0
+        };
+        let x = match graph.attrs.get("labeljust") {
+            Some("l") => x0 + width / 2.0,
+            Some("r") => x1 - width / 2.0,
+            _ => (x0 + x1) / 2.0,
+        };
+        self.graph = Some(Placed { pos: (x, y), size });
+        Some((x0, y0, x1, y1))
+    }
+
+    // Stores the positions as Graphviz does: lp for the labels of the graph, clusters and edges,
+    // with lwidth and lheight in inches for those of the graph and clusters, xlp for xlabels of
+    // nodes and edges, and head_lp and tail_lp for head and tail labels.
+    pub fn set_attrs(&self, graph: &mut Graph) {
+        let graph_label = |attrs: &mut crate::semantic::Attrs, placed: &Placed| {
+            attrs.set("lp", &format_point(placed.pos));
+            attrs.set("lwidth", &format_number(placed.size.0 / POINTS_PER_INCH, 2));
+            attrs.set(
+                "lheight",
+                &format_number(placed.size.1 / POINTS_PER_INCH, 2),
+            );
+        };
+        if let Some(placed) = &self.graph {
+            graph_label(&mut graph.attrs, placed);
+        }
+        for (subgraph, placed) in graph.subgraphs.iter_mut().zip(self.clusters.iter()) {
+            if let Some(placed) = placed {
+                graph_label(&mut subgraph.attrs, placed);
+            }
+        }
+        for (node, placed) in graph.nodes.iter_mut().zip(self.nodes.iter()) {
+            if let Some(placed) = placed {
+                node.attrs.set("xlp", &format_point(placed.pos));
+            }
+        }
+        for (edge, labels) in graph.edges.iter_mut().zip(self.edges.iter()) {
+            for (attr, placed) in [
+                ("lp", &labels.label),
+                ("xlp", &labels.xlabel),
+                ("head_lp", &labels.head),
+                ("tail_lp", &labels.tail),
+            ] {
+                if let Some(placed) = placed {
+                    edge.attrs.set(attr, &format_point(placed.pos));
+                }
+            }
+        }
+    }
+}
+
+// The label of a cluster inside the top of its box, or the bottom when labelloc is b, where
+// the layout left room for it. labeljust l and r put it on the left or right.
+fn cluster_label(graph: &Graph, cluster: usize, (x0, y0, x1, y1): Rect) -> Option<Placed> {
+    let subgraph = &graph.subgraphs[cluster];
+    let size = text::cluster_label_size(graph, subgraph)?;
+    let y = if subgraph.attrs.get("labelloc") == Some("b") {
+        y0 + size.1 / 2.0
+    } else {
+        y1 - size.1 / 2.0
+    };
+    let x = match subgraph.attrs.get("labeljust") {
+        Some("l") => x0 + LABEL_PAD_X + size.0 / 2.0,
+        Some("r") => x1 - LABEL_PAD_X - size.0 / 2.0,
+        _ => (x0 + x1) / 2.0,
+    };
+    Some(Placed { pos: (x, y), size })
+}
+
+// The end of an edge at its head or tail, and a point a little way along the edge from it, as
+// place_portlabel in Graphviz takes them.
+fn edge_end(spline: &Spline, head: bool) -> Option<(Point, Point)> {
+    let points = &spline.points;
+    if points.len() < 4 {
+        return None;
+    }
+    if head {
+        let last = &points[points.len() - 4..];
+        Some(match spline.end {
+            Some(tip) => (tip, last[3]),
+            None => (last[3], route::bezier_point(last, 0.9)),
+        })
+    } else {
+        Some(match spline.start {
+            Some(tip) => (tip, points[0]),
+            None => (points[0], route::bezier_point(&points[..4], 0.1)),
+        })
+    }
+}
+
+// A head or tail label labelangle degrees from the edge and labeldistance times 10 points away
+// from its end. Graphviz places them this way only when one of the two is given, and otherwise
+// like xlabels.
+fn port_label(graph: &Graph, edge: usize, spline: &Spline, head: bool) -> Option<Placed> {
+    let attrs = &graph.edges[edge].attrs;
+    if attrs.get("labelangle").is_none() && attrs.get("labeldistance").is_none() {
+        return None;
+    }
+    let size = text::edge_label_size(graph, &graph.edges[edge], port_attr(head))?;
+    let (end, along) = edge_end(spline, head)?;
+    let angle = layout::number(attrs, "labelangle", DEFAULT_LABEL_ANGLE)
+        .unwrap_or(DEFAULT_LABEL_ANGLE)
+        .max(-180.0);
+    let distance = layout::number(attrs, "labeldistance", 1.0)
+        .unwrap_or(1.0)
+        .max(0.0)
+        * PORT_LABEL_DISTANCE;
+    let angle = (along.1 - end.1).atan2(along.0 - end.0) + angle.to_radians();
+    Some(Placed {
+        pos: (
+            end.0 + distance * angle.cos(),
+            end.1 + distance * angle.sin(),
+        ),
+        size,
+    })
+}
+
+fn port_attr(head: bool) -> &'static str {
+    if head {
+        "headlabel"
+    } else {
+        "taillabel"
+    }
+}
+
+fn overlap(a: Rect, b: Rect) -> f64 {
+    let width = a.2.min(b.2) - a.0.max(b.0);
+    let height = a.3.min(b.3) - a.1.max(b.1);
+    if width > 0.0 && height > 0.0 {
+        width * height
+    } else {
+        0.0
+    }
+}
+
+// Puts a label next to the box it belongs to, trying the corners, starting at the upper left,
+// then the sides. It takes the first place where it covers no node or label placed so far, or
+// else the one where it covers the least.
+fn place_beside(grid: &Grid, (x0, y0, x1, y1): Rect, (width, height): (f64, f64)) -> Point {
+    let (w, h) = (width / 2.0, height / 2.0);
+    let (cx, cy) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+    let candidates = [
+        (x0 - w, y1 + h),
+        (x1 + w, y1 + h),
+        (x0 - w, y0 - h),
+        (x1 + w, y0 - h),
+        (cx, y1 + h),
+        (cx, y0 - h),
+        (x0 - w, cy),
+        (x1 + w, cy),
+    ];
+    let mut best = (f64::INFINITY, candidates[0]);
+    for candidate in candidates {
+        let rect = rect_around(candidate, (width, height));
+        let covered: f64 = grid
+            .near(rect)
+            .into_iter()
+            .map(|i| overlap(rect, grid.rect(i)))
+            .sum();
+        if covered == 0.0 {
+            return candidate;
+        }
+        if covered < best.0 {
+            best = (covered, candidate);
+        }
+    }
+    best.1
+}
+
+// Places the labels drawn outside of nodes, in the coordinates of the layout. Cluster labels go
+// where the layout made room for them, head and tail labels as labelangle and labeldistance ask.
+// The others are placed as Graphviz places xlabels, one after another, each next to its node or
+// to the middle or end of its edge, avoiding the nodes and the labels placed before it.
+pub fn place(
+    graph: &Graph,
+    positions: &[Point],
+    sizes: &[(f64, f64)],
+    clusters: &[ClusterBox],
+    splines: &[Spline],
+) -> Labels {
+    let mut labels = Labels {
+        graph: None,
+        clusters: vec![None; graph.subgraphs.len()],
+        nodes: vec![None; graph.nodes.len()],
+        edges: vec![EdgeLabels::default(); graph.edges.len()],
+    };
+    // Cells about as large as a node.
+    let cell = sizes
+        .iter()
+        .map(|&(width, height)| width.max(height))
+        .sum::<f64>()
+        / sizes.len().max(1) as f64;
+    let mut grid = Grid::new(cell.max(1.0));
+    for (&position, &size) in positions.iter().zip(sizes) {
+        grid.insert(rect_around(position, size));
+    }
+    for &(cluster, rect) in clusters {
+        labels.clusters[cluster] = cluster_label(graph, cluster, rect);
+        if let Some(placed) = labels.clusters[cluster] {
+            grid.insert(placed.rect());
+        }
+    }
+    for (i, spline) in splines.iter().enumerate() {
+        labels.edges[i].head = port_label(graph, i, spline, true);
+        labels.edges[i].tail = port_label(graph, i, spline, false);
+        for placed in [labels.edges[i].head, labels.edges[i].tail]
+            .iter()
+            .flatten()
+        {
+            grid.insert(placed.rect());
+        }
+    }
+
+    let mut place_next = |anchor: Rect, size: (f64, f64)| {
+        let pos = place_beside(&grid, anchor, size);
+        let placed = Placed { pos, size };
+        grid.insert(placed.rect());
+        Some(placed)
+    };
+    let point = |(x, y): Point| (x, y, x, y);
+    for (i, node) in graph.nodes.iter().enumerate() {
+        if let Some(size) = text::node_xlabel_size(graph, node) {
+            labels.nodes[i] = place_next(rect_around(positions[i], sizes[i]), size);
+        }
+    }
+    for attr in ["label", "xlabel", "headlabel", "taillabel"] {
+        for (i, edge) in graph.edges.iter().enumerate() {
+            let placed = &mut labels.edges[i];
+            let slot = match attr {
+                "label" => &mut placed.label,
+                "xlabel" => &mut placed.xlabel,
+                "headlabel" => &mut placed.head,
+                _ => &mut placed.tail,
+            };
+            if slot.is_some() {
+                continue;
+            }
+            let size = match text::edge_label_size(graph, edge, attr) {
+                Some(size) => size,
+                None => continue,
+            };
+            let anchor = match attr {
+                "headlabel" | "taillabel" => {
+                    edge_end(&splines[i], attr == "headlabel").map(|(end, _)| end)
+                }
+                _ => splines[i].midpoint(),
+            };
+            if let Some(anchor) = anchor {
+                *slot = place_next(point(anchor), size);
+            }
+        }
+    }
+    labels
+}
+
+#[test]
+fn test_place() {
+    let graph = from_dot(
+        r#"digraph {
+    a [pos = "0,0!", xlabel = "x"]
+    b [pos = "3,0!"]
+    a -> b [label = "ab", headlabel = "h", taillabel = "t", labelangle = 90]
+    b -> a [label = "ba", xlabel = "y"]
+}"#,
+    );
+    let laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    let point = |attrs: &crate::semantic::Attrs, attr: &str| {
+        layout::parse_pos(attrs.get(attr).unwrap()).unwrap().0
+    };
+    let (a, b) = (
+        point(&laid_out.nodes[0].attrs, "pos"),
+        point(&laid_out.nodes[1].attrs, "pos"),
+    );
+    // The xlabel of a goes to its upper left.
+    let xlp = point(&laid_out.nodes[0].attrs, "xlp");
+    assert!(xlp.0 < a.0 - 27.0 && xlp.1 > a.1 + 18.0);
+    // Labels of the two edges both start at the middle of the line and do not overlap.
+    let (lp0, lp1) = (
+        point(&laid_out.edges[0].attrs, "lp"),
+        point(&laid_out.edges[1].attrs, "lp"),
+    );
+    assert!(lp0.0 > a.0 + 27.0 && lp0.0 < b.0 - 27.0);
+    assert_ne!(lp0, lp1);
+    assert!(laid_out.edges[1].attrs.get("xlp").is_some());
+    // labelangle 90 turns counterclockwise from the edge, which puts the head label 10 points
+    // below the arrow tip and the tail label 10 points above the start of the edge.
+    let head = point(&laid_out.edges[0].attrs, "head_lp");
+    assert!((head.1 - (a.1 - 10.0)).abs() < 0.01);
+    assert!((head.0 - (b.0 - 27.0)).abs() < 0.01);
+    let tail = point(&laid_out.edges[0].attrs, "tail_lp");
+    assert!((tail.1 - (a.1 + 10.0)).abs() < 0.01);
+    // The drawing grows to fit the labels.
+    let bb = laid_out.attrs.get("bb").unwrap();
+    assert_ne!(bb, "0,0,252,36");
+
+    let graph = from_dot(r#"graph { label = "Title"; labelloc = t; a }"#);
+    let laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    let lp = point(&laid_out.attrs, "lp");
+    assert!(lp.1 > point(&laid_out.nodes[0].attrs, "pos").1 + 18.0);
+    assert_eq!(laid_out.attrs.get("lheight"), Some("0.23"));
+}
@@ -0,0 +1,1314 @@
+use crate::arrow::{self, ArrowShape, EdgeArrows};
+use crate::route::{self, Splines};
+use crate::semantic::{Attrs, Edge, Graph, Node};
+use crate::{circo, dot, fdp, html, labels, neato, osage, overlap, pack, patchwork, record, sfdp};
+use crate::{shape, text, twopi};
+use clap::ValueEnum;
+use log::warn;
+use std::collections::HashMap;
+use std::fmt;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+pub const POINTS_PER_INCH: f64 = 72.0;
+
+const DEFAULT_WIDTH: f64 = 0.75;
+const DEFAULT_HEIGHT: f64 = 0.5;
+const DEFAULT_POINT_SIZE: f64 = 0.05;
+// Space between the label and the node outline on each side, in points.
+const LABEL_MARGIN_X: f64 = 8.0;
+const LABEL_MARGIN_Y: f64 = 4.0;
+const ARROW_LENGTH: f64 = 10.0;
+const DEFAULT_SEED: u64 = 1;
+
+pub type Point = (f64, f64);
+// The lower left and upper right corners of a box.
+pub type Rect = (f64, f64, f64, f64);
+// The box of a cluster, given by its index in Graph::subgraphs.
+pub type ClusterBox = (usize, Rect);
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Engine {
+    Dot,
+    Neato,
+    Fdp,
+    Sfdp,
+    Circo,
+    Twopi,
+    Osage,
+    Patchwork,
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+// Reads the layout graph attribute, which -K overrides.
+pub fn engine_of(graph: &Graph) -> Result<Option<Engine>, String> {
+    match graph.attrs.get("layout") {
+        Some(name) => Engine::from_str(name, true)
+            .map(Some)
+            .map_err(|_| format!("{}:{} Unknown layout engine {}", file!(), line!(), name)),
+        None => Ok(None),
+    }
+}
+
+// xorshift64*, so that layouts are reproducible for a given start seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    // A number in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545f4914f6cdd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[test]
+fn test_rng() {
+    let mut a = Rng::new(1);
+    let mut b = Rng::new(1);
+    let xs: Vec<f64> = (0..100).map(|_| a.next_f64()).collect();
+    assert!(xs.iter().all(|x| (0.0..1.0).contains(x)));
+    assert_eq!(xs[..3], [b.next_f64(), b.next_f64(), b.next_f64()]);
+    assert_ne!(Rng::new(2).next_f64(), xs[0]);
+}
+
+pub fn number(attrs: &Attrs, name: &str, default: f64) -> Result<f64, String> {
+    match attrs.get(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("{}:{} Bad {} {}", file!(), line!(), name, value)),
+        None => Ok(default),
+    }
+}
+
+// A width and height in inches such as size or page, given as "w,h" or as a single number for
+// both, in points. The flag tells whether the value ends in '!'.
+pub fn sides(attrs: &Attrs, name: &str) -> Result<Option<(f64, f64, bool)>, String> {
+    let Some(value) = attrs.get(name) else {
+        return Ok(None);
+    };
+    let bad_value = || format!("{}:{} Bad {} {}", file!(), line!(), name, value);
+    let sides: Vec<f64> = value
+        .trim_end_matches('!')
+        .split(',')
+        .map(|side| side.trim().parse().map_err(|_| bad_value()))
+        .collect::<Result<_, _>>()?;
+    let (width, height) = match sides[..] {
+        [side] => (side, side),
+        [width, height] => (width, height),
+        _ => return Err(bad_value()),
+    };
+    Ok(Some((
+        width * POINTS_PER_INCH,
+        height * POINTS_PER_INCH,
+        value.ends_with('!'),
+    )))
+}
+
+// A margin around nodes, as given by sep and esep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Margin {
+    // Points added on the left and right and at the top and bottom.
+    Add(f64, f64),
+    // Factors by which width and height grow.
+    Scale(f64, f64),
+}
+
+impl Margin {
+    pub fn around(self, (width, height): (f64, f64)) -> (f64, f64) {
+        match self {
+            Margin::Add(x, y) => (width + 2.0 * x, height + 2.0 * y),
+            Margin::Scale(x, y) => (width * (1.0 + x), height * (1.0 + y)),
+        }
+    }
+}
+
+// Parses "+x,y", which adds points, or "x,y", which scales by 1 + x and 1 + y. A single number
+// stands for both.
+pub fn margin(attrs: &Attrs, name: &str, default: Margin) -> Result<Margin, String> {
+    let value = match attrs.get(name) {
+        Some(value) => value.trim(),
+        None => return Ok(default),
+    };
+    let bad = || format!("{}:{} Bad {} {}", file!(), line!(), name, value);
+    let (add, rest) = match value.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let numbers: Vec<f64> = rest
+        .split(',')
+        .map(|x| x.trim().parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    let (x, y) = match numbers[..] {
+        [x] => (x, x),
+        [x, y] => (x, y),
+        _ => return Err(bad()),
+    };
+    Ok(if add {
+        Margin::Add(x, y)
+    } else {
+        Margin::Scale(x, y)
+    })
+}
+
+#[test]
+fn test_margin() {
+    let graph = from_dot(r#"graph { sep = "+4"; esep = "0.5,1"; a [sep = "1,2,3"] }"#);
+    let default = Margin::Add(0.0, 0.0);
+    assert_eq!(
+        margin(&graph.attrs, "sep", default),
+        Ok(Margin::Add(4.0, 4.0))
+    );
+    assert_eq!(
+        margin(&graph.attrs, "esep", default),
+        Ok(Margin::Scale(0.5, 1.0))
+    );
+    assert_eq!(margin(&graph.attrs, "pad", default), Ok(default));
+    assert!(margin(&graph.nodes[0].attrs, "sep", default).is_err());
+    assert_eq!(Margin::Add(4.0, 2.0).around((54.0, 36.0)), (62.0, 40.0));
+    assert_eq!(Margin::Scale(0.5, 1.0).around((54.0, 36.0)), (81.0, 72.0));
+}
+
+// Parses pos, "x,y" in inches, where a trailing '!' pins the node like pin=true does.
+pub fn parse_pos(value: &str) -> Result<(Point, bool), String> {
+    let pinned = value.trim().ends_with('!');
+    let coords: Vec<f64> = value
+        .trim()
+        .trim_end_matches('!')
+        .split(',')
+        .map(|x| x.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("{}:{} Bad pos {}", file!(), line!(), value))?;
+    if coords.len() < 2 {
+        return Err(format!("{}:{} Bad pos {}", file!(), line!(), value));
+    }
+    Ok(((coords[0], coords[1]), pinned))
+}
+
+// Starting positions in inches and whether each node is pinned. Nodes with pos start there. The
+// others are placed by start within a square of the given side: "regular" puts them on a circle,
+// "random" with an optional seed, or just a seed, scatters them. Without a seed the same seed is
+// always used, so layouts are reproducible.
+pub fn initial_positions(graph: &Graph, side: f64) -> Result<(Vec<Point>, Vec<bool>), String> {
+    let n = graph.nodes.len();
+    let start = graph.attrs.get("start").unwrap_or_default();
+    let (regular, seed) = match start.strip_prefix("regular") {
+        Some(seed) => (true, seed),
+        None => (
+            false,
+            start
+                .strip_prefix("random")
+                .or(start.strip_prefix("self"))
+                .unwrap_or(start),
+        ),
+    };
+    let seed = match seed {
+        "" => DEFAULT_SEED,
+        seed => seed
+            .parse()
+            .map_err(|_| format!("{}:{} Bad start {}", file!(), line!(), start))?,
+    };
+    let mut rng = Rng::new(seed);
+    let mut positions = Vec::new();
+    let mut pinned = Vec::new();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let (position, is_pinned) = match node.attrs.get("pos") {
+            Some(pos) => parse_pos(pos)?,
+            None if regular => {
+                let angle = 2.0 * std::f64::consts::PI * i as f64 / n as f64;
+                ((side * angle.cos(), side * angle.sin()), false)
+            }
+            None => ((side * rng.next_f64(), side * rng.next_f64()), false),
+        };
+        positions.push(position);
+        pinned.push(is_pinned || node.attrs.get("pin") == Some("true"));
+    }
+    Ok((positions, pinned))
+}
+
+fn inches(node: &Node, attr: &str, default: f64) -> f64 {
+    node.attrs
+        .get(attr)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+// Width and height of a node in points. The label with its margins is fitted inside the shape,
+// and width and height are minimums unless fixedsize is set. Regular shapes are as wide as they
+// are high, taking the larger of width and height when either is given and the smaller default
+// otherwise, and outer peripheries add a gap on every side. Points have no label and default to
+// DEFAULT_POINT_SIZE. Records take the size of their fields, which have margins of their own.
+pub fn node_size(graph: &Graph, node: &Node) -> (f64, f64) {
+    let shape = shape::shape_of(node);
+    let plain = node.attrs.get("shape") == Some("plain");
+    let (default_width, default_height) = if plain {
+        (0.0, 0.0)
+    } else if shape.point {
+        (DEFAULT_POINT_SIZE, DEFAULT_POINT_SIZE)
+    } else {
+        (DEFAULT_WIDTH, DEFAULT_HEIGHT)
+    };
+    let mut min_width = inches(node, "width", default_width) * POINTS_PER_INCH;
+    let mut min_height = inches(node, "height", default_height) * POINTS_PER_INCH;
+    if shape.regular {
+        let size = match (node.attrs.get("width"), node.attrs.get("height")) {
+            (Some(_), None) => min_width,
+            (None, Some(_)) => min_height,
+            // A point takes the smaller size even when both are given.
+            (Some(_), Some(_)) if !shape.point => min_width.max(min_height),
+            _ => min_width.min(min_height),
+        };
+        min_width = size;
+        min_height = size;
+    }
+    let margin = shape.periphery_margin();
+    if shape.point || node.attrs.get("fixedsize") == Some("true") {
+        return (min_width + margin, min_height + margin);
+    }
+    let (margin_x, margin_y) = if plain {
+        (0.0, 0.0)
+    } else {
+        (LABEL_MARGIN_X, LABEL_MARGIN_Y)
+    };
+    let (width, height) = if let Some(size) = record::record_size(graph, node) {
+        size
+    } else if let Some((width, height)) = text::node_label_size(graph, node) {
+        shape.fit((width + 2.0 * margin_x, height + 2.0 * margin_y))
+    } else {
+        (0.0, 0.0)
+    };
+    let (mut width, mut height) = (width.max(min_width), height.max(min_height));
+    if shape.regular {
+        width = width.max(height);
+        height = width;
+    }
+    (width + margin, height + margin)
+}
+
+#[test]
+fn test_node_size() {
+    let graph = from_dot(
+        r#"graph {
+    a
+    b [label = "a much longer label", shape = box]
+    c [width = 2, height = 1, shape = circle]
+    d [label = "a much longer label", fixedsize = true]
+    e [shape = doublecircle]
+    f [shape = point, width = 0.1, height = 1]
+    g [label = "a much longer label", shape = hexagon]
+    h [label = "a much longer label|{a much longer label|x}", shape = record]
+}"#,
+    );
+    assert_eq!(node_size(&graph, &graph.nodes[0]), (54.0, 36.0));
+    let (width, height) = node_size(&graph, &graph.nodes[1]);
+    assert!(width > 54.0 + 2.0 * LABEL_MARGIN_X);
+    assert_eq!(height, 36.0);
+    assert_eq!(node_size(&graph, &graph.nodes[2]), (144.0, 144.0));
+    assert_eq!(node_size(&graph, &graph.nodes[3]), (54.0, 36.0));
+    assert_eq!(node_size(&graph, &graph.nodes[4]), (44.0, 44.0));
+    assert_eq!(node_size(&graph, &graph.nodes[5]), (7.2, 7.2));
+    let (box_width, _) = node_size(&graph, &graph.nodes[1]);
+    assert!(node_size(&graph, &graph.nodes[6]).0 > box_width);
+    // Fields leave as much room around their text as boxes do, and the second long field is
+    // above x.
+    let (width, height) = node_size(&graph, &graph.nodes[7]);
+    assert_eq!(width, 2.0 * box_width);
+    assert_eq!(height, 2.0 * (16.8 + 8.0));
+}
+
+// Neighbours of every node, ignoring the direction of edges, loops and parallel edges.
+pub fn neighbours(graph: &Graph) -> Vec<Vec<usize>> {
+    let mut neighbours = vec![Vec::new(); graph.nodes.len()];
+    for edge in graph.edges.iter().filter(|edge| edge.tail != edge.head) {
+        if !neighbours[edge.tail].contains(&edge.head) {
+            neighbours[edge.tail].push(edge.head);
+            neighbours[edge.head].push(edge.tail);
+        }
+    }
+    neighbours
+}
+
+// The connected components, each in breadth-first order from its first node.
+pub fn components(neighbours: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; neighbours.len()];
+    let mut components = Vec::new();
+    for first in 0..neighbours.len() {
+        if seen[first] {
+            continue;
+        }
+        seen[first] = true;
+        let mut component = vec![first];
+        let mut i = 0;
+        while i < component.len() {
+            for &next in neighbours[component[i]].iter() {
+                if !seen[next] {
+                    seen[next] = true;
+                    component.push(next);
+                }
+            }
+            i += 1;
+        }
+        components.push(component);
+    }
+    components
+}
+
+#[test]
+fn test_components() {
+    let graph = from_dot("graph { a -- b; c; d -- a; b -- a; e -- e -- f }");
+    let neighbours = neighbours(&graph);
+    assert_eq!(neighbours[0], vec![1, 3]);
+    assert_eq!(neighbours[4], vec![5]);
+    assert_eq!(
+        components(&neighbours),
+        vec![vec![0, 1, 3], vec![2], vec![4, 5]]
+    );
+}
+
+// The subgraph induced by the nodes of a component, with the attributes of the graph. root is
+// left out from components that do not have the node it names.
+fn component_graph(graph: &Graph, component: &[usize]) -> Graph {
+    let mut index = vec![usize::MAX; graph.nodes.len()];
+    for (i, &node) in component.iter().enumerate() {
+        index[node] = i;
+    }
+    let mut attrs = Attrs::default();
+    for (name, value) in graph.attrs.ids() {
+        if name != "root"
+            || component
+                .iter()
+                .any(|&i| graph.nodes[i].name == value.value())
+        {
+            attrs.set_id(&name, &value);
+        }
+    }
+    Graph {
+        name: graph.name.clone(),
+        directed: graph.directed,
+        strict: graph.strict,
+        attrs,
+        nodes: component.iter().map(|&i| graph.nodes[i].clone()).collect(),
+        edges: graph
+            .edges
+            .iter()
+            .filter(|edge| index[edge.tail] != usize::MAX)
+            .map(|edge| Edge {
+                tail: index[edge.tail],
+                head: index[edge.head],
+                attrs: edge.attrs.clone(),
+            })
+            .collect(),
+        subgraphs: Vec::new(),
+    }
+}
+
+#[test]
+fn test_component_graph() {
+    let graph = from_dot("graph { root = d; a -- b; c -- d -- c }");
+    let component = component_graph(&graph, &[2, 3]);
+    assert_eq!(component.nodes[0].name, "c");
+    assert_eq!(component.edges.len(), 2);
+    assert_eq!((component.edges[1].tail, component.edges[1].head), (1, 0));
+    assert_eq!(component.attrs.get("root"), Some("d"));
+    assert_eq!(component_graph(&graph, &[0, 1]).attrs.get("root"), None);
+}
+
+// The clusters directly inside a cluster, or inside the root graph for None, looking through
+// subgraphs that are not clusters.
+pub fn clusters_in(graph: &Graph, parent: Option<usize>) -> Vec<usize> {
+    let mut stack: Vec<usize> = match parent {
+        Some(parent) => graph.subgraphs[parent].subgraphs.clone(),
+        None => {
+            let children: Vec<usize> = graph
+                .subgraphs
+                .iter()
+                .flat_map(|subgraph| subgraph.subgraphs.iter().copied())
+                .collect();
+            (0..graph.subgraphs.len())
+                .filter(|i| !children.contains(i))
+                .collect()
+        }
+    };
+    stack.reverse();
+    let mut clusters = Vec::new();
+    while let Some(i) = stack.pop() {
+        if graph.subgraphs[i].is_cluster() {
+            clusters.push(i);
+        } else {
+            stack.extend(graph.subgraphs[i].subgraphs.iter().rev());
+        }
+    }
+    clusters
+}
+
+// The nodes of a cluster, or of the root graph for None, that are not in any of its clusters.
+pub fn nodes_in(graph: &Graph, parent: Option<usize>, clusters: &[usize]) -> Vec<usize> {
+    let nodes: Vec<usize> = match parent {
+        Some(parent) => graph.subgraphs[parent].nodes.clone(),
+        None => (0..graph.nodes.len()).collect(),
+    };
+    nodes
+        .into_iter()
+        .filter(|i| {
+            !clusters
+                .iter()
+                .any(|&cluster| graph.subgraphs[cluster].nodes.contains(i))
+        })
+        .collect()
+}
+
+#[test]
+fn test_clusters_in() {
+    let graph = from_dot(
+        r#"graph {
+    a
+    subgraph cluster_x { b; subgraph cluster_y { c } }
+    subgraph { subgraph cluster_z { d } e }
+}"#,
+    );
+    let names = |clusters: Vec<usize>| -> Vec<String> {
+        clusters
+            .into_iter()
+            .map(|i| graph.subgraphs[i].name.clone())
+            .collect()
+    };
+    let top = clusters_in(&graph, None);
+    assert_eq!(names(top.clone()), vec!["cluster_x", "cluster_z"]);
+    assert_eq!(nodes_in(&graph, None, &top), vec![0, 4]);
+    assert_eq!(names(clusters_in(&graph, Some(top[0]))), vec!["cluster_y"]);
+    let inner = clusters_in(&graph, Some(top[0]));
+    assert_eq!(nodes_in(&graph, Some(top[0]), &inner), vec![1]);
+}
+
+// Formats a coordinate the way Graphviz does, with at most the given number of decimals.
+pub fn format_number(x: f64, decimals: usize) -> String {
+    let s = format!("{:.*}", decimals, x);
+    let s = if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &s
+    };
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+#[test]
+fn test_format_number() {
+    assert_eq!(format_number(27.0, 2), "27");
+    assert_eq!(format_number(0.5, 2), "0.5");
+    assert_eq!(format_number(1.23456, 2), "1.23");
+    assert_eq!(format_number(-0.001, 2), "0");
+    assert_eq!(format_number(100.0, 0), "100");
+}
+
+// Formats a point as "x,y", as in pos.
+pub fn format_point((x, y): Point) -> String {
+    format!("{},{}", format_number(x, 2), format_number(y, 2))
+}
+
+// An edge as Graphviz gives it in pos, "s,x,y e,x,y p0 p1 p2 p3 ...": the control points of a
+// cubic Bezier spline from the tail to the head, and the tips of the arrows at its start and end
+// when there are arrows.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Spline {
+    pub start: Option<Point>,
+    pub end: Option<Point>,
+    pub points: Vec<Point>,
+}
+
+impl Spline {
+    pub fn parse(pos: &str) -> Result<Spline, String> {
+        let mut spline = Spline::default();
+        for point in pos.split_whitespace() {
+            if let Some(tip) = point.strip_prefix("s,") {
+                spline.start = Some(parse_pos(tip)?.0);
+            } else if let Some(tip) = point.strip_prefix("e,") {
+                spline.end = Some(parse_pos(tip)?.0);
+            } else {
+                spline.points.push(parse_pos(point)?.0);
+            }
+        }
+        Ok(spline)
+    }
+
+    // Points along the curve, ten on each of its Bezier segments.
+    pub fn sample(&self) -> Vec<Point> {
+        let mut points: Vec<Point> = self.points.first().copied().into_iter().collect();
+        for segment in self.points.windows(4).step_by(3) {
+            points.extend((1..=10).map(|i| route::bezier_point(segment, i as f64 / 10.0)));
+        }
+        points
+    }
+
+    // The point halfway along the curve.
+    pub fn midpoint(&self) -> Option<Point> {
+        let points = self.sample();
+        let length = |(a, b): (Point, Point)| (b.0 - a.0).hypot(b.1 - a.1);
+        let mut half = points.windows(2).map(|w| length((w[0], w[1]))).sum::<f64>() / 2.0;
+        for w in points.windows(2) {
+            let segment = length((w[0], w[1]));
+            if segment > 0.0 && half <= segment {
+                let t = half / segment;
+                return Some((
+                    w[0].0 + (w[1].0 - w[0].0) * t,
+                    w[0].1 + (w[1].1 - w[0].1) * t,
+                ));
+            }
+            half -= segment;
+        }
+        points.first().copied()
+    }
+
+    // The box around the curve and the arrow tips.
+    pub fn bounds(&self) -> Option<Rect> {
+        self.sample()
+            .into_iter()
+            .chain(self.start)
+            .chain(self.end)
+            .map(|(x, y)| (x, y, x, y))
+            .reduce(union)
+    }
+
+    fn translate(&mut self, (dx, dy): Point) {
+        for point in self
+            .points
+            .iter_mut()
+            .chain(self.start.as_mut())
+            .chain(self.end.as_mut())
+        {
+            *point = (point.0 + dx, point.1 + dy);
+        }
+    }
+}
+
+impl fmt::Display for Spline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(start) = self.start {
+            write!(f, "s,{} ", format_point(start))?;
+        }
+        if let Some(end) = self.end {
+            write!(f, "e,{} ", format_point(end))?;
+        }
+        let points: Vec<String> = self.points.iter().map(|&p| format_point(p)).collect();
+        write!(f, "{}", points.join(" "))
+    }
+}
+
+#[test]
+fn test_spline() {
+    let spline = Spline::parse("e,30,0 s,0,0 3,0 10,0 20,0 27,0").unwrap();
+    assert_eq!(spline.start, Some((0.0, 0.0)));
+    assert_eq!(spline.points.len(), 4);
+    assert_eq!(spline.to_string(), "s,0,0 e,30,0 3,0 10,0 20,0 27,0");
+    assert_eq!(spline.midpoint(), Some((15.0, 0.0)));
+    assert_eq!(spline.bounds(), Some((0.0, 0.0, 30.0, 0.0)));
+    assert_eq!(Spline::default().midpoint(), None);
+    assert!(Spline::parse("e,1 2,3").is_err());
+}
+
+// The smallest box containing both boxes.
+pub fn union(a: Rect, b: Rect) -> Rect {
+    (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))
+}
+
+// The box of the given size centered at a point.
+pub fn rect_around((x, y): Point, (width, height): (f64, f64)) -> Rect {
+    (
+        x - width / 2.0,
+        y - height / 2.0,
+        x + width / 2.0,
+        y + height / 2.0,
+    )
+}
+
+// Boxes sorted into square cells, so that the boxes near a place can be found without looking
+// at all of them.
+pub struct Grid {
+    cell: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    rects: Vec<Rect>,
+}
+
+impl Grid {
+    pub fn new(cell: f64) -> Grid {
+        Grid {
+            cell,
+            cells: HashMap::new(),
+            rects: Vec::new(),
+        }
+    }
+
+    // The range of cells the box covers.
+    fn cells_of(&self, (x0, y0, x1, y1): Rect) -> (i64, i64, i64, i64) {
+        let cell = |x: f64| (x / self.cell).floor() as i64;
+        (cell(x0), cell(y0), cell(x1), cell(y1))
+    }
+
+    pub fn insert(&mut self, rect: Rect) -> usize {
+        let index = self.rects.len();
+        self.rects.push(rect);
+        let (i0, j0, i1, j1) = self.cells_of(rect);
+        for i in i0..=i1 {
+            for j in j0..=j1 {
+                self.cells.entry((i, j)).or_default().push(index);
+            }
+        }
+        index
+    }
+
+    pub fn rect(&self, index: usize) -> Rect {
+        self.rects[index]
+    }
+
+    // The boxes in the cells the box covers, which include all boxes it touches, each once and
+    // in the order they were inserted.
+    pub fn near(&self, rect: Rect) -> Vec<usize> {
+        let (i0, j0, i1, j1) = self.cells_of(rect);
+        let covered = (i1 - i0 + 1) as f64 * (j1 - j0 + 1) as f64;
+        let mut near = Vec::new();
+        if covered > self.cells.len() as f64 {
+            // A box larger than the occupied area is quicker to check against every cell.
+            for (&(i, j), indices) in self.cells.iter() {
+                if (i0..=i1).contains(&i) && (j0..=j1).contains(&j) {
+                    near.extend(indices);
+                }
+            }
+        } else {
+            for i in i0..=i1 {
+                for j in j0..=j1 {
+                    near.extend(self.cells.get(&(i, j)).into_iter().flatten());
+                }
+            }
+        }
+        near.sort_unstable();
+        near.dedup();
+        near
+    }
+}
+
+#[test]
+fn test_grid() {
+    let mut grid = Grid::new(10.0);
+    grid.insert((0.0, 0.0, 5.0, 5.0));
+    grid.insert((-30.0, -30.0, -25.0, -25.0));
+    grid.insert((0.0, 0.0, 100.0, 100.0));
+    assert_eq!(grid.near((1.0, 1.0, 2.0, 2.0)), vec![0, 2]);
+    assert_eq!(grid.near((50.0, 50.0, 51.0, 51.0)), vec![2]);
+    assert_eq!(grid.near((-1e9, -1e9, 1e9, 1e9)), vec![0, 1, 2]);
+    assert_eq!(grid.rect(1), (-30.0, -30.0, -25.0, -25.0));
+}
+
+// Length taken up by the arrows at one end of an edge.
+fn arrow_length(arrows: &[arrow::Arrow], size: f64) -> f64 {
+    if arrows.iter().all(|arrow| arrow.shape == ArrowShape::None) {
+        return 0.0;
+    }
+    ARROW_LENGTH * size * arrows.len() as f64
+}
+
+// Where an edge meets a node: the point it aims at, and the outline around that point it is
+// clipped on. Edges to compass points end there without being clipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct End {
+    pub point: Point,
+    outline: Option<(shape::Shape, (f64, f64))>,
+}
+
+// The end of an edge at a node with the given tailport or headport, such as "f0", "f0:ne" or
+// "ne". A record field or HTML table cell named by the port stands in for the node, as a box.
+// Compass points are on the box of the field, or on the outline of the node in their direction.
+// Unknown ports, which have already been warned about, and c and _ leave the edge aiming at the
+// center.
+pub fn edge_end(graph: &Graph, node: usize, (x, y): Point, size: (f64, f64), port: &str) -> End {
+    let names: Vec<&str> = port.split(':').collect();
+    let field = record::port_rect(graph, &graph.nodes[node], size, names[0])
+        .or_else(|| html::port_rect(graph, &graph.nodes[node].attrs, names[0]));
+    let compass = match (field, names.as_slice()) {
+        (Some(_), [_, compass]) | (None, [compass]) => *compass,
+        _ => "",
+    };
+    let (center, size, outline) = match field {
+        Some((x0, y0, x1, y1)) => (
+            (x + (x0 + x1) / 2.0, y + (y0 + y1) / 2.0),
+            (x1 - x0, y1 - y0),
+            shape::Shape::new(4),
+        ),
+        None => ((x, y), size, shape::shape_of(&graph.nodes[node])),
+    };
+    let (w, h) = (size.0 / 2.0, size.1 / 2.0);
+    let (dx, dy) = match compass {
+        "n" => (0.0, h),
+        "ne" => (w, h),
+        "e" => (w, 0.0),
+        "se" => (w, -h),
+        "s" => (0.0, -h),
+        "sw" => (-w, -h),
+        "w" => (-w, 0.0),
+        "nw" => (-w, h),
+        _ => {
+            return End {
+                point: center,
+                outline: Some((outline, size)),
+            }
+        }
+    };
+    let scale = if field.is_none() && !(w == 0.0 && h == 0.0) {
+        outline.boundary(size, (dx, dy)) / dx.hypot(dy)
+    } else {
+        1.0
+    };
+    End {
+        point: (center.0 + dx * scale, center.1 + dy * scale),
+        outline: None,
+    }
+}
+
+#[test]
+fn test_edge_end() {
+    let graph = from_dot(
+        r#"digraph {
+    a [shape = record, label = "<f0> x|<f1> x"]
+    b
+}"#,
+    );
+    let size = (60.0, 20.0);
+    let end = |node: usize, port: &str| edge_end(&graph, node, (100.0, 100.0), size, port);
+    assert_eq!(end(0, "").point, (100.0, 100.0));
+    assert_eq!(end(0, "f1").point, (115.0, 100.0));
+    assert_eq!(
+        end(0, "f1").outline,
+        Some((shape::Shape::new(4), (30.0, 20.0)))
+    );
+    assert_eq!(
+        end(0, "f0:sw"),
+        End {
+            point: (70.0, 90.0),
+            outline: None
+        }
+    );
+    assert_eq!(end(0, "n").point, (100.0, 110.0));
+    // On an ellipse ne is on the outline, inside the corner of the box.
+    let (x, y) = end(1, "ne").point;
+    assert!(x < 130.0 && y < 110.0);
+    assert!(((x - 100.0) / 30.0).powi(2) + ((y - 100.0) / 10.0).powi(2) - 1.0 < 1e-9);
+    assert_eq!(end(1, "f9").point, (100.0, 100.0));
+}
+
+// The ends of every edge, from tailport to headport.
+fn edge_ends(graph: &Graph, positions: &[Point], sizes: &[(f64, f64)]) -> Vec<(End, End)> {
+    graph
+        .edges
+        .iter()
+        .map(|edge| {
+            let end = |node: usize, attr: &str| {
+                let port = edge.attrs.get(attr).unwrap_or("");
+                edge_end(graph, node, positions[node], sizes[node], port)
+            };
+            (end(edge.tail, "tailport"), end(edge.head, "headport"))
+        })
+        .collect()
+}
+
+// An edge as a cubic Bezier spline going from the tail through the route, the points it bends
+// at, to the head. The ends are clipped at their outlines unless headclip or tailclip is false,
+// and moved back to leave room for arrows, whose tips are given by start and end. Edges with bad
+// arrows, which have already been warned about, are drawn without them.
+fn edge_spline(
+    graph: &Graph,
+    edge: usize,
+    positions: &[Point],
+    sizes: &[(f64, f64)],
+    (tail_end, head_end): (End, End),
+    route: &[Point],
+    smooth: bool,
+) -> Spline {
+    let edge = &graph.edges[edge];
+    let arrows = arrow::edge_arrows(&edge.attrs, graph.directed).unwrap_or(EdgeArrows {
+        head: Vec::new(),
+        tail: Vec::new(),
+        size: 1.0,
+    });
+    if edge.tail == edge.head {
+        // A loop leaves and enters the node on its right side.
+        let p = positions[edge.tail];
+        let (width, height) = sizes[edge.tail];
+        let start = (p.0 + width * 0.35, p.1 + height * 0.35);
+        let end = (p.0 + width * 0.35, p.1 - height * 0.35);
+        return Spline {
+            points: vec![
+                start,
+                (start.0 + width * 0.5, start.1 + width * 0.25),
+                (end.0 + width * 0.5, end.1 - width * 0.25),
+                end,
+            ],
+            ..Spline::default()
+        };
+    }
+    let (p, q) = (tail_end.point, head_end.point);
+    // The first and last segments, which the ends are clipped on.
+    let (first, last) = (
+        route.first().copied().unwrap_or(q),
+        route.last().copied().unwrap_or(p),
+    );
+    let (dx, dy) = (first.0 - p.0, first.1 - p.1);
+    let (ex, ey) = (q.0 - last.0, q.1 - last.1);
+    let (first_length, last_length) = (dx.hypot(dy), ex.hypot(ey));
+    let mut spline = Spline::default();
+    let mut polyline = vec![p];
+    polyline.extend(route.iter().copied());
+    polyline.push(q);
+    if first_length > 0.0 && last_length > 0.0 {
+        let mut from = 0.0;
+        let mut to = 0.0;
+        if let Some((outline, size)) = tail_end.outline {
+            if edge.attrs.get("tailclip") != Some("false") {
+                from = outline.boundary(size, (dx, dy));
+            }
+        }
+        if let Some((outline, size)) = head_end.outline {
+            if edge.attrs.get("headclip") != Some("false") {
+                to = outline.boundary(size, (ex, ey));
+            }
+        }
+        let head_arrow = arrow_length(&arrows.head, arrows.size);
+        let tail_arrow = arrow_length(&arrows.tail, arrows.size);
+        let fits = if route.is_empty() {
+            from + tail_arrow < first_length - to - head_arrow
+        } else {
+            from + tail_arrow < first_length && to + head_arrow < last_length
+        };
+        if fits {
+            let from_tail = |t: f64| (p.0 + dx / first_length * t, p.1 + dy / first_length * t);
+            let to_head = |t: f64| (q.0 - ex / last_length * t, q.1 - ey / last_length * t);
+            if head_arrow > 0.0 {
+                spline.end = Some(to_head(to));
+            }
+            if tail_arrow > 0.0 {
+                spline.start = Some(from_tail(from));
+            }
+            let end = polyline.len() - 1;
+            polyline[0] = from_tail(from + tail_arrow);
+            polyline[end] = to_head(to + head_arrow);
+        }
+    }
+    spline.points = route::bezier(&polyline, smooth);
+    spline
+}
+
+// Where the segment from p, outside the box, to q, inside it, enters the box.
+fn enter(p: Point, q: Point, (x0, y0, x1, y1): Rect) -> Point {
+    let mut t: f64 = 0.0;
+    for (from, to, low, high) in [(p.0, q.0, x0, x1), (p.1, q.1, y0, y1)] {
+        if from < low {
+            t = t.max((low - from) / (to - from));
+        } else if from > high {
+            t = t.max((high - from) / (to - from));
+        }
+    }
+    (p.0 + (q.0 - p.0) * t, p.1 + (q.1 - p.1) * t)
+}
+
+#[test]
+fn test_enter() {
+    let rect = (0.0, 0.0, 10.0, 10.0);
+    assert_eq!(enter((-10.0, 5.0), (5.0, 5.0), rect), (0.0, 5.0));
+    assert_eq!(enter((5.0, 30.0), (5.0, 0.0), rect), (5.0, 10.0));
+    assert_eq!(enter((-10.0, 20.0), (10.0, 0.0), rect), (0.0, 10.0));
+}
+
+// With compound=true, an edge with lhead ends where it enters the box of that cluster, and one
+// with ltail starts where it leaves the box of that cluster. The cluster must hold that end of
+// the edge and not the other.
+fn clip_compound(
+    graph: &Graph,
+    clusters: &[ClusterBox],
+    ends: &mut [(End, End)],
+    routes: &mut [Vec<Point>],
+) {
+    let cluster_box = |edge: &Edge, attr: &str, inside: usize, outside: usize| {
+        let name = edge.attrs.get(attr)?;
+        let Some(&(i, rect)) = clusters
+            .iter()
+            .find(|&&(i, _)| graph.subgraphs[i].name == name)
+        else {
+            warn!("Cluster {} of {} not found", name, attr);
+            return None;
+        };
+        let nodes = &graph.subgraphs[i].nodes;
+        if !nodes.contains(&inside) || nodes.contains(&outside) {
+            warn!(
+                "Edge {} -> {} does not have just one end in its {} {}",
+                graph.nodes[edge.tail].name, graph.nodes[edge.head].name, attr, name
+            );
+            return None;
+        }
+        Some(rect)
+    };
+    let inside = |(x, y): Point, (x0, y0, x1, y1): Rect| x0 <= x && x <= x1 && y0 <= y && y <= y1;
+    for (i, edge) in graph.edges.iter().enumerate() {
+        let head_box = cluster_box(edge, "lhead", edge.head, edge.tail);
+        let tail_box = cluster_box(edge, "ltail", edge.tail, edge.head);
+        if head_box.is_none() && tail_box.is_none() {
+            continue;
+        }
+        let mut points = vec![ends[i].0.point];
+        points.extend(routes[i].iter().copied());
+        points.push(ends[i].1.point);
+        if let Some(rect) = head_box {
+            if let Some(j) = points
+                .iter()
+                .position(|&p| inside(p, rect))
+                .filter(|&j| j > 0)
+            {
+                let point = enter(points[j - 1], points[j], rect);
+                points.truncate(j);
+                points.push(point);
+                ends[i].1 = End {
+                    point,
+                    outline: None,
+                };
+            }
+        }
+        if let Some(rect) = tail_box {
+            let last = points.len() - 1;
+            if let Some(j) = points
+                .iter()
+                .rposition(|&p| inside(p, rect))
+                .filter(|&j| j < last)
+            {
+                let point = enter(points[j + 1], points[j], rect);
+                points.drain(..=j);
+                points.insert(0, point);
+                ends[i].0 = End {
+                    point,
+                    outline: None,
+                };
+            }
+        }
+        routes[i] = points[1..points.len() - 1].to_vec();
+    }
+}
+
+// Lays out the graph with an engine that can put nodes on top of each other, and removes the
+// overlaps as the overlap attribute asks.
+fn spread(graph: &Graph, engine: Engine) -> Result<Vec<Point>, String> {
+    let mut positions = match engine {
+        Engine::Neato => neato::layout(graph)?,
+        Engine::Fdp => fdp::layout(graph)?,
+        Engine::Sfdp => sfdp::layout(graph)?,
+        Engine::Circo => circo::layout(graph)?,
+        Engine::Twopi => twopi::layout(graph)?,
+        Engine::Dot | Engine::Osage | Engine::Patchwork => unreachable!(),
+    };
+    let sizes: Vec<(f64, f64)> = graph
+        .nodes
+        .iter()
+        .map(|node| node_size(graph, node))
+        .collect();
+    overlap::remove(graph, &mut positions, &sizes)?;
+    Ok(positions)
+}
+
+// Spreads the nodes apart to give the drawing the aspect ratio the ratio attribute asks for: a
+// number is the height divided by the width, fill fills size and expand grows the drawing to
+// size. As in Graphviz, only the distances between nodes change, not the nodes themselves, and
+// compress and auto leave the layout as it is. Returns how much x and y were scaled by.
+fn apply_ratio(
+    graph: &Graph,
+    positions: &mut [Point],
+    sizes: &[(f64, f64)],
+) -> Result<Point, String> {
+    let Some(ratio) = graph.attrs.get("ratio") else {
+        return Ok((1.0, 1.0));
+    };
+    let Some(bb) = (0..positions.len())
+        .map(|i| rect_around(positions[i], sizes[i]))
+        .reduce(union)
+    else {
+        return Ok((1.0, 1.0));
+    };
+    let (width, height) = (bb.2 - bb.0, bb.3 - bb.1);
+    if width <= 0.0 || height <= 0.0 {
+        return Ok((1.0, 1.0));
+    }
+    let size = sides(&graph.attrs, "size")?;
+    let (x_scale, y_scale) = match (ratio, size) {
+        ("fill", Some((max_width, max_height, _))) => {
+            let (x_scale, y_scale) = (max_width / width, max_height / height);
+            // A drawing larger than size keeps its shape here and is scaled down when drawn.
+            if x_scale < 1.0 || y_scale < 1.0 {
+                if x_scale < y_scale {
+                    (1.0, y_scale / x_scale)
+                } else {
+                    (x_scale / y_scale, 1.0)
+                }
+            } else {
+                (x_scale, y_scale)
+            }
+        }
+        ("expand", Some((max_width, max_height, _))) => {
+            let scale = f64::min(max_width / width, max_height / height).max(1.0);
+            (scale, scale)
+        }
+        ("fill" | "expand" | "compress" | "auto", _) => return Ok((1.0, 1.0)),
+        _ => {
+            let desired: f64 = ratio
+                .parse()
+                .map_err(|_| format!("{}:{} Bad ratio {}", file!(), line!(), ratio))?;
+            if desired <= 0.0 {
+                return Ok((1.0, 1.0));
+            }
+            let actual = height / width;
+            if actual < desired {
+                (1.0, desired / actual)
+            } else {
+                (actual / desired, 1.0)
+            }
+        }
+    };
+    for position in positions.iter_mut() {
+        *position = (position.0 * x_scale, position.1 * y_scale);
+    }
+    Ok((x_scale, y_scale))
+}
+
+#[test]
+fn test_apply_ratio() {
+    let ratio = |attrs: &str| {
+        let graph = from_dot(&format!("graph {{ {} a; b }}", attrs));
+        let mut positions = vec![(0.0, 0.0), (100.0, 0.0)];
+        apply_ratio(&graph, &mut positions, &[(20.0, 20.0); 2]).unwrap();
+        positions[1]
+    };
+    // The drawing is 120 wide and 20 high.
+    assert_eq!(ratio(""), (100.0, 0.0));
+    assert_eq!(ratio("ratio = 0.5"), (100.0, 0.0));
+    assert!((ratio("ratio = 0.1").0 - 166.67).abs() < 0.01);
+    assert_eq!(ratio(r#"ratio = expand; size = "5,5""#), (300.0, 0.0));
+    assert_eq!(ratio(r#"ratio = fill; size = "5,5""#), (300.0, 0.0));
+    assert_eq!(ratio(r#"ratio = fill; size = "1,1""#), (100.0, 0.0));
+    assert_eq!(ratio("ratio = compress"), (100.0, 0.0));
+}
+
+// Runs the layout engine and returns a copy of the graph with the results stored as Graphviz
+// does: pos, width and height on nodes, pos on edges, the bounding box bb on the graph and its
+// clusters, and the positions of labels, see labels::Labels::set_attrs.
+pub fn apply(graph: &Graph, engine: Engine) -> Result<Graph, String> {
+    let mut sizes: Vec<(f64, f64)> = graph
+        .nodes
+        .iter()
+        .map(|node| node_size(graph, node))
+        .collect();
+    let mut clusters = Vec::new();
+    // dot routes edges through the ranks it puts them on.
+    let mut layout_routes = None;
+    let mut positions = match engine {
+        Engine::Dot => {
+            let (positions, boxes, routes) = dot::layout(graph, &sizes)?;
+            clusters = boxes;
+            layout_routes = Some(routes);
+            positions
+        }
+        Engine::Osage => {
+            let (positions, boxes) = osage::layout(graph, &sizes)?;
+            clusters = boxes;
+            positions
+        }
+        Engine::Patchwork => {
+            let (tiles, boxes) = patchwork::layout(graph)?;
+            sizes = tiles.iter().map(|t| (t.2 - t.0, t.3 - t.1)).collect();
+            clusters = boxes;
+            tiles
+                .iter()
+                .map(|t| ((t.0 + t.2) / 2.0, (t.1 + t.3) / 2.0))
+                .collect()
+        }
+        _ => {
+            // neato lays out components together unless asked to pack them, the others pack by
+            // default. circo and twopi always lay components out separately, and put them in a
+            // row when they do not pack them.
+            let separate = matches!(engine, Engine::Circo | Engine::Twopi);
+            match pack::pack_of(&graph.attrs, engine != Engine::Neato)? {
+                None if !separate => spread(graph, engine)?,
+                pack => {
+                    let components = components(&neighbours(graph));
+                    let mut positions = vec![(0.0, 0.0); graph.nodes.len()];
+                    for component in components.iter() {
+                        let laid_out = spread(&component_graph(graph, component), engine)?;
+                        for (&i, position) in component.iter().zip(laid_out) {
+                            positions[i] = position;
+                        }
+                    }
+                    let pack = pack.unwrap_or(pack::Pack {
+                        margin: pack::DEFAULT_MARGIN,
+                        mode: pack::Mode::Array(pack::Array {
+                            user_order: true,
+                            count: Some(components.len()),
+                            ..pack::Array::default()
+                        }),
+                    });
+                    pack::pack(graph, &components, &sizes, &mut positions, &pack);
+                    positions
+                }
+            }
+        }
+    };
+
+    if clusters.is_empty() {
+        let (x_scale, y_scale) = apply_ratio(graph, &mut positions, &sizes)?;
+        for point in layout_routes.iter_mut().flatten().flatten() {
+            *point = (point.0 * x_scale, point.1 * y_scale);
+        }
+    }
+
+    // As in Graphviz, dot draws edges as splines unless asked otherwise.
+    let splines = match (engine, graph.attrs.get("splines")) {
+        (Engine::Dot, None) => Splines::Spline,
+        _ => route::splines(graph),
+    };
+    let mut ends = edge_ends(graph, &positions, &sizes);
+    let mut routes = match (splines, layout_routes) {
+        (Splines::Line, _) => vec![Vec::new(); graph.edges.len()],
+        (_, Some(routes)) => routes,
+        _ => route::routes(graph, &ends, &positions, &sizes)?,
+    };
+    if graph.attrs.get("compound") == Some("true") {
+        clip_compound(graph, &clusters, &mut ends, &mut routes);
+    }
+    let mut edge_splines: Vec<Spline> = routes
+        .iter()
+        .enumerate()
+        .map(|(i, route)| {
+            let smooth = splines == Splines::Spline;
+            edge_spline(graph, i, &positions, &sizes, ends[i], route, smooth)
+        })
+        .collect();
+    let mut labels = labels::place(graph, &positions, &sizes, &clusters, &edge_splines);
+
+    // The box around everything drawn, with room for the graph label.
+    let rects: Vec<Rect> = (0..positions.len())
+        .map(|i| rect_around(positions[i], sizes[i]))
+        .chain(clusters.iter().map(|&(_, rect)| rect))
+        .chain(edge_splines.iter().filter_map(Spline::bounds))
+        .chain(labels.rects())
+        .collect();
+    let mut bb = rects.into_iter().reduce(union).unwrap_or_default();
+    if let Some(grown) = labels.place_graph_label(graph, bb) {
+        bb = grown;
+    }
+
+    // Move the drawing so that its lower left corner is at the origin.
+    let offset = (-bb.0, -bb.1);
+    let shift = |(x, y): Point| (x + offset.0, y + offset.1);
+    for position in positions.iter_mut() {
+        *position = shift(*position);
+    }
+    for spline in edge_splines.iter_mut() {
+        spline.translate(offset);
+    }
+    labels.translate(offset);
+
+    let mut laid_out = graph.clone();
+    laid_out.attrs.set(
+        "bb",
+        &format!(
+            "0,0,{},{}",
+            format_number(bb.2 - bb.0, 2),
+            format_number(bb.3 - bb.1, 2)
+        ),
+    );
+    for &(i, (x0, y0, x1, y1)) in clusters.iter() {
+        let (x0, y0) = shift((x0, y0));
+        let (x1, y1) = shift((x1, y1));
+        let bb: Vec<String> = [x0, y0, x1, y1]
+            .iter()
+            .map(|&x| format_number(x, 2))
+            .collect();
+        laid_out.subgraphs[i].attrs.set("bb", &bb.join(","));
+    }
+    labels.set_attrs(&mut laid_out);
+    for (i, node) in laid_out.nodes.iter_mut().enumerate() {
+        let (width, height) = sizes[i];
+        node.attrs.set("pos", &format_point(positions[i]));
+        node.attrs
+            .set("width", &format_number(width / POINTS_PER_INCH, 4));
+        node.attrs
+            .set("height", &format_number(height / POINTS_PER_INCH, 4));
+    }
+    for (edge, spline) in laid_out.edges.iter_mut().zip(edge_splines) {
+        edge.attrs.set("pos", &spline.to_string());
+    }
+    Ok(laid_out)
+}
+
+#[test]
+fn test_apply() {
+    let graph = from_dot(
+        r#"digraph {
+    a [pos = "0,0!"]
+    b [pos = "2,0!"]
+    a -> b
+    b -> b [dir = none]
+}"#,
+    );
+    let laid_out = apply(&graph, Engine::Neato).unwrap();
+    // The loop on b sticks out to the right.
+    assert_eq!(laid_out.attrs.get("bb"), Some("0,0,210.15,36"));
+    assert_eq!(laid_out.nodes[0].attrs.get("pos"), Some("27,18"));
+    assert_eq!(laid_out.nodes[1].attrs.get("pos"), Some("171,18"));
+    assert_eq!(laid_out.nodes[1].attrs.get("width"), Some("0.75"));
+    assert_eq!(
+        laid_out.edges[0].attrs.get("pos"),
+        Some("e,144,18 54,18 80.67,18 107.33,18 134,18")
+    );
+    assert!(!laid_out.edges[1]
+        .attrs
+        .get("pos")
+        .unwrap()
+        .starts_with("e,"));
+    assert_eq!(graph.nodes[0].attrs.get("width"), None);
+}
+
+#[test]
+fn test_apply_pack() {
+    let pos = |graph: &Graph, i: usize| {
+        let (position, _) = parse_pos(graph.nodes[i].attrs.get("pos").unwrap()).unwrap();
+        position
+    };
+    // Without pack, twopi puts the components in a row.
+    let graph = from_dot("graph { pack = false; a -- b; c; d }");
+    let laid_out = apply(&graph, Engine::Twopi).unwrap();
+    assert!(pos(&laid_out, 0).0.max(pos(&laid_out, 1).0) < pos(&laid_out, 2).0);
+    assert_eq!(pos(&laid_out, 2).1, pos(&laid_out, 3).1);
+    assert!(pos(&laid_out, 2).0 + 62.0 <= pos(&laid_out, 3).0);
+
+    // Packed, the nodes of different components are at least pack points apart.
+    for engine in [Engine::Neato, Engine::Fdp, Engine::Circo] {
+        let graph = from_dot(r#"graph { pack = 20; a -- b; c -- d; e }"#);
+        let laid_out = apply(&graph, engine).unwrap();
+        for (i, j) in [(0, 2), (0, 4), (1, 3), (2, 4)] {
+            let (p, q) = (pos(&laid_out, i), pos(&laid_out, j));
+            assert!(
+                (p.0 - q.0).abs() >= 74.0 || (p.1 - q.1).abs() >= 56.0,
+                "{} {} {}",
+                engine,
+                i,
+                j
+            );
+        }
+    }
+}
@@ -1,32 +1,106 @@
+use ast::AttrStmtKind;
 use clap::Parser;
-use env_logger;
-use log::info;
-use std::env;
-use std::io::Write;
+use input::InputFormat;
+use log::{error, info, warn};
+use output::OutputFormat;
+use std::io::{Read, Write};
 
+mod arrow;
+mod ascii;
 mod ast;
+mod circo;
+mod color;
+mod dot;
+mod draw;
+mod fdp;
+mod graphml;
+mod html;
+mod input;
+mod json;
+mod labels;
+mod layout;
+mod neato;
+mod osage;
+mod output;
+mod overlap;
+mod pack;
+mod patchwork;
+mod pdf;
+mod plain;
+mod png;
+mod ps;
+mod record;
+mod route;
+mod schema;
+mod semantic;
+mod sfdp;
+mod shape;
+mod svg;
+mod text;
 mod tokenize;
+mod twopi;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct NNDotArgs {
+    /// Input file. Reads from stdin when no input file is given.
+    #[arg(conflicts_with = "input_file")]
+    input: Option<String>,
+    /// Same as INPUT.
     #[arg(short, long)]
-    input_file: String,
+    input_file: Option<String>,
+    /// Input format. Inferred from the input file extension when omitted, otherwise dot.
+    #[arg(long, value_enum)]
+    input_format: Option<InputFormat>,
+    /// Output file. Writes to stdout when omitted.
     #[arg(short, long)]
-    output_file: String,
+    output_file: Option<String>,
+    /// Output format. Inferred from the output file extension when omitted, otherwise canon.
+    #[arg(short = 'T', long, value_enum)]
+    format: Option<OutputFormat>,
+    /// Layout engine. Overrides the layout graph attribute.
+    #[arg(short = 'K', long, value_enum)]
+    layout: Option<layout::Engine>,
+    /// Set a graph attribute. Attributes set in the input file take precedence.
+    #[arg(short = 'G', value_name = "NAME[=VALUE]", value_parser = parse_attr_arg)]
+    graph_attrs: Vec<(String, String)>,
+    /// Set a default node attribute. Attributes set in the input file take precedence.
+    #[arg(short = 'N', value_name = "NAME[=VALUE]", value_parser = parse_attr_arg)]
+    node_attrs: Vec<(String, String)>,
+    /// Set a default edge attribute. Attributes set in the input file take precedence.
+    #[arg(short = 'E', value_name = "NAME[=VALUE]", value_parser = parse_attr_arg)]
+    edge_attrs: Vec<(String, String)>,
 }
 
-fn parse_dot(dot_str: String) {
-    info!("parsing dot string");
-    let tokens = tokenize::tokenize(dot_str);
-    for token in tokens {
-        info!("token: {}", token);
+// Parses "name=value" given to -G, -N or -E. As with dot, the value defaults to true.
+fn parse_attr_arg(arg: &str) -> Result<(String, String), String> {
+    let (name, value) = arg.split_once('=').unwrap_or((arg, "true"));
+    if name.is_empty() {
+        return Err(format!("Expected NAME[=VALUE] but got {}", arg));
     }
+    Ok((name.to_string(), value.to_string()))
+}
+
+#[test]
+fn test_parse_attr_arg() {
+    assert_eq!(
+        parse_attr_arg("rankdir=LR"),
+        Ok(("rankdir".to_string(), "LR".to_string()))
+    );
+    assert_eq!(
+        parse_attr_arg("label=a=b"),
+        Ok(("label".to_string(), "a=b".to_string()))
+    );
+    assert_eq!(
+        parse_attr_arg("center"),
+        Ok(("center".to_string(), "true".to_string()))
+    );
+    assert!(parse_attr_arg("=LR").is_err());
 }
 
 fn main() {
-    env::set_var("RUST_LOG", "info");
-    env_logger::Builder::from_default_env()
+    // RUST_LOG=info shows how the input is read and laid out.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"))
         .format(|buf, record| {
             let ts = buf.timestamp();
             writeln!(
@@ -43,8 +117,72 @@ fn main() {
         .init();
 
     let args = NNDotArgs::parse();
-    info!("input file: {}", args.input_file);
 
-    let dot_str = std::fs::read_to_string(&args.input_file).unwrap();
-    parse_dot(dot_str);
+    let input_file = args.input_file.or(args.input);
+    let input_format = args
+        .input_format
+        .or(input_file.as_deref().and_then(input::infer_input_format))
+        .unwrap_or(InputFormat::Dot);
+    let input_str = match input_file {
+        Some(input_file) => {
+            info!("input file: {}", input_file);
+            std::fs::read_to_string(&input_file).unwrap()
+        }
+        None => {
+            info!("input file: <stdin>");
+            let mut input_str = String::new();
+            std::io::stdin().read_to_string(&mut input_str).unwrap();
+            input_str
+        }
+    };
+    let mut graph = match input::parse(input_str, input_format) {
+        Ok(graph) => graph,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    graph.prepend_attr_stmt(AttrStmtKind::Edge, &args.edge_attrs);
+    graph.prepend_attr_stmt(AttrStmtKind::Node, &args.node_attrs);
+    graph.prepend_attr_stmt(AttrStmtKind::Graph, &args.graph_attrs);
+    info!("graph:\n{}", graph);
+
+    let format = args
+        .format
+        .or(args
+            .output_file
+            .as_deref()
+            .and_then(output::infer_output_format))
+        .unwrap_or(OutputFormat::Canon);
+    info!("output format: {}", format);
+    let semantic_graph = semantic::Graph::from_ast(&graph);
+    for warning in schema::check(&semantic_graph)
+        .into_iter()
+        .chain(record::check(&semantic_graph))
+        .chain(html::check(&semantic_graph))
+        .chain(color::check(&semantic_graph))
+        .chain(arrow::check(&semantic_graph))
+    {
+        warn!("{}", warning);
+    }
+    let engine = match args.layout {
+        Some(engine) => Ok(Some(engine)),
+        None => layout::engine_of(&semantic_graph),
+    };
+    let rendered = match engine.and_then(|engine| {
+        if let Some(engine) = engine {
+            info!("layout engine: {}", engine);
+        }
+        output::render(&graph, &semantic_graph, format, engine)
+    }) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match args.output_file {
+        Some(output_file) => std::fs::write(&output_file, rendered).unwrap(),
+        None => std::io::stdout().write_all(&rendered).unwrap(),
+    }
 }
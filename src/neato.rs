@@ -0,0 +1,314 @@
+use crate::layout::{self, number, Point, POINTS_PER_INCH};
+use crate::semantic::Graph;
+use log::warn;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz defaults. Lengths are in inches while the layout runs.
+const DEFAULT_LEN: f64 = 1.0;
+const DEFAULT_EPSILON: f64 = 0.0001;
+const DEFAULT_MAXITER: usize = 200;
+
+// An entry of the Dijkstra queue, ordered so that BinaryHeap pops the nearest node first.
+#[derive(PartialEq)]
+struct Visit {
+    distance: f64,
+    node: usize,
+}
+
+impl Eq for Visit {}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Visit) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Visit) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Shortest path lengths between all pairs of nodes, with len as the length of an edge. Nodes
+// in different components are put a little further apart than the farthest connected pair.
+fn distances(graph: &Graph) -> Result<Vec<Vec<f64>>, String> {
+    let n = graph.nodes.len();
+    let mut adjacency = vec![Vec::new(); n];
+    for edge in graph.edges.iter() {
+        let len = number(&edge.attrs, "len", DEFAULT_LEN)?;
+        if len < 0.0 {
+            return Err(format!("{}:{} Negative len {}", file!(), line!(), len));
+        }
+        if edge.tail != edge.head {
+            adjacency[edge.tail].push((edge.head, len));
+            adjacency[edge.head].push((edge.tail, len));
+        }
+    }
+
+    let mut d = vec![vec![f64::INFINITY; n]; n];
+    for source in 0..n {
+        let distances = &mut d[source];
+        distances[source] = 0.0;
+        let mut queue = BinaryHeap::new();
+        queue.push(Visit {
+            distance: 0.0,
+            node: source,
+        });
+        while let Some(Visit { distance, node }) = queue.pop() {
+            if distance > distances[node] {
+                continue;
+            }
+            for &(next, len) in adjacency[node].iter() {
+                if distance + len < distances[next] {
+                    distances[next] = distance + len;
+                    queue.push(Visit {
+                        distance: distance + len,
+                        node: next,
+                    });
+                }
+            }
+        }
+    }
+
+    let farthest = d
+        .iter()
+        .flatten()
+        .filter(|x| x.is_finite())
+        .fold(0.0, |a: f64, &b| a.max(b));
+    for x in d.iter_mut().flatten() {
+        if x.is_infinite() {
+            *x = farthest + DEFAULT_LEN;
+        }
+    }
+    Ok(d)
+}
+
+#[test]
+fn test_distances() {
+    let graph = from_dot(r#"graph { a -- b [len = 2]; b -- c; a -- c [len = 5]; d; e -- e }"#);
+    let d = distances(&graph).unwrap();
+    assert_eq!(d[0], vec![0.0, 2.0, 3.0, 4.0, 4.0]);
+    assert_eq!(d[2][1], 1.0);
+    assert_eq!(d[3][4], 4.0);
+    assert_eq!(d[4][4], 0.0);
+}
+
+fn stress(d: &[Vec<f64>], positions: &[Point]) -> f64 {
+    let mut stress = 0.0;
+    for i in 0..positions.len() {
+        for j in i + 1..positions.len() {
+            let (dx, dy) = (
+                positions[i].0 - positions[j].0,
+                positions[i].1 - positions[j].1,
+            );
+            stress += (dx.hypot(dy) - d[i][j]).powi(2) / d[i][j].powi(2).max(1e-9);
+        }
+    }
+    stress
+}
+
+// Stress majorization: every free node moves to the weighted average of where each other node
+// would like it to be, with weights 1/d^2, until the stress improves by less than epsilon.
+fn stress_majorization(
+    d: &[Vec<f64>],
+    positions: &mut Vec<Point>,
+    pinned: &[bool],
+    epsilon: f64,
+    maxiter: usize,
+) {
+    let n = positions.len();
+    let mut old_stress = stress(d, positions);
+    for _ in 0..maxiter {
+        let mut next = positions.clone();
+        for i in (0..n).filter(|&i| !pinned[i]) {
+            let (mut x, mut y, mut total) = (0.0, 0.0, 0.0);
+            for j in (0..n).filter(|&j| j != i) {
+                let w = 1.0 / d[i][j].powi(2).max(1e-9);
+                let (dx, dy) = (
+                    positions[i].0 - positions[j].0,
+                    positions[i].1 - positions[j].1,
+                );
+                let distance = dx.hypot(dy).max(1e-9);
+                x += w * (positions[j].0 + d[i][j] * dx / distance);
+                y += w * (positions[j].1 + d[i][j] * dy / distance);
+                total += w;
+            }
+            if total > 0.0 {
+                next[i] = (x / total, y / total);
+            }
+        }
+        *positions = next;
+        let new_stress = stress(d, positions);
+        if old_stress <= 0.0 || (old_stress - new_stress) / old_stress < epsilon {
+            break;
+        }
+        old_stress = new_stress;
+    }
+}
+
+// Kamada-Kawai: repeatedly takes the free node with the largest energy gradient and moves it by
+// a Newton-Raphson step, until every gradient is below epsilon. As in Graphviz, the gradients are
+// kept up to date as nodes move, so that an iteration takes O(n) rather than O(n^2).
+fn kamada_kawai(
+    d: &[Vec<f64>],
+    positions: &mut [Point],
+    pinned: &[bool],
+    epsilon: f64,
+    maxiter: usize,
+) {
+    let n = positions.len();
+    // The part of the gradient of m that comes from i.
+    let term = |positions: &[Point], m: usize, i: usize| {
+        let k = 1.0 / d[m][i].powi(2).max(1e-9);
+        let (dx, dy) = (
+            positions[m].0 - positions[i].0,
+            positions[m].1 - positions[i].1,
+        );
+        let distance = dx.hypot(dy).max(1e-9);
+        (
+            k * (dx - d[m][i] * dx / distance),
+            k * (dy - d[m][i] * dy / distance),
+        )
+    };
+    let gradient = |positions: &[Point], m: usize| {
+        (0..n)
+            .filter(|&i| i != m)
+            .map(|i| term(positions, m, i))
+            .fold((0.0, 0.0), |(gx, gy), (x, y)| (gx + x, gy + y))
+    };
+    let mut gradients: Vec<Point> = (0..n).map(|m| gradient(positions, m)).collect();
+    for _ in 0..maxiter {
+        let (m, delta) = (0..n)
+            .filter(|&i| !pinned[i])
+            .map(|i| (i, gradients[i].0.hypot(gradients[i].1)))
+            .fold(
+                (0, 0.0),
+                |best, next| if next.1 > best.1 { next } else { best },
+            );
+        if delta < epsilon {
+            break;
+        }
+        let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+        for i in (0..n).filter(|&i| i != m) {
+            let k = 1.0 / d[m][i].powi(2).max(1e-9);
+            let (dx, dy) = (
+                positions[m].0 - positions[i].0,
+                positions[m].1 - positions[i].1,
+            );
+            let cube = dx.hypot(dy).max(1e-9).powi(3);
+            xx += k * (1.0 - d[m][i] * dy * dy / cube);
+            yy += k * (1.0 - d[m][i] * dx * dx / cube);
+            xy += k * d[m][i] * dx * dy / cube;
+        }
+        let (gx, gy) = gradients[m];
+        let determinant = xx * yy - xy * xy;
+        if determinant.abs() < 1e-12 {
+            break;
+        }
+        // The term m contributes to every other gradient changes with its position.
+        let old: Vec<Point> = (0..n).map(|i| term(positions, i, m)).collect();
+        positions[m].0 -= (yy * gx - xy * gy) / determinant;
+        positions[m].1 -= (xx * gy - xy * gx) / determinant;
+        for i in (0..n).filter(|&i| i != m) {
+            let new = term(positions, i, m);
+            gradients[i].0 += new.0 - old[i].0;
+            gradients[i].1 += new.1 - old[i].1;
+        }
+        gradients[m] = gradient(positions, m);
+    }
+}
+
+// Positions of the nodes in points. mode=KK selects Kamada-Kawai instead of stress
+// majorization. epsilon and maxiter bound the iterations. Both keep the n^2 matrix of distances
+// and take O(n^2) time for each stress iteration or every n Kamada-Kawai steps, which suits graphs
+// of up to a few thousand nodes; sfdp is meant for larger ones.
+pub fn layout(graph: &Graph) -> Result<Vec<Point>, String> {
+    let n = graph.nodes.len();
+    let d = distances(graph)?;
+    let side = (n as f64).sqrt() * DEFAULT_LEN;
+    let (mut positions, pinned) = layout::initial_positions(graph, side)?;
+    let kk = match graph.attrs.get("mode").map(|mode| mode.to_lowercase()) {
+        Some(mode) if mode == "kk" => true,
+        Some(mode) if mode != "major" => {
+            warn!("Mode {} is not supported, using major", mode);
+            false
+        }
+        _ => false,
+    };
+    let maxiter = match graph.attrs.get("maxiter") {
+        Some(maxiter) => maxiter
+            .parse()
+            .map_err(|_| format!("{}:{} Bad maxiter {}", file!(), line!(), maxiter))?,
+        None if kk => 100 * n,
+        None => DEFAULT_MAXITER,
+    };
+    let epsilon = if kk {
+        number(&graph.attrs, "epsilon", DEFAULT_EPSILON * n as f64)?
+    } else {
+        number(&graph.attrs, "epsilon", DEFAULT_EPSILON)?
+    };
+    if kk {
+        kamada_kawai(&d, &mut positions, &pinned, epsilon, maxiter);
+    } else {
+        stress_majorization(&d, &mut positions, &pinned, epsilon, maxiter);
+    }
+    Ok(positions
+        .into_iter()
+        .map(|(x, y)| (x * POINTS_PER_INCH, y * POINTS_PER_INCH))
+        .collect())
+}
+
+#[test]
+fn test_layout() {
+    let distance = |positions: &[Point], i: usize, j: usize| {
+        let (a, b): (Point, Point) = (positions[i], positions[j]);
+        (a.0 - b.0).hypot(a.1 - b.1) / POINTS_PER_INCH
+    };
+    for mode in ["major", "KK"] {
+        let graph = from_dot(&format!(
+            r#"graph {{ mode = {}; a -- b -- c -- d -- a; a -- e [len = 2] }}"#,
+            mode
+        ));
+        let positions = layout(&graph).unwrap();
+        // The square keeps its sides and the long edge is about twice as long.
+        for (i, j) in [(0, 1), (1, 2), (2, 3), (3, 0)] {
+            assert!((distance(&positions, i, j) - 1.0).abs() < 0.2, "{}", mode);
+        }
+        assert!((distance(&positions, 0, 4) - 2.0).abs() < 0.3, "{}", mode);
+        assert_eq!(positions, layout(&graph).unwrap());
+    }
+
+    let graph =
+        from_dot(r#"graph { a [pos = "1,2!"]; b [pos = "0,0", pin = true]; c; a -- c -- b }"#);
+    let positions = layout(&graph).unwrap();
+    assert_eq!(positions[0], (72.0, 144.0));
+    assert_eq!(positions[1], (0.0, 0.0));
+
+    // A large epsilon stops stress majorization after the first iteration and Kamada-Kawai
+    // before it moves any node.
+    let with = |attrs: &str| {
+        layout(&from_dot(&format!(
+            "graph {{ a -- b -- c -- a; {} }}",
+            attrs
+        )))
+    };
+    assert_eq!(with("maxiter = 1"), with("epsilon = 10"));
+    assert_ne!(with("maxiter = 1"), with(""));
+    assert_eq!(
+        with("mode = KK; maxiter = 0"),
+        with("mode = KK; epsilon = 1000000000")
+    );
+    assert_ne!(with("mode = KK; maxiter = 0"), with("mode = KK"));
+    assert!(with("maxiter = -1").is_err());
+
+    assert!(layout(&from_dot(r#"graph { a [pos = "x"] }"#)).is_err());
+    assert!(layout(&from_dot(r#"graph { start = "randomly"; a }"#)).is_err());
+    assert_ne!(
+        layout(&from_dot(r#"graph { start = 7; a -- b -- c }"#)),
+        layout(&from_dot(r#"graph { start = 8; a -- b -- c }"#))
+    );
+}
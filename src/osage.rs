@@ -0,0 +1,195 @@
+use crate::layout::{self, ClusterBox, Point, Rect};
+use crate::pack::{self, Array, Mode, Pack};
+use crate::semantic::{Attrs, Graph};
+use crate::text;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Space around the contents of a cluster, in points.
+const MARGIN: f64 = 8.0;
+
+enum Item {
+    Node(usize),
+    Cluster(usize),
+}
+
+// Moves a cluster together with everything inside it.
+fn shift(
+    graph: &Graph,
+    cluster: usize,
+    (dx, dy): Point,
+    positions: &mut [Point],
+    boxes: &mut [Option<Rect>],
+) {
+    for &i in graph.subgraphs[cluster].nodes.iter() {
+        positions[i].0 += dx;
+        positions[i].1 += dy;
+    }
+    let mut stack = vec![cluster];
+    while let Some(subgraph) = stack.pop() {
+        if let Some((x0, y0, x1, y1)) = boxes[subgraph] {
+            boxes[subgraph] = Some((x0 + dx, y0 + dy, x1 + dx, y1 + dy));
+        }
+        stack.extend(graph.subgraphs[subgraph].subgraphs.iter());
+    }
+}
+
+// Lays out the contents of a cluster, or of the root graph for None, and returns their bounding
+// box. The child clusters are laid out first and get a margin around their contents and room for
+// their label, at the top unless labelloc is b. Then they are packed with the nodes as pack and
+// packmode ask, in a grid by default. With the u flag of array packing they are in the order of
+// their sortv. attrs are the graph attributes in effect, those of the cluster and the ones it
+// inherits from the clusters around it.
+fn place(
+    graph: &Graph,
+    parent: Option<usize>,
+    attrs: &Attrs,
+    sizes: &[(f64, f64)],
+    positions: &mut [Point],
+    boxes: &mut [Option<Rect>],
+) -> Result<Rect, String> {
+    let clusters = layout::clusters_in(graph, parent);
+    let mut items = Vec::new();
+    for &cluster in clusters.iter() {
+        let subgraph = &graph.subgraphs[cluster];
+        let mut inherited = attrs.clone();
+        inherited.extend(&subgraph.attrs.ids());
+        let (x0, y0, x1, y1) = place(graph, Some(cluster), &inherited, sizes, positions, boxes)?;
+        let mut rect = (x0 - MARGIN, y0 - MARGIN, x1 + MARGIN, y1 + MARGIN);
+        if let Some((width, height)) = text::cluster_label_size(graph, subgraph) {
+            if subgraph.attrs.get("labelloc") == Some("b") {
+                rect.1 -= height;
+            } else {
+                rect.3 += height;
+            }
+            let grow = f64::max(width + 2.0 * MARGIN - (rect.2 - rect.0), 0.0) / 2.0;
+            rect.0 -= grow;
+            rect.2 += grow;
+        }
+        boxes[cluster] = Some(rect);
+        items.push((Item::Cluster(cluster), rect));
+    }
+    for node in layout::nodes_in(graph, parent, &clusters) {
+        let (width, height) = (sizes[node].0 / 2.0, sizes[node].1 / 2.0);
+        items.push((Item::Node(node), (-width, -height, width, height)));
+    }
+    if items.is_empty() {
+        return Ok((0.0, 0.0, 0.0, 0.0));
+    }
+
+    let mut pack = pack::pack_of(attrs, true)?.unwrap_or(Pack {
+        margin: pack::DEFAULT_MARGIN,
+        mode: Mode::Node,
+    });
+    if attrs.get("packmode").is_none() {
+        pack.mode = Mode::Array(Array::default());
+    }
+    if let Mode::Array(Array {
+        user_order: true, ..
+    }) = pack.mode
+    {
+        let sortv = |item: &Item| {
+            let attrs = match *item {
+                Item::Node(node) => &graph.nodes[node].attrs,
+                Item::Cluster(cluster) => &graph.subgraphs[cluster].attrs,
+            };
+            layout::number(attrs, "sortv", 0.0).unwrap_or(0.0)
+        };
+        items.sort_by(|(a, _), (b, _)| sortv(a).total_cmp(&sortv(b)));
+    }
+    let rects: Vec<Rect> = items.iter().map(|&(_, rect)| rect).collect();
+    let offsets = pack::pack_boxes(&rects, &pack);
+    let mut bb = (
+        f64::INFINITY,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NEG_INFINITY,
+    );
+    for ((item, (x0, y0, x1, y1)), (dx, dy)) in items.into_iter().zip(offsets) {
+        match item {
+            Item::Node(node) => positions[node] = (dx, dy),
+            Item::Cluster(cluster) => shift(graph, cluster, (dx, dy), positions, boxes),
+        }
+        bb = (
+            bb.0.min(x0 + dx),
+            bb.1.min(y0 + dy),
+            bb.2.max(x1 + dx),
+            bb.3.max(y1 + dy),
+        );
+    }
+    Ok(bb)
+}
+
+// Positions of the nodes and boxes of the clusters, in points. Every cluster is packed on its
+// own and then treated as a single item by the cluster around it.
+pub fn layout(
+    graph: &Graph,
+    sizes: &[(f64, f64)],
+) -> Result<(Vec<Point>, Vec<ClusterBox>), String> {
+    let mut positions = vec![(0.0, 0.0); graph.nodes.len()];
+    let mut boxes = vec![None; graph.subgraphs.len()];
+    place(graph, None, &graph.attrs, sizes, &mut positions, &mut boxes)?;
+    let boxes = boxes
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, rect)| rect.map(|rect| (i, rect)))
+        .collect();
+    Ok((positions, boxes))
+}
+
+#[test]
+fn test_layout() {
+    let graph = from_dot(
+        r#"graph {
+    subgraph cluster_a { a; b; c; subgraph cluster_b { d } }
+    e
+    f -- a
+}"#,
+    );
+    let sizes = vec![(54.0, 36.0); graph.nodes.len()];
+    let (positions, boxes) = layout(&graph, &sizes).unwrap();
+    let inside = |(x, y): Point, (x0, y0, x1, y1): Rect| {
+        x - 27.0 >= x0 && x + 27.0 <= x1 && y - 18.0 >= y0 && y + 18.0 <= y1
+    };
+    assert_eq!(boxes.len(), 2);
+    let (a, b) = (boxes[0].1, boxes[1].1);
+    assert!(positions[..4].iter().all(|&position| inside(position, a)));
+    assert!(inside(positions[3], b));
+    assert!(!inside(positions[4], a) && !inside(positions[5], a));
+    // Nothing overlaps.
+    for i in 0..positions.len() {
+        for j in 0..i {
+            let (dx, dy) = (
+                positions[i].0 - positions[j].0,
+                positions[i].1 - positions[j].1,
+            );
+            assert!(dx.abs() >= 54.0 || dy.abs() >= 36.0, "{} {}", i, j);
+        }
+    }
+    // Cluster b holds one node and a margin around it.
+    assert_eq!((b.2 - b.0, b.3 - b.1), (54.0 + 16.0, 36.0 + 16.0));
+}
+
+#[test]
+fn test_layout_label_and_pack() {
+    let graph = from_dot(
+        r#"graph {
+    packmode = array_u1
+    subgraph cluster_a { label = "a long cluster label"; a }
+    b [sortv = 1]
+    c [sortv = -1]
+}"#,
+    );
+    let sizes = vec![(54.0, 36.0); graph.nodes.len()];
+    let (positions, boxes) = layout(&graph, &sizes).unwrap();
+    let (x0, y0, x1, y1) = boxes[0].1;
+    // The label goes above the node and is wider than it.
+    assert!(x1 - x0 > 54.0 + 16.0);
+    assert!(y1 - y0 > 36.0 + 16.0);
+    assert_eq!(positions[0].1 - 18.0 - MARGIN, y0);
+    assert!(positions[0].1 + 18.0 + MARGIN < y1);
+    // One column, from top to bottom in the order of sortv.
+    assert!(positions[2].1 > y1 && y0 > positions[1].1);
+    assert_eq!(positions[1].0, positions[2].0);
+}
@@ -0,0 +1,133 @@
+use crate::ast::Graph;
+use crate::layout::{self, Engine};
+use crate::{ascii, draw, json, pdf, plain, png, ps, semantic, svg};
+use clap::ValueEnum;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Svg,
+    Png,
+    Pdf,
+    Ps,
+    Eps,
+    Json,
+    Json0,
+    Dot,
+    Xdot,
+    Plain,
+    PlainExt,
+    Ascii,
+    Unicode,
+    Canon,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+// Guesses the output format from the extension of the output file, e.g. "graph.svg".
+pub fn infer_output_format(output_file: &str) -> Option<OutputFormat> {
+    let extension = Path::new(output_file).extension()?.to_str()?;
+    match extension.to_lowercase().as_str() {
+        "svg" => Some(OutputFormat::Svg),
+        "png" => Some(OutputFormat::Png),
+        "pdf" => Some(OutputFormat::Pdf),
+        "ps" => Some(OutputFormat::Ps),
+        "eps" => Some(OutputFormat::Eps),
+        "json" => Some(OutputFormat::Json),
+        "dot" | "gv" => Some(OutputFormat::Dot),
+        "xdot" => Some(OutputFormat::Xdot),
+        "plain" => Some(OutputFormat::Plain),
+        "ascii" => Some(OutputFormat::Ascii),
+        "canon" => Some(OutputFormat::Canon),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_infer_output_format() {
+    assert_eq!(infer_output_format("a.svg"), Some(OutputFormat::Svg));
+    assert_eq!(infer_output_format("dir/a.PNG"), Some(OutputFormat::Png));
+    assert_eq!(infer_output_format("a.gv"), Some(OutputFormat::Dot));
+    assert_eq!(infer_output_format("fig.eps"), Some(OutputFormat::Eps));
+    assert_eq!(infer_output_format("a.b.xdot"), Some(OutputFormat::Xdot));
+    assert_eq!(infer_output_format("a.txt"), None);
+    assert_eq!(infer_output_format("a"), None);
+}
+
+// Renders the graph in the output format, as text for every format but PNG.
+pub fn render(
+    graph: &Graph,
+    semantic_graph: &semantic::Graph,
+    format: OutputFormat,
+    engine: Option<Engine>,
+) -> Result<Vec<u8>, String> {
+    match (format, engine) {
+        (OutputFormat::Png, engine) => png::render(&layout::apply(
+            semantic_graph,
+            engine.unwrap_or(Engine::Dot),
+        )?),
+        _ => render_text(graph, semantic_graph, format, engine).map(String::into_bytes),
+    }
+}
+
+fn render_text(
+    graph: &Graph,
+    semantic_graph: &semantic::Graph,
+    format: OutputFormat,
+    engine: Option<Engine>,
+) -> Result<String, String> {
+    match (format, engine) {
+        (OutputFormat::Canon, _) => Ok(graph.to_string()),
+        // Without a layout there is nothing to draw, so json is the same as json0.
+        (OutputFormat::Json | OutputFormat::Json0, None) => json::render(semantic_graph, false),
+        (OutputFormat::Json | OutputFormat::Json0, Some(engine)) => json::render(
+            &layout::apply(semantic_graph, engine)?,
+            format == OutputFormat::Json,
+        ),
+        // As in Graphviz, positioned output is laid out with dot unless another engine is given.
+        (
+            OutputFormat::Svg
+            | OutputFormat::Pdf
+            | OutputFormat::Ps
+            | OutputFormat::Eps
+            | OutputFormat::Ascii
+            | OutputFormat::Unicode
+            | OutputFormat::Dot
+            | OutputFormat::Xdot
+            | OutputFormat::Plain
+            | OutputFormat::PlainExt,
+            None,
+        ) => render_text(graph, semantic_graph, format, Some(Engine::Dot)),
+        // Both carry the xdot drawing operations, as interactive viewers read them from either.
+        (OutputFormat::Dot | OutputFormat::Xdot, Some(engine)) => {
+            let mut laid_out = layout::apply(semantic_graph, engine)?;
+            draw::set_attrs(&mut laid_out)?;
+            Ok(laid_out.to_ast()?.to_string())
+        }
+        (OutputFormat::Svg, Some(engine)) => svg::render(&layout::apply(semantic_graph, engine)?),
+        (OutputFormat::Pdf, Some(engine)) => pdf::render(&layout::apply(semantic_graph, engine)?),
+        (OutputFormat::Ps | OutputFormat::Eps, Some(engine)) => ps::render(
+            &layout::apply(semantic_graph, engine)?,
+            format == OutputFormat::Eps,
+        ),
+        (OutputFormat::Ascii | OutputFormat::Unicode, Some(engine)) => ascii::render(
+            &layout::apply(semantic_graph, engine)?,
+            format == OutputFormat::Unicode,
+        ),
+        (OutputFormat::Plain | OutputFormat::PlainExt, Some(engine)) => plain::render(
+            &layout::apply(semantic_graph, engine)?,
+            format == OutputFormat::PlainExt,
+        ),
+        _ => Err(format!(
+            "{}:{} Output format {} is not supported yet",
+            file!(),
+            line!(),
+            format
+        )),
+    }
+}
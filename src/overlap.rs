@@ -0,0 +1,493 @@
+use crate::layout::{margin, Margin, Point};
+use crate::semantic::Graph;
+use log::warn;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz defaults: the margin around nodes, in points, and the number of prism iterations.
+const DEFAULT_SEP: Margin = Margin::Add(4.0, 4.0);
+const DEFAULT_PRISM_ITERATIONS: usize = 1000;
+// The most two overlapping nodes are pushed apart in one prism iteration, as a factor of their
+// distance, and the number of majorization sweeps per iteration.
+const MAX_STRETCH: f64 = 1.5;
+const SWEEPS: usize = 5;
+// Overlaps smaller than this, in points, are rounding errors.
+const EPSILON: f64 = 1e-6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Keep,
+    Scale,
+    ScaleXY,
+    Compress,
+    Prism(usize),
+    // Separation constraints, solved horizontally first unless x_first is false.
+    Vpsc { x_first: bool },
+}
+
+// Reads overlap. Values Graphviz has but we do not, such as voronoi, fall back to prism as
+// Graphviz does for values it does not know.
+fn mode(graph: &Graph) -> Result<Mode, String> {
+    let value = graph
+        .attrs
+        .get("overlap")
+        .unwrap_or("true")
+        .trim()
+        .to_lowercase();
+    Ok(match value.as_str() {
+        "" | "true" | "yes" => Mode::Keep,
+        "false" | "no" => Mode::Prism(DEFAULT_PRISM_ITERATIONS),
+        "scale" => Mode::Scale,
+        "scalexy" => Mode::ScaleXY,
+        "compress" => Mode::Compress,
+        "vpsc" | "ortho" | "orthoxy" => Mode::Vpsc { x_first: true },
+        "orthoyx" => Mode::Vpsc { x_first: false },
+        _ => match value.strip_prefix("prism") {
+            Some("") => Mode::Prism(DEFAULT_PRISM_ITERATIONS),
+            Some(iterations) => Mode::Prism(
+                iterations
+                    .parse()
+                    .map_err(|_| format!("{}:{} Bad overlap {}", file!(), line!(), value))?,
+            ),
+            None => {
+                warn!("Overlap {} is not supported, using prism", value);
+                Mode::Prism(DEFAULT_PRISM_ITERATIONS)
+            }
+        },
+    })
+}
+
+#[test]
+fn test_mode() {
+    let mode_of = |value: &str| mode(&from_dot(&format!(r#"graph {{ overlap = "{}" }}"#, value)));
+    assert_eq!(mode(&from_dot("graph { a }")), Ok(Mode::Keep));
+    assert_eq!(mode_of("False"), Ok(Mode::Prism(1000)));
+    assert_eq!(mode_of("prism0"), Ok(Mode::Prism(0)));
+    assert_eq!(mode_of("orthoyx"), Ok(Mode::Vpsc { x_first: false }));
+    assert_eq!(mode_of("voronoi"), Ok(Mode::Prism(1000)));
+    assert!(mode_of("prismx").is_err());
+}
+
+fn coord(p: Point, axis: usize) -> f64 {
+    if axis == 0 {
+        p.0
+    } else {
+        p.1
+    }
+}
+
+fn set_coord(p: &mut Point, axis: usize, value: f64) {
+    if axis == 0 {
+        p.0 = value;
+    } else {
+        p.1 = value;
+    }
+}
+
+// How much two nodes, given by their half sizes, overlap along an axis.
+fn overlap(positions: &[Point], half: &[Point], i: usize, j: usize, axis: usize) -> f64 {
+    coord(half[i], axis) + coord(half[j], axis)
+        - (coord(positions[i], axis) - coord(positions[j], axis)).abs()
+}
+
+// Pairs of nodes whose extents along an axis overlap, found by sweeping along it.
+fn projection_overlaps(positions: &[Point], half: &[Point], axis: usize) -> Vec<(usize, usize)> {
+    let low = |i: usize| coord(positions[i], axis) - coord(half[i], axis);
+    let high = |i: usize| coord(positions[i], axis) + coord(half[i], axis);
+    let mut order: Vec<usize> = (0..positions.len()).collect();
+    order.sort_by(|&a, &b| low(a).total_cmp(&low(b)));
+    let mut pairs = Vec::new();
+    for (k, &i) in order.iter().enumerate() {
+        for &j in order[k + 1..].iter() {
+            if low(j) >= high(i) - EPSILON {
+                break;
+            }
+            pairs.push((i, j));
+        }
+    }
+    pairs
+}
+
+fn overlapping_pairs(positions: &[Point], half: &[Point]) -> Vec<(usize, usize)> {
+    projection_overlaps(positions, half, 0)
+        .into_iter()
+        .filter(|&(i, j)| overlap(positions, half, i, j, 1) > EPSILON)
+        .collect()
+}
+
+// The factors by which the horizontal and vertical distance of two nodes must grow for them
+// not to overlap.
+fn stretch(positions: &[Point], half: &[Point], i: usize, j: usize) -> Point {
+    let factor = |axis: usize| {
+        let distance = (coord(positions[i], axis) - coord(positions[j], axis)).abs();
+        if distance > 0.0 {
+            (coord(half[i], axis) + coord(half[j], axis)) / distance
+        } else {
+            f64::INFINITY
+        }
+    };
+    (factor(0), factor(1))
+}
+
+// Nodes at the same place cannot be pushed apart, so every copy is moved a little.
+fn separate_coincident(positions: &mut [Point]) {
+    let original = positions.to_vec();
+    let mut order: Vec<usize> = (0..positions.len()).collect();
+    order.sort_by(|&a, &b| {
+        positions[a]
+            .0
+            .total_cmp(&positions[b].0)
+            .then(positions[a].1.total_cmp(&positions[b].1))
+    });
+    let mut copies = 0;
+    for k in 1..order.len() {
+        if original[order[k]] == original[order[k - 1]] {
+            copies += 1;
+        } else {
+            copies = 0;
+        }
+        if copies > 0 {
+            let angle = copies as f64 * 2.4;
+            positions[order[k]].0 += angle.cos();
+            positions[order[k]].1 += angle.sin();
+        }
+    }
+}
+
+fn scale(positions: &mut [Point], (sx, sy): Point) {
+    for position in positions.iter_mut() {
+        position.0 *= sx;
+        position.1 *= sy;
+    }
+}
+
+// The smallest sx and sy, by their product, that separate every pair, each of which needs
+// either its horizontal stretch or its vertical one.
+fn scale_xy(stretches: &mut [Point]) -> Point {
+    stretches.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut best = (1.0, stretches.iter().map(|s| s.1).fold(1.0, f64::max));
+    let mut sy: f64 = 1.0;
+    for &(fx, fy) in stretches.iter() {
+        if fx * sy < best.0 * best.1 {
+            best = (fx.max(1.0), sy);
+        }
+        sy = sy.max(fy);
+    }
+    best
+}
+
+// Moves every node to the weighted average of where the terms, pairs of nodes and their target
+// distances, would put it.
+fn majorize(positions: &mut [Point], terms: &[(usize, usize, f64)]) {
+    let mut sums = vec![(0.0, 0.0, 0.0); positions.len()];
+    for &(i, j, target) in terms.iter() {
+        let (dx, dy) = (
+            positions[i].0 - positions[j].0,
+            positions[i].1 - positions[j].1,
+        );
+        let distance = dx.hypot(dy).max(EPSILON);
+        let (ux, uy) = (dx / distance * target, dy / distance * target);
+        let weight = 1.0 / (target * target);
+        for (node, other, sign) in [(i, j, 1.0), (j, i, -1.0)] {
+            sums[node].0 += weight * (positions[other].0 + sign * ux);
+            sums[node].1 += weight * (positions[other].1 + sign * uy);
+            sums[node].2 += weight;
+        }
+    }
+    for (position, (x, y, weight)) in positions.iter_mut().zip(sums) {
+        if weight > 0.0 {
+            *position = (x / weight, y / weight);
+        }
+    }
+}
+
+// After PRISM by Gansner and Hu: every iteration pushes overlapping nodes apart by stress
+// majorization, while the edges of the graph keep their lengths. Graphviz uses the proximity
+// graph of a Delaunay triangulation to keep the shape instead of the edges. What iterations do
+// not fix is removed by scaling.
+fn prism(graph: &Graph, positions: &mut [Point], half: &[Point], iterations: usize) {
+    let edges: Vec<(usize, usize)> = graph
+        .edges
+        .iter()
+        .filter(|edge| edge.tail != edge.head)
+        .map(|edge| (edge.tail, edge.head))
+        .collect();
+    for _ in 0..iterations {
+        let pairs = overlapping_pairs(positions, half);
+        if pairs.is_empty() {
+            return;
+        }
+        let distance = |positions: &[Point], i: usize, j: usize| {
+            (positions[i].0 - positions[j].0)
+                .hypot(positions[i].1 - positions[j].1)
+                .max(EPSILON)
+        };
+        let mut terms: Vec<(usize, usize, f64)> = pairs
+            .iter()
+            .map(|&(i, j)| {
+                let (fx, fy) = stretch(positions, half, i, j);
+                (
+                    i,
+                    j,
+                    distance(positions, i, j) * fx.min(fy).min(MAX_STRETCH),
+                )
+            })
+            .collect();
+        for &(i, j) in edges.iter() {
+            terms.push((i, j, distance(positions, i, j)));
+        }
+        for _ in 0..SWEEPS {
+            majorize(positions, &terms);
+        }
+    }
+    let factor = overlapping_pairs(positions, half)
+        .into_iter()
+        .map(|(i, j)| {
+            let (fx, fy) = stretch(positions, half, i, j);
+            fx.min(fy)
+        })
+        .fold(1.0, f64::max);
+    scale(positions, (factor, factor));
+}
+
+// Moves the variables as little as possible, in the least squares sense, so that
+// x[right] - x[left] >= gap for every constraint (left, right, gap). Every constraint must go
+// forward in the order of desired positions, ties broken by index. Variables held together by
+// tight constraints are merged into blocks, as in the satisfy step of VPSC by Dwyer, Marriott
+// and Stuckey.
+fn solve(desired: &[f64], constraints: &[(usize, usize, f64)]) -> Vec<f64> {
+    let n = desired.len();
+    let mut incoming = vec![Vec::new(); n];
+    for (c, &(_, right, _)) in constraints.iter().enumerate() {
+        incoming[right].push(c);
+    }
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| desired[a].total_cmp(&desired[b]).then(a.cmp(&b)));
+    // Every variable is in the block of its index to start with, and sits at its offset from the
+    // position of its block.
+    let mut block_of: Vec<usize> = (0..n).collect();
+    let mut members: Vec<Vec<usize>> = (0..n).map(|i| vec![i]).collect();
+    let mut offsets = vec![0.0; n];
+    let mut block_positions = desired.to_vec();
+    for &v in order.iter() {
+        let mut block = block_of[v];
+        loop {
+            let mut most_violated = None;
+            let mut violation = EPSILON;
+            for &i in members[block].iter() {
+                for &c in incoming[i].iter() {
+                    let (left, right, gap) = constraints[c];
+                    if block_of[left] == block {
+                        continue;
+                    }
+                    let x = |i: usize| block_positions[block_of[i]] + offsets[i];
+                    if x(left) + gap - x(right) > violation {
+                        violation = x(left) + gap - x(right);
+                        most_violated = Some(c);
+                    }
+                }
+            }
+            let Some(c) = most_violated else {
+                break;
+            };
+            // Merge this block into the one on the left, with the constraint tight.
+            let (left, right, gap) = constraints[c];
+            let target = block_of[left];
+            let shift = offsets[left] + gap - offsets[right];
+            let moved = std::mem::take(&mut members[block]);
+            for &i in moved.iter() {
+                offsets[i] += shift;
+                block_of[i] = target;
+            }
+            members[target].extend(moved);
+            block_positions[target] = members[target]
+                .iter()
+                .map(|&i| desired[i] - offsets[i])
+                .sum::<f64>()
+                / members[target].len() as f64;
+            block = target;
+        }
+    }
+    let mut x: Vec<f64> = (0..n)
+        .map(|i| block_positions[block_of[i]] + offsets[i])
+        .collect();
+    // Moving a block can break a constraint met before, which pushing right repairs.
+    for &v in order.iter() {
+        for &c in incoming[v].iter() {
+            let (left, _, gap) = constraints[c];
+            x[v] = x[v].max(x[left] + gap);
+        }
+    }
+    x
+}
+
+#[test]
+fn test_solve() {
+    assert_eq!(
+        solve(&[0.0, 0.0, 0.0], &[(0, 1, 1.0), (1, 2, 1.0)]),
+        vec![-1.0, 0.0, 1.0]
+    );
+    // Only the constrained pair moves.
+    assert_eq!(
+        solve(&[0.0, 1.0, 5.0], &[(0, 1, 3.0), (1, 2, 1.0)]),
+        vec![-1.0, 2.0, 5.0]
+    );
+    let x = solve(
+        &[0.0, 0.5, 1.0, 1.5],
+        &[(0, 1, 2.0), (1, 2, 2.0), (0, 3, 1.0)],
+    );
+    assert!(x[1] - x[0] >= 2.0 && x[2] - x[1] >= 2.0 && x[3] - x[0] >= 1.0);
+}
+
+// Separates the nodes along one axis, keeping their order along it. Only pairs overlapping along
+// the other axis are kept apart, and when skip_cheaper is set, not even those of them that
+// overlap less along the other axis, which are left to it.
+fn separate(positions: &mut [Point], half: &[Point], axis: usize, skip_cheaper: bool) {
+    let other = 1 - axis;
+    let desired: Vec<f64> = positions.iter().map(|&p| coord(p, axis)).collect();
+    let mut constraints = Vec::new();
+    for (i, j) in projection_overlaps(positions, half, other) {
+        let overlap_here = overlap(positions, half, i, j, axis);
+        if skip_cheaper
+            && overlap_here > 0.0
+            && overlap_here > overlap(positions, half, i, j, other)
+        {
+            continue;
+        }
+        let gap = coord(half[i], axis) + coord(half[j], axis);
+        if (desired[i], i) < (desired[j], j) {
+            constraints.push((i, j, gap));
+        } else {
+            constraints.push((j, i, gap));
+        }
+    }
+    for (position, x) in positions.iter_mut().zip(solve(&desired, &constraints)) {
+        set_coord(position, axis, x);
+    }
+}
+
+// Removes node overlaps as overlap asks, keeping a margin of sep around every node. Positions
+// and sizes are in points.
+pub fn remove(graph: &Graph, positions: &mut [Point], sizes: &[(f64, f64)]) -> Result<(), String> {
+    let mode = mode(graph)?;
+    if mode == Mode::Keep {
+        return Ok(());
+    }
+    let sep = margin(&graph.attrs, "sep", DEFAULT_SEP)?;
+    let half: Vec<Point> = sizes
+        .iter()
+        .map(|&size| {
+            let (width, height) = sep.around(size);
+            (width / 2.0, height / 2.0)
+        })
+        .collect();
+    separate_coincident(positions);
+    match mode {
+        Mode::Keep => {}
+        Mode::Scale => {
+            let factor = overlapping_pairs(positions, &half)
+                .into_iter()
+                .map(|(i, j)| {
+                    let (fx, fy) = stretch(positions, &half, i, j);
+                    fx.min(fy)
+                })
+                .fold(1.0, f64::max);
+            scale(positions, (factor, factor));
+        }
+        Mode::ScaleXY => {
+            let mut stretches: Vec<Point> = overlapping_pairs(positions, &half)
+                .into_iter()
+                .map(|(i, j)| stretch(positions, &half, i, j))
+                .collect();
+            scale(positions, scale_xy(&mut stretches));
+        }
+        // Scales down as well as up, until some pair touches.
+        Mode::Compress => {
+            let mut factor: f64 = 0.0;
+            for i in 0..positions.len() {
+                for j in 0..i {
+                    let (fx, fy) = stretch(positions, &half, i, j);
+                    factor = factor.max(fx.min(fy));
+                }
+            }
+            if factor > 0.0 {
+                scale(positions, (factor, factor));
+            }
+        }
+        Mode::Prism(iterations) => prism(graph, positions, &half, iterations),
+        Mode::Vpsc { x_first } => {
+            let (first, second) = if x_first { (0, 1) } else { (1, 0) };
+            separate(positions, &half, first, true);
+            separate(positions, &half, second, false);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_remove() {
+    let sizes = vec![(54.0, 36.0); 2];
+    let remove_with = |overlap: &str, positions: &[Point]| {
+        let graph = from_dot(&format!(r#"graph {{ overlap = "{}"; a -- b }}"#, overlap));
+        let mut positions = positions.to_vec();
+        remove(&graph, &mut positions, &sizes).unwrap();
+        positions
+    };
+    let close = [(0.0, 0.0), (10.0, 5.0)];
+    assert_eq!(remove_with("true", &close), close);
+    // With the default sep of 4 points, the nodes need 62 points between their centers.
+    assert_eq!(remove_with("scale", &close), vec![(0.0, 0.0), (62.0, 31.0)]);
+    assert_eq!(
+        remove_with("compress", &[(0.0, 0.0), (100.0, 0.0)]),
+        vec![(0.0, 0.0), (62.0, 0.0)]
+    );
+    assert_eq!(
+        remove_with("scalexy", &close),
+        vec![(0.0, 0.0), (62.0, 5.0)]
+    );
+    // vpsc moves the nodes along the axis where they overlap less.
+    assert_eq!(
+        remove_with("vpsc", &close),
+        vec![(0.0, -19.5), (10.0, 24.5)]
+    );
+    assert_eq!(
+        remove_with("vpsc", &[(0.0, 0.0), (40.0, 5.0)]),
+        vec![(-11.0, 0.0), (51.0, 5.0)]
+    );
+    assert_eq!(
+        remove_with("orthoyx", &[(0.0, 0.0), (5.0, 10.0)]),
+        vec![(0.0, -17.0), (5.0, 27.0)]
+    );
+    let apart = remove_with("false", &close);
+    assert!(overlapping_pairs(&apart, &[(31.0, 22.0); 2]).is_empty());
+    // The same place is not a problem.
+    let apart = remove_with("prism", &[(0.0, 0.0), (0.0, 0.0)]);
+    assert!(overlapping_pairs(&apart, &[(31.0, 22.0); 2]).is_empty());
+
+    let graph = from_dot(r#"graph { overlap = false; sep = "x" }"#);
+    assert!(remove(&graph, &mut [], &[]).is_err());
+}
+
+#[test]
+fn test_remove_many() {
+    let n = 30;
+    let graph = from_dot(r#"graph { overlap = vpsc }"#);
+    let sizes = vec![(54.0, 36.0); n];
+    let half = vec![(31.0, 22.0); n];
+    let mut rng = crate::layout::Rng::new(7);
+    let start: Vec<Point> = (0..n)
+        .map(|_| (100.0 * rng.next_f64(), 100.0 * rng.next_f64()))
+        .collect();
+    for overlap in ["false", "scale", "scalexy", "compress", "vpsc", "orthoyx"] {
+        let mut graph = graph.clone();
+        graph.attrs.set("overlap", overlap);
+        let mut positions = start.clone();
+        remove(&graph, &mut positions, &sizes).unwrap();
+        assert!(
+            overlapping_pairs(&positions, &half).is_empty(),
+            "{}",
+            overlap
+        );
+    }
+}
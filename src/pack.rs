@@ -0,0 +1,606 @@
+use crate::layout::{self, Point, Rect};
+use crate::semantic::{Attrs, Graph};
+use std::collections::HashSet;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz default for the margin around components, in points.
+pub const DEFAULT_MARGIN: f64 = 8.0;
+// The number of grid cells polyomino packing aims to give a component on average.
+const CELLS_PER_COMPONENT: f64 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    // Polyominoes covering the nodes and edges of every component.
+    Node,
+    // The same, with top level clusters covered as a whole.
+    Clust,
+    // Polyominoes covering the bounding box of every component.
+    Graph,
+    Array(Array),
+}
+
+// Components in a grid, as packmode=array_flagsN asks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Array {
+    // Fill columns first instead of rows, flag c.
+    pub column_major: bool,
+    // Keep components in the order they are found instead of largest first, flag u.
+    pub user_order: bool,
+    // Where a component goes in its cell horizontally and vertically: -1 for the left or bottom,
+    // flags l and b, 1 for the right or top, flags r and t, and 0 for the center.
+    pub align: (i8, i8),
+    // The number of columns, or of rows when column_major is set. About the square root of the
+    // number of components when not given.
+    pub count: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pack {
+    pub margin: f64,
+    pub mode: Mode,
+}
+
+fn mode(value: &str) -> Result<Mode, String> {
+    let bad = || format!("{}:{} Bad packmode {}", file!(), line!(), value);
+    let value = value.trim().to_lowercase();
+    let rest = match value.as_str() {
+        "node" => return Ok(Mode::Node),
+        "clust" => return Ok(Mode::Clust),
+        "graph" => return Ok(Mode::Graph),
+        _ => value.strip_prefix("array").ok_or_else(bad)?,
+    };
+    let (flags, count) = match rest.strip_prefix('_') {
+        Some(rest) => rest.split_at(
+            rest.find(|c: char| c.is_ascii_digit())
+                .unwrap_or(rest.len()),
+        ),
+        None => ("", rest),
+    };
+    let mut array = Array {
+        count: match count {
+            "" => None,
+            count => Some(
+                count
+                    .parse()
+                    .ok()
+                    .filter(|&count| count > 0)
+                    .ok_or_else(bad)?,
+            ),
+        },
+        ..Array::default()
+    };
+    for flag in flags.chars() {
+        match flag {
+            'c' => array.column_major = true,
+            'u' => array.user_order = true,
+            'l' => array.align.0 = -1,
+            'r' => array.align.0 = 1,
+            'b' => array.align.1 = -1,
+            't' => array.align.1 = 1,
+            _ => return Err(bad()),
+        }
+    }
+    Ok(Mode::Array(array))
+}
+
+// Reads pack and packmode. pack is true, false or a margin in points, and setting packmode alone
+// turns packing on. Otherwise components are packed when on_by_default is set. Returns None
+// when they are not.
+pub fn pack_of(attrs: &Attrs, on_by_default: bool) -> Result<Option<Pack>, String> {
+    let packmode = attrs.get("packmode");
+    let margin = match attrs.get("pack").map(|value| value.trim().to_lowercase()) {
+        Some(value) => match value.as_str() {
+            "true" | "yes" => Some(DEFAULT_MARGIN),
+            "false" | "no" => None,
+            _ => match value.parse::<i64>() {
+                Ok(margin) if margin >= 0 => Some(margin as f64),
+                Ok(_) => None,
+                Err(_) => return Err(format!("{}:{} Bad pack {}", file!(), line!(), value)),
+            },
+        },
+        None if on_by_default || packmode.is_some() => Some(DEFAULT_MARGIN),
+        None => None,
+    };
+    let mode = match packmode {
+        Some(value) => mode(value)?,
+        None => Mode::Node,
+    };
+    Ok(margin.map(|margin| Pack { margin, mode }))
+}
+
+#[test]
+fn test_pack_of() {
+    let pack = |attrs: &str, on_by_default: bool| {
+        pack_of(
+            &from_dot(&format!("graph {{ {} a }}", attrs)).attrs,
+            on_by_default,
+        )
+    };
+    assert_eq!(pack("", false), Ok(None));
+    assert_eq!(
+        pack("", true),
+        Ok(Some(Pack {
+            margin: 8.0,
+            mode: Mode::Node
+        }))
+    );
+    assert_eq!(pack("pack = false;", true), Ok(None));
+    assert_eq!(pack(r#"pack = 20;"#, false).unwrap().unwrap().margin, 20.0);
+    assert_eq!(
+        pack("packmode = array_cr3;", false).unwrap().unwrap().mode,
+        Mode::Array(Array {
+            column_major: true,
+            user_order: false,
+            align: (1, 0),
+            count: Some(3)
+        })
+    );
+    assert_eq!(
+        pack("packmode = array4;", false).unwrap().unwrap().mode,
+        Mode::Array(Array {
+            count: Some(4),
+            ..Array::default()
+        })
+    );
+    assert_eq!(
+        pack("packmode = clust;", false).unwrap().unwrap().mode,
+        Mode::Clust
+    );
+    assert!(pack("packmode = array_x;", false).is_err());
+    assert!(pack("pack = maybe;", false).is_err());
+}
+
+fn rect_of(position: Point, (width, height): (f64, f64), margin: f64) -> Rect {
+    (
+        position.0 - width / 2.0 - margin,
+        position.1 - height / 2.0 - margin,
+        position.0 + width / 2.0 + margin,
+        position.1 + height / 2.0 + margin,
+    )
+}
+
+fn union(rects: impl Iterator<Item = Rect>) -> Rect {
+    rects.fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)),
+    )
+}
+
+fn translate(component: &[usize], positions: &mut [Point], (dx, dy): Point) {
+    for &i in component.iter() {
+        positions[i].0 += dx;
+        positions[i].1 += dy;
+    }
+}
+
+// The side of a grid cell, chosen as Graphviz does so that components cover about
+// CELLS_PER_COMPONENT cells on average.
+fn step(boxes: &[Rect]) -> f64 {
+    if boxes.is_empty() {
+        return 1.0;
+    }
+    let a = CELLS_PER_COMPONENT * boxes.len() as f64 - 1.0;
+    let b: f64 = -boxes.iter().map(|r| (r.2 - r.0) + (r.3 - r.1)).sum::<f64>();
+    let c: f64 = -boxes.iter().map(|r| (r.2 - r.0) * (r.3 - r.1)).sum::<f64>();
+    f64::max(
+        ((-b + (b * b - 4.0 * a * c).sqrt()) / (2.0 * a)).floor(),
+        1.0,
+    )
+}
+
+// Adds the cells under a box, given relative to the center of its component.
+fn cover(cells: &mut HashSet<(i64, i64)>, (x0, y0, x1, y1): Rect, step: f64) {
+    let (i0, j0) = ((x0 / step).floor() as i64, (y0 / step).floor() as i64);
+    let (i1, j1) = ((x1 / step).ceil() as i64, (y1 / step).ceil() as i64);
+    for i in i0..i1.max(i0 + 1) {
+        for j in j0..j1.max(j0 + 1) {
+            cells.insert((i, j));
+        }
+    }
+}
+
+// Occupied grid cells, kept in a square around the center that grows as needed.
+struct Grid {
+    radius: i64,
+    cells: Vec<bool>,
+}
+
+impl Grid {
+    fn new() -> Grid {
+        Grid {
+            radius: 0,
+            cells: vec![false],
+        }
+    }
+
+    fn index(&self, (i, j): (i64, i64)) -> Option<usize> {
+        let side = 2 * self.radius + 1;
+        if i.abs() > self.radius || j.abs() > self.radius {
+            return None;
+        }
+        Some(((i + self.radius) * side + j + self.radius) as usize)
+    }
+
+    fn contains(&self, cell: (i64, i64)) -> bool {
+        self.index(cell).is_some_and(|index| self.cells[index])
+    }
+
+    fn insert(&mut self, cell: (i64, i64)) {
+        while self.index(cell).is_none() {
+            let mut grown = Grid {
+                radius: 2 * self.radius + 1,
+                cells: Vec::new(),
+            };
+            let side = 2 * grown.radius + 1;
+            grown.cells = vec![false; (side * side) as usize];
+            for i in -self.radius..=self.radius {
+                for j in -self.radius..=self.radius {
+                    if self.contains((i, j)) {
+                        let index = grown.index((i, j)).unwrap();
+                        grown.cells[index] = true;
+                    }
+                }
+            }
+            *self = grown;
+        }
+        let index = self.index(cell).unwrap();
+        self.cells[index] = true;
+    }
+}
+
+// Packs the components as polyominoes, after Freivalds, Dogrusoz and Kikusts. Each covers the
+// grid cells under its nodes, grown by half the margin, and its edges, or in graph mode its
+// whole bounding box. The largest goes first, and every other is put at the grid position
+// nearest to the center where it does not cover an occupied cell.
+fn polyominoes(
+    graph: &Graph,
+    components: &[Vec<usize>],
+    sizes: &[(f64, f64)],
+    positions: &mut [Point],
+    pack: &Pack,
+) {
+    let half = pack.margin / 2.0;
+    let boxes: Vec<Rect> = components
+        .iter()
+        .map(|component| {
+            union(
+                component
+                    .iter()
+                    .map(|&i| rect_of(positions[i], sizes[i], half)),
+            )
+        })
+        .collect();
+    let step = step(&boxes);
+    let mut component_of = vec![0; graph.nodes.len()];
+    for (c, component) in components.iter().enumerate() {
+        for &i in component.iter() {
+            component_of[i] = c;
+        }
+    }
+    let centers: Vec<Point> = boxes
+        .iter()
+        .map(|r| ((r.0 + r.2) / 2.0, (r.1 + r.3) / 2.0))
+        .collect();
+    let relative = |r: Rect, c: usize| {
+        let (x, y) = centers[c];
+        (r.0 - x, r.1 - y, r.2 - x, r.3 - y)
+    };
+
+    let mut polyominoes = vec![HashSet::new(); components.len()];
+    if pack.mode == Mode::Graph {
+        for (c, cells) in polyominoes.iter_mut().enumerate() {
+            cover(cells, relative(boxes[c], c), step);
+        }
+    } else {
+        for (i, &position) in positions.iter().enumerate() {
+            let c = component_of[i];
+            cover(
+                &mut polyominoes[c],
+                relative(rect_of(position, sizes[i], half), c),
+                step,
+            );
+        }
+        // Edges are straight at this point, so the cells along them are enough.
+        for edge in graph.edges.iter() {
+            let c = component_of[edge.tail];
+            let (p, q) = (positions[edge.tail], positions[edge.head]);
+            let samples = ((q.0 - p.0).hypot(q.1 - p.1) / (step / 2.0)).ceil() as usize;
+            for k in 0..=samples {
+                let t = k as f64 / samples.max(1) as f64;
+                let point = (p.0 + (q.0 - p.0) * t, p.1 + (q.1 - p.1) * t);
+                cover(
+                    &mut polyominoes[c],
+                    relative(rect_of(point, (0.0, 0.0), 0.0), c),
+                    step,
+                );
+            }
+        }
+    }
+    if pack.mode == Mode::Clust {
+        let clusters = layout::clusters_in(graph, None);
+        for &cluster in clusters.iter() {
+            let nodes = &graph.subgraphs[cluster].nodes;
+            if let Some(&first) = nodes.first() {
+                let c = component_of[first];
+                let rects = nodes
+                    .iter()
+                    .filter(|&&i| component_of[i] == c)
+                    .map(|&i| rect_of(positions[i], sizes[i], half));
+                cover(&mut polyominoes[c], relative(union(rects), c), step);
+            }
+        }
+    }
+
+    for (c, (i, j)) in place_polyominoes(polyominoes, &boxes)
+        .into_iter()
+        .enumerate()
+    {
+        let (x, y) = centers[c];
+        translate(
+            &components[c],
+            positions,
+            (i as f64 * step - x, j as f64 * step - y),
+        );
+    }
+}
+
+// Grid offsets for polyominoes given as cells relative to the centers of their boxes. The
+// largest box goes first, and every other is put at the grid position nearest to the center
+// where it does not cover an occupied cell.
+fn place_polyominoes(polyominoes: Vec<HashSet<(i64, i64)>>, boxes: &[Rect]) -> Vec<(i64, i64)> {
+    let mut order: Vec<usize> = (0..polyominoes.len()).collect();
+    order.sort_by(|&a, &b| {
+        let size = |c: usize| (boxes[c].2 - boxes[c].0) + (boxes[c].3 - boxes[c].1);
+        size(b).total_cmp(&size(a))
+    });
+    let polyominoes: Vec<Vec<(i64, i64)>> = polyominoes
+        .into_iter()
+        .map(|cells| cells.into_iter().collect())
+        .collect();
+    let mut offsets = vec![(0, 0); polyominoes.len()];
+    let mut occupied = Grid::new();
+    // Grid positions ring by ring around the center, nearest first within a ring.
+    let mut rings: Vec<Vec<(i64, i64)>> = Vec::new();
+    for c in order {
+        let fits = |(i, j): (i64, i64)| {
+            polyominoes[c]
+                .iter()
+                .all(|&(x, y)| !occupied.contains((x + i, y + j)))
+        };
+        let mut ring = 0;
+        let offset = loop {
+            if ring == rings.len() {
+                let r = ring as i64;
+                let mut candidates: Vec<(i64, i64)> = (-r..=r)
+                    .flat_map(|i| (-r..=r).map(move |j| (i, j)))
+                    .filter(|&(i, j)| i.abs() == r || j.abs() == r)
+                    .collect();
+                candidates.sort_by_key(|&(i, j)| (i * i + j * j, -j, i));
+                rings.push(candidates);
+            }
+            if let Some(&offset) = rings[ring].iter().find(|&&offset| fits(offset)) {
+                break offset;
+            }
+            ring += 1;
+        };
+        for &(x, y) in polyominoes[c].iter() {
+            occupied.insert((x + offset.0, y + offset.1));
+        }
+        offsets[c] = offset;
+    }
+    offsets
+}
+
+// Puts the components in a grid.
+fn array(components: &[Vec<usize>], sizes: &[(f64, f64)], positions: &mut [Point], pack: &Pack) {
+    let Mode::Array(flags) = pack.mode else {
+        return;
+    };
+    let boxes: Vec<Rect> = components
+        .iter()
+        .map(|component| {
+            union(
+                component
+                    .iter()
+                    .map(|&i| rect_of(positions[i], sizes[i], 0.0)),
+            )
+        })
+        .collect();
+    let offsets = array_offsets(&boxes, flags, pack.margin);
+    for (component, offset) in components.iter().zip(offsets) {
+        translate(component, positions, offset);
+    }
+}
+
+// How far to move each box to put them in a grid, margin apart, with every column as wide as its
+// widest box and every row as high as its highest. Rows go from top to bottom.
+fn array_offsets(boxes: &[Rect], flags: Array, margin: f64) -> Vec<Point> {
+    let n = boxes.len();
+    let mut order: Vec<usize> = (0..n).collect();
+    if !flags.user_order {
+        order.sort_by(|&a, &b| {
+            let area = |c: usize| (boxes[c].2 - boxes[c].0) * (boxes[c].3 - boxes[c].1);
+            area(b).total_cmp(&area(a))
+        });
+    }
+    let count = flags
+        .count
+        .unwrap_or((n as f64).sqrt().ceil() as usize)
+        .max(1);
+    let (columns, rows) = if flags.column_major {
+        (n.div_ceil(count), count)
+    } else {
+        (count, n.div_ceil(count))
+    };
+    let cell = |k: usize| {
+        if flags.column_major {
+            (k % rows, k / rows)
+        } else {
+            (k / columns, k % columns)
+        }
+    };
+    let mut widths = vec![0.0; columns];
+    let mut heights = vec![0.0; rows];
+    let mut offsets = vec![(0.0, 0.0); n];
+    for (k, &c) in order.iter().enumerate() {
+        let (row, column) = cell(k);
+        widths[column] = f64::max(widths[column], boxes[c].2 - boxes[c].0);
+        heights[row] = f64::max(heights[row], boxes[c].3 - boxes[c].1);
+    }
+    for (k, &c) in order.iter().enumerate() {
+        let (row, column) = cell(k);
+        let (width, height) = (boxes[c].2 - boxes[c].0, boxes[c].3 - boxes[c].1);
+        let left = widths[..column].iter().sum::<f64>() + margin * column as f64;
+        let top = -heights[..row].iter().sum::<f64>() - margin * row as f64;
+        let x = match flags.align.0 {
+            -1 => left,
+            1 => left + widths[column] - width,
+            _ => left + (widths[column] - width) / 2.0,
+        };
+        let y = match flags.align.1 {
+            -1 => top - heights[row],
+            1 => top - height,
+            _ => top - (heights[row] + height) / 2.0,
+        };
+        offsets[c] = (x - boxes[c].0, y - boxes[c].1);
+    }
+    offsets
+}
+
+// Moves separately laid out components, given by their nodes, so that they do not overlap.
+pub fn pack(
+    graph: &Graph,
+    components: &[Vec<usize>],
+    sizes: &[(f64, f64)],
+    positions: &mut [Point],
+    pack: &Pack,
+) {
+    match pack.mode {
+        Mode::Array(_) => array(components, sizes, positions, pack),
+        Mode::Node | Mode::Clust | Mode::Graph => {
+            polyominoes(graph, components, sizes, positions, pack)
+        }
+    }
+}
+
+// How far to move each box, such as the nodes and clusters inside a cluster, so that they do not
+// overlap. Array mode puts them in a grid, the other modes pack every box, grown by half the
+// margin, as a polyomino of its own, which is what Graphviz does with rectangles.
+pub fn pack_boxes(boxes: &[Rect], pack: &Pack) -> Vec<Point> {
+    if let Mode::Array(flags) = pack.mode {
+        return array_offsets(boxes, flags, pack.margin);
+    }
+    let half = pack.margin / 2.0;
+    let boxes: Vec<Rect> = boxes
+        .iter()
+        .map(|r| (r.0 - half, r.1 - half, r.2 + half, r.3 + half))
+        .collect();
+    let step = step(&boxes);
+    let centers: Vec<Point> = boxes
+        .iter()
+        .map(|r| ((r.0 + r.2) / 2.0, (r.1 + r.3) / 2.0))
+        .collect();
+    let polyominoes = boxes
+        .iter()
+        .zip(centers.iter())
+        .map(|(r, &(x, y))| {
+            let mut cells = HashSet::new();
+            cover(&mut cells, (r.0 - x, r.1 - y, r.2 - x, r.3 - y), step);
+            cells
+        })
+        .collect();
+    place_polyominoes(polyominoes, &boxes)
+        .into_iter()
+        .zip(centers)
+        .map(|((i, j), (x, y))| (i as f64 * step - x, j as f64 * step - y))
+        .collect()
+}
+
+#[cfg(test)]
+fn overlapping(positions: &[Point], sizes: &[(f64, f64)], margin: f64) -> bool {
+    (0..positions.len()).any(|i| {
+        (0..i).any(|j| {
+            (positions[i].0 - positions[j].0).abs() < (sizes[i].0 + sizes[j].0) / 2.0 + margin
+                && (positions[i].1 - positions[j].1).abs()
+                    < (sizes[i].1 + sizes[j].1) / 2.0 + margin
+        })
+    })
+}
+
+#[test]
+fn test_pack() {
+    let graph = from_dot("graph { a -- b; c; d -- e -- f }");
+    let components = vec![vec![0, 1], vec![2], vec![3, 4, 5]];
+    let sizes = vec![(54.0, 36.0); 6];
+    // Every component starts out on top of the others.
+    let start = vec![
+        (0.0, 0.0),
+        (100.0, 0.0),
+        (0.0, 0.0),
+        (0.0, 0.0),
+        (0.0, 100.0),
+        (0.0, 200.0),
+    ];
+    for mode in ["node", "clust", "graph", "array", "array_c1", "array_ut"] {
+        let pack = Pack {
+            margin: 8.0,
+            mode: self::mode(mode).unwrap(),
+        };
+        let mut positions = start.clone();
+        self::pack(&graph, &components, &sizes, &mut positions, &pack);
+        assert!(!overlapping(&positions, &sizes, 7.99), "{}", mode);
+        // Components move as a whole.
+        assert_eq!(positions[1].0 - positions[0].0, 100.0);
+        assert_eq!(positions[5].1 - positions[3].1, 200.0);
+    }
+
+    // One row in the order found, with tops aligned.
+    let pack = Pack {
+        margin: 8.0,
+        mode: mode("array_ut3").unwrap(),
+    };
+    let mut positions = start.clone();
+    self::pack(&graph, &components, &sizes, &mut positions, &pack);
+    assert_eq!(
+        &positions[..3],
+        &[(27.0, -18.0), (127.0, -18.0), (189.0, -18.0)]
+    );
+}
+
+#[test]
+fn test_polyominoes() {
+    // Many small components end up in a roughly square drawing rather than a long row.
+    let n = 100;
+    let graph = from_dot(&format!(
+        "graph {{ {} }}",
+        (0..n)
+            .map(|i| format!("a{} -- b{};", i, i))
+            .collect::<String>()
+    ));
+    let components: Vec<Vec<usize>> = (0..n).map(|i| vec![2 * i, 2 * i + 1]).collect();
+    let sizes = vec![(54.0, 36.0); 2 * n];
+    let mut positions: Vec<Point> = (0..2 * n)
+        .map(|i| (if i % 2 == 0 { 0.0 } else { 72.0 }, 0.0))
+        .collect();
+    let pack = Pack {
+        margin: 8.0,
+        mode: Mode::Node,
+    };
+    self::pack(&graph, &components, &sizes, &mut positions, &pack);
+    assert!(!overlapping(&positions, &sizes, 7.99));
+    let bb = union((0..2 * n).map(|i| rect_of(positions[i], sizes[i], 0.0)));
+    let (width, height) = (bb.2 - bb.0, bb.3 - bb.1);
+    assert!(
+        width < 3.0 * height && height < 3.0 * width,
+        "{}x{}",
+        width,
+        height
+    );
+}
@@ -0,0 +1,201 @@
+use crate::layout::{self, number, ClusterBox, Rect, POINTS_PER_INCH};
+use crate::semantic::{Attrs, Graph};
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz default, in square inches.
+const DEFAULT_AREA: f64 = 1.0;
+
+fn area(attrs: &Attrs) -> Result<f64, String> {
+    let area = number(attrs, "area", DEFAULT_AREA)?;
+    if area <= 0.0 {
+        return Err(format!(
+            "{}:{} area must be positive: {}",
+            file!(),
+            line!(),
+            area
+        ));
+    }
+    Ok(area)
+}
+
+// How badly a row of tiles with the given areas, laid along a side of the given length, departs
+// from squares: the largest ratio of the long side to the short side.
+fn worst(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    row.iter()
+        .map(|&area| {
+            f64::max(
+                side * side * area / (sum * sum),
+                sum * sum / (side * side * area),
+            )
+        })
+        .fold(0.0, f64::max)
+}
+
+// Splits rect into tiles with areas in proportion to the given ones, as close to squares as the
+// squarified treemap of Bruls, Huizing and van Wijk gets them. Tiles are returned in the order
+// of areas.
+fn squarify(areas: &[f64], rect: Rect) -> Vec<Rect> {
+    let total: f64 = areas.iter().sum();
+    let scale = (rect.2 - rect.0) * (rect.3 - rect.1) / total;
+    let mut order: Vec<usize> = (0..areas.len()).collect();
+    order.sort_by(|&a, &b| areas[b].total_cmp(&areas[a]));
+    let mut tiles = vec![(0.0, 0.0, 0.0, 0.0); areas.len()];
+    let mut rest = rect;
+    let mut row: Vec<usize> = Vec::new();
+    let mut i = 0;
+    while i < order.len() || !row.is_empty() {
+        let (width, height) = (rest.2 - rest.0, rest.3 - rest.1);
+        let side = width.min(height);
+        let scaled =
+            |row: &[usize]| -> Vec<f64> { row.iter().map(|&j| areas[j] * scale).collect() };
+        if i < order.len() {
+            let mut longer = row.clone();
+            longer.push(order[i]);
+            if row.is_empty() || worst(&scaled(&longer), side) <= worst(&scaled(&row), side) {
+                row = longer;
+                i += 1;
+                continue;
+            }
+        }
+        // Lay the row along the shorter side, starting at the upper left corner, and go on with
+        // the rest of the rectangle.
+        let sum: f64 = scaled(&row).iter().sum();
+        let thickness = sum / side;
+        let mut offset = 0.0;
+        for &j in row.iter() {
+            let length = areas[j] * scale / thickness;
+            tiles[j] = if width >= height {
+                (
+                    rest.0,
+                    rest.3 - offset - length,
+                    rest.0 + thickness,
+                    rest.3 - offset,
+                )
+            } else {
+                (
+                    rest.0 + offset,
+                    rest.3 - thickness,
+                    rest.0 + offset + length,
+                    rest.3,
+                )
+            };
+            offset += length;
+        }
+        if width >= height {
+            rest.0 += thickness;
+        } else {
+            rest.3 -= thickness;
+        }
+        row.clear();
+    }
+    tiles
+}
+
+#[test]
+fn test_squarify() {
+    let tiles = squarify(&[1.0; 4], (0.0, 0.0, 2.0, 2.0));
+    assert_eq!(
+        tiles,
+        vec![
+            (0.0, 1.0, 1.0, 2.0),
+            (0.0, 0.0, 1.0, 1.0),
+            (1.0, 1.0, 2.0, 2.0),
+            (1.0, 0.0, 2.0, 1.0)
+        ]
+    );
+    // The example from the paper: the two largest tiles fill the left half.
+    let tiles = squarify(&[6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0], (0.0, 0.0, 6.0, 4.0));
+    assert_eq!(tiles[0], (0.0, 2.0, 3.0, 4.0));
+    assert_eq!(tiles[1], (0.0, 0.0, 3.0, 2.0));
+    let area: f64 = tiles.iter().map(|t| (t.2 - t.0) * (t.3 - t.1)).sum();
+    assert!((area - 24.0).abs() < 1e-9);
+}
+
+// The area of every cluster is the sum of what is inside it, or its own area when empty.
+fn cluster_area(graph: &Graph, cluster: usize) -> Result<f64, String> {
+    let clusters = layout::clusters_in(graph, Some(cluster));
+    let nodes = layout::nodes_in(graph, Some(cluster), &clusters);
+    if clusters.is_empty() && nodes.is_empty() {
+        return area(&graph.subgraphs[cluster].attrs);
+    }
+    let mut sum = 0.0;
+    for cluster in clusters {
+        sum += cluster_area(graph, cluster)?;
+    }
+    for node in nodes {
+        sum += area(&graph.nodes[node].attrs)?;
+    }
+    Ok(sum)
+}
+
+fn place(
+    graph: &Graph,
+    parent: Option<usize>,
+    rect: Rect,
+    tiles: &mut [Rect],
+    boxes: &mut Vec<ClusterBox>,
+) -> Result<(), String> {
+    let clusters = layout::clusters_in(graph, parent);
+    let nodes = layout::nodes_in(graph, parent, &clusters);
+    let mut areas = Vec::new();
+    for &cluster in clusters.iter() {
+        areas.push(cluster_area(graph, cluster)?);
+    }
+    for &node in nodes.iter() {
+        areas.push(area(&graph.nodes[node].attrs)?);
+    }
+    if areas.is_empty() {
+        return Ok(());
+    }
+    let split = squarify(&areas, rect);
+    for (&cluster, &rect) in clusters.iter().zip(split.iter()) {
+        boxes.push((cluster, rect));
+        place(graph, Some(cluster), rect, tiles, boxes)?;
+    }
+    for (&node, &rect) in nodes.iter().zip(split[clusters.len()..].iter()) {
+        tiles[node] = rect;
+    }
+    Ok(())
+}
+
+// Treemap layout: the drawing is a square of the total area, in which every node gets a tile
+// of its area, in square inches, and every cluster a rectangle holding the tiles of its
+// contents. Returns the tiles of the nodes and the boxes of the clusters, in points.
+pub fn layout(graph: &Graph) -> Result<(Vec<Rect>, Vec<ClusterBox>), String> {
+    let clusters = layout::clusters_in(graph, None);
+    let mut total = 0.0;
+    for &cluster in clusters.iter() {
+        total += cluster_area(graph, cluster)?;
+    }
+    for node in layout::nodes_in(graph, None, &clusters) {
+        total += area(&graph.nodes[node].attrs)?;
+    }
+    let side = total.sqrt() * POINTS_PER_INCH;
+    let mut tiles = vec![(0.0, 0.0, 0.0, 0.0); graph.nodes.len()];
+    let mut boxes = Vec::new();
+    place(graph, None, (0.0, 0.0, side, side), &mut tiles, &mut boxes)?;
+    Ok((tiles, boxes))
+}
+
+#[test]
+fn test_layout() {
+    let graph = from_dot(
+        r#"graph {
+    subgraph cluster_a { a [area = 4]; b [area = 4] }
+    c [area = 8]
+}"#,
+    );
+    let (tiles, boxes) = layout(&graph).unwrap();
+    // The drawing is a 4 by 4 inch square, split in halves.
+    assert_eq!(boxes, vec![(0, (0.0, 144.0, 288.0, 288.0))]);
+    assert_eq!(tiles[2], (0.0, 0.0, 288.0, 144.0));
+    for (x0, y0, x1, y1) in [tiles[0], tiles[1]] {
+        assert_eq!((x1 - x0) * (y1 - y0), 144.0 * 144.0);
+        assert!(y0 >= 144.0);
+    }
+
+    assert!(layout(&from_dot(r#"graph { a [area = 0] }"#)).is_err());
+}
@@ -0,0 +1,233 @@
+use crate::draw::{self, Canvas, Op, Pen};
+use crate::layout::format_number;
+use crate::ps::{self, PathOps};
+use crate::semantic::Graph;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+#[cfg(test)]
+use crate::layout::{self, Engine};
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+const PATH_OPS: PathOps = PathOps {
+    move_to: "m",
+    line_to: "l",
+    curve_to: "c",
+    close: "h",
+};
+
+// The content of the pages, with the fonts and translucent colors it uses, which the pages
+// list as resources.
+#[derive(Default)]
+struct Content {
+    out: String,
+    fonts: Vec<&'static str>,
+    // Alphas of the fill (ca) and pen (CA) colors other than opaque.
+    alphas: BTreeSet<(&'static str, u8)>,
+}
+
+impl Content {
+    fn alpha(&mut self, name: &'static str, alpha: u8) {
+        if alpha != 255 {
+            self.alphas.insert((name, alpha));
+            writeln!(self.out, "/{}{} gs", name, alpha).unwrap();
+        }
+    }
+
+    // Draws one xdot attribute, which starts with the default pen. Each shape and text is drawn
+    // between q and Q, which restore the graphics state after it.
+    fn ops(&mut self, ops: &[Op]) {
+        let mut pen = Pen::default();
+        for op in ops {
+            if pen.set(op) {
+                continue;
+            }
+            if let Some((path, filled)) = draw::path(op) {
+                let fill = filled && pen.fill.a != 0;
+                let stroke = pen.color.a != 0;
+                self.out.push_str("q\n");
+                if fill {
+                    writeln!(self.out, "{} rg", ps::rgb(pen.fill)).unwrap();
+                    self.alpha("ca", pen.fill.a);
+                }
+                if stroke {
+                    writeln!(
+                        self.out,
+                        "{} RG {} w {} d",
+                        ps::rgb(pen.color),
+                        format_number(pen.width, 2),
+                        ps::dash(pen.dash)
+                    )
+                    .unwrap();
+                    self.alpha("CA", pen.color.a);
+                }
+                ps::write_path(&mut self.out, &path, &PATH_OPS);
+                let paint = match (fill, stroke) {
+                    (true, true) => "B",
+                    (true, false) => "f",
+                    (false, true) => "S",
+                    (false, false) => "n",
+                };
+                writeln!(self.out, "{}\nQ", paint).unwrap();
+            } else if let Op::Text {
+                pos,
+                justification,
+                width,
+                text,
+            } = op
+            {
+                if pen.color.a == 0 {
+                    continue;
+                }
+                let font = draw::standard_font(&pen.font, pen.flags);
+                let index = match self.fonts.iter().position(|&f| f == font) {
+                    Some(index) => index,
+                    None => {
+                        self.fonts.push(font);
+                        self.fonts.len() - 1
+                    }
+                };
+                let (x, y) = draw::text_start(*pos, *justification, *width);
+                writeln!(self.out, "q\n{} rg", ps::rgb(pen.color)).unwrap();
+                self.alpha("ca", pen.color.a);
+                writeln!(
+                    self.out,
+                    "BT /F{} {} Tf {} {} Td {} Tj ET\nQ",
+                    index + 1,
+                    format_number(pen.font_size, 2),
+                    format_number(x, 2),
+                    format_number(y, 2),
+                    ps::string(text)
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+// Renders a laid-out graph as PDF with the standard fonts, which readers have built in. Graphs
+// larger than page are printed on several pages in the order of pagedir.
+pub fn render(graph: &Graph) -> Result<String, String> {
+    let canvas = Canvas::new(graph)?;
+    let pages = draw::pages(graph, &canvas)?;
+    let mut content = Content::default();
+    for (_, ops) in draw::objects(graph)? {
+        for (_, ops) in ops {
+            content.ops(&ops);
+        }
+    }
+
+    // Objects are numbered from 1: the catalog, the page tree, the resources, the fonts and
+    // then each page followed by its content.
+    let mut objects = Vec::new();
+    objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_string());
+    let first_page = 4 + content.fonts.len();
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", first_page + 2 * i))
+        .collect();
+    objects.push(format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        kids.join(" "),
+        pages.len()
+    ));
+    let fonts: Vec<String> = (0..content.fonts.len())
+        .map(|i| format!("/F{} {} 0 R", i + 1, 4 + i))
+        .collect();
+    let alphas: Vec<String> = content
+        .alphas
+        .iter()
+        .map(|(name, alpha)| {
+            format!(
+                "/{}{} << /{} {} >>",
+                name,
+                alpha,
+                name,
+                format_number(*alpha as f64 / 255.0, 3)
+            )
+        })
+        .collect();
+    let mut resources = format!("<< /Font << {} >>", fonts.join(" "));
+    if !alphas.is_empty() {
+        write!(resources, " /ExtGState << {} >>", alphas.join(" ")).unwrap();
+    }
+    resources.push_str(" >>");
+    objects.push(resources);
+    for font in &content.fonts {
+        objects.push(format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+            font
+        ));
+    }
+    let n = |x: f64| format_number(x, 4);
+    for (i, page) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources 3 0 R /Contents {} 0 R >>",
+            format_number(page.2 - page.0, 2),
+            format_number(page.3 - page.1, 2),
+            first_page + 2 * i + 1
+        ));
+        let (x, y) = ps::page_offset(&canvas, *page);
+        let stream = format!(
+            "{} 0 0 {} {} {} cm\n{}",
+            n(canvas.scale),
+            n(canvas.scale),
+            n(x),
+            n(y),
+            content.out
+        );
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            stream.len(),
+            stream
+        ));
+    }
+
+    let mut out = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        writeln!(out, "{} 0 obj\n{}\nendobj", i + 1, object).unwrap();
+    }
+    let xref = out.len();
+    writeln!(out, "xref\n0 {}\n0000000000 65535 f ", objects.len() + 1).unwrap();
+    for offset in offsets {
+        writeln!(out, "{:010} 00000 n ", offset).unwrap();
+    }
+    writeln!(
+        out,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+        objects.len() + 1,
+        xref
+    )
+    .unwrap();
+    Ok(out)
+}
+
+#[test]
+fn test_render() {
+    let graph = from_dot(
+        r##"digraph G {
+    a [pos = "0,0!", style = filled, fillcolor = "#ff000080"]
+    b [pos = "2,0!", shape = box, fontname = Courier]
+    a -> b
+}"##,
+    );
+    let laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    let pdf = render(&laid_out).unwrap();
+    assert!(pdf.starts_with("%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n"));
+    assert!(pdf.contains("<< /Type /Pages /Kids [6 0 R] /Count 1 >>"));
+    assert!(pdf
+        .contains("<< /Font << /F1 4 0 R /F2 5 0 R >> /ExtGState << /ca128 << /ca 0.502 >> >> >>"));
+    assert!(pdf.contains("/BaseFont /Courier /Encoding /WinAnsiEncoding"));
+    assert!(pdf.contains("/MediaBox [0 0 206 44]"));
+    assert!(pdf.contains("stream\n1 0 0 1 4 4 cm\n"));
+    assert!(pdf.contains("q\n1 0 0 rg\n/ca128 gs\n0 0 0 RG 1 w [] 0 d\n54 18 m\n"));
+    assert!(pdf.contains("BT /F2 14 Tf 166.8 14.3 Td (b) Tj ET"));
+    // The cross-reference table points at each object.
+    let xref = pdf.rsplit("startxref\n").next().unwrap();
+    let xref: usize = xref.lines().next().unwrap().parse().unwrap();
+    assert!(pdf[xref..].starts_with("xref\n0 8\n"));
+    let second: usize = pdf[xref..].lines().nth(4).unwrap()[..10].parse().unwrap();
+    assert!(pdf[second..].starts_with("2 0 obj\n"));
+}
@@ -0,0 +1,139 @@
+use crate::ast::ID;
+use crate::layout::{format_number, parse_pos, Spline, POINTS_PER_INCH};
+use crate::semantic::{Attrs, Graph};
+use crate::text;
+
+#[cfg(test)]
+use crate::layout::{self, Engine};
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// A name or label as a DOT ID, quoted when it has to be.
+fn id(attrs: &Attrs, attr: &str, value: &str) -> String {
+    if attrs.is_html(attr) {
+        ID::from_html(value).to_string()
+    } else {
+        ID::from_value(value).to_string()
+    }
+}
+
+// Points to inches.
+fn inches(x: f64) -> String {
+    format_number(x / POINTS_PER_INCH, 4)
+}
+
+// Writes the laid-out graph in the layout of `dot -Tplain`, one line per graph, node and edge, with
+// coordinates and sizes in inches. plain-ext also gives the ports of edges, as in `a:f0`. The
+// graph must have been through layout::apply, whose pos, width, height and bb are read back.
+pub fn render(graph: &Graph, extended: bool) -> Result<String, String> {
+    let bb: Vec<f64> = graph
+        .attrs
+        .get("bb")
+        .unwrap_or("0,0,0,0")
+        .split(',')
+        .map(|x| x.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("{}:{} Bad bb", file!(), line!()))?;
+    let mut plain = format!(
+        "graph 1 {} {}\n",
+        inches(bb[2] - bb[0]),
+        inches(bb[3] - bb[1])
+    );
+    for node in graph.nodes.iter() {
+        let attrs = &node.attrs;
+        let ((x, y), _) = parse_pos(attrs.get("pos").unwrap_or("0,0"))?;
+        let label = text::node_text(graph, node, attrs.get("label").unwrap_or("\\N"));
+        // As in dot, the fill falls back on the pen color and then on lightgrey.
+        let fillcolor = attrs
+            .get("fillcolor")
+            .or(attrs.get("color"))
+            .unwrap_or("lightgrey");
+        plain.push_str(&format!(
+            "node {} {} {} {} {} {} {} {} {} {}\n",
+            ID::from_value(&node.name),
+            inches(x),
+            inches(y),
+            attrs.get("width").unwrap_or("0"),
+            attrs.get("height").unwrap_or("0"),
+            id(attrs, "label", &label),
+            id(attrs, "style", attrs.get("style").unwrap_or("solid")),
+            id(attrs, "shape", attrs.get("shape").unwrap_or("ellipse")),
+            id(attrs, "color", attrs.get("color").unwrap_or("black")),
+            id(attrs, "fillcolor", fillcolor),
+        ));
+    }
+    for edge in graph.edges.iter() {
+        let attrs = &edge.attrs;
+        let end = |node: usize, port: &str| {
+            let name = ID::from_value(&graph.nodes[node].name).to_string();
+            match attrs.get(port) {
+                Some(port) if extended => format!("{}:{}", name, ID::from_value(port)),
+                _ => name,
+            }
+        };
+        // The arrow tips are not part of the spline.
+        let points: Vec<String> = Spline::parse(attrs.get("pos").unwrap_or(""))?
+            .points
+            .iter()
+            .map(|&(x, y)| format!("{} {}", inches(x), inches(y)))
+            .collect();
+        // Edge labels are given only once the layout has placed them at lp.
+        let label = match (attrs.get("label"), attrs.get("lp")) {
+            (Some(label), Some(lp)) => {
+                let ((x, y), _) = parse_pos(lp)?;
+                let label = text::edge_text(graph, edge, label);
+                format!(
+                    " {} {} {}",
+                    id(attrs, "label", &label),
+                    inches(x),
+                    inches(y)
+                )
+            }
+            _ => String::new(),
+        };
+        plain.push_str(&format!(
+            "edge {} {} {} {}{} {} {}\n",
+            end(edge.tail, "tailport"),
+            end(edge.head, "headport"),
+            points.len(),
+            points.join(" "),
+            label,
+            id(attrs, "style", attrs.get("style").unwrap_or("solid")),
+            id(attrs, "color", attrs.get("color").unwrap_or("black")),
+        ));
+    }
+    plain.push_str("stop\n");
+    Ok(plain)
+}
+
+#[test]
+fn test_render() {
+    let graph = from_dot(
+        r#"digraph {
+    a [pos = "0,0!", label = "\N!", color = red]
+    b [pos = "2,0!", shape = box, style = filled, fillcolor = green]
+    a:e -> b [color = blue]
+}"#,
+    );
+    let laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    let plain = render(&laid_out, false).unwrap();
+    let lines: Vec<&str> = plain.lines().collect();
+    assert_eq!(lines[0], "graph 1 2.75 0.5");
+    assert_eq!(
+        lines[1],
+        r#"node a 0.375 0.25 0.75 0.5 "a!" solid ellipse red red"#
+    );
+    assert_eq!(
+        lines[2],
+        "node b 2.375 0.25 0.75 0.5 b filled box black green"
+    );
+    assert!(lines[3].starts_with("edge a b 4 0.75 0.25 "));
+    assert!(lines[3].ends_with(" solid blue"));
+    assert_eq!(lines[4], "stop");
+    let plain_ext = render(&laid_out, true).unwrap();
+    assert!(plain_ext
+        .lines()
+        .nth(3)
+        .unwrap()
+        .starts_with("edge a:e b 4 "));
+}
@@ -0,0 +1,339 @@
+use crate::color::Rgba;
+use crate::draw::{self, Canvas, Dash, Op, Pen, Segment};
+use crate::layout::{self, Point, POINTS_PER_INCH};
+use crate::semantic::Graph;
+use crate::text;
+use std::collections::HashMap;
+use tiny_skia::{Color, FillRule, Paint, Path, PathBuilder, Pixmap, Stroke, StrokeDash, Transform};
+
+#[cfg(test)]
+use crate::layout::Engine;
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// The font text is drawn with unless fontname is a TrueType or OpenType file, so that PNG
+// output looks the same everywhere. Text keeps the width it was laid out with.
+const DEFAULT_FONT: &[u8] = include_bytes!("fonts/DejaVuSerif.ttf");
+const DEFAULT_DPI: f64 = 96.0;
+// How far italic text leans, in widths per height.
+const SLANT: f32 = 0.2;
+// The extra width of the strokes of bold text, in font sizes.
+const BOLDNESS: f32 = 0.03;
+
+fn paint(color: Rgba) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color.r, color.g, color.b, color.a);
+    paint.anti_alias = true;
+    paint
+}
+
+fn stroke(pen: &Pen) -> Stroke {
+    let dash = match pen.dash {
+        Dash::Solid => None,
+        Dash::Dashed => StrokeDash::new(vec![5.0, 2.0], 0.0),
+        Dash::Dotted => StrokeDash::new(vec![1.0, 5.0], 0.0),
+    };
+    Stroke {
+        width: pen.width as f32,
+        dash,
+        ..Stroke::default()
+    }
+}
+
+fn build_path(segments: &[Segment]) -> Option<Path> {
+    let mut builder = PathBuilder::new();
+    for segment in segments {
+        match *segment {
+            Segment::Move((x, y)) => builder.move_to(x as f32, y as f32),
+            Segment::Line((x, y)) => builder.line_to(x as f32, y as f32),
+            Segment::Curve(a, b, c) => builder.cubic_to(
+                a.0 as f32, a.1 as f32, b.0 as f32, b.1 as f32, c.0 as f32, c.1 as f32,
+            ),
+            Segment::Close => builder.close(),
+        }
+    }
+    builder.finish()
+}
+
+// Collects the outline of a glyph, which is in font units with y going up, at its place in the
+// layout.
+struct Outline<'a> {
+    builder: &'a mut PathBuilder,
+    origin: (f32, f32),
+    scale: (f32, f32),
+    slant: f32,
+}
+
+impl Outline<'_> {
+    fn map(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.origin.0 + (x + self.slant * y) * self.scale.0,
+            self.origin.1 + y * self.scale.1,
+        )
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Outline<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.map(x, y);
+        self.builder.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.map(x, y);
+        self.builder.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x1, y1) = self.map(x1, y1);
+        let (x, y) = self.map(x, y);
+        self.builder.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (x1, y1) = self.map(x1, y1);
+        let (x2, y2) = self.map(x2, y2);
+        let (x, y) = self.map(x, y);
+        self.builder.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.builder.close();
+    }
+}
+
+struct Painter {
+    pixmap: Pixmap,
+    // From layout coordinates to pixels, with y going down.
+    transform: Transform,
+    fontpath: Option<String>,
+    // The contents of the font files read so far, or None when they cannot be read.
+    fonts: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl Painter {
+    fn fill(&mut self, path: &Path, color: Rgba) {
+        if color.a != 0 {
+            let transform = self.transform;
+            self.pixmap
+                .fill_path(path, &paint(color), FillRule::Winding, transform, None);
+        }
+    }
+
+    fn stroke(&mut self, path: &Path, pen: &Pen) {
+        if pen.color.a != 0 {
+            let transform = self.transform;
+            self.pixmap
+                .stroke_path(path, &paint(pen.color), &stroke(pen), transform, None);
+        }
+    }
+
+    // The font file of fontname when it names one that can be read.
+    fn font_data(&mut self, fontname: &str) -> Option<&[u8]> {
+        let lower = fontname.to_lowercase();
+        if !lower.ends_with(".ttf") && !lower.ends_with(".otf") {
+            return None;
+        }
+        let fontpath = self.fontpath.as_deref();
+        self.fonts
+            .entry(fontname.to_string())
+            .or_insert_with(|| {
+                text::font_files(fontname, fontpath)
+                    .into_iter()
+                    .filter_map(|path| std::fs::read(path).ok())
+                    .find(|data| ttf_parser::Face::parse(data, 0).is_ok())
+            })
+            .as_deref()
+    }
+
+    // Draws a line of text from its outlines, stretched or squeezed to the width it was laid
+    // out with, as the font may not be the one it was measured with.
+    fn text(&mut self, pen: &Pen, start: Point, width: f64, text: &str) {
+        let face = self
+            .font_data(&pen.font)
+            .and_then(|data| ttf_parser::Face::parse(data, 0).ok())
+            .unwrap_or_else(|| ttf_parser::Face::parse(DEFAULT_FONT, 0).unwrap());
+        let size = pen.font_size as f32;
+        let scale = size / face.units_per_em() as f32;
+        let glyphs: Vec<(Option<ttf_parser::GlyphId>, f32)> = text
+            .chars()
+            .map(|c| {
+                let glyph = face.glyph_index(c);
+                let advance = glyph
+                    .and_then(|glyph| face.glyph_hor_advance(glyph))
+                    .unwrap_or(face.units_per_em() / 2);
+                (glyph, advance as f32)
+            })
+            .collect();
+        let natural: f32 = glyphs.iter().map(|(_, advance)| advance * scale).sum();
+        let fit = if natural > 0.0 && width > 0.0 {
+            width as f32 / natural
+        } else {
+            1.0
+        };
+        let mut y = start.1 as f32;
+        if pen.flags & draw::SUPERSCRIPT != 0 {
+            y += size * 0.3;
+        } else if pen.flags & draw::SUBSCRIPT != 0 {
+            y -= size * 0.2;
+        }
+        let bold = pen.flags & draw::BOLD != 0 || pen.font.contains("Bold");
+        let italic = pen.flags & draw::ITALIC != 0
+            || pen.font.contains("Italic")
+            || pen.font.contains("Oblique");
+        let mut builder = PathBuilder::new();
+        let mut x = start.0 as f32;
+        for (glyph, advance) in glyphs {
+            if let Some(glyph) = glyph {
+                let mut outline = Outline {
+                    builder: &mut builder,
+                    origin: (x, y),
+                    scale: (scale * fit, scale),
+                    slant: if italic { SLANT } else { 0.0 },
+                };
+                face.outline_glyph(glyph, &mut outline);
+            }
+            x += advance * scale * fit;
+        }
+        // Underlines and the other lines through the text are drawn under, across and over it.
+        let end = x;
+        for (flag, height) in [
+            (draw::UNDERLINE, -0.1),
+            (draw::STRIKE, 0.3),
+            (draw::OVERLINE, 0.8),
+        ] {
+            let (y0, y1) = (y + height * size, y + (height + 0.05) * size);
+            if pen.flags & flag != 0 {
+                if let Some(rect) = tiny_skia::Rect::from_ltrb(start.0 as f32, y0, end, y1) {
+                    builder.push_rect(rect);
+                }
+            }
+        }
+        let Some(path) = builder.finish() else {
+            return;
+        };
+        self.fill(&path, pen.color);
+        if bold {
+            let pen = Pen {
+                width: (size * BOLDNESS) as f64,
+                dash: Dash::Solid,
+                ..pen.clone()
+            };
+            self.stroke(&path, &pen);
+        }
+    }
+
+    // Draws one xdot attribute, which starts with the default pen.
+    fn ops(&mut self, ops: &[Op]) {
+        let mut pen = Pen::default();
+        for op in ops {
+            if pen.set(op) {
+                continue;
+            }
+            if let Some((segments, filled)) = draw::path(op) {
+                let Some(path) = build_path(&segments) else {
+                    continue;
+                };
+                if filled {
+                    self.fill(&path, pen.fill);
+                }
+                self.stroke(&path, &pen);
+            } else if let Op::Text {
+                pos,
+                justification,
+                width,
+                text,
+            } = op
+            {
+                let start = draw::text_start(*pos, *justification, *width);
+                self.text(&pen, start, *width, text);
+            }
+        }
+    }
+}
+
+// Renders a laid-out graph as a PNG image with dpi pixels per inch, on a canvas of bgcolor.
+pub fn render(graph: &Graph) -> Result<Vec<u8>, String> {
+    let canvas = Canvas::new(graph)?;
+    let (width, height) = canvas.size();
+    let zoom = layout::number(&graph.attrs, "dpi", DEFAULT_DPI)? / POINTS_PER_INCH;
+    let pixels = |points: f64| (points * zoom).ceil().max(1.0) as u32;
+    let mut pixmap = Pixmap::new(pixels(width), pixels(height)).ok_or_else(|| {
+        format!(
+            "{}:{} Image of {}x{} points is too large",
+            file!(),
+            line!(),
+            width,
+            height
+        )
+    })?;
+    let background = draw::background(graph);
+    pixmap.fill(Color::from_rgba8(
+        background.r,
+        background.g,
+        background.b,
+        background.a,
+    ));
+    let (scale, zoom) = (canvas.scale as f32, zoom as f32);
+    let transform = Transform::from_row(
+        scale * zoom,
+        0.0,
+        0.0,
+        -scale * zoom,
+        (canvas.pad as f32 - canvas.bb.0 as f32 * scale) * zoom,
+        (height as f32 - canvas.pad as f32 + canvas.bb.1 as f32 * scale) * zoom,
+    );
+    let mut painter = Painter {
+        pixmap,
+        transform,
+        fontpath: graph.attrs.get("fontpath").map(str::to_string),
+        fonts: HashMap::new(),
+    };
+    for (_, ops) in draw::objects(graph)? {
+        for (_, ops) in ops {
+            painter.ops(&ops);
+        }
+    }
+    painter
+        .pixmap
+        .encode_png()
+        .map_err(|e| format!("{}:{} {}", file!(), line!(), e))
+}
+
+#[test]
+fn test_render() {
+    let graph = from_dot(
+        r#"digraph {
+    a [pos = "0,0!", style = filled, fillcolor = red]
+    b [pos = "2,0!", shape = box, label = <<b>b</b>>]
+    a -> b
+}"#,
+    );
+    let laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    let png = render(&laid_out).unwrap();
+    let pixmap = Pixmap::decode_png(&png).unwrap();
+    // 206 by 44 points at 96 dpi.
+    assert_eq!((pixmap.width(), pixmap.height()), (275, 59));
+    let pixel = |x: u32, y: u32| {
+        let p = pixmap.pixel(x, y).unwrap();
+        (p.red(), p.green(), p.blue(), p.alpha())
+    };
+    // The padding is the background color, the middle of a is red and the edge is black.
+    assert_eq!(pixel(1, 1), (255, 255, 255, 255));
+    assert_eq!(pixel(41, 20), (255, 0, 0, 255));
+    assert_eq!(pixel(120, 29), (0, 0, 0, 255));
+    // The label of b is drawn in its box.
+    let inked = (200..260)
+        .flat_map(|x| (10..50).map(move |y| (x, y)))
+        .filter(|&(x, y)| pixel(x, y).0 < 128)
+        .count();
+    assert!(inked > 20 && inked < 400, "{}", inked);
+
+    let mut graph = laid_out;
+    graph.attrs.set("dpi", "72");
+    graph.attrs.set("size", "1,1");
+    graph.attrs.set("bgcolor", "transparent");
+    let pixmap = Pixmap::decode_png(&render(&graph).unwrap()).unwrap();
+    assert_eq!((pixmap.width(), pixmap.height()), (72 + 8, 22));
+    assert_eq!(pixmap.pixel(0, 0).unwrap().alpha(), 0);
+}
@@ -0,0 +1,261 @@
+use crate::color::Rgba;
+use crate::draw::{self, Canvas, Dash, Op, Pen, Segment};
+use crate::layout::{format_number, Point, Rect};
+use crate::semantic::Graph;
+use std::fmt::Write;
+
+#[cfg(test)]
+use crate::layout::{self, Engine};
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// A string literal of PostScript and PDF. Characters outside Latin-1, which the fonts are
+// encoded with, become question marks.
+pub fn string(text: &str) -> String {
+    let mut literal = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            ' '..='~' => literal.push(c),
+            '\u{a0}'..='\u{ff}' => write!(literal, "\\{:03o}", c as u32).unwrap(),
+            _ => literal.push('?'),
+        }
+    }
+    literal.push(')');
+    literal
+}
+
+#[test]
+fn test_string() {
+    assert_eq!(string("a(b)\\"), "(a\\(b\\)\\\\)");
+    assert_eq!(string("café→"), "(caf\\351?)");
+}
+
+// The red, green and blue of a color, from 0 to 1.
+pub fn rgb(color: Rgba) -> String {
+    let n = |c: u8| format_number(c as f64 / 255.0, 3);
+    format!("{} {} {}", n(color.r), n(color.g), n(color.b))
+}
+
+// The dash array of a line style, as in "[5 2] 0".
+pub fn dash(dash: Dash) -> &'static str {
+    match dash {
+        Dash::Solid => "[] 0",
+        Dash::Dashed => "[5 2] 0",
+        Dash::Dotted => "[1 5] 0",
+    }
+}
+
+// Path construction in PostScript, which PDF spells differently.
+pub struct PathOps {
+    pub move_to: &'static str,
+    pub line_to: &'static str,
+    pub curve_to: &'static str,
+    pub close: &'static str,
+}
+
+const PATH_OPS: PathOps = PathOps {
+    move_to: "moveto",
+    line_to: "lineto",
+    curve_to: "curveto",
+    close: "closepath",
+};
+
+pub fn write_path(out: &mut String, path: &[Segment], ops: &PathOps) {
+    let n = |x: f64| format_number(x, 2);
+    for segment in path {
+        match segment {
+            Segment::Move((x, y)) => writeln!(out, "{} {} {}", n(*x), n(*y), ops.move_to),
+            Segment::Line((x, y)) => writeln!(out, "{} {} {}", n(*x), n(*y), ops.line_to),
+            Segment::Curve(a, b, c) => writeln!(
+                out,
+                "{} {} {} {} {} {} {}",
+                n(a.0),
+                n(a.1),
+                n(b.0),
+                n(b.1),
+                n(c.0),
+                n(c.1),
+                ops.curve_to
+            ),
+            Segment::Close => writeln!(out, "{}", ops.close),
+        }
+        .unwrap();
+    }
+}
+
+// Sets the font given as name and size with the Latin-1 encoding, as the standard fonts come
+// with an encoding that lacks most accented letters.
+const PROLOG: &str = "/latin1font {
+    exch findfont dup length dict begin
+        { 1 index /FID ne { def } { pop pop } ifelse } forall
+        /Encoding ISOLatin1Encoding def
+        currentdict
+    end
+    /Latin1Font exch definefont exch scalefont setfont
+} bind def
+";
+
+// Draws one xdot attribute, which starts with the default pen.
+fn write_ops(out: &mut String, ops: &[Op]) {
+    let mut pen = Pen::default();
+    for op in ops {
+        if pen.set(op) {
+            continue;
+        }
+        if let Some((path, filled)) = draw::path(op) {
+            out.push_str("newpath\n");
+            write_path(out, &path, &PATH_OPS);
+            // PostScript has no transparency, so only fully transparent colors are left out.
+            if filled && pen.fill.a != 0 {
+                writeln!(out, "gsave {} setrgbcolor fill grestore", rgb(pen.fill)).unwrap();
+            }
+            if pen.color.a != 0 {
+                writeln!(
+                    out,
+                    "{} setlinewidth {} setdash {} setrgbcolor stroke",
+                    format_number(pen.width, 2),
+                    dash(pen.dash),
+                    rgb(pen.color)
+                )
+                .unwrap();
+            }
+        } else if let Op::Text {
+            pos,
+            justification,
+            width,
+            text,
+        } = op
+        {
+            if pen.color.a == 0 {
+                continue;
+            }
+            let (x, y) = draw::text_start(*pos, *justification, *width);
+            writeln!(
+                out,
+                "{} setrgbcolor /{} {} latin1font {} {} moveto {} show",
+                rgb(pen.color),
+                draw::standard_font(&pen.font, pen.flags),
+                format_number(pen.font_size, 2),
+                format_number(x, 2),
+                format_number(y, 2),
+                string(text)
+            )
+            .unwrap();
+        }
+    }
+}
+
+// The drawing operations of the whole graph, in layout coordinates.
+fn body(graph: &Graph) -> Result<String, String> {
+    let mut out = String::new();
+    for (_, ops) in draw::objects(graph)? {
+        for (_, ops) in ops {
+            write_ops(&mut out, &ops);
+        }
+    }
+    Ok(out)
+}
+
+// Where the origin of the layout goes on a page that shows the part of the canvas at page. The
+// layout is then scaled by the scale of the canvas.
+pub fn page_offset(canvas: &Canvas, page: Rect) -> Point {
+    (
+        canvas.pad - page.0 - canvas.bb.0 * canvas.scale,
+        canvas.pad - page.1 - canvas.bb.1 * canvas.scale,
+    )
+}
+
+// Renders a laid-out graph as PostScript, with standard fonts. Graphs larger than page are
+// printed on several pages in the order of pagedir. EPS is a single page the size of the
+// drawing, for including in other documents.
+pub fn render(graph: &Graph, eps: bool) -> Result<String, String> {
+    let canvas = Canvas::new(graph)?;
+    let pages = if eps {
+        let (width, height) = canvas.size();
+        vec![(0.0, 0.0, width, height)]
+    } else {
+        draw::pages(graph, &canvas)?
+    };
+    let body = body(graph)?;
+    let (width, height) = (pages[0].2 - pages[0].0, pages[0].3 - pages[0].1);
+    let mut out = String::new();
+    out.push_str(if eps {
+        "%!PS-Adobe-3.0 EPSF-3.0\n"
+    } else {
+        "%!PS-Adobe-3.0\n"
+    });
+    writeln!(out, "%%Creator: nndot").unwrap();
+    writeln!(out, "%%Title: {}", graph.name).unwrap();
+    writeln!(out, "%%Pages: {}", pages.len()).unwrap();
+    writeln!(out, "%%BoundingBox: 0 0 {} {}", width.ceil(), height.ceil()).unwrap();
+    out.push_str("%%EndComments\n%%BeginProlog\n");
+    out.push_str(PROLOG);
+    out.push_str("%%EndProlog\n");
+    for (i, page) in pages.iter().enumerate() {
+        writeln!(out, "%%Page: {} {}", i + 1, i + 1).unwrap();
+        if !eps {
+            writeln!(
+                out,
+                "%%BeginPageSetup\n<< /PageSize [{} {}] >> setpagedevice\n%%EndPageSetup",
+                format_number(width, 2),
+                format_number(height, 2)
+            )
+            .unwrap();
+        }
+        let (x, y) = page_offset(&canvas, *page);
+        let n = |x: f64| format_number(x, 4);
+        writeln!(
+            out,
+            "gsave\n{} {} translate {} {} scale",
+            n(x),
+            n(y),
+            n(canvas.scale),
+            n(canvas.scale)
+        )
+        .unwrap();
+        out.push_str(&body);
+        out.push_str("grestore\nshowpage\n");
+    }
+    out.push_str("%%Trailer\n%%EOF\n");
+    Ok(out)
+}
+
+#[test]
+fn test_render() {
+    let graph = from_dot(
+        r#"digraph G {
+    a [pos = "0,0!", style = filled, fontname = "Helvetica-Bold"]
+    b [pos = "2,0!", shape = box, style = dashed]
+    a -> b
+}"#,
+    );
+    let laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    let ps = render(&laid_out, false).unwrap();
+    assert!(ps.starts_with("%!PS-Adobe-3.0\n%%Creator: nndot\n%%Title: G\n%%Pages: 1\n"));
+    assert!(ps.contains("%%BoundingBox: 0 0 206 44\n"));
+    assert!(ps.contains("gsave\n4 4 translate 1 1 scale\n"));
+    assert!(ps.contains("newpath\n54 18 moveto\n54 27.94 41.91 36 27 36 curveto\n"));
+    assert!(ps.contains("gsave 0.827 0.827 0.827 setrgbcolor fill grestore\n"));
+    assert!(ps.contains("1 setlinewidth [5 2] 0 setdash 0 0 0 setrgbcolor stroke\n"));
+    assert!(
+        ps.contains("0 0 0 setrgbcolor /Helvetica-Bold 14 latin1font 23.11 14.3 moveto (a) show\n")
+    );
+    assert!(ps.ends_with("grestore\nshowpage\n%%Trailer\n%%EOF\n"));
+
+    let eps = render(&laid_out, true).unwrap();
+    assert!(eps.starts_with("%!PS-Adobe-3.0 EPSF-3.0\n"));
+    assert!(!eps.contains("setpagedevice"));
+
+    // Each page shows its part of the drawing.
+    let mut graph = laid_out;
+    graph.attrs.set("page", "2,1");
+    let ps = render(&graph, false).unwrap();
+    assert!(ps.contains("%%Pages: 2\n"));
+    assert!(ps.contains("<< /PageSize [144 72] >> setpagedevice"));
+    assert!(ps.contains("%%Page: 2 2\n"));
+    assert!(ps.contains("gsave\n-140 4 translate 1 1 scale\n"));
+}
@@ -0,0 +1,387 @@
+use crate::layout::Rect;
+use crate::semantic::{Graph, Node};
+use crate::text;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+#[cfg(test)]
+use crate::{ast, tokenize::tokenize};
+
+// A field of a record label such as "<f0> left|{<f1> mid|right}". A braced group is laid out
+// in the opposite direction to the fields around it.
+#[derive(Debug, PartialEq)]
+pub enum RecordField {
+    Text { port: Option<String>, text: String },
+    Fields(Vec<RecordField>),
+}
+
+pub const COMPASS_POINTS: [&str; 10] = ["n", "ne", "e", "se", "s", "sw", "w", "nw", "c", "_"];
+
+// Space Graphviz leaves around the text of a field, in points.
+const FIELD_PAD_X: f64 = 16.0;
+const FIELD_PAD_Y: f64 = 8.0;
+
+// A text field of a laid-out record and its box, relative to the center of the node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldBox {
+    pub port: Option<String>,
+    pub text: String,
+    pub rect: Rect,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    // Reads until one of the stop characters. A backslash escapes the characters with a
+    // meaning in record labels. Other escapes such as \l are kept for text justification.
+    fn read_until(&mut self, stops: &[char]) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if stops.contains(&c) {
+                break;
+            }
+            self.pos += 1;
+            if c == '\\' {
+                match self.peek() {
+                    Some(next) if "{}|<> ".contains(next) => {
+                        text.push(next);
+                        self.pos += 1;
+                        continue;
+                    }
+                    Some(next) => {
+                        text.push(c);
+                        text.push(next);
+                        self.pos += 1;
+                        continue;
+                    }
+                    None => {}
+                }
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    fn parse_field(&mut self) -> Result<RecordField, String> {
+        self.skip_spaces();
+        if self.peek() == Some('{') {
+            self.pos += 1;
+            let fields = self.parse_fields(true)?;
+            self.pos += 1;
+            self.skip_spaces();
+            return Ok(RecordField::Fields(fields));
+        }
+        let mut port = None;
+        if self.peek() == Some('<') {
+            self.pos += 1;
+            let name = self.read_until(&['>']);
+            if self.peek() != Some('>') {
+                return Err(format!("{}:{} Expected '>'", file!(), line!()));
+            }
+            self.pos += 1;
+            port = Some(name.trim().to_string());
+        }
+        let text = self.read_until(&['|', '{', '}', '<', '>']);
+        if let Some(c @ ('{' | '<' | '>')) = self.peek() {
+            return Err(format!("{}:{} Unexpected '{}'", file!(), line!(), c));
+        }
+        Ok(RecordField::Text {
+            port,
+            text: text.trim().to_string(),
+        })
+    }
+
+    // Parses fields separated by '|'. Nested lists stop in front of their closing '}'.
+    fn parse_fields(&mut self, nested: bool) -> Result<Vec<RecordField>, String> {
+        let mut fields = Vec::new();
+        loop {
+            fields.push(self.parse_field()?);
+            match self.peek() {
+                Some('|') => self.pos += 1,
+                Some('}') if nested => return Ok(fields),
+                None if !nested => return Ok(fields),
+                Some(c) => return Err(format!("{}:{} Unexpected '{}'", file!(), line!(), c)),
+                None => return Err(format!("{}:{} Expected '}}'", file!(), line!())),
+            }
+        }
+    }
+}
+
+pub fn parse_record_label(label: &str) -> Result<Vec<RecordField>, String> {
+    let mut parser = Parser {
+        chars: label.chars().collect(),
+        pos: 0,
+    };
+    parser.parse_fields(false)
+}
+
+#[test]
+fn test_parse_record_label() {
+    let text = |port: Option<&str>, text: &str| RecordField::Text {
+        port: port.map(|port| port.to_string()),
+        text: text.to_string(),
+    };
+    assert_eq!(
+        parse_record_label("<f0> left|{<f1> mid\\ dle|right}| <f2>").unwrap(),
+        vec![
+            text(Some("f0"), "left"),
+            RecordField::Fields(vec![text(Some("f1"), "mid dle"), text(None, "right")]),
+            text(Some("f2"), ""),
+        ]
+    );
+    assert_eq!(
+        parse_record_label("a\\|b\\l|{c|{d}}").unwrap(),
+        vec![
+            text(None, "a|b\\l"),
+            RecordField::Fields(vec![
+                text(None, "c"),
+                RecordField::Fields(vec![text(None, "d")]),
+            ]),
+        ]
+    );
+    assert_eq!(parse_record_label("").unwrap(), vec![text(None, "")]);
+
+    assert!(parse_record_label("{a|b").is_err());
+    assert!(parse_record_label("a}").is_err());
+    assert!(parse_record_label("<f0 a").is_err());
+    assert!(parse_record_label("a <f0> b").is_err());
+    assert!(parse_record_label("a {b}").is_err());
+}
+
+fn collect_ports<'a>(fields: &'a [RecordField], ports: &mut Vec<&'a str>) {
+    for field in fields {
+        match field {
+            RecordField::Text {
+                port: Some(port), ..
+            } => ports.push(port),
+            RecordField::Text { port: None, .. } => {}
+            RecordField::Fields(fields) => collect_ports(fields, ports),
+        }
+    }
+}
+
+fn record_label(node: &Node) -> Option<Result<Vec<RecordField>, String>> {
+    match node.attrs.get("shape") {
+        Some("record") | Some("Mrecord") => {}
+        _ => return None,
+    }
+    // An HTML label replaces the record fields.
+    if node.attrs.is_html("label") {
+        return None;
+    }
+    let label = match node.attrs.get("label") {
+        Some(label) if label != "\\N" => label.to_string(),
+        _ => node.name.clone(),
+    };
+    Some(parse_record_label(&label))
+}
+
+// Whether the fields at the top level of a record go from left to right. They go from top to
+// bottom when rankdir turns the ranks sideways.
+fn left_to_right(graph: &Graph) -> bool {
+    !matches!(graph.attrs.get("rankdir"), Some("LR") | Some("RL"))
+}
+
+fn field_size(graph: &Graph, node: &Node, field: &RecordField, lr: bool) -> (f64, f64) {
+    match field {
+        RecordField::Text { text, .. } => match text::field_size(graph, node, text) {
+            (0.0, 0.0) => (0.0, 0.0),
+            (width, height) => (width + FIELD_PAD_X, height + FIELD_PAD_Y),
+        },
+        RecordField::Fields(fields) => fields_size(graph, node, fields, !lr),
+    }
+}
+
+// Size of fields side by side, or one above the other when lr is false.
+fn fields_size(graph: &Graph, node: &Node, fields: &[RecordField], lr: bool) -> (f64, f64) {
+    fields.iter().fold((0.0, 0.0), |(width, height), field| {
+        let size = field_size(graph, node, field, lr);
+        if lr {
+            (width + size.0, height.max(size.1))
+        } else {
+            (width.max(size.0), height + size.1)
+        }
+    })
+}
+
+// The smallest size the fields of a record node fit in, or None when the node is not a record
+// or its label is bad.
+pub fn record_size(graph: &Graph, node: &Node) -> Option<(f64, f64)> {
+    let fields = record_label(node)?.ok()?;
+    Some(fields_size(graph, node, &fields, left_to_right(graph)))
+}
+
+// Lays fields out in the box. As in Graphviz, the room left over is shared equally among them
+// along their direction, and each takes the whole box across it.
+fn place_fields(
+    graph: &Graph,
+    node: &Node,
+    fields: &[RecordField],
+    lr: bool,
+    (x0, y0, x1, y1): Rect,
+    boxes: &mut Vec<FieldBox>,
+) {
+    let (width, height) = fields_size(graph, node, fields, lr);
+    let extra = if lr {
+        (x1 - x0 - width) / fields.len() as f64
+    } else {
+        (y1 - y0 - height) / fields.len() as f64
+    };
+    let (mut x, mut y) = (x0, y1);
+    for field in fields {
+        let size = field_size(graph, node, field, lr);
+        let rect = if lr {
+            x += size.0 + extra;
+            (x - size.0 - extra, y0, x, y1)
+        } else {
+            y -= size.1 + extra;
+            (x0, y, x1, y + size.1 + extra)
+        };
+        match field {
+            RecordField::Text { port, text } => boxes.push(FieldBox {
+                port: port.clone(),
+                text: text.clone(),
+                rect,
+            }),
+            RecordField::Fields(fields) => place_fields(graph, node, fields, !lr, rect, boxes),
+        }
+    }
+}
+
+// The text fields of a record node of the given size, in the order of its label.
+pub fn field_boxes(graph: &Graph, node: &Node, (width, height): (f64, f64)) -> Vec<FieldBox> {
+    let mut boxes = Vec::new();
+    if let Some(Ok(fields)) = record_label(node) {
+        let rect = (-width / 2.0, -height / 2.0, width / 2.0, height / 2.0);
+        place_fields(graph, node, &fields, left_to_right(graph), rect, &mut boxes);
+    }
+    boxes
+}
+
+// The box of the field of a record node named by port, relative to the center of the node.
+pub fn port_rect(graph: &Graph, node: &Node, size: (f64, f64), port: &str) -> Option<Rect> {
+    field_boxes(graph, node, size)
+        .into_iter()
+        .find(|field| field.port.as_deref() == Some(port))
+        .map(|field| field.rect)
+}
+
+#[test]
+fn test_field_boxes() {
+    let graph = from_dot(
+        r#"graph {
+    node [shape = record, fontname = Courier, fontsize = 10]
+    a [label = "<f0> a|{<f1> bb|c}|<f2>"]
+}"#,
+    );
+    let node = &graph.nodes[0];
+    // a is 6 points wide and 12 high, bb 12 by 12, and the empty field takes no room.
+    assert_eq!(record_size(&graph, node), Some((22.0 + 28.0, 40.0)));
+    let boxes = field_boxes(&graph, node, (80.0, 40.0));
+    let rects: Vec<Rect> = boxes.iter().map(|field| field.rect).collect();
+    assert_eq!(
+        rects,
+        vec![
+            (-40.0, -20.0, -8.0, 20.0),
+            (-8.0, 0.0, 30.0, 20.0),
+            (-8.0, -20.0, 30.0, 0.0),
+            (30.0, -20.0, 40.0, 20.0),
+        ]
+    );
+    assert_eq!(
+        port_rect(&graph, node, (80.0, 40.0), "f2"),
+        Some((30.0, -20.0, 40.0, 20.0))
+    );
+    assert_eq!(port_rect(&graph, node, (80.0, 40.0), "f9"), None);
+
+    // rankdir = LR puts the top level fields one above the other.
+    let graph = from_dot(r#"graph { rankdir = LR; a [shape = record, label = "a|b"] }"#);
+    let boxes = field_boxes(&graph, &graph.nodes[0], (54.0, 36.0));
+    assert_eq!(boxes[0].rect, (-27.0, 0.0, 27.0, 18.0));
+    assert_eq!(boxes[1].rect, (-27.0, -18.0, 27.0, 0.0));
+}
+
+// Checks record labels and that ports used by edges name a field of the record.
+pub fn check(graph: &Graph) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut fields = Vec::new();
+    for node in graph.nodes.iter() {
+        match record_label(node) {
+            Some(Err(e)) => {
+                warnings.push(format!("Node {} has a bad record label: {}", node.name, e));
+                fields.push(None);
+            }
+            Some(Ok(record_fields)) => fields.push(Some(record_fields)),
+            None => fields.push(None),
+        }
+    }
+    let ports: Vec<Option<Vec<&str>>> = fields
+        .iter()
+        .map(|fields| {
+            fields.as_ref().map(|fields| {
+                let mut ports = Vec::new();
+                collect_ports(fields, &mut ports);
+                ports
+            })
+        })
+        .collect();
+    warnings.extend(check_ports(graph, &ports));
+    warnings
+}
+
+// Checks that the ports edges use are among the ports of their node or are compass points.
+// ports has the port names of every node whose label has ports, and None for the other nodes,
+// which are not checked. Record and HTML labels share this.
+pub fn check_ports(graph: &Graph, ports: &[Option<Vec<&str>>]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for edge in graph.edges.iter() {
+        for (node, attr) in [(edge.tail, "tailport"), (edge.head, "headport")] {
+            let (ports, port) = match (&ports[node], edge.attrs.get(attr)) {
+                (Some(ports), Some(port)) => (ports, port),
+                _ => continue,
+            };
+            let name = port.split(':').next().unwrap_or_default();
+            if !name.is_empty() && !ports.contains(&name) && !COMPASS_POINTS.contains(&name) {
+                warnings.push(format!(
+                    "Node {} has no port {}",
+                    graph.nodes[node].name, name
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+#[test]
+fn test_check() {
+    let tokens = tokenize(
+        r#"digraph {
+    node [shape = record]
+    a [label = "<f0> a|{<f1> b|c}"]
+    b [label = "{x"]
+    c [shape = box]
+    a:f1 -> a:f0
+    a:f2 -> c:f9
+    a:s -> a:f1:n
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let warnings = check(&Graph::from_ast(&graph));
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0].starts_with("Node b has a bad record label"));
+    assert_eq!(warnings[1], "Node a has no port f2");
+}
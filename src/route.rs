@@ -0,0 +1,353 @@
+#[cfg(test)]
+use crate::layout::edge_end;
+use crate::layout::{margin, End, Grid, Margin, Point};
+use crate::semantic::Graph;
+use crate::shape;
+use log::warn;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz default for the margin kept between edges and nodes, in points.
+const DEFAULT_ESEP: Margin = Margin::Add(3.0, 3.0);
+// Crossings shorter than this, in points, are rounding errors.
+const EPSILON: f64 = 1e-6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Splines {
+    Line,
+    Polyline,
+    Spline,
+}
+
+// Reads splines. Edges are straight lines unless it asks for routing around nodes.
+pub fn splines(graph: &Graph) -> Splines {
+    let value = match graph.attrs.get("splines") {
+        Some(value) => value.trim().to_lowercase(),
+        None => return Splines::Line,
+    };
+    match value.as_str() {
+        "true" | "yes" | "spline" => Splines::Spline,
+        "polyline" => Splines::Polyline,
+        "" | "false" | "no" | "none" | "line" => Splines::Line,
+        _ => {
+            warn!("Splines {} is not supported, using line", value);
+            Splines::Line
+        }
+    }
+}
+
+// Whether the segment from a to b goes through the inside of the convex polygon, whose corners
+// are counterclockwise. Running along its sides or touching a corner does not count.
+fn crosses(a: Point, b: Point, polygon: &[Point]) -> bool {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0.0, 1.0);
+    // Clip the segment to the polygon shrunk a little, as Cyrus and Beck do.
+    for (i, &(x0, y0)) in polygon.iter().enumerate() {
+        let (x1, y1) = polygon[(i + 1) % polygon.len()];
+        let length = (x1 - x0).hypot(y1 - y0);
+        if length == 0.0 {
+            continue;
+        }
+        // The outward normal of the side, and how far inside it a is.
+        let normal = ((y1 - y0) / length, (x0 - x1) / length);
+        let p = normal.0 * dx + normal.1 * dy;
+        let q = -(normal.0 * (a.0 - x0) + normal.1 * (a.1 - y0)) - EPSILON;
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else if p < 0.0 {
+            t0 = f64::max(t0, q / p);
+        } else {
+            t1 = f64::min(t1, q / p);
+        }
+    }
+    t0 < t1
+}
+
+#[test]
+fn test_crosses() {
+    let square = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+    assert!(crosses((-1.0, 1.0), (3.0, 1.0), &square));
+    assert!(crosses((1.0, 1.0), (1.0, 1.0), &square));
+    assert!(!crosses((-1.0, 3.0), (3.0, 3.0), &square));
+    assert!(!crosses((0.0, 0.0), (0.0, 2.0), &square));
+    assert!(!crosses((-1.0, 1.0), (1.0, 3.0), &square));
+    // The line misses the diamond but not its bounding box.
+    let diamond = [(1.0, 0.0), (2.0, 1.0), (1.0, 2.0), (0.0, 1.0)];
+    assert!(!crosses((-1.0, 0.5), (0.5, -1.0), &diamond));
+    assert!(crosses((0.0, 0.0), (2.0, 2.0), &diamond));
+}
+
+// The nodes edges are routed around, as polygons kept esep away from them, with a grid of their
+// bounding boxes to find those near a segment.
+struct Obstacles {
+    polygons: Vec<Vec<Point>>,
+    grid: Grid,
+}
+
+impl Obstacles {
+    // Whether the segment from a to b stays out of all polygons but those in skip.
+    fn visible(&self, a: Point, b: Point, skip: [usize; 2]) -> bool {
+        let rect = (a.0.min(b.0), a.1.min(b.1), a.0.max(b.0), a.1.max(b.1));
+        self.grid
+            .near(rect)
+            .into_iter()
+            .all(|i| skip.contains(&i) || !crosses(a, b, &self.polygons[i]))
+    }
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+// The shortest path from p on tail to q on head that stays out of the other obstacles, through
+// their corners. Which corners see each other is worked out as the search needs it and kept in
+// visibility for the next edges. Returns the corners passed, or None when there is no such path.
+fn shortest_path(
+    obstacles: &Obstacles,
+    corners: &[Point],
+    visibility: &mut HashMap<(usize, usize), bool>,
+    (tail, p): (usize, Point),
+    (head, q): (usize, Point),
+) -> Option<Vec<Point>> {
+    let skip = [tail, head];
+    let n = corners.len();
+    let mut distances: Vec<f64> = corners
+        .iter()
+        .map(|&c| {
+            if obstacles.visible(p, c, skip) {
+                distance(p, c)
+            } else {
+                f64::INFINITY
+            }
+        })
+        .collect();
+    let mut previous = vec![usize::MAX; n];
+    let mut done = vec![false; n];
+    let mut best: Option<(f64, usize)> = None;
+    // A* on the visibility graph, starting from the corners seen from tail, with the straight
+    // line to q as the estimate of the length left. The bits of non-negative floats sort like
+    // the floats.
+    let estimate = |i: usize, d: f64| Reverse(((d + distance(corners[i], q)).to_bits(), i));
+    let mut queue: BinaryHeap<_> = (0..n)
+        .filter(|&i| distances[i].is_finite())
+        .map(|i| estimate(i, distances[i]))
+        .collect();
+    while let Some(Reverse((bits, i))) = queue.pop() {
+        if done[i] {
+            continue;
+        }
+        if best.is_some_and(|(length, _)| f64::from_bits(bits) >= length) {
+            break;
+        }
+        done[i] = true;
+        if obstacles.visible(corners[i], q, skip) {
+            let length = distances[i] + distance(corners[i], q);
+            if best.is_none_or(|(best, _)| length < best) {
+                best = Some((length, i));
+            }
+        }
+        for j in 0..n {
+            let d = distances[i] + distance(corners[i], corners[j]);
+            if done[j] || d >= distances[j] {
+                continue;
+            }
+            let seen = *visibility
+                .entry((i.min(j), i.max(j)))
+                .or_insert_with(|| obstacles.visible(corners[i], corners[j], [usize::MAX; 2]));
+            if seen {
+                distances[j] = d;
+                previous[j] = i;
+                queue.push(estimate(j, d));
+            }
+        }
+    }
+    let (_, mut i) = best?;
+    let mut path = vec![corners[i]];
+    while previous[i] != usize::MAX {
+        i = previous[i];
+        path.push(corners[i]);
+    }
+    path.reverse();
+    Some(path)
+}
+
+// Routes every edge between its ends, see layout::edge_end, around the other nodes, kept esep
+// away from their outlines. Ellipses are replaced by the octagons around them, see
+// shape::Shape::polygon. Returns the points each edge bends at, none for edges with a clear
+// straight line, loops and edges that cannot be routed, such as those between overlapping nodes.
+pub fn routes(
+    graph: &Graph,
+    ends: &[(End, End)],
+    positions: &[Point],
+    sizes: &[(f64, f64)],
+) -> Result<Vec<Vec<Point>>, String> {
+    let esep = margin(&graph.attrs, "esep", DEFAULT_ESEP)?;
+    let polygons: Vec<Vec<Point>> = graph
+        .nodes
+        .iter()
+        .zip(positions.iter().zip(sizes))
+        .map(|(node, (&(x, y), &size))| {
+            shape::shape_of(node)
+                .polygon(esep.around(size))
+                .into_iter()
+                .map(|(dx, dy)| (x + dx, y + dy))
+                .collect()
+        })
+        .collect();
+    // Cells about as large as a node.
+    let cell = sizes.iter().map(|&(w, h)| w.max(h)).sum::<f64>() / sizes.len().max(1) as f64;
+    let mut grid = Grid::new(cell.max(1.0));
+    for polygon in polygons.iter() {
+        let bounds = polygon.iter().fold(
+            (
+                f64::INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(x0, y0, x1, y1), &(x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        );
+        grid.insert(bounds);
+    }
+    let corners: Vec<Point> = polygons.iter().flatten().copied().collect();
+    let obstacles = Obstacles { polygons, grid };
+    let mut visibility = HashMap::new();
+    let mut routes = Vec::new();
+    for (edge, (tail_end, head_end)) in graph.edges.iter().zip(ends) {
+        let (p, q) = (tail_end.point, head_end.point);
+        let skip = [edge.tail, edge.head];
+        if edge.tail == edge.head || obstacles.visible(p, q, skip) {
+            routes.push(Vec::new());
+            continue;
+        }
+        routes.push(
+            shortest_path(
+                &obstacles,
+                &corners,
+                &mut visibility,
+                (edge.tail, p),
+                (edge.head, q),
+            )
+            .unwrap_or_default(),
+        );
+    }
+    Ok(routes)
+}
+
+#[test]
+fn test_routes() {
+    let graph =
+        from_dot(r#"graph { a; b [shape = box]; c; d [shape = diamond]; a -- c; a -- b; b -- b }"#);
+    // b sits between a and c, and is taller than it is wide. d is far away.
+    let positions = [(0.0, 0.0), (100.0, 0.0), (200.0, 0.0), (100.0, 500.0)];
+    let sizes = [(54.0, 36.0), (20.0, 100.0), (54.0, 36.0), (54.0, 36.0)];
+    let ends: Vec<(End, End)> = graph
+        .edges
+        .iter()
+        .map(|edge| {
+            let end = |node: usize| edge_end(&graph, node, positions[node], sizes[node], "");
+            (end(edge.tail), end(edge.head))
+        })
+        .collect();
+    let bends = routes(&graph, &ends, &positions, &sizes).unwrap();
+    // The edge from a to c goes around b, over or under it, esep away from its corners.
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    assert_eq!(bends[0].len(), 2);
+    assert!(close(bends[0][0].0, 87.0));
+    assert!(close(bends[0][1].0, 113.0));
+    assert!(close(bends[0][0].1.abs(), 53.0));
+    assert!(bends[1].is_empty());
+    assert!(bends[2].is_empty());
+
+    // An ellipse in the way is passed closer than its bounding box would allow.
+    let graph = from_dot(r#"graph { a; b; c; a -- c }"#);
+    let positions = [(0.0, 0.0), (100.0, 0.0), (200.0, 0.0)];
+    let sizes = [(54.0, 36.0), (20.0, 100.0), (54.0, 36.0)];
+    let ends: Vec<(End, End)> = [(0, 2)]
+        .iter()
+        .map(|&(tail, head)| {
+            let end = |node: usize| edge_end(&graph, node, positions[node], sizes[node], "");
+            (end(tail), end(head))
+        })
+        .collect();
+    let bends = routes(&graph, &ends, &positions, &sizes).unwrap();
+    assert_eq!(bends[0].len(), 2);
+    assert!(bends[0][0].0 > 87.0 && bends[0][1].0 < 113.0);
+    assert!(close(bends[0][0].1.abs(), 53.0));
+
+    let graph = from_dot(r#"graph { esep = "x"; a }"#);
+    assert!(routes(&graph, &[], &[(0.0, 0.0)], &[(54.0, 36.0)]).is_err());
+}
+
+// Control points of a cubic Bezier spline through the points, in the order of the pos attribute.
+// Polylines are made of straight segments, while smooth curves go through inner points parallel
+// to the line joining their neighbours.
+pub fn bezier(points: &[Point], smooth: bool) -> Vec<Point> {
+    let direction = |from: Point, to: Point| {
+        let length = distance(from, to);
+        if length > 0.0 {
+            ((to.0 - from.0) / length, (to.1 - from.1) / length)
+        } else {
+            (0.0, 0.0)
+        }
+    };
+    let last = points.len() - 1;
+    let mut controls = vec![points[0]];
+    for i in 1..points.len() {
+        let (a, b) = (points[i - 1], points[i]);
+        let third = distance(a, b) / 3.0;
+        let (ta, tb) = if smooth {
+            (
+                direction(points[(i - 1).saturating_sub(1)], b),
+                direction(a, points[(i + 1).min(last)]),
+            )
+        } else {
+            (direction(a, b), direction(a, b))
+        };
+        controls.push((a.0 + ta.0 * third, a.1 + ta.1 * third));
+        controls.push((b.0 - tb.0 * third, b.1 - tb.1 * third));
+        controls.push(b);
+    }
+    controls
+}
+
+// The point at t in [0, 1] on the cubic Bezier curve with the four control points.
+pub fn bezier_point(controls: &[Point], t: f64) -> Point {
+    let mut points = controls[..4].to_vec();
+    for k in (1..4).rev() {
+        for i in 0..k {
+            points[i] = (
+                points[i].0 + (points[i + 1].0 - points[i].0) * t,
+                points[i].1 + (points[i + 1].1 - points[i].1) * t,
+            );
+        }
+    }
+    points[0]
+}
+
+#[test]
+fn test_bezier() {
+    let straight = bezier(&[(0.0, 0.0), (3.0, 0.0)], true);
+    assert_eq!(
+        straight,
+        vec![(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)]
+    );
+    let polyline = bezier(&[(0.0, 0.0), (3.0, 0.0), (3.0, 3.0)], false);
+    assert_eq!(polyline.len(), 7);
+    assert_eq!(polyline[4], (3.0, 1.0));
+    // The curve goes through the corner heading diagonally.
+    let curve = bezier(&[(0.0, 0.0), (3.0, 0.0), (3.0, 3.0)], true);
+    assert_eq!(curve[3], (3.0, 0.0));
+    let (dx, dy) = (curve[4].0 - curve[2].0, curve[4].1 - curve[2].1);
+    assert!((dx - dy).abs() < 1e-9 && dx > 0.0);
+
+    let controls = [(0.0, 0.0), (0.0, 3.0), (3.0, 3.0), (3.0, 0.0)];
+    assert_eq!(bezier_point(&controls, 0.0), (0.0, 0.0));
+    assert_eq!(bezier_point(&controls, 0.5), (1.5, 2.25));
+    assert_eq!(bezier_point(&controls, 1.0), (3.0, 0.0));
+}
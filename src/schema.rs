@@ -0,0 +1,518 @@
+use crate::semantic::{Attrs, Graph};
+
+#[cfg(test)]
+use crate::{ast, tokenize::tokenize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Bool,
+    Int,
+    Double,
+    // "x,y", optionally followed by ",z" and '!'.
+    Point,
+    DoubleOrPoint,
+    // A double or point that may start with '+'.
+    AddPoint,
+    Rect,
+    Enum(&'static [&'static str]),
+    Shape,
+    Style,
+    // Free text, and values of mixed types that Graphviz reads leniently.
+    Str,
+    // Colors, arrows, dir and labels. Their values are checked by the color, arrow and html
+    // modules.
+    Checked,
+}
+
+// An attribute with the elements it applies to, G for the root graph, S for subgraphs, C for
+// clusters, N for nodes and E for edges, and its Graphviz default.
+pub struct AttrSpec {
+    pub name: &'static str,
+    pub used_by: &'static str,
+    pub kind: Kind,
+    pub default: Option<&'static str>,
+}
+
+const fn attr(
+    name: &'static str,
+    used_by: &'static str,
+    kind: Kind,
+    default: Option<&'static str>,
+) -> AttrSpec {
+    AttrSpec {
+        name,
+        used_by,
+        kind,
+        default,
+    }
+}
+
+const RANKDIR: &[&str] = &["TB", "LR", "BT", "RL"];
+const RANK: &[&str] = &["same", "min", "source", "max", "sink"];
+const CLUSTER_MODE: &[&str] = &["local", "global", "none"];
+const OUTPUT_MODE: &[&str] = &["breadthfirst", "nodesfirst", "edgesfirst"];
+const PACK_MODE: &[&str] = &["node", "clust", "graph", "array"];
+const PAGEDIR: &[&str] = &["BL", "BR", "TL", "TR", "RB", "RT", "LB", "LT"];
+const ORDERING: &[&str] = &["", "in", "out"];
+const SMOOTHING: &[&str] = &[
+    "none",
+    "avg_dist",
+    "graph_dist",
+    "power_dist",
+    "rng",
+    "spring",
+    "triangle",
+];
+const SPLINES: &[&str] = &[
+    "", "true", "false", "yes", "no", "none", "line", "spline", "polyline", "ortho", "curved",
+    "compound",
+];
+const MODE: &[&str] = &["major", "kk", "hier", "ipsep", "spring", "maxent", "sgd"];
+const MODEL: &[&str] = &["circuit", "subset", "mds", "shortpath"];
+const LABELLOC: &[&str] = &["t", "b", "c"];
+const LABELJUST: &[&str] = &["l", "r", "c"];
+
+const SHAPES: [&str; 61] = [
+    "assembly",
+    "box",
+    "box3d",
+    "cds",
+    "circle",
+    "component",
+    "cylinder",
+    "diamond",
+    "doublecircle",
+    "doubleoctagon",
+    "egg",
+    "ellipse",
+    "epsf",
+    "fivepoverhang",
+    "folder",
+    "hexagon",
+    "house",
+    "insulator",
+    "invhouse",
+    "invtrapezium",
+    "invtriangle",
+    "larrow",
+    "lpromoter",
+    "mcircle",
+    "mdiamond",
+    "mrecord",
+    "msquare",
+    "none",
+    "note",
+    "noverhang",
+    "octagon",
+    "oval",
+    "parallelogram",
+    "pentagon",
+    "plain",
+    "plaintext",
+    "point",
+    "polygon",
+    "primersite",
+    "promoter",
+    "proteasesite",
+    "proteinstab",
+    "rarrow",
+    "record",
+    "rect",
+    "rectangle",
+    "restrictionsite",
+    "ribosite",
+    "rnastab",
+    "rpromoter",
+    "septagon",
+    "signature",
+    "square",
+    "star",
+    "tab",
+    "terminator",
+    "threepoverhang",
+    "trapezium",
+    "triangle",
+    "underline",
+    "utr",
+];
+
+const STYLES: [&str; 14] = [
+    "bold",
+    "dashed",
+    "diagonals",
+    "dotted",
+    "filled",
+    "invis",
+    "radial",
+    "rounded",
+    "setlinewidth",
+    "solid",
+    "striped",
+    "tapered",
+    "wedged",
+    "",
+];
+
+pub const ATTRS: [AttrSpec; 176] = [
+    attr("_background", "G", Kind::Str, None),
+    attr("area", "NC", Kind::Double, Some("1.0")),
+    attr("arrowhead", "E", Kind::Checked, Some("normal")),
+    attr("arrowsize", "E", Kind::Double, Some("1.0")),
+    attr("arrowtail", "E", Kind::Checked, Some("normal")),
+    attr("bb", "GC", Kind::Rect, None),
+    attr("beautify", "G", Kind::Bool, Some("false")),
+    attr("bgcolor", "GC", Kind::Checked, None),
+    attr("center", "G", Kind::Bool, Some("false")),
+    attr("charset", "G", Kind::Str, Some("UTF-8")),
+    attr("class", "GCNE", Kind::Str, None),
+    attr("cluster", "SC", Kind::Bool, Some("false")),
+    attr("clusterrank", "G", Kind::Enum(CLUSTER_MODE), Some("local")),
+    attr("color", "CNE", Kind::Checked, Some("black")),
+    attr("colorscheme", "GCNE", Kind::Str, None),
+    attr("comment", "GNE", Kind::Str, None),
+    attr("compound", "G", Kind::Bool, Some("false")),
+    attr("concentrate", "G", Kind::Bool, Some("false")),
+    attr("constraint", "E", Kind::Bool, Some("true")),
+    attr("Damping", "G", Kind::Double, Some("0.99")),
+    attr("decorate", "E", Kind::Bool, Some("false")),
+    attr("defaultdist", "G", Kind::Double, None),
+    attr("dim", "G", Kind::Int, Some("2")),
+    attr("dimen", "G", Kind::Int, Some("2")),
+    attr("dir", "E", Kind::Checked, Some("forward")),
+    attr("diredgeconstraints", "G", Kind::Str, Some("false")),
+    attr("distortion", "N", Kind::Double, Some("0.0")),
+    attr("dpi", "G", Kind::Double, Some("96.0")),
+    attr("edgehref", "E", Kind::Str, None),
+    attr("edgetarget", "E", Kind::Str, None),
+    attr("edgetooltip", "E", Kind::Str, None),
+    attr("edgeURL", "E", Kind::Str, None),
+    attr("epsilon", "G", Kind::Double, None),
+    attr("esep", "G", Kind::AddPoint, Some("+3")),
+    attr("fillcolor", "CNE", Kind::Checked, Some("lightgrey")),
+    attr("fixedsize", "N", Kind::Str, Some("false")),
+    attr("fontcolor", "GCNE", Kind::Checked, Some("black")),
+    attr("fontname", "GCNE", Kind::Str, Some("Times-Roman")),
+    attr("fontnames", "G", Kind::Str, None),
+    attr("fontpath", "G", Kind::Str, None),
+    attr("fontsize", "GCNE", Kind::Double, Some("14.0")),
+    attr("forcelabels", "G", Kind::Bool, Some("true")),
+    attr("gradientangle", "GCN", Kind::Int, None),
+    attr("group", "N", Kind::Str, None),
+    attr("head_lp", "E", Kind::Point, None),
+    attr("headclip", "E", Kind::Bool, Some("true")),
+    attr("headhref", "E", Kind::Str, None),
+    attr("headlabel", "E", Kind::Checked, None),
+    attr("headport", "E", Kind::Str, Some("center")),
+    attr("headtarget", "E", Kind::Str, None),
+    attr("headtooltip", "E", Kind::Str, None),
+    attr("headURL", "E", Kind::Str, None),
+    attr("height", "N", Kind::Double, Some("0.5")),
+    attr("href", "GCNE", Kind::Str, None),
+    attr("id", "GCNE", Kind::Str, None),
+    attr("image", "N", Kind::Str, None),
+    attr("imagepath", "G", Kind::Str, None),
+    attr("imagepos", "N", Kind::Str, Some("mc")),
+    attr("imagescale", "N", Kind::Str, Some("false")),
+    attr("inputscale", "G", Kind::Double, None),
+    attr("K", "GC", Kind::Double, Some("0.3")),
+    attr("label", "GCNE", Kind::Checked, None),
+    attr("label_scheme", "G", Kind::Int, Some("0")),
+    attr("labelangle", "E", Kind::Double, Some("-25.0")),
+    attr("labeldistance", "E", Kind::Double, Some("1.0")),
+    attr("labelfloat", "E", Kind::Bool, Some("false")),
+    attr("labelfontcolor", "E", Kind::Checked, Some("black")),
+    attr("labelfontname", "E", Kind::Str, Some("Times-Roman")),
+    attr("labelfontsize", "E", Kind::Double, Some("14.0")),
+    attr("labelhref", "E", Kind::Str, None),
+    attr("labeljust", "GC", Kind::Enum(LABELJUST), Some("c")),
+    attr("labelloc", "GCN", Kind::Enum(LABELLOC), None),
+    attr("labeltarget", "E", Kind::Str, None),
+    attr("labeltooltip", "E", Kind::Str, None),
+    attr("labelURL", "E", Kind::Str, None),
+    attr("landscape", "G", Kind::Bool, Some("false")),
+    attr("layer", "CNE", Kind::Str, None),
+    attr("layerlistsep", "G", Kind::Str, Some(",")),
+    attr("layers", "G", Kind::Str, None),
+    attr("layerselect", "G", Kind::Str, None),
+    attr("layersep", "G", Kind::Str, Some(":\t ")),
+    attr("layout", "G", Kind::Str, None),
+    attr("len", "E", Kind::Double, Some("1.0")),
+    attr("levels", "G", Kind::Int, None),
+    attr("levelsgap", "G", Kind::Double, Some("0.0")),
+    attr("lhead", "E", Kind::Str, None),
+    attr("lheight", "GC", Kind::Double, None),
+    attr("linelength", "G", Kind::Int, Some("128")),
+    attr("lp", "GCE", Kind::Point, None),
+    attr("ltail", "E", Kind::Str, None),
+    attr("lwidth", "GC", Kind::Double, None),
+    attr("margin", "GCN", Kind::DoubleOrPoint, None),
+    attr("maxiter", "G", Kind::Int, None),
+    attr("mclimit", "G", Kind::Double, Some("1.0")),
+    attr("mindist", "G", Kind::Double, Some("1.0")),
+    attr("minlen", "E", Kind::Int, Some("1")),
+    attr("mode", "G", Kind::Enum(MODE), Some("major")),
+    attr("model", "G", Kind::Enum(MODEL), Some("shortpath")),
+    attr("newrank", "G", Kind::Bool, Some("false")),
+    attr("nodesep", "G", Kind::Double, Some("0.25")),
+    attr("nojustify", "GCNE", Kind::Bool, Some("false")),
+    attr("normalize", "G", Kind::Str, Some("false")),
+    attr("notranslate", "G", Kind::Bool, Some("false")),
+    attr("nslimit", "G", Kind::Double, None),
+    attr("nslimit1", "G", Kind::Double, None),
+    attr("oneblock", "G", Kind::Bool, Some("false")),
+    attr("ordering", "GN", Kind::Enum(ORDERING), None),
+    attr("orientation", "GN", Kind::Str, None),
+    attr(
+        "outputorder",
+        "G",
+        Kind::Enum(OUTPUT_MODE),
+        Some("breadthfirst"),
+    ),
+    attr("overlap", "G", Kind::Str, Some("true")),
+    attr("overlap_scaling", "G", Kind::Double, Some("-4")),
+    attr("overlap_shrink", "G", Kind::Bool, Some("true")),
+    attr("pack", "G", Kind::Bool, Some("false")),
+    attr("packmode", "G", Kind::Enum(PACK_MODE), Some("node")),
+    attr("pad", "G", Kind::DoubleOrPoint, Some("0.0555")),
+    attr("page", "G", Kind::DoubleOrPoint, None),
+    attr("pagedir", "G", Kind::Enum(PAGEDIR), Some("BL")),
+    attr("pencolor", "C", Kind::Checked, Some("black")),
+    attr("penwidth", "CNE", Kind::Double, Some("1.0")),
+    attr("peripheries", "CN", Kind::Int, None),
+    attr("pin", "N", Kind::Bool, Some("false")),
+    attr("pos", "NE", Kind::Str, None),
+    attr("quadtree", "G", Kind::Str, Some("normal")),
+    attr("quantum", "G", Kind::Double, Some("0.0")),
+    attr("rank", "S", Kind::Enum(RANK), None),
+    attr("rankdir", "G", Kind::Enum(RANKDIR), Some("TB")),
+    attr("ranksep", "G", Kind::Str, Some("0.5")),
+    attr("ratio", "G", Kind::Str, None),
+    attr("rects", "N", Kind::Str, None),
+    attr("regular", "N", Kind::Bool, Some("false")),
+    attr("remincross", "G", Kind::Bool, Some("true")),
+    attr("repulsiveforce", "G", Kind::Double, Some("1.0")),
+    attr("resolution", "G", Kind::Double, Some("96.0")),
+    attr("root", "GN", Kind::Str, None),
+    attr("rotate", "G", Kind::Int, Some("0")),
+    attr("rotation", "G", Kind::Double, Some("0")),
+    attr("samehead", "E", Kind::Str, None),
+    attr("sametail", "E", Kind::Str, None),
+    attr("samplepoints", "N", Kind::Int, Some("8")),
+    attr("scale", "G", Kind::DoubleOrPoint, None),
+    attr("searchsize", "G", Kind::Int, Some("30")),
+    attr("sep", "G", Kind::AddPoint, Some("+4")),
+    attr("shape", "N", Kind::Shape, Some("ellipse")),
+    attr("shapefile", "N", Kind::Str, None),
+    attr("showboxes", "GNE", Kind::Int, Some("0")),
+    attr("sides", "N", Kind::Int, Some("4")),
+    attr("size", "G", Kind::DoubleOrPoint, None),
+    attr("skew", "N", Kind::Double, Some("0.0")),
+    attr("smoothing", "G", Kind::Enum(SMOOTHING), Some("none")),
+    attr("sortv", "GCN", Kind::Int, Some("0")),
+    attr("splines", "G", Kind::Enum(SPLINES), None),
+    attr("start", "G", Kind::Str, None),
+    attr("style", "GCNE", Kind::Style, None),
+    attr("stylesheet", "G", Kind::Str, None),
+    attr("tail_lp", "E", Kind::Point, None),
+    attr("tailclip", "E", Kind::Bool, Some("true")),
+    attr("tailhref", "E", Kind::Str, None),
+    attr("taillabel", "E", Kind::Checked, None),
+    attr("tailport", "E", Kind::Str, Some("center")),
+    attr("tailtarget", "E", Kind::Str, None),
+    attr("tailtooltip", "E", Kind::Str, None),
+    attr("tailURL", "E", Kind::Str, None),
+    attr("target", "GCNE", Kind::Str, None),
+    attr("TBbalance", "G", Kind::Str, None),
+    attr("tooltip", "CNE", Kind::Str, None),
+    attr("truecolor", "G", Kind::Bool, None),
+    attr("URL", "GCNE", Kind::Str, None),
+    attr("vertices", "N", Kind::Str, None),
+    attr("viewport", "G", Kind::Str, None),
+    attr("voro_margin", "G", Kind::Double, Some("0.05")),
+    attr("weight", "E", Kind::Double, Some("1")),
+    attr("width", "N", Kind::Double, Some("0.75")),
+    attr("xdotversion", "G", Kind::Str, None),
+    attr("xlabel", "NE", Kind::Checked, None),
+    attr("xlp", "NE", Kind::Point, None),
+    attr("z", "N", Kind::Double, Some("0.0")),
+];
+
+pub fn find(name: &str) -> Option<&'static AttrSpec> {
+    ATTRS.iter().find(|spec| spec.name == name)
+}
+
+fn is_double(value: &str) -> bool {
+    value.trim().parse::<f64>().is_ok()
+}
+
+fn is_point(value: &str) -> bool {
+    let coords: Vec<&str> = value.trim().trim_end_matches('!').split(',').collect();
+    (2..=3).contains(&coords.len()) && coords.iter().all(|coord| is_double(coord))
+}
+
+// Checks a value against its kind. Names in enums, shapes and styles are case insensitive.
+pub fn check_value(kind: Kind, value: &str) -> Result<(), String> {
+    let lower = value.trim().to_lowercase();
+    let valid = match kind {
+        Kind::Bool => {
+            ["true", "false", "yes", "no"].contains(&lower.as_str()) || lower.parse::<i64>().is_ok()
+        }
+        Kind::Int => lower.parse::<i64>().is_ok(),
+        Kind::Double => is_double(value),
+        Kind::Point => is_point(value),
+        Kind::DoubleOrPoint => is_double(value) || is_point(value),
+        Kind::AddPoint => {
+            let value = value.trim().trim_start_matches('+');
+            is_double(value) || is_point(value)
+        }
+        Kind::Rect => {
+            let coords: Vec<&str> = value.split(',').collect();
+            coords.len() == 4 && coords.iter().all(|coord| is_double(coord))
+        }
+        // packmode also takes flags after array, as in array_c4.
+        Kind::Enum(names) => {
+            names.iter().any(|name| name.to_lowercase() == lower)
+                || (names.contains(&"array") && lower.starts_with("array"))
+        }
+        Kind::Shape => SHAPES.contains(&lower.as_str()),
+        Kind::Style => lower.split(',').all(|style| {
+            // setlinewidth(2) and similar take arguments in parentheses.
+            let name = style.split('(').next().unwrap_or_default();
+            STYLES.contains(&name.trim())
+        }),
+        Kind::Str | Kind::Checked => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}:{} Expected {:?} but got {}",
+            file!(),
+            line!(),
+            kind,
+            value
+        ))
+    }
+}
+
+#[test]
+fn test_check_value() {
+    assert!(check_value(Kind::Bool, "Yes").is_ok());
+    assert!(check_value(Kind::Bool, "2").is_ok());
+    assert!(check_value(Kind::Bool, "maybe").is_err());
+    assert!(check_value(Kind::Int, "1.5").is_err());
+    assert!(check_value(Kind::Double, "1.5").is_ok());
+    assert!(check_value(Kind::Point, "1,2!").is_ok());
+    assert!(check_value(Kind::Point, "1").is_err());
+    assert!(check_value(Kind::DoubleOrPoint, "7.5,10").is_ok());
+    assert!(check_value(Kind::AddPoint, "+3,4").is_ok());
+    assert!(check_value(Kind::Rect, "0,0,10,20").is_ok());
+    assert!(check_value(Kind::Rect, "0,0,10").is_err());
+    assert!(check_value(Kind::Enum(RANKDIR), "lr").is_ok());
+    assert!(check_value(Kind::Enum(RANKDIR), "up").is_err());
+    assert!(check_value(Kind::Enum(PACK_MODE), "array_c4").is_ok());
+    assert!(check_value(Kind::Enum(RANKDIR), "array").is_err());
+    assert!(check_value(Kind::Shape, "Mrecord").is_ok());
+    assert!(check_value(Kind::Shape, "blob").is_err());
+    assert!(check_value(Kind::Style, "filled, setlinewidth(2)").is_ok());
+    assert!(check_value(Kind::Style, "wavy").is_err());
+}
+
+#[test]
+fn test_defaults() {
+    for spec in ATTRS.iter() {
+        assert!(spec.used_by.chars().all(|c| "GSCNE".contains(c)));
+        if let Some(default) = spec.default {
+            assert!(check_value(spec.kind, default).is_ok(), "{}", spec.name);
+        }
+    }
+    assert_eq!(find("shape").unwrap().default, Some("ellipse"));
+    assert!(find("shapes").is_none());
+}
+
+// Checks the attributes of one element. contexts lists the letters of used_by that apply.
+fn check_attrs(what: &str, contexts: &str, attrs: &Attrs, warnings: &mut Vec<String>) {
+    for (name, value) in attrs.iter() {
+        let spec = match find(name) {
+            Some(spec) => spec,
+            None => {
+                warnings.push(format!("{} has an unknown attribute {}", what, name));
+                continue;
+            }
+        };
+        if !spec.used_by.chars().any(|c| contexts.contains(c)) {
+            warnings.push(format!("{} has {}, which does not apply to it", what, name));
+            continue;
+        }
+        if let Err(e) = check_value(spec.kind, value) {
+            let fallback = match spec.default {
+                Some(default) => format!(", using {}", default),
+                None => String::new(),
+            };
+            warnings.push(format!("{} has a bad {}: {}{}", what, name, e, fallback));
+        }
+    }
+}
+
+// Warns about unknown attributes, attributes set on elements they do not apply to and values
+// that do not parse. Graph attributes set in a subgraph are accepted since Graphviz lets
+// subgraphs override them.
+pub fn check(graph: &Graph) -> Vec<String> {
+    let mut warnings = Vec::new();
+    check_attrs(
+        format!("Graph {}", graph.name).trim_end(),
+        "G",
+        &graph.attrs,
+        &mut warnings,
+    );
+    for subgraph in graph.subgraphs.iter() {
+        let what = format!("Subgraph {}", subgraph.name);
+        check_attrs(&what, "GSC", &subgraph.attrs, &mut warnings);
+    }
+    for node in graph.nodes.iter() {
+        check_attrs(
+            &format!("Node {}", node.name),
+            "N",
+            &node.attrs,
+            &mut warnings,
+        );
+    }
+    let op = if graph.directed { "->" } else { "--" };
+    for edge in graph.edges.iter() {
+        let what = format!(
+            "Edge {} {} {}",
+            graph.nodes[edge.tail].name, op, graph.nodes[edge.head].name
+        );
+        check_attrs(&what, "E", &edge.attrs, &mut warnings);
+    }
+    warnings
+}
+
+#[test]
+fn test_check() {
+    let tokens = tokenize(
+        r#"digraph G {
+    rankdir = up
+    subgraph cluster_a {
+        rank = same
+        a [rankdir = LR, shape = box, color = nonsense]
+    }
+    a -> b [weight = heavy, frobnicate = 1]
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let warnings = check(&Graph::from_ast(&graph));
+    assert_eq!(warnings.len(), 4);
+    assert!(warnings[0].starts_with("Graph G has a bad rankdir"));
+    assert_eq!(
+        warnings[1],
+        "Node a has rankdir, which does not apply to it"
+    );
+    assert!(warnings[2].starts_with("Edge a -> b has a bad weight"));
+    assert!(warnings[2].ends_with("heavy, using 1"));
+    assert_eq!(
+        warnings[3],
+        "Edge a -> b has an unknown attribute frobnicate"
+    );
+}
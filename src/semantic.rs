@@ -0,0 +1,564 @@
+use crate::ast::{self, AttrStmtKind, EdgeStmtEdge, Stmt, ID};
+use std::collections::HashMap;
+
+#[cfg(test)]
+use crate::tokenize::tokenize;
+
+// Attributes in the order they were first set. Setting an attribute again overrides its value.
+// HTML strings keep their angle brackets and are flagged, as the quoted string "<b>" is not one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Attrs {
+    values: Vec<(String, String)>,
+    html: Vec<bool>,
+}
+
+impl Attrs {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn is_html(&self, name: &str) -> bool {
+        match self.values.iter().position(|(n, _)| n == name) {
+            Some(i) => self.html[i],
+            None => false,
+        }
+    }
+
+    fn put(&mut self, name: &str, value: &str, html: bool) {
+        match self.values.iter().position(|(n, _)| n == name) {
+            Some(i) => {
+                self.values[i].1 = value.to_string();
+                self.html[i] = html;
+            }
+            None => {
+                self.values.push((name.to_string(), value.to_string()));
+                self.html.push(html);
+            }
+        }
+    }
+
+    // Sets a plain string value.
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.put(name, value, false);
+    }
+
+    pub fn set_id(&mut self, name: &str, value: &ID) {
+        self.put(name, &value.value(), value.is_html());
+    }
+
+    pub fn extend(&mut self, attrs: &[(String, ID)]) {
+        for (name, value) in attrs {
+            self.set_id(name, value);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, String)> {
+        self.values.iter()
+    }
+
+    // The values as IDs, which are written out as HTML strings where they were read as one.
+    pub fn ids(&self) -> Vec<(String, ID)> {
+        self.values
+            .iter()
+            .zip(self.html.iter())
+            .map(|((name, value), &html)| {
+                let id = if html {
+                    ID::from_html(value)
+                } else {
+                    ID::from_value(value)
+                };
+                (name.clone(), id)
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub attrs: Attrs,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edge {
+    // Indices into Graph::nodes.
+    pub tail: usize,
+    pub head: usize,
+    pub attrs: Attrs,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subgraph {
+    pub name: String,
+    pub attrs: Attrs,
+    // Indices into Graph::nodes, Graph::edges and Graph::subgraphs. Nodes and edges of nested
+    // subgraphs are included, subgraphs lists only the direct children.
+    pub nodes: Vec<usize>,
+    pub edges: Vec<usize>,
+    pub subgraphs: Vec<usize>,
+}
+
+impl Subgraph {
+    pub fn is_cluster(&self) -> bool {
+        ast::is_cluster(&self.name)
+    }
+}
+
+// The graph with edge chains expanded, node and edge defaults applied and subgraphs flattened
+// in depth-first order. This is what layout and output work on instead of the syntax tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Graph {
+    pub name: String,
+    pub directed: bool,
+    pub strict: bool,
+    pub attrs: Attrs,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    pub subgraphs: Vec<Subgraph>,
+}
+
+impl Graph {
+    pub fn from_ast(ast_graph: &ast::Graph) -> Graph {
+        let mut graph = Graph {
+            name: ast_graph.id().map(|id| id.value()).unwrap_or_default(),
+            directed: ast_graph.is_digraph(),
+            strict: ast_graph.is_strict(),
+            attrs: Attrs::default(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            subgraphs: Vec::new(),
+        };
+        let mut builder = Builder {
+            graph: &mut graph,
+            node_indices: HashMap::new(),
+            subgraph_indices: HashMap::new(),
+            anonymous_subgraphs: 0,
+        };
+        builder.walk(
+            &ast_graph.stmts(),
+            &[],
+            &mut Attrs::default(),
+            &mut Attrs::default(),
+        );
+        graph
+    }
+
+    fn find_edge(&self, tail: usize, head: usize) -> Option<usize> {
+        self.edges.iter().position(|e| {
+            (e.tail == tail && e.head == head)
+                || (!self.directed && e.tail == head && e.head == tail)
+        })
+    }
+}
+
+struct Builder<'a> {
+    graph: &'a mut Graph,
+    node_indices: HashMap<String, usize>,
+    subgraph_indices: HashMap<String, usize>,
+    anonymous_subgraphs: usize,
+}
+
+impl Builder<'_> {
+    // path is the chain of subgraphs enclosing stmts, outermost first.
+    fn walk(
+        &mut self,
+        stmts: &[&Stmt],
+        path: &[usize],
+        node_defaults: &mut Attrs,
+        edge_defaults: &mut Attrs,
+    ) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::IDEqStmt(id_eq_stmt) => {
+                    let (name, value) = id_eq_stmt.attr();
+                    self.graph_attrs(path).set_id(&name, &value);
+                }
+                Stmt::AttrStmt(attr_stmt) => match attr_stmt.kind() {
+                    AttrStmtKind::Graph => self.graph_attrs(path).extend(&attr_stmt.attrs()),
+                    AttrStmtKind::Node => node_defaults.extend(&attr_stmt.attrs()),
+                    AttrStmtKind::Edge => edge_defaults.extend(&attr_stmt.attrs()),
+                },
+                Stmt::EdgeStmt(edge_stmt) => {
+                    // Each end is a node with its port, or all nodes of a subgraph as in
+                    // `a -> {b c}`.
+                    let mut ends: Vec<Vec<(usize, Option<String>)>> = Vec::new();
+                    for edge_end in edge_stmt.edge_ends() {
+                        ends.push(match edge_end {
+                            EdgeStmtEdge::NodeID(id, port) => {
+                                let node = self.node(&id.value(), path, node_defaults);
+                                vec![(node, port.as_ref().map(|port| port.value()))]
+                            }
+                            EdgeStmtEdge::Subgraph(subgraph) => {
+                                let index = self.subgraph_stmt(
+                                    subgraph,
+                                    path,
+                                    node_defaults,
+                                    edge_defaults,
+                                );
+                                let nodes = &self.graph.subgraphs[index].nodes;
+                                nodes.iter().map(|&node| (node, None)).collect()
+                            }
+                        });
+                    }
+                    if ends.len() == 1 {
+                        for &(node, _) in &ends[0] {
+                            self.graph.nodes[node].attrs.extend(&edge_stmt.attrs());
+                        }
+                    }
+                    for pair in ends.windows(2) {
+                        for (tail, tailport) in &pair[0] {
+                            for (head, headport) in &pair[1] {
+                                let mut attrs = edge_defaults.clone();
+                                attrs.extend(&edge_stmt.attrs());
+                                // As in dot, `a:p -> b:q` is stored as the tailport and headport
+                                // attributes.
+                                if let Some(tailport) = tailport {
+                                    attrs.set("tailport", tailport);
+                                }
+                                if let Some(headport) = headport {
+                                    attrs.set("headport", headport);
+                                }
+                                self.edge(*tail, *head, attrs, path);
+                            }
+                        }
+                    }
+                }
+                Stmt::Subgraph(subgraph) => {
+                    self.subgraph_stmt(subgraph, path, node_defaults, edge_defaults);
+                }
+            }
+        }
+    }
+
+    // Walks a subgraph with copies of the defaults, which do not leak out of it, and returns its
+    // index.
+    fn subgraph_stmt(
+        &mut self,
+        subgraph: &ast::Subgraph,
+        path: &[usize],
+        node_defaults: &Attrs,
+        edge_defaults: &Attrs,
+    ) -> usize {
+        let name = match subgraph.id() {
+            Some(id) => id.value(),
+            None => {
+                self.anonymous_subgraphs += 1;
+                format!("%{}", self.anonymous_subgraphs)
+            }
+        };
+        let index = self.subgraph(&name, path);
+        let mut sub_path = path.to_vec();
+        sub_path.push(index);
+        self.walk(
+            &subgraph.stmts(),
+            &sub_path,
+            &mut node_defaults.clone(),
+            &mut edge_defaults.clone(),
+        );
+        index
+    }
+
+    fn graph_attrs(&mut self, path: &[usize]) -> &mut Attrs {
+        match path.last() {
+            Some(&index) => &mut self.graph.subgraphs[index].attrs,
+            None => &mut self.graph.attrs,
+        }
+    }
+
+    // Returns the node named name, creating it with the current defaults on first use.
+    fn node(&mut self, name: &str, path: &[usize], node_defaults: &Attrs) -> usize {
+        let index = match self.node_indices.get(name) {
+            Some(&index) => index,
+            None => {
+                let index = self.graph.nodes.len();
+                self.graph.nodes.push(Node {
+                    name: name.to_string(),
+                    attrs: node_defaults.clone(),
+                });
+                self.node_indices.insert(name.to_string(), index);
+                index
+            }
+        };
+        for &subgraph in path {
+            if !self.graph.subgraphs[subgraph].nodes.contains(&index) {
+                self.graph.subgraphs[subgraph].nodes.push(index);
+            }
+        }
+        index
+    }
+
+    fn edge(&mut self, tail: usize, head: usize, attrs: Attrs, path: &[usize]) {
+        // A strict graph has at most one edge between two nodes, later edges update it.
+        if self.graph.strict {
+            if let Some(index) = self.graph.find_edge(tail, head) {
+                self.graph.edges[index].attrs.extend(&attrs.ids());
+                return;
+            }
+        }
+        let index = self.graph.edges.len();
+        self.graph.edges.push(Edge { tail, head, attrs });
+        for &subgraph in path {
+            self.graph.subgraphs[subgraph].edges.push(index);
+        }
+    }
+
+    // Subgraphs with the same name are the same subgraph, as in dot.
+    fn subgraph(&mut self, name: &str, path: &[usize]) -> usize {
+        if let Some(&index) = self.subgraph_indices.get(name) {
+            return index;
+        }
+        let index = self.graph.subgraphs.len();
+        self.graph.subgraphs.push(Subgraph {
+            name: name.to_string(),
+            attrs: Attrs::default(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            subgraphs: Vec::new(),
+        });
+        self.subgraph_indices.insert(name.to_string(), index);
+        if let Some(&parent) = path.last() {
+            self.graph.subgraphs[parent].subgraphs.push(index);
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn from_dot(dot_str: &str) -> Graph {
+    let tokens = tokenize(dot_str.to_string());
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    Graph::from_ast(&graph)
+}
+
+#[test]
+fn test_from_ast() {
+    let graph = from_dot(
+        r#"digraph G {
+    rankdir = LR
+    node [shape = box]
+    a -> b -> c [color = red]
+    node [shape = circle]
+    d [label = "D"]
+    a -> c
+}"#,
+    );
+    assert_eq!(graph.name, "G");
+    assert!(graph.directed);
+    assert!(!graph.strict);
+    assert_eq!(graph.attrs.get("rankdir"), Some("LR"));
+    let names: Vec<&str> = graph.nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, vec!["a", "b", "c", "d"]);
+    assert_eq!(graph.nodes[0].attrs.get("shape"), Some("box"));
+    assert_eq!(graph.nodes[3].attrs.get("shape"), Some("circle"));
+    assert_eq!(graph.nodes[3].attrs.get("label"), Some("D"));
+    let edges: Vec<(usize, usize)> = graph.edges.iter().map(|e| (e.tail, e.head)).collect();
+    assert_eq!(edges, vec![(0, 1), (1, 2), (0, 2)]);
+    assert_eq!(graph.edges[1].attrs.get("color"), Some("red"));
+    assert_eq!(graph.edges[2].attrs.get("color"), None);
+
+    let graph = from_dot("digraph { a:f0 -> b:f1:n -> c [tailport = x] }");
+    assert_eq!(graph.edges[0].attrs.get("tailport"), Some("f0"));
+    assert_eq!(graph.edges[0].attrs.get("headport"), Some("f1:n"));
+    assert_eq!(graph.edges[1].attrs.get("tailport"), Some("f1:n"));
+    assert_eq!(graph.edges[1].attrs.get("headport"), None);
+}
+
+#[test]
+fn test_from_ast_subgraphs() {
+    let graph = from_dot(
+        r#"graph {
+    edge [color = blue]
+    subgraph cluster_a {
+        label = "A"
+        node [shape = box]
+        a -- b
+        { c }
+    }
+    node [color = red]
+    c -- d
+    subgraph cluster_a { e }
+}"#,
+    );
+    assert_eq!(graph.subgraphs.len(), 2);
+    let cluster = &graph.subgraphs[0];
+    assert_eq!(cluster.name, "cluster_a");
+    assert_eq!(cluster.attrs.get("label"), Some("A"));
+    assert_eq!(cluster.nodes, vec![0, 1, 2, 4]);
+    assert_eq!(cluster.edges, vec![0]);
+    assert_eq!(cluster.subgraphs, vec![1]);
+    assert_eq!(graph.subgraphs[1].name, "%1");
+    assert_eq!(graph.subgraphs[1].nodes, vec![2]);
+    // Defaults set in a subgraph do not leak out of it.
+    assert_eq!(graph.nodes[2].attrs.get("shape"), Some("box"));
+    assert_eq!(graph.nodes[3].attrs.get("shape"), None);
+    assert_eq!(graph.nodes[3].attrs.get("color"), Some("red"));
+    assert_eq!(graph.edges[0].attrs.get("color"), Some("blue"));
+    assert_eq!(graph.edges[1].attrs.get("color"), Some("blue"));
+}
+
+#[test]
+fn test_from_ast_subgraph_edges() {
+    let graph = from_dot(
+        r#"digraph {
+    a -> {b c} [color = red]
+    subgraph cluster_0 { d e -> f } -> a:n
+}"#,
+    );
+    let names: Vec<&str> = graph.nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, vec!["a", "b", "c", "d", "e", "f"]);
+    let edges: Vec<(usize, usize)> = graph.edges.iter().map(|e| (e.tail, e.head)).collect();
+    assert_eq!(edges, vec![(0, 1), (0, 2), (4, 5), (3, 0), (4, 0), (5, 0)]);
+    assert_eq!(graph.edges[1].attrs.get("color"), Some("red"));
+    assert_eq!(graph.edges[3].attrs.get("headport"), Some("n"));
+    assert_eq!(graph.edges[3].attrs.get("tailport"), None);
+    assert_eq!(graph.subgraphs[0].nodes, vec![1, 2]);
+    assert_eq!(graph.subgraphs[1].name, "cluster_0");
+    assert_eq!(graph.subgraphs[1].nodes, vec![3, 4, 5]);
+    assert_eq!(graph.subgraphs[1].edges, vec![2]);
+}
+
+#[test]
+fn test_from_ast_strict() {
+    let graph = from_dot("strict graph { a -- b b -- a [color = red] a -- a }");
+    assert!(graph.strict);
+    assert_eq!(graph.edges.len(), 2);
+    assert_eq!(graph.edges[0].attrs.get("color"), Some("red"));
+
+    let graph = from_dot("graph { a -- b b -- a }");
+    assert_eq!(graph.edges.len(), 2);
+}
+
+impl Graph {
+    // Builds a syntax tree that reads back into this graph, as json::parse does for `-Tjson`.
+    // Nodes come first with their attributes. Each subgraph follows once, with its attributes,
+    // its nodes, the edges it is the innermost subgraph of and its own subgraphs. Edges keep
+    // their order within each subgraph.
+    pub fn to_ast(&self) -> Result<ast::Graph, String> {
+        // Subgraphs are numbered depth first, so the innermost one containing an edge is the
+        // last one listing it.
+        let mut innermost = vec![None; self.edges.len()];
+        for (i, subgraph) in self.subgraphs.iter().enumerate() {
+            for &edge in subgraph.edges.iter() {
+                innermost[edge] = Some(i);
+            }
+        }
+        let edge_stmts = |subgraph: Option<usize>| -> Vec<Stmt> {
+            self.edges
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| innermost[i] == subgraph)
+                .map(|(_, edge)| {
+                    Stmt::edge(
+                        &self.nodes[edge.tail].name,
+                        &self.nodes[edge.head].name,
+                        self.directed,
+                        &edge.attrs.ids(),
+                    )
+                })
+                .collect()
+        };
+        fn subgraph_stmt(
+            graph: &Graph,
+            i: usize,
+            edge_stmts: &dyn Fn(Option<usize>) -> Vec<Stmt>,
+        ) -> Stmt {
+            let subgraph = &graph.subgraphs[i];
+            let mut stmts: Vec<Stmt> = subgraph
+                .attrs
+                .ids()
+                .into_iter()
+                .map(|(name, value)| Stmt::id_eq(&name, value))
+                .collect();
+            for &node in subgraph.nodes.iter() {
+                stmts.push(Stmt::node(&graph.nodes[node].name, &[]));
+            }
+            stmts.extend(edge_stmts(Some(i)));
+            for &child in subgraph.subgraphs.iter() {
+                stmts.push(subgraph_stmt(graph, child, edge_stmts));
+            }
+            // Anonymous subgraphs are named %1, %2, ... when the graph is built.
+            let name = (!subgraph.name.starts_with('%')).then_some(subgraph.name.as_str());
+            Stmt::subgraph(name, stmts)
+        }
+
+        let mut stmts: Vec<Stmt> = self
+            .attrs
+            .ids()
+            .into_iter()
+            .map(|(name, value)| Stmt::id_eq(&name, value))
+            .collect();
+        for node in self.nodes.iter() {
+            stmts.push(Stmt::node(&node.name, &node.attrs.ids()));
+        }
+        let children: Vec<usize> = self
+            .subgraphs
+            .iter()
+            .flat_map(|subgraph| subgraph.subgraphs.iter().copied())
+            .collect();
+        for i in (0..self.subgraphs.len()).filter(|i| !children.contains(i)) {
+            stmts.push(subgraph_stmt(self, i, &edge_stmts));
+        }
+        stmts.extend(edge_stmts(None));
+        let name = (!self.name.is_empty()).then_some(self.name.as_str());
+        Ok(ast::Graph::new(self.strict, self.directed, name, stmts))
+    }
+}
+
+#[test]
+fn test_to_ast() {
+    let dot_str = r#"strict digraph G {
+    rankdir = LR
+    a [shape = box]
+    subgraph cluster_a {
+        label = "A b"
+        b -> c [color = red]
+        subgraph cluster_b { c -> a }
+        { d }
+    }
+    a -> b
+}"#;
+    let graph = from_dot(dot_str);
+    let ast_graph = graph.to_ast().unwrap();
+    assert_eq!(
+        ast_graph.to_string(),
+        r#"strict digraph G {
+    rankdir=LR;
+    a [shape=box];
+    b;
+    c;
+    d;
+    subgraph cluster_a {
+        label="A b";
+        b;
+        c;
+        a;
+        d;
+        b -> c [color=red];
+        subgraph cluster_b {
+            c;
+            a;
+            c -> a;
+        }
+        {
+            d;
+        }
+    }
+    a -> b;
+}
+"#
+    );
+    assert_eq!(Graph::from_ast(&ast_graph), graph);
+
+    // HTML strings stay HTML strings, and quoted strings that look like one stay quoted.
+    let graph = from_dot(r#"graph { a [label = <<b>x</b>>]; b [label = "<b>"] }"#);
+    assert!(graph.nodes[0].attrs.is_html("label"));
+    assert!(!graph.nodes[1].attrs.is_html("label"));
+    assert_eq!(graph.nodes[1].attrs.get("label"), Some("<b>"));
+    assert_eq!(
+        graph.to_ast().unwrap().to_string(),
+        "graph {\n    a [label=<<b>x</b>>];\n    b [label=\"<b>\"];\n}\n"
+    );
+}
@@ -0,0 +1,487 @@
+use crate::fdp;
+use crate::layout::{self, number, Point, POINTS_PER_INCH};
+use crate::semantic::Graph;
+use std::ops::Range;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Strength of the repulsion relative to the springs, and Barnes-Hut opening angle, as in Hu,
+// "Efficient and high quality force-directed graph drawing".
+const REPULSION: f64 = 0.2;
+const THETA: f64 = 0.6;
+const COOLING: f64 = 0.9;
+// The step length grows again after this many improving iterations in a row.
+const PATIENCE: usize = 5;
+// A level is refined until its step length falls below this fraction of K.
+const TOLERANCE: f64 = 0.05;
+const DEFAULT_MAXITER: usize = 600;
+const MAX_DEPTH: usize = 32;
+
+// One graph of the multilevel hierarchy. Every node of the finer graph belongs to parent[i] in
+// this one, and mass counts the nodes of the original graph it stands for.
+struct Level {
+    springs: Vec<(usize, usize, f64)>,
+    mass: Vec<f64>,
+    pinned: Vec<bool>,
+    parent: Vec<usize>,
+}
+
+// Merges the ends of a maximal matching of the edges. Pinned nodes are never merged so that they
+// keep their positions on every level.
+fn coarsen(springs: &[(usize, usize, f64)], mass: &[f64], pinned: &[bool]) -> Level {
+    let n = mass.len();
+    let mut parent = vec![usize::MAX; n];
+    let mut count = 0;
+    for &(tail, head, _) in springs.iter() {
+        if tail != head
+            && parent[tail] == usize::MAX
+            && parent[head] == usize::MAX
+            && !pinned[tail]
+            && !pinned[head]
+        {
+            parent[tail] = count;
+            parent[head] = count;
+            count += 1;
+        }
+    }
+    for p in parent.iter_mut().filter(|p| **p == usize::MAX) {
+        *p = count;
+        count += 1;
+    }
+    let mut coarse_mass = vec![0.0; count];
+    let mut coarse_pinned = vec![false; count];
+    for i in 0..n {
+        coarse_mass[parent[i]] += mass[i];
+        coarse_pinned[parent[i]] |= pinned[i];
+    }
+    let mut coarse_springs: Vec<(usize, usize, f64)> = springs
+        .iter()
+        .map(|&(tail, head, weight)| {
+            let (a, b) = (parent[tail], parent[head]);
+            (a.min(b), a.max(b), weight)
+        })
+        .filter(|(a, b, _)| a != b)
+        .collect();
+    coarse_springs.sort_by_key(|x| (x.0, x.1));
+    coarse_springs.dedup_by(|x, y| {
+        if (x.0, x.1) == (y.0, y.1) {
+            y.2 += x.2;
+            true
+        } else {
+            false
+        }
+    });
+    Level {
+        springs: coarse_springs,
+        mass: coarse_mass,
+        pinned: coarse_pinned,
+        parent,
+    }
+}
+
+#[test]
+fn test_coarsen() {
+    let springs = [(0, 1, 1.0), (1, 2, 1.0), (2, 3, 1.0), (3, 0, 1.0)];
+    let level = coarsen(&springs, &[1.0; 4], &[false; 4]);
+    assert_eq!(level.parent, vec![0, 0, 1, 1]);
+    assert_eq!(level.mass, vec![2.0, 2.0]);
+    assert_eq!(level.springs, vec![(0, 1, 2.0)]);
+
+    let level = coarsen(&springs, &[1.0; 4], &[true, false, false, false]);
+    assert_eq!(level.parent, vec![1, 0, 0, 2]);
+    assert_eq!(level.pinned, vec![false, true, false]);
+}
+
+// A cell of the Barnes-Hut quadtree. Leaves hold a range of the node order, other cells up to
+// four children, where 0, the root, stands for none.
+struct Cell {
+    mass: f64,
+    centroid: Point,
+    size: f64,
+    children: [usize; 4],
+    nodes: Range<usize>,
+}
+
+// Moves the nodes for which pred holds to the front and returns how many there are.
+fn partition(nodes: &mut [usize], pred: impl Fn(usize) -> bool) -> usize {
+    let mut count = 0;
+    for i in 0..nodes.len() {
+        if pred(nodes[i]) {
+            nodes.swap(count, i);
+            count += 1;
+        }
+    }
+    count
+}
+
+// Builds the cell for the nodes order[range], reordering them by quadrant so that every cell
+// owns a contiguous range without allocating.
+fn build(
+    cells: &mut Vec<Cell>,
+    (positions, mass): (&[Point], &[f64]),
+    order: &mut [usize],
+    range: Range<usize>,
+    (center, size): (Point, f64),
+    depth: usize,
+) -> usize {
+    let nodes = &mut order[range.clone()];
+    let total: f64 = nodes.iter().map(|&i| mass[i]).sum();
+    let centroid = (
+        nodes.iter().map(|&i| positions[i].0 * mass[i]).sum::<f64>() / total,
+        nodes.iter().map(|&i| positions[i].1 * mass[i]).sum::<f64>() / total,
+    );
+    let index = cells.len();
+    cells.push(Cell {
+        mass: total,
+        centroid,
+        size,
+        children: [0; 4],
+        nodes: range.start..range.start,
+    });
+    if nodes.len() == 1 || depth == MAX_DEPTH {
+        cells[index].nodes = range;
+        return index;
+    }
+    // Splits by y and then each half by x, leaving the quadrants in the order lower left, lower
+    // right, upper left and upper right.
+    let bottom = partition(nodes, |i| positions[i].1 < center.1);
+    let left_bottom = partition(&mut nodes[..bottom], |i| positions[i].0 < center.0);
+    let left_top = partition(&mut nodes[bottom..], |i| positions[i].0 < center.0);
+    let ends = [left_bottom, bottom, bottom + left_top, nodes.len()];
+    let mut start = range.start;
+    for (q, end) in ends.into_iter().enumerate() {
+        let end = range.start + end;
+        if end > start {
+            let quarter = size / 4.0;
+            let x = if q & 1 == 1 { quarter } else { -quarter };
+            let y = if q & 2 == 2 { quarter } else { -quarter };
+            cells[index].children[q] = build(
+                cells,
+                (positions, mass),
+                order,
+                start..end,
+                ((center.0 + x, center.1 + y), size / 2.0),
+                depth + 1,
+            );
+        }
+        start = end;
+    }
+    index
+}
+
+// Repulsive forces K^(1+p) / d^p, where p is repulsiveforce. With a quadtree, cells that are
+// small compared with their distance act as a single mass at their centroid.
+fn repulsion(positions: &[Point], mass: &[f64], k: f64, p: f64, quadtree: bool) -> Vec<Point> {
+    let n = positions.len();
+    let scale = REPULSION * k.powf(1.0 + p);
+    let push = |from: Point, to: Point, mass: f64, force: &mut Point| {
+        let (dx, dy) = (from.0 - to.0, from.1 - to.1);
+        // This is the inner loop, so the default p = 1 avoids sqrt and powf.
+        let squared = dx * dx + dy * dy;
+        if squared > 0.0 {
+            let f = if p == 1.0 {
+                scale * mass / squared
+            } else {
+                scale * mass / squared.powf((p + 1.0) / 2.0)
+            };
+            force.0 += dx * f;
+            force.1 += dy * f;
+        }
+    };
+    let mut forces = vec![(0.0, 0.0); n];
+    if !quadtree {
+        for i in 0..n {
+            for j in (0..n).filter(|&j| j != i) {
+                push(positions[i], positions[j], mass[j], &mut forces[i]);
+            }
+        }
+        return forces;
+    }
+
+    let (min_x, max_x, min_y, max_y) = positions.iter().fold(
+        (
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(a, b, c, d), &(x, y)| (a.min(x), b.max(x), c.min(y), d.max(y)),
+    );
+    let center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let size = (max_x - min_x).max(max_y - min_y).max(k) * 1.01;
+    let mut cells = Vec::new();
+    let mut order: Vec<usize> = (0..n).collect();
+    build(
+        &mut cells,
+        (positions, mass),
+        &mut order,
+        0..n,
+        (center, size),
+        0,
+    );
+    for i in 0..n {
+        let mut stack = vec![0];
+        while let Some(cell) = stack.pop() {
+            let cell = &cells[cell];
+            if !cell.nodes.is_empty() {
+                for &j in order[cell.nodes.clone()].iter().filter(|&&j| j != i) {
+                    push(positions[i], positions[j], mass[j], &mut forces[i]);
+                }
+                continue;
+            }
+            let (dx, dy) = (
+                positions[i].0 - cell.centroid.0,
+                positions[i].1 - cell.centroid.1,
+            );
+            if cell.size * cell.size < THETA * THETA * (dx * dx + dy * dy) {
+                push(positions[i], cell.centroid, cell.mass, &mut forces[i]);
+            } else {
+                stack.extend(cell.children.iter().filter(|&&child| child != 0));
+            }
+        }
+    }
+    forces
+}
+
+#[test]
+fn test_repulsion() {
+    let positions: Vec<Point> = (0..50)
+        .map(|i| ((i % 7) as f64 * 0.3, (i / 7) as f64 * 0.4))
+        .collect();
+    let mass = vec![1.0; positions.len()];
+    let exact = repulsion(&positions, &mass, 0.3, 1.0, false);
+    let approximate = repulsion(&positions, &mass, 0.3, 1.0, true);
+    // Nodes in the middle feel almost no net force, so errors are measured against the largest.
+    let largest = exact.iter().map(|f| f.0.hypot(f.1)).fold(0.0, f64::max);
+    for (a, b) in exact.iter().zip(approximate.iter()) {
+        assert!(
+            (a.0 - b.0).hypot(a.1 - b.1) < 0.1 * largest,
+            "{:?} {:?}",
+            a,
+            b
+        );
+    }
+    assert!(exact[0].0 < 0.0 && exact[0].1 < 0.0);
+}
+
+// The spring-electrical model with an adaptive step length: the step shrinks whenever the
+// energy goes up and grows after a run of improvements.
+fn refine(
+    level: &Level,
+    positions: &mut [Point],
+    (k, p, quadtree): (f64, f64, bool),
+    maxiter: usize,
+) {
+    let mut step = k;
+    let mut energy = f64::INFINITY;
+    let mut progress = 0;
+    for _ in 0..maxiter {
+        let mut forces = repulsion(positions, &level.mass, k, p, quadtree);
+        for &(tail, head, weight) in level.springs.iter() {
+            let (dx, dy) = (
+                positions[head].0 - positions[tail].0,
+                positions[head].1 - positions[tail].1,
+            );
+            let f = weight * dx.hypot(dy) / k;
+            forces[tail].0 += dx * f;
+            forces[tail].1 += dy * f;
+            forces[head].0 -= dx * f;
+            forces[head].1 -= dy * f;
+        }
+        let mut new_energy = 0.0;
+        for i in (0..positions.len()).filter(|&i| !level.pinned[i]) {
+            let length = forces[i].0.hypot(forces[i].1);
+            new_energy += length * length;
+            if length > 0.0 {
+                positions[i].0 += forces[i].0 / length * step;
+                positions[i].1 += forces[i].1 / length * step;
+            }
+        }
+        if new_energy < energy {
+            progress += 1;
+            if progress >= PATIENCE {
+                progress = 0;
+                step /= COOLING;
+            }
+        } else {
+            progress = 0;
+            step *= COOLING;
+        }
+        energy = new_energy;
+        if step < TOLERANCE * k {
+            break;
+        }
+    }
+}
+
+// Multilevel Barnes-Hut layout for large graphs. The graph is coarsened by merging the ends of
+// edges up to levels times, the coarsest graph is laid out first and each finer graph starts
+// from the positions of the nodes it was merged into. quadtree=none or false computes the
+// repulsion exactly, which is slower but needs no approximation.
+pub fn layout(graph: &Graph) -> Result<Vec<Point>, String> {
+    let n = graph.nodes.len();
+    let k = fdp::spring_constant(graph)?;
+    let p = number(&graph.attrs, "repulsiveforce", 1.0)?;
+    if p <= 0.0 {
+        return Err(format!(
+            "{}:{} repulsiveforce must be positive: {}",
+            file!(),
+            line!(),
+            p
+        ));
+    }
+    let levels = match graph.attrs.get("levels") {
+        Some(levels) => levels
+            .parse()
+            .map_err(|_| format!("{}:{} Bad levels {}", file!(), line!(), levels))?,
+        None => usize::MAX,
+    };
+    let quadtree = match graph.attrs.get("quadtree").unwrap_or("normal") {
+        "normal" | "fast" | "true" => true,
+        "none" | "false" => false,
+        quadtree => return Err(format!("{}:{} Bad quadtree {}", file!(), line!(), quadtree)),
+    };
+    let maxiter = match graph.attrs.get("maxiter") {
+        Some(maxiter) => maxiter
+            .parse()
+            .map_err(|_| format!("{}:{} Bad maxiter {}", file!(), line!(), maxiter))?,
+        None => DEFAULT_MAXITER,
+    };
+    let (initial, pinned) = layout::initial_positions(graph, (n as f64).sqrt() * k)?;
+
+    let mut hierarchy = vec![Level {
+        springs: fdp::springs(graph)?,
+        mass: vec![1.0; n],
+        pinned,
+        parent: Vec::new(),
+    }];
+    let mut starts = vec![initial];
+    while hierarchy.len() <= levels {
+        let finer = hierarchy.last().unwrap();
+        let coarser = coarsen(&finer.springs, &finer.mass, &finer.pinned);
+        // Stop when merging no longer pays off.
+        if coarser.mass.len() as f64 > 0.9 * finer.mass.len() as f64 {
+            break;
+        }
+        // A merged node starts at the centroid of its parts, or where a pinned part is.
+        let fine = starts.last().unwrap();
+        let mut start = vec![(0.0, 0.0); coarser.mass.len()];
+        for (i, &parent) in coarser.parent.iter().enumerate() {
+            if finer.pinned[i] {
+                start[parent] = fine[i];
+            } else if !coarser.pinned[parent] {
+                let share = finer.mass[i] / coarser.mass[parent];
+                start[parent].0 += fine[i].0 * share;
+                start[parent].1 += fine[i].1 * share;
+            }
+        }
+        starts.push(start);
+        hierarchy.push(coarser);
+    }
+
+    let mut positions = starts.pop().unwrap();
+    refine(
+        hierarchy.last().unwrap(),
+        &mut positions,
+        (k, p, quadtree),
+        maxiter,
+    );
+    while hierarchy.len() > 1 {
+        let coarser = hierarchy.pop().unwrap();
+        let start = starts.pop().unwrap();
+        // The two parts of a merged node are put K/4 from it, in the same direction from each
+        // other as at the start.
+        let mut parts = vec![Vec::new(); coarser.mass.len()];
+        for (i, &parent) in coarser.parent.iter().enumerate() {
+            parts[parent].push(i);
+        }
+        let level = hierarchy.last().unwrap();
+        let mut finer = Vec::new();
+        for (i, &parent) in coarser.parent.iter().enumerate() {
+            if level.pinned[i] {
+                finer.push(start[i]);
+                continue;
+            }
+            let offset = match parts[parent].iter().find(|&&j| j != i) {
+                Some(&other) => {
+                    let (dx, dy) = (start[i].0 - start[other].0, start[i].1 - start[other].1);
+                    let d = dx.hypot(dy).max(f64::MIN_POSITIVE);
+                    (dx / d * k / 4.0, dy / d * k / 4.0)
+                }
+                None => (0.0, 0.0),
+            };
+            finer.push((
+                positions[parent].0 + offset.0,
+                positions[parent].1 + offset.1,
+            ));
+        }
+        positions = finer;
+        refine(
+            hierarchy.last().unwrap(),
+            &mut positions,
+            (k, p, quadtree),
+            maxiter,
+        );
+    }
+    Ok(positions
+        .into_iter()
+        .map(|(x, y)| (x * POINTS_PER_INCH, y * POINTS_PER_INCH))
+        .collect())
+}
+
+#[test]
+fn test_layout() {
+    let distance = |positions: &[Point], i: usize, j: usize| {
+        let (a, b): (Point, Point) = (positions[i], positions[j]);
+        (a.0 - b.0).hypot(a.1 - b.1) / POINTS_PER_INCH
+    };
+    // A long path unfolds instead of collapsing onto itself.
+    let path: Vec<String> = (0..60).map(|i| format!("n{}", i)).collect();
+    for attrs in ["", "quadtree = none;", "levels = 0;"] {
+        let graph = from_dot(&format!("graph {{ {} {} }}", attrs, path.join(" -- ")));
+        let positions = layout(&graph).unwrap();
+        assert!(
+            distance(&positions, 0, 59) > 10.0 * distance(&positions, 0, 1),
+            "{}",
+            attrs
+        );
+        assert_eq!(positions, layout(&graph).unwrap());
+    }
+
+    let graph = from_dot(r#"graph { a [pos = "1,2!"]; a -- b -- c -- d }"#);
+    assert_eq!(layout(&graph).unwrap()[0], (72.0, 144.0));
+
+    assert!(layout(&from_dot(r#"graph { repulsiveforce = 0; a }"#)).is_err());
+    assert!(layout(&from_dot(r#"graph { quadtree = sometimes; a }"#)).is_err());
+}
+
+// Takes about a minute in release builds, run it with `cargo test --release -- --ignored`.
+#[test]
+#[ignore]
+fn test_layout_large() {
+    // A random tree over 50k nodes with 25k more edges between random nodes.
+    let n = 50000;
+    let mut state: u64 = 1;
+    let mut random = |below: usize| {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) as usize % below
+    };
+    let mut dot = String::from("graph {\n");
+    for i in 1..n {
+        dot += &format!("n{} -- n{};\n", random(i), i);
+    }
+    for _ in 0..n / 2 {
+        dot += &format!("n{} -- n{};\n", random(n), random(n));
+    }
+    dot += "}\n";
+    let graph = from_dot(&dot);
+    assert_eq!(graph.nodes.len(), n);
+    let positions = layout(&graph).unwrap();
+    assert_eq!(positions.len(), n);
+    assert!(positions
+        .iter()
+        .all(|&(x, y)| x.is_finite() && y.is_finite()));
+}
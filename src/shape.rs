@@ -0,0 +1,376 @@
+use crate::layout::Point;
+use crate::semantic::Node;
+use std::f64::consts::{PI, SQRT_2};
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Space between the peripheries of a node, in points.
+pub const PERIPHERY_GAP: f64 = 4.0;
+// Radius of the inner corners of a star, relative to the outer ones.
+const STAR_INNER: f64 = 0.381966;
+// Sides of the polygon around an ellipse that stands in for it when routing edges.
+const ELLIPSE_SIDES: usize = 8;
+
+// The outline of a node shape as Graphviz describes its polygons. Shapes with fewer than 3 sides
+// are ellipses. Shapes such as note, tab or cylinder only differ from a box in how they are drawn,
+// so for layout they are boxes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shape {
+    pub sides: usize,
+    pub peripheries: usize,
+    // In degrees, counterclockwise.
+    pub orientation: f64,
+    pub distortion: f64,
+    pub skew: f64,
+    pub regular: bool,
+    pub star: bool,
+    pub point: bool,
+}
+
+// The convex hull of the points, counterclockwise, by Andrew's monotone chain.
+fn convex_hull(mut points: Vec<Point>) -> Vec<Point> {
+    points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    let turn = |o: Point, a: Point, b: Point| (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0);
+    let mut hull: Vec<Point> = Vec::new();
+    for pass in 0..2 {
+        let start = hull.len();
+        for &p in points.iter() {
+            while hull.len() >= start + 2
+                && turn(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+        if pass == 0 {
+            points.reverse();
+        }
+    }
+    hull
+}
+
+impl Shape {
+    pub fn new(sides: usize) -> Shape {
+        Shape {
+            sides,
+            peripheries: 1,
+            orientation: 0.0,
+            distortion: 0.0,
+            skew: 0.0,
+            regular: false,
+            star: false,
+            point: false,
+        }
+    }
+
+    pub fn is_ellipse(&self) -> bool {
+        self.sides < 3
+    }
+
+    // Whether the outline is its bounding box, which labels fill without being scaled.
+    fn is_box(&self) -> bool {
+        self.sides == 4
+            && self.orientation % 90.0 == 0.0
+            && self.distortion == 0.0
+            && self.skew == 0.0
+    }
+
+    // Corners of the outline centered at the origin, before they are scaled to the node size.
+    // This follows poly_init in Graphviz, which walks around a regular polygon and bends its
+    // corners by distortion and skew.
+    fn unit_vertices(&self) -> Vec<Point> {
+        if self.star {
+            return (0..10)
+                .map(|i| {
+                    let angle = PI / 2.0 + i as f64 * PI / 5.0;
+                    let radius = if i % 2 == 0 { 0.5 } else { 0.5 * STAR_INNER };
+                    (radius * angle.cos(), radius * angle.sin())
+                })
+                .collect();
+        }
+        let sector = 2.0 * PI / self.sides as f64;
+        let side = (sector / 2.0).sin();
+        let skew_distance = (self.distortion.abs() + self.skew.abs()).hypot(1.0);
+        let distortion = self.distortion * SQRT_2 / (sector / 2.0).cos();
+        let skew = self.skew / 2.0;
+        let mut angle = (sector - PI) / 2.0;
+        let mut r = (0.5 * angle.cos(), 0.5 * angle.sin());
+        angle += (PI - sector) / 2.0;
+        let mut vertices = Vec::new();
+        for _ in 0..self.sides {
+            angle += sector;
+            r = (r.0 + side * angle.cos(), r.1 + side * angle.sin());
+            let p = (r.0 * (skew_distance + r.1 * distortion) + r.1 * skew, r.1);
+            let alpha = self.orientation.to_radians() + p.1.atan2(p.0);
+            let length = p.0.hypot(p.1);
+            vertices.push((length * alpha.cos(), length * alpha.sin()));
+        }
+        vertices
+    }
+
+    // Width and height of the bounding box of the unit outline.
+    fn unit_extent(&self) -> (f64, f64) {
+        self.unit_vertices()
+            .iter()
+            .fold((0.0, 0.0), |(x, y), p| (p.0.abs().max(x), p.1.abs().max(y)))
+    }
+
+    // Size of the outline that fits a label box of the given size. Ellipses take sqrt(2) times
+    // the box, polygons other than boxes leave room for their slanted sides.
+    pub fn fit(&self, (width, height): (f64, f64)) -> (f64, f64) {
+        if self.is_ellipse() {
+            return (width * SQRT_2, height * SQRT_2);
+        }
+        if self.is_box() {
+            return (width, height);
+        }
+        let scale = if self.star {
+            1.0 / (STAR_INNER * (PI / 5.0).cos())
+        } else {
+            1.0 / (PI / self.sides as f64).cos()
+        };
+        let (x, y) = self.unit_extent();
+        (2.0 * x * width * scale, 2.0 * y * height * scale)
+    }
+
+    // Points the outer peripheries add to the width and height.
+    pub fn periphery_margin(&self) -> f64 {
+        2.0 * PERIPHERY_GAP * self.peripheries.saturating_sub(1) as f64
+    }
+
+    // Distance from the center of a node of the given size to its outline in the direction
+    // (dx, dy), which must not be zero.
+    pub fn boundary(&self, (width, height): (f64, f64), (dx, dy): (f64, f64)) -> f64 {
+        let length = dx.hypot(dy);
+        let (ux, uy) = (dx / length, dy / length);
+        if self.is_ellipse() {
+            let (a, b) = (width / 2.0, height / 2.0);
+            return 1.0 / ((ux / a).powi(2) + (uy / b).powi(2)).sqrt();
+        }
+        let vertices = self.vertices((width, height));
+        // The nearest side the ray from the center crosses.
+        let cross = |a: Point, b: Point| a.0 * b.1 - a.1 * b.0;
+        let mut nearest = f64::INFINITY;
+        for (i, &a) in vertices.iter().enumerate() {
+            let b = vertices[(i + 1) % vertices.len()];
+            let side = (b.0 - a.0, b.1 - a.1);
+            let denominator = cross((ux, uy), side);
+            if denominator.abs() < 1e-12 {
+                continue;
+            }
+            let t = cross(a, side) / denominator;
+            let s = cross(a, (ux, uy)) / denominator;
+            if t > 0.0 && (-1e-9..=1.0 + 1e-9).contains(&s) {
+                nearest = nearest.min(t);
+            }
+        }
+        nearest
+    }
+
+    // A convex polygon around the outline of a node of the given size centered at the origin,
+    // counterclockwise. Ellipses are replaced by the octagon around them, and concave outlines
+    // such as stars by their convex hull.
+    pub fn polygon(&self, (width, height): (f64, f64)) -> Vec<Point> {
+        let (a, b) = (width / 2.0, height / 2.0);
+        if self.is_ellipse() {
+            let half = PI / ELLIPSE_SIDES as f64;
+            return (0..ELLIPSE_SIDES)
+                .map(|i| {
+                    let angle = half + 2.0 * half * i as f64;
+                    (a * angle.cos() / half.cos(), b * angle.sin() / half.cos())
+                })
+                .collect();
+        }
+        convex_hull(self.vertices((width, height)))
+    }
+
+    // Corners of the outline of a polygon node of the given size centered at the origin, as it
+    // is drawn.
+    pub fn vertices(&self, (width, height): (f64, f64)) -> Vec<Point> {
+        let (x, y) = self.unit_extent();
+        self.unit_vertices()
+            .iter()
+            .map(|p| (p.0 * width / (2.0 * x), p.1 * height / (2.0 * y)))
+            .collect()
+    }
+}
+
+#[test]
+fn test_polygon() {
+    let close = |a: Point, b: Point| (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9;
+    let rect = Shape::new(4).polygon((4.0, 2.0));
+    assert_eq!(rect.len(), 4);
+    for corner in [(2.0, 1.0), (-2.0, 1.0), (-2.0, -1.0), (2.0, -1.0)] {
+        assert!(rect.iter().any(|&p| close(p, corner)));
+    }
+    // The octagon around an ellipse touches it at the ends of its axes.
+    let octagon = Shape::new(1).polygon((4.0, 2.0));
+    assert_eq!(octagon.len(), 8);
+    assert!(close(octagon[0], (2.0, 2.0 * (PI / 8.0).tan() / 2.0)));
+    let star = Shape {
+        star: true,
+        ..Shape::new(10)
+    };
+    assert_eq!(star.polygon((40.0, 40.0)).len(), 5);
+}
+
+fn number(node: &Node, attr: &str, default: f64) -> f64 {
+    node.attrs
+        .get(attr)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+// The shape of a node from shape, peripheries, orientation and regular. Only shape=polygon reads
+// sides, skew and distortion. Records, HTML-like and unknown shapes are boxes.
+pub fn shape_of(node: &Node) -> Shape {
+    let name = node.attrs.get("shape").unwrap_or("ellipse").to_lowercase();
+    let mut shape = match name.as_str() {
+        "ellipse" | "oval" => Shape::new(1),
+        "circle" | "mcircle" => Shape {
+            regular: true,
+            ..Shape::new(1)
+        },
+        "doublecircle" => Shape {
+            regular: true,
+            peripheries: 2,
+            ..Shape::new(1)
+        },
+        "point" => Shape {
+            regular: true,
+            point: true,
+            ..Shape::new(1)
+        },
+        "egg" => Shape {
+            distortion: -0.3,
+            ..Shape::new(1)
+        },
+        "triangle" => Shape::new(3),
+        "invtriangle" => Shape {
+            orientation: 180.0,
+            ..Shape::new(3)
+        },
+        "diamond" | "mdiamond" => Shape {
+            orientation: 45.0,
+            ..Shape::new(4)
+        },
+        "square" | "msquare" => Shape {
+            regular: true,
+            ..Shape::new(4)
+        },
+        "trapezium" => Shape {
+            distortion: -0.4,
+            ..Shape::new(4)
+        },
+        "invtrapezium" => Shape {
+            orientation: 180.0,
+            distortion: -0.4,
+            ..Shape::new(4)
+        },
+        "parallelogram" => Shape {
+            skew: 0.6,
+            ..Shape::new(4)
+        },
+        "house" => Shape {
+            distortion: -0.64,
+            ..Shape::new(5)
+        },
+        "invhouse" => Shape {
+            orientation: 180.0,
+            distortion: -0.64,
+            ..Shape::new(5)
+        },
+        "pentagon" => Shape::new(5),
+        "hexagon" => Shape::new(6),
+        "septagon" => Shape::new(7),
+        "octagon" => Shape::new(8),
+        "doubleoctagon" => Shape {
+            peripheries: 2,
+            ..Shape::new(8)
+        },
+        "tripleoctagon" => Shape {
+            peripheries: 3,
+            ..Shape::new(8)
+        },
+        "star" => Shape {
+            star: true,
+            ..Shape::new(10)
+        },
+        "plaintext" | "plain" | "none" => Shape {
+            peripheries: 0,
+            ..Shape::new(4)
+        },
+        "polygon" => Shape {
+            sides: (number(node, "sides", 4.0) as usize).max(3),
+            skew: number(node, "skew", 0.0),
+            distortion: number(node, "distortion", 0.0),
+            ..Shape::new(4)
+        },
+        _ => Shape::new(4),
+    };
+    if !shape.point {
+        shape.orientation += number(node, "orientation", 0.0);
+    }
+    shape.regular |= node.attrs.get("regular") == Some("true");
+    if let Some(peripheries) = node.attrs.get("peripheries").and_then(|p| p.parse().ok()) {
+        shape.peripheries = peripheries;
+    }
+    shape
+}
+
+#[test]
+fn test_shape_of() {
+    let graph = from_dot(
+        r#"graph {
+    a
+    b [shape = Diamond]
+    c [shape = polygon, sides = 5, skew = 0.2, peripheries = 3]
+    d [shape = note]
+}"#,
+    );
+    let shapes: Vec<Shape> = graph.nodes.iter().map(shape_of).collect();
+    assert!(shapes[0].is_ellipse());
+    assert_eq!(shapes[1].orientation, 45.0);
+    assert_eq!((shapes[2].sides, shapes[2].skew), (5, 0.2));
+    assert_eq!(shapes[2].periphery_margin(), 16.0);
+    assert!(shapes[3].is_box());
+}
+
+#[test]
+fn test_boundary() {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    let ellipse = Shape::new(1);
+    assert!(close(ellipse.boundary((54.0, 36.0), (1.0, 0.0)), 27.0));
+    assert!(close(ellipse.boundary((54.0, 36.0), (0.0, -2.0)), 18.0));
+    let rect = Shape::new(4);
+    assert!(close(
+        rect.boundary((54.0, 36.0), (1.0, 1.0)),
+        18.0 * SQRT_2
+    ));
+    // A diamond is clipped halfway to the corner of its box along the diagonal.
+    let diamond = Shape {
+        orientation: 45.0,
+        ..Shape::new(4)
+    };
+    assert!(close(diamond.boundary((40.0, 40.0), (1.0, 0.0)), 20.0));
+    assert!(close(
+        diamond.boundary((40.0, 40.0), (1.0, 1.0)),
+        10.0 * SQRT_2
+    ));
+    // The point of a triangle is at the top, its base at the bottom.
+    let triangle = Shape::new(3);
+    assert!(close(triangle.boundary((40.0, 40.0), (0.0, 1.0)), 20.0));
+    assert!(close(triangle.boundary((40.0, 40.0), (0.0, -1.0)), 10.0));
+    assert!(triangle.boundary((40.0, 40.0), (1.0, 0.0)) < 20.0);
+}
+
+#[test]
+fn test_fit() {
+    assert_eq!(Shape::new(4).fit((20.0, 10.0)), (20.0, 10.0));
+    let (width, height) = Shape::new(1).fit((20.0, 10.0));
+    assert!((width - 20.0 * SQRT_2).abs() < 1e-9 && (height - 10.0 * SQRT_2).abs() < 1e-9);
+    let (width, height) = Shape::new(6).fit((20.0, 10.0));
+    assert!(width > 20.0 && height > 10.0);
+}
@@ -0,0 +1,398 @@
+use crate::color::Rgba;
+use crate::draw::{self, Canvas, Dash, Object, Op, Pen};
+use crate::layout::{format_number, Point};
+use crate::semantic::{Attrs, Graph};
+use crate::text::Justification;
+use std::fmt::Write;
+
+#[cfg(test)]
+use crate::layout::{self, Engine};
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Escapes text for XML. Dashes are escaped too, so that names such as "a--b" can go in comments.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            '-' => escaped.push_str("&#45;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(escape("a->b"), "a&#45;&gt;b");
+    assert_eq!(
+        escape("<\"x\" & 'y'>"),
+        "&lt;&quot;x&quot; &amp; &#39;y&#39;&gt;"
+    );
+}
+
+// A paint attribute such as fill="#ff0000" with the opacity of translucent colors, or "none"
+// for transparent ones.
+fn paint(name: &str, color: Rgba) -> String {
+    match color.a {
+        0 => format!("{}=\"none\"", name),
+        255 => format!("{}=\"{}\"", name, color_name(color)),
+        a => format!(
+            "{}=\"{}\" {}-opacity=\"{}\"",
+            name,
+            color_name(Rgba { a: 255, ..color }),
+            name,
+            format_number(a as f64 / 255.0, 6)
+        ),
+    }
+}
+
+fn color_name(color: Rgba) -> String {
+    match (color.r, color.g, color.b) {
+        (0, 0, 0) => "black".to_string(),
+        (255, 255, 255) => "white".to_string(),
+        _ => color.to_string(),
+    }
+}
+
+// The generic family that follows a PostScript font name, as browsers rarely have the fonts.
+fn font_family(font: &str) -> String {
+    let family = font.split('-').next().unwrap_or(font);
+    match family {
+        "Times" => "Times,serif".to_string(),
+        "Helvetica" | "Arial" => "Helvetica,sans-Serif".to_string(),
+        "Courier" => "Courier,monospace".to_string(),
+        _ => escape(family),
+    }
+}
+
+#[test]
+fn test_font_family() {
+    assert_eq!(font_family("Times-Roman"), "Times,serif");
+    assert_eq!(font_family("Helvetica-BoldOblique"), "Helvetica,sans-Serif");
+    assert_eq!(font_family("DejaVu Sans"), "DejaVu Sans");
+}
+
+// Writes SVG elements for the drawing operations, with y going down the page.
+struct Writer {
+    canvas: Canvas,
+    svg: String,
+}
+
+impl Writer {
+    fn point(&self, (x, y): Point) -> String {
+        format!(
+            "{},{}",
+            format_number(x - self.canvas.bb.0, 2),
+            format_number(-(y - self.canvas.bb.1), 2)
+        )
+    }
+
+    fn points(&self, points: &[Point]) -> String {
+        let points: Vec<String> = points.iter().map(|&p| self.point(p)).collect();
+        points.join(" ")
+    }
+
+    fn stroke(&self, pen: &Pen) -> String {
+        let mut stroke = paint("stroke", pen.color);
+        if pen.width != 1.0 {
+            write!(stroke, " stroke-width=\"{}\"", format_number(pen.width, 2)).unwrap();
+        }
+        match pen.dash {
+            Dash::Solid => {}
+            Dash::Dashed => stroke.push_str(" stroke-dasharray=\"5,2\""),
+            Dash::Dotted => stroke.push_str(" stroke-dasharray=\"1,5\""),
+        }
+        stroke
+    }
+
+    fn fill(&self, pen: &Pen, filled: bool) -> String {
+        if filled {
+            paint("fill", pen.fill)
+        } else {
+            "fill=\"none\"".to_string()
+        }
+    }
+
+    fn text(&mut self, pen: &Pen, pos: Point, justification: Justification, text: &str) {
+        let anchor = match justification {
+            Justification::Left => "start",
+            Justification::Center => "middle",
+            Justification::Right => "end",
+        };
+        let (x, y) = (pos.0 - self.canvas.bb.0, -(pos.1 - self.canvas.bb.1));
+        let mut element = format!(
+            "<text text-anchor=\"{}\" x=\"{}\" y=\"{}\" font-family=\"{}\"",
+            anchor,
+            format_number(x, 2),
+            format_number(y, 2),
+            font_family(&pen.font)
+        );
+        if pen.flags & draw::BOLD != 0 || pen.font.contains("Bold") {
+            element.push_str(" font-weight=\"bold\"");
+        }
+        if pen.flags & draw::ITALIC != 0
+            || pen.font.contains("Italic")
+            || pen.font.contains("Oblique")
+        {
+            element.push_str(" font-style=\"italic\"");
+        }
+        let decorations: Vec<&str> = [
+            (draw::UNDERLINE, "underline"),
+            (draw::OVERLINE, "overline"),
+            (draw::STRIKE, "line-through"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| pen.flags & flag != 0)
+        .map(|(_, decoration)| decoration)
+        .collect();
+        if !decorations.is_empty() {
+            write!(element, " text-decoration=\"{}\"", decorations.join(" ")).unwrap();
+        }
+        if pen.flags & draw::SUPERSCRIPT != 0 {
+            element.push_str(" baseline-shift=\"super\"");
+        } else if pen.flags & draw::SUBSCRIPT != 0 {
+            element.push_str(" baseline-shift=\"sub\"");
+        }
+        write!(element, " font-size=\"{:.2}\"", pen.font_size).unwrap();
+        // Black is the default fill of text.
+        if pen.color != draw::BLACK {
+            write!(element, " {}", paint("fill", pen.color)).unwrap();
+        }
+        writeln!(self.svg, "{}>{}</text>", element, escape(text)).unwrap();
+    }
+
+    // Draws one xdot attribute, which starts with the default pen.
+    fn ops(&mut self, ops: &[Op]) {
+        let mut pen = Pen::default();
+        for op in ops {
+            if pen.set(op) {
+                continue;
+            }
+            let element = match op {
+                Op::Ellipse {
+                    filled,
+                    center,
+                    radii,
+                } => {
+                    let (x, y) = (center.0 - self.canvas.bb.0, -(center.1 - self.canvas.bb.1));
+                    format!(
+                        "<ellipse {} {} cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\"/>",
+                        self.fill(&pen, *filled),
+                        self.stroke(&pen),
+                        format_number(x, 2),
+                        format_number(y, 2),
+                        format_number(radii.0, 2),
+                        format_number(radii.1, 2)
+                    )
+                }
+                Op::Polygon { filled, points } => {
+                    // SVG closes polygons itself, but Graphviz repeats the first point.
+                    let closed: Vec<Point> = points.iter().chain(points.first()).copied().collect();
+                    format!(
+                        "<polygon {} {} points=\"{}\"/>",
+                        self.fill(&pen, *filled),
+                        self.stroke(&pen),
+                        self.points(&closed)
+                    )
+                }
+                Op::Polyline(points) => format!(
+                    "<polyline fill=\"none\" {} points=\"{}\"/>",
+                    self.stroke(&pen),
+                    self.points(points)
+                ),
+                Op::Bezier { filled, points } => {
+                    let Some((first, rest)) = points.split_first() else {
+                        continue;
+                    };
+                    format!(
+                        "<path {} {} d=\"M{}C{}\"/>",
+                        self.fill(&pen, *filled),
+                        self.stroke(&pen),
+                        self.point(*first),
+                        self.points(rest)
+                    )
+                }
+                Op::Text {
+                    pos,
+                    justification,
+                    text,
+                    ..
+                } => {
+                    self.text(&pen, *pos, *justification, text);
+                    continue;
+                }
+                _ => continue,
+            };
+            writeln!(self.svg, "{}", element).unwrap();
+        }
+    }
+}
+
+// The title of an edge, as in "a:p->b".
+fn edge_title(graph: &Graph, edge: usize) -> String {
+    let edge = &graph.edges[edge];
+    let end = |node: usize, port: &str| match edge.attrs.get(port) {
+        Some(port) if !port.is_empty() => format!("{}:{}", graph.nodes[node].name, port),
+        _ => graph.nodes[node].name.clone(),
+    };
+    format!(
+        "{}{}{}",
+        end(edge.tail, "tailport"),
+        if graph.directed { "->" } else { "--" },
+        end(edge.head, "headport")
+    )
+}
+
+// Renders a laid-out graph as SVG, with a group of elements for each cluster, node and edge.
+// The groups carry the id and class attributes, and a link when the object has a URL or href.
+pub fn render(graph: &Graph) -> Result<String, String> {
+    let canvas = Canvas::new(graph)?;
+    let (width, height) = canvas.size();
+    let mut writer = Writer {
+        canvas,
+        svg: String::new(),
+    };
+    let svg = &mut writer.svg;
+    svg.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    svg.push_str("<!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 1.1//EN\"\n");
+    svg.push_str(" \"http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd\">\n");
+    writeln!(svg, "<!-- Title: {} Pages: 1 -->", escape(&graph.name)).unwrap();
+    writeln!(
+        svg,
+        "<svg width=\"{:.0}pt\" height=\"{:.0}pt\"\n viewBox=\"0.00 0.00 {:.2} {:.2}\" \
+         xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\">",
+        width, height, width, height
+    )
+    .unwrap();
+    // The drawing is scaled around the top left corner of the graph, pad points into the page.
+    let scale = canvas.scale;
+    writeln!(
+        svg,
+        "<g id=\"graph0\" class=\"graph\" transform=\"scale({s} {s}) rotate(0) translate({} {})\">",
+        format_number(canvas.pad / scale, 2),
+        format_number((height - canvas.pad) / scale, 2),
+        s = format_number(scale, 6)
+    )
+    .unwrap();
+    writeln!(svg, "<title>{}</title>", escape(&graph.name)).unwrap();
+
+    let mut clusters = 0;
+    for (object, ops) in draw::objects(graph)? {
+        let (attrs, kind, title, id): (&Attrs, _, _, _) = match object {
+            Object::Graph => {
+                for (_, ops) in &ops {
+                    writer.ops(ops);
+                }
+                continue;
+            }
+            Object::Cluster(i) => {
+                clusters += 1;
+                let cluster = &graph.subgraphs[i];
+                (
+                    &cluster.attrs,
+                    "cluster",
+                    cluster.name.clone(),
+                    format!("clust{}", clusters),
+                )
+            }
+            Object::Node(i) => {
+                let node = &graph.nodes[i];
+                (
+                    &node.attrs,
+                    "node",
+                    node.name.clone(),
+                    format!("node{}", i + 1),
+                )
+            }
+            Object::Edge(i) => (
+                &graph.edges[i].attrs,
+                "edge",
+                edge_title(graph, i),
+                format!("edge{}", i + 1),
+            ),
+        };
+        let id = attrs.get("id").map(str::to_string).unwrap_or(id);
+        let class = match attrs.get("class") {
+            Some(class) if !class.is_empty() => format!("{} {}", kind, class),
+            _ => kind.to_string(),
+        };
+        let svg = &mut writer.svg;
+        writeln!(svg, "<!-- {} -->", escape(&title)).unwrap();
+        writeln!(
+            svg,
+            "<g id=\"{}\" class=\"{}\">",
+            escape(&id),
+            escape(&class)
+        )
+        .unwrap();
+        writeln!(svg, "<title>{}</title>", escape(&title)).unwrap();
+        let url = attrs.get("href").or(attrs.get("URL"));
+        let tooltip = attrs.get("tooltip");
+        let link = url.is_some() || tooltip.is_some();
+        if link {
+            let mut a = String::from("<a");
+            if let Some(url) = url {
+                write!(a, " xlink:href=\"{}\"", escape(url)).unwrap();
+            }
+            // Browsers show the title when hovering, which defaults to the label.
+            let label = tooltip.or(attrs.get("label")).unwrap_or(&title);
+            write!(a, " xlink:title=\"{}\"", escape(label)).unwrap();
+            if let Some(target) = attrs.get("target") {
+                write!(a, " target=\"{}\"", escape(target)).unwrap();
+            }
+            writeln!(svg, "<g id=\"a_{}\">{}>", escape(&id), a).unwrap();
+        }
+        for (_, ops) in &ops {
+            writer.ops(ops);
+        }
+        if link {
+            writer.svg.push_str("</a>\n</g>\n");
+        }
+        writer.svg.push_str("</g>\n");
+    }
+    writer.svg.push_str("</g>\n</svg>\n");
+    Ok(writer.svg)
+}
+
+#[test]
+fn test_render() {
+    let graph = from_dot(
+        r##"digraph G {
+    a [pos = "0,0!", style = "filled,dashed", fillcolor = "#ff000080", URL = "x.html"]
+    b [pos = "2,0!", shape = box, class = "big", id = "bee", fontcolor = red]
+    a -> b [label = "a<b"]
+}"##,
+    );
+    let laid_out = layout::apply(&graph, Engine::Neato).unwrap();
+    let svg = render(&laid_out).unwrap();
+    assert!(svg.starts_with("<?xml version=\"1.0\""));
+    assert!(
+        svg.contains("<svg width=\"206pt\" height=\"44pt\"\n viewBox=\"0.00 0.00 206.00 44.00\"")
+    );
+    assert!(svg.contains("transform=\"scale(1 1) rotate(0) translate(4 40)\""));
+    assert!(svg.contains(
+        "<polygon fill=\"white\" stroke=\"none\" points=\"0,0 198,0 198,-36 0,-36 0,0\"/>"
+    ));
+    assert!(svg.contains(
+        "<g id=\"node1\" class=\"node\">\n<title>a</title>\n\
+         <g id=\"a_node1\"><a xlink:href=\"x.html\" xlink:title=\"a\">\n\
+         <ellipse fill=\"#ff0000\" fill-opacity=\"0.501961\" stroke=\"black\" \
+         stroke-dasharray=\"5,2\" cx=\"27\" cy=\"-18\" rx=\"27\" ry=\"18\"/>\n"
+    ));
+    assert!(svg.contains("<g id=\"bee\" class=\"node big\">"));
+    assert!(svg.contains(
+        "<text text-anchor=\"middle\" x=\"27\" y=\"-14.3\" font-family=\"Times,serif\" \
+         font-size=\"14.00\">a</text>"
+    ));
+    assert!(svg.contains("font-size=\"14.00\" fill=\"#ff0000\">b</text>"));
+    assert!(svg.contains("<!-- a&#45;&gt;b -->\n<g id=\"edge1\" class=\"edge\">"));
+    assert!(svg.contains(">a&lt;b</text>"));
+    assert!(svg.contains("<path fill=\"none\" stroke=\"black\" d=\"M"));
+    assert!(svg.ends_with("</g>\n</svg>\n"));
+}
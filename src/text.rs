@@ -0,0 +1,498 @@
+use crate::html;
+use crate::semantic::{Attrs, Edge, Graph, Node, Subgraph};
+use log::warn;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+#[cfg(test)]
+use crate::{ast, tokenize::tokenize};
+
+// Advance widths of the printable ASCII characters from ' ' to '~' in 1/1000 em, taken from the
+// Adobe Core 14 AFM files. Italic faces are measured with the upright widths.
+const TIMES_ROMAN: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 180, // !"#$%&'
+    333, 333, 500, 564, 250, 333, 250, 278, // ()*+,-./
+    500, 500, 500, 500, 500, 500, 500, 500, // 01234567
+    500, 500, 278, 278, 564, 564, 564, 444, // 89:;<=>?
+    921, 722, 667, 667, 722, 611, 556, 722, // @ABCDEFG
+    722, 333, 389, 722, 611, 889, 722, 722, // HIJKLMNO
+    556, 722, 667, 556, 611, 722, 722, 944, // PQRSTUVW
+    722, 722, 611, 333, 278, 333, 469, 500, // XYZ[\]^_
+    333, 444, 500, 444, 500, 444, 333, 500, // `abcdefg
+    500, 278, 278, 500, 278, 778, 500, 500, // hijklmno
+    500, 500, 333, 389, 278, 500, 500, 722, // pqrstuvw
+    500, 500, 444, 480, 200, 480, 541, // xyz{|}~
+];
+
+const TIMES_BOLD: [u16; 95] = [
+    250, 333, 555, 500, 500, 1000, 833, 278, // !"#$%&'
+    333, 333, 500, 570, 250, 333, 250, 278, // ()*+,-./
+    500, 500, 500, 500, 500, 500, 500, 500, // 01234567
+    500, 500, 333, 333, 570, 570, 570, 500, // 89:;<=>?
+    930, 722, 667, 722, 722, 667, 611, 778, // @ABCDEFG
+    778, 389, 500, 778, 667, 944, 722, 778, // HIJKLMNO
+    611, 778, 722, 556, 667, 722, 722, 1000, // PQRSTUVW
+    722, 722, 667, 333, 278, 333, 581, 500, // XYZ[\]^_
+    333, 500, 556, 444, 556, 444, 333, 500, // `abcdefg
+    556, 278, 333, 556, 278, 833, 556, 500, // hijklmno
+    556, 556, 444, 389, 333, 556, 500, 722, // pqrstuvw
+    500, 500, 444, 394, 220, 394, 520, // xyz{|}~
+];
+
+const HELVETICA: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, // !"#$%&'
+    333, 333, 389, 584, 278, 333, 278, 278, // ()*+,-./
+    556, 556, 556, 556, 556, 556, 556, 556, // 01234567
+    556, 556, 278, 278, 584, 584, 584, 556, // 89:;<=>?
+    1015, 667, 667, 722, 722, 667, 611, 778, // @ABCDEFG
+    722, 278, 500, 667, 556, 833, 722, 778, // HIJKLMNO
+    667, 778, 722, 667, 611, 722, 667, 944, // PQRSTUVW
+    667, 667, 611, 278, 278, 278, 469, 556, // XYZ[\]^_
+    333, 556, 556, 500, 556, 556, 278, 556, // `abcdefg
+    556, 222, 222, 500, 222, 833, 556, 556, // hijklmno
+    556, 556, 333, 500, 278, 556, 500, 722, // pqrstuvw
+    500, 500, 500, 334, 260, 334, 584, // xyz{|}~
+];
+
+const HELVETICA_BOLD: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, // !"#$%&'
+    333, 333, 389, 584, 278, 333, 278, 278, // ()*+,-./
+    556, 556, 556, 556, 556, 556, 556, 556, // 01234567
+    556, 556, 333, 333, 584, 584, 584, 611, // 89:;<=>?
+    975, 722, 722, 722, 722, 667, 611, 778, // @ABCDEFG
+    722, 278, 556, 722, 611, 833, 722, 778, // HIJKLMNO
+    667, 778, 722, 667, 611, 722, 667, 944, // PQRSTUVW
+    667, 667, 611, 333, 278, 333, 584, 556, // XYZ[\]^_
+    333, 556, 611, 556, 611, 556, 333, 611, // `abcdefg
+    611, 278, 278, 556, 278, 889, 611, 611, // hijklmno
+    611, 611, 389, 556, 333, 611, 556, 778, // pqrstuvw
+    556, 556, 500, 389, 280, 389, 584, // xyz{|}~
+];
+
+const COURIER: [u16; 95] = [600; 95];
+
+// Graphviz puts 1.2 times the font size between the baselines of two lines.
+pub const LINE_SPACING: f64 = 1.2;
+
+pub const DEFAULT_FONTNAME: &str = "Times-Roman";
+pub const DEFAULT_FONTSIZE: f64 = 14.0;
+
+#[derive(Debug, PartialEq)]
+pub enum Font {
+    Afm(&'static [u16; 95]),
+    TrueType(TrueType),
+}
+
+// The metrics of a TrueType or OpenType file, read once when it is loaded.
+#[derive(Debug, PartialEq)]
+pub struct TrueType {
+    units_per_em: u16,
+    // Advance widths of the characters the font maps, in font units.
+    advances: HashMap<char, u16>,
+    // AFM widths of the closest standard font, for characters the font does not map.
+    fallback: &'static [u16; 95],
+}
+
+impl TrueType {
+    fn parse(data: &[u8], fallback: &'static [u16; 95]) -> Option<TrueType> {
+        let face = ttf_parser::Face::parse(data, 0).ok()?;
+        let mut advances = HashMap::new();
+        for subtable in face.tables().cmap.iter().flat_map(|cmap| cmap.subtables) {
+            if !subtable.is_unicode() {
+                continue;
+            }
+            subtable.codepoints(|code_point| {
+                let advance = char::from_u32(code_point)
+                    .and_then(|c| Some((c, face.glyph_hor_advance(face.glyph_index(c)?)?)));
+                if let Some((c, advance)) = advance {
+                    advances.insert(c, advance);
+                }
+            });
+        }
+        Some(TrueType {
+            units_per_em: face.units_per_em(),
+            advances,
+            fallback,
+        })
+    }
+}
+
+// A fontname and fontpath.
+type FontKey = (String, Option<String>);
+
+// Fonts loaded so far, so that each is read and parsed once a run.
+static FONTS: LazyLock<Mutex<HashMap<FontKey, Arc<Font>>>> = LazyLock::new(Default::default);
+
+// Picks the standard font closest to a font name such as "Helvetica-Bold" or "Arial".
+fn afm_widths(fontname: &str) -> &'static [u16; 95] {
+    let name = fontname.to_lowercase();
+    let bold = name.contains("bold");
+    if name.contains("courier") || name.contains("mono") {
+        &COURIER
+    } else if ["helvetica", "arial", "sans"]
+        .iter()
+        .any(|family| name.contains(family))
+    {
+        if bold {
+            &HELVETICA_BOLD
+        } else {
+            &HELVETICA
+        }
+    } else if bold {
+        &TIMES_BOLD
+    } else {
+        &TIMES_ROMAN
+    }
+}
+
+// Width of a character in thousandths of the font size. Characters outside ASCII are assumed to
+// be as wide as 'n'.
+fn afm_width(widths: &[u16; 95], c: char) -> u16 {
+    match c {
+        ' '..='~' => widths[c as usize - ' ' as usize],
+        _ => widths[(b'n' - b' ') as usize],
+    }
+}
+
+// Where a font file may be: the fontname itself, and the fontname in each directory of fontpath.
+pub fn font_files(fontname: &str, fontpath: Option<&str>) -> Vec<PathBuf> {
+    let mut candidates = vec![Path::new(fontname).to_path_buf()];
+    for dir in fontpath.unwrap_or_default().split(':') {
+        if !dir.is_empty() {
+            candidates.push(Path::new(dir).join(fontname));
+        }
+    }
+    candidates
+}
+
+impl Font {
+    // A fontname ending in .ttf or .otf is read from the file, looked up in the directories of
+    // fontpath when it is relative. Other names and unreadable files use the AFM metrics.
+    pub fn load(fontname: &str, fontpath: Option<&str>) -> Arc<Font> {
+        let key = (fontname.to_string(), fontpath.map(str::to_string));
+        let mut fonts = FONTS.lock().unwrap();
+        fonts
+            .entry(key)
+            .or_insert_with(|| Arc::new(Font::read(fontname, fontpath)))
+            .clone()
+    }
+
+    fn read(fontname: &str, fontpath: Option<&str>) -> Font {
+        let lower = fontname.to_lowercase();
+        if !lower.ends_with(".ttf") && !lower.ends_with(".otf") {
+            return Font::Afm(afm_widths(fontname));
+        }
+        for path in font_files(fontname, fontpath) {
+            if let Ok(data) = std::fs::read(&path) {
+                if let Some(font) = TrueType::parse(&data, afm_widths(fontname)) {
+                    return Font::TrueType(font);
+                }
+                warn!("{} is not a font file", path.display());
+            }
+        }
+        warn!("Cannot load font {}, using standard metrics", fontname);
+        Font::Afm(afm_widths(fontname))
+    }
+
+    // Width of a single line of text in points.
+    pub fn width(&self, text: &str, fontsize: f64) -> f64 {
+        match self {
+            Font::Afm(widths) => {
+                let units: u32 = text.chars().map(|c| afm_width(widths, c) as u32).sum();
+                units as f64 * fontsize / 1000.0
+            }
+            // Characters missing from the font take the width they have in the fallback font.
+            Font::TrueType(font) => text
+                .chars()
+                .map(|c| match font.advances.get(&c) {
+                    Some(&advance) => advance as f64 * fontsize / font.units_per_em as f64,
+                    None => afm_width(font.fallback, c) as f64 * fontsize / 1000.0,
+                })
+                .sum(),
+        }
+    }
+}
+
+#[test]
+fn test_font_width() {
+    assert_eq!(*Font::load("Times-Roman", None), Font::Afm(&TIMES_ROMAN));
+    assert_eq!(*Font::load("Arial Bold", None), Font::Afm(&HELVETICA_BOLD));
+    assert_eq!(*Font::load("Courier New", None), Font::Afm(&COURIER));
+    assert_eq!(
+        *Font::load("missing-Bold.ttf", Some("/nonexistent")),
+        Font::Afm(&TIMES_BOLD)
+    );
+    // Loading again gives the same font without reading it again.
+    assert!(Arc::ptr_eq(
+        &Font::load("missing-Bold.ttf", Some("/nonexistent")),
+        &Font::load("missing-Bold.ttf", Some("/nonexistent"))
+    ));
+
+    let times = Font::load(DEFAULT_FONTNAME, None);
+    assert!((times.width("Ab", 10.0) - 12.22).abs() < 1e-9);
+    assert!((times.width("né", 10.0) - 10.0).abs() < 1e-9);
+    assert_eq!(Font::load("Courier", None).width("abc", 10.0), 18.0);
+
+    let font = Font::TrueType(TrueType {
+        units_per_em: 2048,
+        advances: HashMap::from([('a', 1024)]),
+        fallback: &COURIER,
+    });
+    assert_eq!(font.width("a", 10.0), 5.0);
+    assert_eq!(font.width("ab", 10.0), 11.0);
+    assert_eq!(font.width("a€", 10.0), 11.0);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Justification {
+    Left,
+    Center,
+    Right,
+}
+
+// Splits an escString label at \n, \l and \r, which end a centered, left or right justified
+// line. Text after the last of them forms a centered line. Other escapes are unescaped.
+pub fn split_lines(label: &str) -> Vec<(String, Justification)> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut chars = label.chars();
+    while let Some(c) = chars.next() {
+        let justification = match c {
+            '\\' => match chars.next() {
+                Some('n') => Justification::Center,
+                Some('l') => Justification::Left,
+                Some('r') => Justification::Right,
+                Some(c) => {
+                    line.push(c);
+                    continue;
+                }
+                None => break,
+            },
+            '\n' => Justification::Center,
+            _ => {
+                line.push(c);
+                continue;
+            }
+        };
+        lines.push((std::mem::take(&mut line), justification));
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push((line, Justification::Center));
+    }
+    lines
+}
+
+#[test]
+fn test_split_lines() {
+    assert_eq!(
+        split_lines("a\\lbb\\r\\\"c\\\"\nd"),
+        vec![
+            ("a".to_string(), Justification::Left),
+            ("bb".to_string(), Justification::Right),
+            ("\"c\"".to_string(), Justification::Center),
+            ("d".to_string(), Justification::Center),
+        ]
+    );
+    assert_eq!(
+        split_lines("a\\n"),
+        vec![("a".to_string(), Justification::Center)]
+    );
+    assert_eq!(
+        split_lines(""),
+        vec![(String::new(), Justification::Center)]
+    );
+}
+
+// Width and height in points of a possibly multi-line label.
+pub fn text_size(label: &str, font: &Font, fontsize: f64) -> (f64, f64) {
+    let lines = split_lines(label);
+    let width = lines
+        .iter()
+        .map(|(line, _)| font.width(line, fontsize))
+        .fold(0.0, f64::max);
+    (width, lines.len() as f64 * fontsize * LINE_SPACING)
+}
+
+// Expands \N in a label of a node to its name and \G to the name of the graph.
+pub fn node_text(graph: &Graph, node: &Node, label: &str) -> String {
+    label.replace("\\N", &node.name).replace("\\G", &graph.name)
+}
+
+// Expands \E in a label of an edge to the edge as in a->b, \T and \H to its tail and head and \G
+// to the name of the graph.
+pub fn edge_text(graph: &Graph, edge: &Edge, label: &str) -> String {
+    let (tail, head) = (&graph.nodes[edge.tail].name, &graph.nodes[edge.head].name);
+    let op = if graph.directed { "->" } else { "--" };
+    label
+        .replace("\\E", &format!("{}{}{}", tail, op, head))
+        .replace("\\T", tail)
+        .replace("\\H", head)
+        .replace("\\G", &graph.name)
+}
+
+// Measures the label of a node, with \N and \G expanded in text labels. Record labels have a
+// structure of their own and are not measured here.
+pub fn node_label_size(graph: &Graph, node: &Node) -> Option<(f64, f64)> {
+    let label = node.attrs.get("label").unwrap_or("\\N");
+    if node.attrs.is_html("label") {
+        return html_size(graph, &node.attrs, "label", "");
+    }
+    if matches!(node.attrs.get("shape"), Some("record") | Some("Mrecord")) {
+        return None;
+    }
+    let (fontname, fontsize) = font_of(&node.attrs, "");
+    Some(label_size(
+        graph,
+        fontname,
+        fontsize,
+        &node_text(graph, node, label),
+    ))
+}
+
+// Measures the text of a field of a record node in the font of the node. Empty fields take no
+// room, as in Graphviz.
+pub fn field_size(graph: &Graph, node: &Node, text: &str) -> (f64, f64) {
+    if text.is_empty() {
+        return (0.0, 0.0);
+    }
+    let (fontname, fontsize) = font_of(&node.attrs, "");
+    label_size(graph, fontname, fontsize, &node_text(graph, node, text))
+}
+
+// Measures the xlabel of a node, which is drawn outside of it.
+pub fn node_xlabel_size(graph: &Graph, node: &Node) -> Option<(f64, f64)> {
+    let label = node.attrs.get("xlabel").filter(|label| !label.is_empty())?;
+    if node.attrs.is_html("xlabel") {
+        return html_size(graph, &node.attrs, "xlabel", "");
+    }
+    let (fontname, fontsize) = font_of(&node.attrs, "");
+    Some(label_size(
+        graph,
+        fontname,
+        fontsize,
+        &node_text(graph, node, label),
+    ))
+}
+
+// Measures label, xlabel, headlabel or taillabel of an edge. Head and tail labels are in
+// labelfontname and labelfontsize, which default to fontname and fontsize.
+pub fn edge_label_size(graph: &Graph, edge: &Edge, attr: &str) -> Option<(f64, f64)> {
+    let label = edge.attrs.get(attr).filter(|label| !label.is_empty())?;
+    let prefix = if attr == "headlabel" || attr == "taillabel" {
+        "label"
+    } else {
+        ""
+    };
+    if edge.attrs.is_html(attr) {
+        return html_size(graph, &edge.attrs, attr, prefix);
+    }
+    let (fontname, fontsize) = font_of(&edge.attrs, prefix);
+    Some(label_size(
+        graph,
+        fontname,
+        fontsize,
+        &edge_text(graph, edge, label),
+    ))
+}
+
+// The fontname and fontsize given by attributes with the prefix, falling back on those without
+// it and then on the defaults.
+pub fn font_of<'a>(attrs: &'a Attrs, prefix: &str) -> (&'a str, f64) {
+    let get = |name: &str| {
+        attrs
+            .get(&format!("{}{}", prefix, name))
+            .or(attrs.get(name))
+    };
+    let fontname = get("fontname").unwrap_or(DEFAULT_FONTNAME);
+    let fontsize = get("fontsize")
+        .and_then(|fontsize| fontsize.parse().ok())
+        .unwrap_or(DEFAULT_FONTSIZE);
+    (fontname, fontsize)
+}
+
+// Measures a label in the given font, read from fontpath when it is a font file.
+fn label_size(graph: &Graph, fontname: &str, fontsize: f64, label: &str) -> (f64, f64) {
+    let font = Font::load(fontname, graph.attrs.get("fontpath"));
+    text_size(label, &font, fontsize)
+}
+
+// Measures an HTML label, or gives None when it is bad, which has already been warned about.
+fn html_size(graph: &Graph, attrs: &Attrs, attr: &str, prefix: &str) -> Option<(f64, f64)> {
+    let label = html::parse_html_label(attrs.get(attr)?).ok()?;
+    Some(label.size(html::base_font(graph, attrs, prefix)))
+}
+
+// Measures the label of a cluster, where \G stands for the name of the cluster in text labels.
+// Clusters have no label by default.
+pub fn cluster_label_size(graph: &Graph, cluster: &Subgraph) -> Option<(f64, f64)> {
+    subgraph_label_size(graph, &cluster.attrs, &cluster.name)
+}
+
+// Measures the label of the root graph in the same way.
+pub fn graph_label_size(graph: &Graph) -> Option<(f64, f64)> {
+    subgraph_label_size(graph, &graph.attrs, &graph.name)
+}
+
+fn subgraph_label_size(graph: &Graph, attrs: &Attrs, name: &str) -> Option<(f64, f64)> {
+    let label = attrs.get("label").filter(|label| !label.is_empty())?;
+    if attrs.is_html("label") {
+        return html_size(graph, attrs, "label", "");
+    }
+    let (fontname, fontsize) = font_of(attrs, "");
+    Some(label_size(
+        graph,
+        fontname,
+        fontsize,
+        &label.replace("\\G", name),
+    ))
+}
+
+#[test]
+fn test_node_label_size() {
+    let tokens = tokenize(
+        r#"graph G {
+    a
+    b [label = "\G\lxx", fontname = Courier, fontsize = 10]
+    c [shape = record]
+    d [label = <<b>d</b>>]
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let graph = Graph::from_ast(&graph);
+    let (width, height) = node_label_size(&graph, &graph.nodes[0]).unwrap();
+    assert!((width - 6.216).abs() < 1e-9);
+    assert!((height - 16.8).abs() < 1e-9);
+    assert_eq!(node_label_size(&graph, &graph.nodes[1]), Some((12.0, 24.0)));
+    assert_eq!(node_label_size(&graph, &graph.nodes[2]), None);
+    // HTML labels are measured with the bold metrics for <b>.
+    let (width, height) = node_label_size(&graph, &graph.nodes[3]).unwrap();
+    assert!((width - 7.784).abs() < 1e-9);
+    assert!((height - 16.8).abs() < 1e-9);
+}
+
+#[test]
+fn test_edge_label_size() {
+    let tokens = tokenize(
+        r#"digraph G {
+    edge [fontname = Courier, fontsize = 10]
+    a -> b [label = "\E", headlabel = "\H", labelfontsize = 20, xlabel = ""]
+    b [xlabel = "x\N", fontsize = 10]
+}"#
+        .to_string(),
+    );
+    let (graph, _) = ast::parse_graph(&tokens).unwrap();
+    let graph = Graph::from_ast(&graph);
+    let edge = &graph.edges[0];
+    assert_eq!(edge_text(&graph, edge, "\\T\\G"), "aG");
+    assert_eq!(edge_label_size(&graph, edge, "label"), Some((24.0, 12.0)));
+    assert_eq!(
+        edge_label_size(&graph, edge, "headlabel"),
+        Some((12.0, 24.0))
+    );
+    assert_eq!(edge_label_size(&graph, edge, "xlabel"), None);
+    assert_eq!(edge_label_size(&graph, edge, "taillabel"), None);
+    let (width, _) = node_xlabel_size(&graph, &graph.nodes[1]).unwrap();
+    assert!((width - 10.0).abs() < 1e-9);
+}
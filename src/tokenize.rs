@@ -1,13 +1,66 @@
+#[cfg(test)]
 macro_rules! vec_of_strings {
     ($($x:expr),*) => (vec![$($x.to_string()),*]);
 }
 
-fn raw_tokenize_dot(dot_str: String) -> Vec<String> {
+// Comments are skipped here, before HTML strings are recognised, so that a '<' in a comment does
+// not start one.
+pub fn tokenize(dot_str: String) -> Vec<String> {
     let mut tokens = Vec::new();
     let mut token = String::new();
     let mut in_quote = false;
+    // Nesting depth of '<' in an HTML string such as <<b>bold</b>>.
+    let mut html_depth = 0;
+    let mut in_cpp_comment = false;
+    let mut in_c_comment = false;
+    let mut line_start = true;
     let mut last_char = ' ';
-    for c in dot_str.chars() {
+    let mut chars = dot_str.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_cpp_comment {
+            if c == '\n' {
+                in_cpp_comment = false;
+                line_start = true;
+            }
+            continue;
+        }
+        if in_c_comment {
+            if c == '*' && chars.peek() == Some(&'/') {
+                chars.next();
+                in_c_comment = false;
+            }
+            continue;
+        }
+        if html_depth > 0 {
+            match c {
+                '<' => html_depth += 1,
+                '>' => html_depth -= 1,
+                _ => {}
+            }
+            token.push(c);
+            last_char = c;
+            continue;
+        }
+        if !in_quote {
+            match (c, chars.peek()) {
+                ('/', Some('/')) => in_cpp_comment = true,
+                ('/', Some('*')) => {
+                    chars.next();
+                    in_c_comment = true;
+                }
+                // Lines starting with '#' are output of the C preprocessor.
+                ('#', _) if line_start => in_cpp_comment = true,
+                _ => {}
+            }
+            if in_cpp_comment || in_c_comment {
+                if !token.is_empty() {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+                continue;
+            }
+        }
+        line_start = c == '\n' || (line_start && (c == ' ' || c == '\t'));
         match c {
             ' ' | '\t' | '\n' | ';' => {
                 if in_quote {
@@ -17,13 +70,38 @@ fn raw_tokenize_dot(dot_str: String) -> Vec<String> {
                         tokens.push(token.clone());
                         token.clear();
                     }
-                    // We need '\n' to parse C++ style comments
-                    if c == ';' || c == '\n' {
-                        let t = c.to_string();
-                        tokens.push(t);
+                    if c == ';' {
+                        tokens.push(c.to_string());
                     }
                 }
             }
+            '{' | '}' | '[' | ']' | '=' | ',' | ':' => {
+                if in_quote {
+                    token.push(c);
+                } else {
+                    if !token.is_empty() {
+                        tokens.push(token.clone());
+                        token.clear();
+                    }
+                    tokens.push(c.to_string());
+                }
+            }
+            // Edge operators end the token before them even without spaces, as in a->b.
+            '-' if !in_quote && matches!(chars.peek(), Some('>') | Some('-')) => {
+                if !token.is_empty() {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+                let mut op = c.to_string();
+                op.extend(chars.next());
+                tokens.push(op);
+                last_char = ' ';
+                continue;
+            }
+            '<' if !in_quote => {
+                html_depth = 1;
+                token.push(c);
+            }
             '"' => {
                 if last_char != '\\' {
                     in_quote = !in_quote;
@@ -43,7 +121,7 @@ fn raw_tokenize_dot(dot_str: String) -> Vec<String> {
 }
 
 #[test]
-fn test_raw_tokenize_dot() {
+fn test_tokenize() {
     let dot_str = r#"graph {
     a -- b;
     b -- c;
@@ -52,55 +130,97 @@ fn test_raw_tokenize_dot() {
     e -- c;
     e -- a;
 }"#;
-    let tokens = raw_tokenize_dot(dot_str.to_string());
+    let tokens = tokenize(dot_str.to_string());
     assert_eq!(
         tokens,
         vec_of_strings![
-            "graph", "{", "\n", "a", "--", "b", ";", "\n", "b", "--", "c", ";", "\n", "a", "--",
-            "c", ";", "\n", "d", "--", "c", ";", "\n", "e", "--", "c", ";", "\n", "e", "--", "a",
-            ";", "\n", "}"
+            "graph", "{", "a", "--", "b", ";", "b", "--", "c", ";", "a", "--", "c", ";", "d", "--",
+            "c", ";", "e", "--", "c", ";", "e", "--", "a", ";", "}"
         ]
     );
 }
 
-// Because newline characters are used to parse C++ style comments, we remove them here.
-fn remove_comments(tokens: Vec<String>) -> Vec<String> {
-    let mut new_tokens = Vec::new();
-    let mut in_cpp_comment = false;
-    let mut in_c_comment = false;
-    for token in tokens {
-        if in_cpp_comment {
-            if token == "\n" {
-                in_cpp_comment = false;
-            }
-            continue;
-        }
-        if in_c_comment {
-            if token == "*/" {
-                in_c_comment = false;
-            }
-            continue;
-        }
-        if token == "//" {
-            in_cpp_comment = true;
-            continue;
-        }
-        if token == "/*" {
-            in_c_comment = true;
-            continue;
-        }
-        new_tokens.push(token);
-    }
+#[test]
+fn test_tokenize_delimiters() {
+    let dot_str = r#"subgraph cluster_a {label="a, [b]"; a -> b [lhead=cluster_a,color=red]}"#;
+    let tokens = tokenize(dot_str.to_string());
+    assert_eq!(
+        tokens,
+        vec_of_strings![
+            "subgraph",
+            "cluster_a",
+            "{",
+            "label",
+            "=",
+            "\"a, [b]\"",
+            ";",
+            "a",
+            "->",
+            "b",
+            "[",
+            "lhead",
+            "=",
+            "cluster_a",
+            ",",
+            "color",
+            "=",
+            "red",
+            "]",
+            "}"
+        ]
+    );
+}
+
+#[test]
+fn test_tokenize_compact_edges() {
+    assert_eq!(
+        tokenize("digraph{a->b}".to_string()),
+        vec_of_strings!["digraph", "{", "a", "->", "b", "}"]
+    );
+    assert_eq!(
+        tokenize(r#"graph{a--b; c--"d-e"--f:p--1--2}"#.to_string()),
+        vec_of_strings![
+            "graph", "{", "a", "--", "b", ";", "c", "--", "\"d-e\"", "--", "f", ":", "p", "--",
+            "1", "--", "2", "}"
+        ]
+    );
+    assert_eq!(
+        tokenize("a -- -1".to_string()),
+        vec_of_strings!["a", "--", "-1"]
+    );
+}
 
-    new_tokens = new_tokens
-        .into_iter()
-        .filter(|t| t != &"\n".to_string())
-        .collect();
-    new_tokens
+#[test]
+fn test_tokenize_ports() {
+    let dot_str = r#"a:f0:n -> "b:c":f1"#;
+    let tokens = tokenize(dot_str.to_string());
+    assert_eq!(
+        tokens,
+        vec_of_strings!["a", ":", "f0", ":", "n", "->", "\"b:c\"", ":", "f1"]
+    );
+}
+
+#[test]
+fn test_tokenize_html() {
+    let dot_str = "a [label=<<b>x, y</b><br/>\"z\">]; b";
+    let tokens = tokenize(dot_str.to_string());
+    assert_eq!(
+        tokens,
+        vec_of_strings![
+            "a",
+            "[",
+            "label",
+            "=",
+            "<<b>x, y</b><br/>\"z\">",
+            "]",
+            ";",
+            "b"
+        ]
+    );
 }
 
 #[test]
-fn test_remove_comments() {
+fn test_tokenize_comments() {
     let dot_str = r#"graph {
     // This is a comment
     a -- b;
@@ -116,7 +236,6 @@ fn test_remove_comments() {
     /* "This is a comment" */
 }"#;
     let tokens = tokenize(dot_str.to_string());
-    let tokens = remove_comments(tokens);
     assert_eq!(
         tokens,
         vec_of_strings![
@@ -126,8 +245,28 @@ fn test_remove_comments() {
     );
 }
 
-pub fn tokenize(dot_str: String) -> Vec<String> {
-    let tokens = raw_tokenize_dot(dot_str);
-    let tokens = remove_comments(tokens);
-    tokens
+#[test]
+fn test_tokenize_comments_before_html() {
+    let dot_str = "graph {\n// a < b\na -- b /* c < d */\n# f < g\n  #line 1\n}";
+    assert_eq!(
+        tokenize(dot_str.to_string()),
+        vec_of_strings!["graph", "{", "a", "--", "b", "}"]
+    );
+
+    let dot_str = r#"a [label=<<b>//x</b>>, tooltip="/* y */"]//z"#;
+    assert_eq!(
+        tokenize(dot_str.to_string()),
+        vec_of_strings![
+            "a",
+            "[",
+            "label",
+            "=",
+            "<<b>//x</b>>",
+            ",",
+            "tooltip",
+            "=",
+            "\"/* y */\"",
+            "]"
+        ]
+    );
 }
@@ -0,0 +1,147 @@
+use crate::layout::{self, Point, POINTS_PER_INCH};
+use crate::semantic::Graph;
+use log::warn;
+
+#[cfg(test)]
+use crate::semantic::from_dot;
+
+// Graphviz defaults, in inches.
+const DEFAULT_RANKSEP: f64 = 1.0;
+const MIN_RANKSEP: f64 = 0.02;
+
+// ranksep gives the distance between consecutive rings, as a list such as "0.5:1" whose last
+// value repeats.
+fn ranksep(graph: &Graph) -> Result<Vec<f64>, String> {
+    let value = match graph.attrs.get("ranksep") {
+        Some(value) => value,
+        None => return Ok(vec![DEFAULT_RANKSEP]),
+    };
+    value
+        .split(':')
+        .map(|x| match x.trim().parse::<f64>() {
+            Ok(x) => Ok(x.max(MIN_RANKSEP)),
+            Err(_) => Err(format!("{}:{} Bad ranksep {}", file!(), line!(), value)),
+        })
+        .collect()
+}
+
+// The node at the center of a component: the one named by the root graph attribute, a node
+// with root=true, or otherwise one whose farthest node is nearest.
+fn root_of(graph: &Graph, component: &[usize], neighbours: &[Vec<usize>]) -> usize {
+    if let Some(name) = graph.attrs.get("root") {
+        if let Some(&root) = component.iter().find(|&&i| graph.nodes[i].name == name) {
+            return root;
+        }
+    }
+    if let Some(&root) = component
+        .iter()
+        .find(|&&i| graph.nodes[i].attrs.get("root") == Some("true"))
+    {
+        return root;
+    }
+    let mut best = (usize::MAX, component[0]);
+    for &source in component.iter() {
+        let (depths, _) = tree(neighbours, source);
+        let eccentricity = component.iter().map(|&i| depths[i]).max().unwrap_or(0);
+        if eccentricity < best.0 {
+            best = (eccentricity, source);
+        }
+    }
+    best.1
+}
+
+// A breadth-first tree from root: the depth of every node, usize::MAX when unreachable, and
+// the nodes in the order they were reached together with their parents.
+fn tree(neighbours: &[Vec<usize>], root: usize) -> (Vec<usize>, Vec<(usize, usize)>) {
+    let mut depths = vec![usize::MAX; neighbours.len()];
+    depths[root] = 0;
+    let mut order = vec![(root, root)];
+    let mut i = 0;
+    while i < order.len() {
+        let (node, _) = order[i];
+        for &next in neighbours[node].iter() {
+            if depths[next] == usize::MAX {
+                depths[next] = depths[node] + 1;
+                order.push((next, node));
+            }
+        }
+        i += 1;
+    }
+    (depths, order)
+}
+
+// Radial layout: the root of each component is at its center and the nodes at depth d of the
+// breadth-first tree on the d-th ring. Every subtree gets a wedge in proportion to its number of
+// leaves and its root is placed in the middle of it. Components are all centered at the origin,
+// layout::apply packs them.
+pub fn layout(graph: &Graph) -> Result<Vec<Point>, String> {
+    let n = graph.nodes.len();
+    let ranksep = ranksep(graph)?;
+    let radius = |depth: usize| {
+        (0..depth)
+            .map(|i| ranksep[i.min(ranksep.len() - 1)])
+            .sum::<f64>()
+            * POINTS_PER_INCH
+    };
+    if let Some(name) = graph.attrs.get("root") {
+        if !graph.nodes.iter().any(|node| node.name == name) {
+            warn!("Root {} is not a node, using the center", name);
+        }
+    }
+    let neighbours = layout::neighbours(graph);
+    let components = layout::components(&neighbours);
+    let mut positions = vec![(0.0, 0.0); n];
+    for component in components.iter() {
+        let root = root_of(graph, component, &neighbours);
+        let (depths, order) = tree(&neighbours, root);
+        // The number of leaves below each node, which for a node with children is their sum.
+        let mut leaves = vec![0.0; n];
+        for &(node, parent) in order.iter().rev() {
+            if leaves[node] == 0.0 {
+                leaves[node] = 1.0;
+            }
+            if node != root {
+                leaves[parent] += leaves[node];
+            }
+        }
+        // The wedge of each node, from its start angle and with its width.
+        let mut wedges = vec![(0.0, 0.0); n];
+        wedges[root] = (0.0, 2.0 * std::f64::consts::PI);
+        let mut next_start = vec![0.0; n];
+        for &(node, parent) in order.iter() {
+            if node != root {
+                let (start, width) = wedges[parent];
+                let share = width * leaves[node] / leaves[parent];
+                wedges[node] = (start + next_start[parent], share);
+                next_start[parent] += share;
+                let angle = wedges[node].0 + share / 2.0;
+                let r = radius(depths[node]);
+                positions[node] = (r * angle.cos(), r * angle.sin());
+            }
+        }
+    }
+    Ok(positions)
+}
+
+#[test]
+fn test_layout() {
+    let distance = |a: Point, b: Point| (a.0 - b.0).hypot(a.1 - b.1);
+    let graph = from_dot("graph { b -- c; a -- b; a -- d; a -- e; d -- f }");
+    let positions = layout(&graph).unwrap();
+    // a is the center, with b, d and e on the first ring and c and f on the second.
+    let a = positions[2];
+    for (i, r) in [(0, 72.0), (1, 144.0), (3, 72.0), (4, 72.0), (5, 144.0)] {
+        assert!((distance(a, positions[i]) - r).abs() < 1e-9, "{}", i);
+    }
+
+    let graph = from_dot(r#"graph { root = c; ranksep = "0.5:2"; a -- b -- c }"#);
+    let positions = layout(&graph).unwrap();
+    assert!((distance(positions[2], positions[1]) - 36.0).abs() < 1e-9);
+    assert!((distance(positions[2], positions[0]) - 180.0).abs() < 1e-9);
+
+    let graph = from_dot("graph { a -- b -- c; b [root = true] }");
+    let positions = layout(&graph).unwrap();
+    assert!((distance(positions[1], positions[0]) - 72.0).abs() < 1e-9);
+
+    assert!(layout(&from_dot(r#"graph { ranksep = "x"; a }"#)).is_err());
+}